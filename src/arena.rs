@@ -0,0 +1,1298 @@
+use crate::{
+    engine::{Engine, SearchParams, TimeConfig},
+    epd::parse_epd_collection,
+    eval::EvalWeights,
+    game::Game,
+    mcts::MctsParams,
+    opening::{BookLearning, BookOptions, OpeningBook},
+    pgn::{clock_comment, parse_pgn_collection, to_pgn_annotated},
+    pieces::{Color, PieceType},
+};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Two-sided 95% quantile of the normal distribution.
+const Z_95: f64 = 1.959_964;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameScore {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl GameScore {
+    pub fn for_player(winner: Option<Color>, player: Color) -> Self {
+        match winner {
+            Some(color) if color == player => GameScore::Win,
+            Some(_) => GameScore::Loss,
+            None => GameScore::Draw,
+        }
+    }
+
+    pub fn points(self) -> f64 {
+        match self {
+            GameScore::Win => 1.0,
+            GameScore::Draw => 0.5,
+            GameScore::Loss => 0.0,
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+// Comma separated values filling `values` in order.
+fn parse_list<T: FromStr>(key: &str, value: &str, values: &mut [T]) -> Result<(), String> {
+    let items: Vec<&str> = value.split(',').collect();
+    if items.len() != values.len() {
+        return Err(format!("{} expects {} values", key, values.len()));
+    }
+    for (slot, item) in values.iter_mut().zip(items) {
+        *slot = parse_value(key, item)?;
+    }
+    Ok(())
+}
+
+// Search and evaluation settings of one match engine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    pub depth: u32,
+    pub params: SearchParams,
+    pub weights: EvalWeights,
+    // Opening book replacing the built-in one, checked when it is set.
+    pub book_file: Option<String>,
+    // Where the results of the engine's book moves are kept.
+    pub book_learning: Option<String>,
+    // Name on the rating ladder, the settings summary when unset.
+    pub name: Option<String>,
+}
+
+impl EngineConfig {
+    pub fn new(depth: u32) -> Self {
+        Self {
+            depth,
+            params: SearchParams::default(),
+            weights: EvalWeights::default(),
+            book_file: None,
+            book_learning: None,
+            name: None,
+        }
+    }
+
+    pub fn build(&self, threads: usize, table_size: usize) -> Engine {
+        let mut engine = Engine::with_threads_and_table(self.depth, threads, table_size);
+        if let Some(path) = &self.book_file {
+            let _ = engine.load_book(path);
+        }
+        if let Some(path) = &self.book_learning {
+            let _ = engine.enable_book_learning(path);
+        }
+        engine.params = self.params.clone();
+        engine.set_seed(self.params.seed);
+        engine.weights = self.weights;
+        let _ = engine.load_syzygy_from_env();
+        engine
+    }
+
+    // Sets one option by name, as given on the command line or in a config file.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "name" => self.name = Some(value.to_string()),
+            "depth" => self.depth = parse_value(key, value)?,
+            "book" => self.params.use_book = parse_value(key, value)?,
+            "book_depth" => self.params.book.max_ply = parse_value(key, value)?,
+            "book_weighted" => self.params.book.weighted = parse_value(key, value)?,
+            "book_min_score" => self.params.book.min_score = parse_value(key, value)?,
+            "book_file" => {
+                OpeningBook::load(value).map_err(|e| format!("{}: {}", value, e))?;
+                self.book_file = Some(value.to_string());
+            }
+            "book_learning" => {
+                BookLearning::open(value).map_err(|e| format!("{}: {}", value, e))?;
+                self.book_learning = Some(value.to_string());
+            }
+            "null_move" => self.params.null_move = parse_value(key, value)?,
+            "late_move_reductions" => self.params.late_move_reductions = parse_value(key, value)?,
+            "history_pruning" => self.params.history_pruning = parse_value(key, value)?,
+            "multi_cut" => self.params.multi_cut = parse_value(key, value)?,
+            "seed" => self.params.seed = Some(parse_value(key, value)?),
+            "mcts" => {
+                let enabled: bool = parse_value(key, value)?;
+                if !enabled {
+                    self.params.mcts = None;
+                } else if self.params.mcts.is_none() {
+                    self.params.mcts = Some(MctsParams::default());
+                }
+            }
+            "mcts_playouts" | "mcts_exploration" => {
+                let mcts = self.params.mcts.get_or_insert_with(MctsParams::default);
+                let value = parse_value(key, value)?;
+                if key == "mcts_playouts" {
+                    mcts.playouts = value;
+                } else {
+                    mcts.exploration = value;
+                }
+            }
+            "futility_margins" => parse_list(key, value, &mut self.params.rfp_margins[1..])?,
+            "lmp_limits" => parse_list(key, value, &mut self.params.lmp_limits[1..])?,
+            "material" => self.weights.material = parse_value(key, value)?,
+            "pawn_structure" => self.weights.pawn_structure = parse_value(key, value)?,
+            "pieces" => self.weights.pieces = parse_value(key, value)?,
+            "king_safety" => self.weights.king_safety = parse_value(key, value)?,
+            _ => return Err(format!("unknown engine option: {}", key)),
+        }
+        Ok(())
+    }
+
+    // One-line summary written to the PGN headers.
+    pub fn describe(&self) -> String {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        let join = |values: Vec<String>| values.join("/");
+        let params = &self.params;
+        let weights = &self.weights;
+        let mut summary = format!(
+            "depth {}, book {}, null move {}, LMR {}, history pruning {}, multi-cut {}, futility {}, LMP {}, weights {}/{}/{}/{}",
+            self.depth,
+            on_off(params.use_book),
+            on_off(params.null_move),
+            on_off(params.late_move_reductions),
+            on_off(params.history_pruning),
+            on_off(params.multi_cut),
+            join(
+                params.rfp_margins[1..]
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect()
+            ),
+            join(
+                params.lmp_limits[1..]
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect()
+            ),
+            weights.material,
+            weights.pawn_structure,
+            weights.pieces,
+            weights.king_safety,
+        );
+        if params.book != BookOptions::default() {
+            let book = &params.book;
+            summary.push_str(&format!(
+                ", book depth {}, {} book moves, book min score {}%",
+                book.max_ply,
+                if book.weighted { "weighted" } else { "best" },
+                book.min_score
+            ));
+        }
+        if let Some(mcts) = &params.mcts {
+            summary.push_str(&format!(
+                ", MCTS {} playouts, exploration {:.2}",
+                mcts.playouts,
+                mcts.exploration as f64 / 100.0
+            ));
+        }
+        if let Some(path) = &self.book_file {
+            summary.push_str(&format!(", book file {}", path));
+        }
+        if let Some(path) = &self.book_learning {
+            summary.push_str(&format!(", book learning {}", path));
+        }
+        summary
+    }
+
+    // The engine's name on the rating ladder.
+    pub fn ladder_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.describe())
+    }
+}
+
+// How long engines think per move. `Depth` searches each engine's own depth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeControl {
+    Depth,
+    MoveTime(u64),
+    Clock { base_ms: u64, inc_ms: u64 },
+}
+
+// Parses `depth`, `movetime=<ms>` or `<base>+<inc>` in seconds, e.g. `10+0.1`.
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let invalid = || format!("invalid time control: {}", text);
+        if text == "depth" {
+            return Ok(TimeControl::Depth);
+        }
+        if let Some(ms) = text.strip_prefix("movetime=") {
+            return ms.parse().map(TimeControl::MoveTime).map_err(|_| invalid());
+        }
+        let (base, inc) = text.split_once('+').unwrap_or((text, "0"));
+        let seconds = |s: &str| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|v| *v >= 0.0)
+                .map(|v| (v * 1000.0) as u64)
+        };
+        match (seconds(base), seconds(inc)) {
+            (Some(base_ms), Some(inc_ms)) if base_ms > 0 => {
+                Ok(TimeControl::Clock { base_ms, inc_ms })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// Remaining time of both sides under a `TimeControl`.
+#[derive(Clone, Debug)]
+pub struct GameClock {
+    pub time_control: TimeControl,
+    remaining_ms: [u64; 2],
+    // Time left after each ply, `None` for plies played off the clock.
+    history: Vec<Option<u64>>,
+}
+
+impl GameClock {
+    pub fn new(time_control: TimeControl) -> Self {
+        let base = match time_control {
+            TimeControl::Clock { base_ms, .. } => base_ms,
+            _ => 0,
+        };
+        Self {
+            time_control,
+            remaining_ms: [base; 2],
+            history: Vec::new(),
+        }
+    }
+
+    pub fn remaining_ms(&self, color: Color) -> u64 {
+        self.remaining_ms[color as usize]
+    }
+
+    // Time left to `color` when playing on a clock, as written to `[%clk]`
+    // comments.
+    pub fn clock_ms(&self, color: Color) -> Option<u64> {
+        matches!(self.time_control, TimeControl::Clock { .. }).then(|| self.remaining_ms(color))
+    }
+
+    pub fn time_config(&self, depth: u32) -> TimeConfig {
+        match self.time_control {
+            TimeControl::Depth => TimeConfig::fixed_depth(depth),
+            TimeControl::MoveTime(ms) => TimeConfig::fixed_time(ms),
+            TimeControl::Clock { inc_ms, .. } => TimeConfig {
+                wtime: Some(self.remaining_ms(Color::White)),
+                btime: Some(self.remaining_ms(Color::Black)),
+                winc: Some(inc_ms),
+                binc: Some(inc_ms),
+                ..TimeConfig::default()
+            },
+        }
+    }
+
+    // Charges a move to `color`. Returns false when the flag falls: the move
+    // took longer than the time left, which loses the game on time. Fixed
+    // depths and move times never flag.
+    pub fn spend(&mut self, color: Color, elapsed: Duration) -> bool {
+        if let TimeControl::Clock { inc_ms, .. } = self.time_control {
+            let remaining = &mut self.remaining_ms[color as usize];
+            let elapsed = elapsed.as_millis() as u64;
+            if elapsed > *remaining {
+                *remaining = 0;
+                return false;
+            }
+            *remaining = *remaining - elapsed + inc_ms;
+        }
+        true
+    }
+
+    // Notes the time `color` has left after playing ply `ply`.
+    pub fn record(&mut self, ply: usize, color: Color) {
+        self.history.resize(ply, None);
+        self.history.push(self.clock_ms(color));
+    }
+
+    // Time left after each ply recorded, as written to `[%clk]` comments.
+    pub fn history(&self) -> &[Option<u64>] {
+        &self.history
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    InsufficientMaterial,
+    MoveLimit,
+    ResignAdjudication,
+    DrawAdjudication,
+    TablebaseAdjudication,
+    // The engine failed to produce a legal move.
+    NoMove,
+    // The flag fell.
+    Timeout,
+    // A player gave up.
+    Resignation,
+}
+
+impl Termination {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "threefold repetition",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::MoveLimit => "move limit",
+            Termination::ResignAdjudication => "resign adjudication",
+            Termination::DrawAdjudication => "draw adjudication",
+            Termination::TablebaseAdjudication => "tablebase adjudication",
+            Termination::NoMove => "no move",
+            Termination::Timeout => "time forfeit",
+            Termination::Resignation => "resignation",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameOutcome {
+    pub winner: Option<Color>,
+    pub termination: Termination,
+}
+
+impl GameOutcome {
+    pub fn draw(termination: Termination) -> Self {
+        Self {
+            winner: None,
+            termination,
+        }
+    }
+
+    // `flagged` ran out of time. The opponent wins unless it cannot mate by
+    // any series of moves: a bare king, or a single minor piece against one.
+    pub fn timeout(game: &Game, flagged: Color) -> Self {
+        let board = &game.board;
+        let winner = opposite(flagged);
+        let minors = |c: Color| {
+            (board.pieces_of(c, PieceType::Knight) | board.pieces_of(c, PieceType::Bishop))
+                .count_ones()
+        };
+        let majors = board.pieces_of(winner, PieceType::Pawn)
+            | board.pieces_of(winner, PieceType::Rook)
+            | board.pieces_of(winner, PieceType::Queen);
+        let bare = |c: Color| board.occupied_by(c).count_ones() == 1;
+        let can_mate = majors != 0 || minors(winner) > 1 || (minors(winner) == 1 && !bare(flagged));
+        Self {
+            winner: can_mate.then_some(winner),
+            termination: Termination::Timeout,
+        }
+    }
+
+    pub fn result_str(&self) -> &'static str {
+        match self.winner {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+// Returns the outcome once the game is over; long games are adjudicated as
+// draws after `max_plies` plies.
+pub fn game_outcome(game: &mut Game, max_plies: usize) -> Option<GameOutcome> {
+    if game.legal_moves().is_empty() {
+        return Some(match game.result {
+            Some(winner) => GameOutcome {
+                winner: Some(winner),
+                termination: Termination::Checkmate,
+            },
+            None => GameOutcome::draw(Termination::Stalemate),
+        });
+    }
+    if game.is_threefold_repetition() {
+        return Some(GameOutcome::draw(Termination::Repetition));
+    }
+    if game.is_insufficient_material() {
+        return Some(GameOutcome::draw(Termination::InsufficientMaterial));
+    }
+    if game.history.len() >= max_plies {
+        return Some(GameOutcome::draw(Termination::MoveLimit));
+    }
+    None
+}
+
+// Scores are in centipawns and move counts in full moves, so a rule holding
+// for N moves needs the evaluations of both engines N times in a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdjudicationRules {
+    pub resign: bool,
+    pub resign_score: i32,
+    pub resign_moves: u32,
+    pub draw: bool,
+    pub draw_score: i32,
+    pub draw_moves: u32,
+    pub draw_after_move: u32,
+    pub tablebase: bool,
+}
+
+impl Default for AdjudicationRules {
+    fn default() -> Self {
+        Self {
+            resign: true,
+            resign_score: 1000,
+            resign_moves: 3,
+            draw: true,
+            draw_score: 10,
+            draw_moves: 8,
+            draw_after_move: 40,
+            tablebase: true,
+        }
+    }
+}
+
+impl AdjudicationRules {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "resign" => self.resign = parse_value(key, value)?,
+            "resign_score" => self.resign_score = parse_value(key, value)?,
+            "resign_moves" => self.resign_moves = parse_value(key, value)?,
+            "draw" => self.draw = parse_value(key, value)?,
+            "draw_score" => self.draw_score = parse_value(key, value)?,
+            "draw_moves" => self.draw_moves = parse_value(key, value)?,
+            "draw_after_move" => self.draw_after_move = parse_value(key, value)?,
+            "tablebase" => self.tablebase = parse_value(key, value)?,
+            _ => return Err(format!("unknown adjudication option: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+// Tracks the engines' evaluations over one game.
+#[derive(Clone, Debug)]
+pub struct Adjudicator {
+    pub rules: AdjudicationRules,
+    // Side both engines agree is lost, and for how many plies.
+    losing: Option<Color>,
+    resign_plies: u32,
+    draw_plies: u32,
+}
+
+impl Adjudicator {
+    pub fn new(rules: AdjudicationRules) -> Self {
+        Self {
+            rules,
+            losing: None,
+            resign_plies: 0,
+            draw_plies: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.rules.clone());
+    }
+
+    // Called after every move with the mover's own evaluation; moves without
+    // one (book or random moves) break both streaks.
+    pub fn record(&mut self, game: &Game, mover: Color, score: Option<i32>) -> Option<GameOutcome> {
+        let Some(score) = score else {
+            self.losing = None;
+            self.resign_plies = 0;
+            self.draw_plies = 0;
+            return None;
+        };
+        let white_score = if mover == Color::White { score } else { -score };
+
+        let losing = if white_score <= -self.rules.resign_score {
+            Some(Color::White)
+        } else if white_score >= self.rules.resign_score {
+            Some(Color::Black)
+        } else {
+            None
+        };
+        self.resign_plies = match losing {
+            Some(_) if losing == self.losing => self.resign_plies + 1,
+            Some(_) => 1,
+            None => 0,
+        };
+        self.losing = losing;
+        if self.rules.resign
+            && let Some(loser) = self.losing
+            && self.resign_plies >= self.rules.resign_moves * 2
+        {
+            return Some(GameOutcome {
+                winner: Some(opposite(loser)),
+                termination: Termination::ResignAdjudication,
+            });
+        }
+
+        let full_moves = game.history.len() as u32 / 2;
+        if full_moves >= self.rules.draw_after_move && white_score.abs() <= self.rules.draw_score {
+            self.draw_plies += 1;
+        } else {
+            self.draw_plies = 0;
+        }
+        if self.rules.draw && self.draw_plies >= self.rules.draw_moves * 2 {
+            return Some(GameOutcome::draw(Termination::DrawAdjudication));
+        }
+        None
+    }
+
+    // Ends the game with the tablebase result once few enough pieces remain.
+    pub fn tablebase(&self, game: &Game, engine: &Engine) -> Option<GameOutcome> {
+        if !self.rules.tablebase {
+            return None;
+        }
+        let score = engine.probe_tablebase(&game.board, game.current_turn)?;
+        let winner = match score.signum() {
+            1 => Some(game.current_turn),
+            -1 => Some(opposite(game.current_turn)),
+            _ => None,
+        };
+        Some(GameOutcome {
+            winner,
+            termination: Termination::TablebaseAdjudication,
+        })
+    }
+
+    // The `Match::adjudicate` hook: the last mover's evaluation first, then
+    // the tablebases of `referee`.
+    pub fn check(
+        &mut self,
+        game: &Game,
+        score: Option<i32>,
+        referee: &Engine,
+    ) -> Option<GameOutcome> {
+        self.record(game, opposite(game.current_turn), score)
+            .or_else(|| self.tablebase(game, referee))
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+// PGN of a finished match game. `players` holds the name and settings of the
+// White and Black players, in that order, and `clocks` the time left after
+// each ply, if known.
+pub fn game_pgn(
+    game: &Game,
+    round: u32,
+    players: [(&str, &str); 2],
+    outcome: &GameOutcome,
+    clocks: &[Option<u64>],
+) -> String {
+    let [(white, white_settings), (black, black_settings)] = players;
+    let tags = [
+        ("Event", "Chessmind arena"),
+        ("Site", "?"),
+        ("Date", "????.??.??"),
+        ("Round", &round.to_string()),
+        ("White", white),
+        ("Black", black),
+        ("Result", outcome.result_str()),
+        ("WhiteSettings", white_settings),
+        ("BlackSettings", black_settings),
+        ("Termination", outcome.termination.reason()),
+    ]
+    .map(|(key, value)| (key.to_string(), value.to_string()));
+    let comments: Vec<Option<String>> = clocks.iter().map(|c| c.map(clock_comment)).collect();
+    to_pgn_annotated(game, &tags, &comments)
+}
+
+pub fn append_pgn(path: &Path, pgn: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", pgn)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EloEstimate {
+    pub elo: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl EloEstimate {
+    fn from_score(mean: f64, stderr: f64) -> Self {
+        Self {
+            elo: score_to_elo(mean),
+            lower: score_to_elo(mean - Z_95 * stderr),
+            upper: score_to_elo(mean + Z_95 * stderr),
+        }
+    }
+}
+
+// Games 2k and 2k + 1 share an opening, with the first engine playing White
+// in the first and Black in the second.
+pub fn first_player_color(game_index: u32) -> Color {
+    if game_index.is_multiple_of(2) {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct OpeningSuite {
+    pub openings: Vec<Game>,
+}
+
+impl OpeningSuite {
+    pub fn from_epd(text: &str) -> Self {
+        let openings = parse_epd_collection(text)
+            .into_iter()
+            .map(|record| record.game)
+            .collect();
+        Self { openings }
+    }
+
+    pub fn from_pgn(text: &str) -> Self {
+        let openings = parse_pgn_collection(text)
+            .into_iter()
+            .map(|pgn| pgn.game)
+            .collect();
+        Self { openings }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let is_pgn = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pgn"));
+        let suite = if is_pgn {
+            Self::from_pgn(&text)
+        } else {
+            Self::from_epd(&text)
+        };
+        if suite.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no openings found in {}", path.display()),
+            ));
+        }
+        Ok(suite)
+    }
+
+    // Same openings in an order fixed by `seed`.
+    pub fn shuffled(mut self, seed: u64) -> Self {
+        self.openings.shuffle(&mut StdRng::seed_from_u64(seed));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.openings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.openings.is_empty()
+    }
+
+    // Opening played in a match game; the suite is cycled once exhausted.
+    pub fn opening_index(&self, game_index: u32) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(game_index as usize / 2 % self.len())
+    }
+
+    pub fn game_for(&self, game_index: u32) -> Game {
+        match self.opening_index(game_index) {
+            Some(i) => self.openings[i].clone(),
+            None => Game::new(),
+        }
+    }
+}
+
+// Search effort of one engine summed over its moves; book moves are not counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchTotals {
+    pub searches: u64,
+    pub depth: u64,
+    pub nodes: u64,
+}
+
+impl SearchTotals {
+    pub fn add(&mut self, depth: u32, nodes: u64) {
+        self.searches += 1;
+        self.depth += depth as u64;
+        self.nodes += nodes;
+    }
+
+    pub fn merge(&mut self, other: &SearchTotals) {
+        self.searches += other.searches;
+        self.depth += other.depth;
+        self.nodes += other.nodes;
+    }
+
+    pub fn average_depth(&self) -> Option<f64> {
+        (self.searches > 0).then(|| self.depth as f64 / self.searches as f64)
+    }
+
+    pub fn average_nodes(&self) -> Option<f64> {
+        (self.searches > 0).then(|| self.nodes as f64 / self.searches as f64)
+    }
+}
+
+// Lets the engines learn from the book moves they played from `from_ply` on,
+// the first one having played `first_color`.
+pub fn learn_from_game(
+    engines: &[Engine],
+    game: &Game,
+    from_ply: usize,
+    first_color: Color,
+    winner: Option<Color>,
+) -> io::Result<()> {
+    for (engine, color) in engines.iter().zip([first_color, opposite(first_color)]) {
+        engine.learn_from_game(game, from_ply, color, winner)?;
+    }
+    Ok(())
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// Results are seen from the first engine's side. Games 2k and 2k + 1 form a pair
+// played with colors reversed, which feeds the pentanomial counts.
+#[derive(Clone, Debug, Default)]
+pub struct MatchStats {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    // Number of pairs scoring 0, 0.5, 1, 1.5 and 2 points.
+    pub pentanomial: [u32; 5],
+    unpaired: HashMap<u32, GameScore>,
+}
+
+impl MatchStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, game_index: u32, score: GameScore) {
+        match score {
+            GameScore::Win => self.wins += 1,
+            GameScore::Draw => self.draws += 1,
+            GameScore::Loss => self.losses += 1,
+        }
+        if let Some(other) = self.unpaired.remove(&(game_index ^ 1)) {
+            let points = score.points() + other.points();
+            self.pentanomial[(points * 2.0) as usize] += 1;
+        } else {
+            self.unpaired.insert(game_index, score);
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn pairs(&self) -> u32 {
+        self.pentanomial.iter().sum()
+    }
+
+    pub fn score(&self) -> f64 {
+        let games = self.games();
+        if games == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 * 0.5) / games as f64
+    }
+
+    // Mean and variance of the score of a single game.
+    fn game_moments(&self) -> Option<(f64, f64)> {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return None;
+        }
+        let mean = self.score();
+        let variance = (self.wins as f64 * (1.0 - mean).powi(2)
+            + self.draws as f64 * (0.5 - mean).powi(2)
+            + self.losses as f64 * mean.powi(2))
+            / n;
+        Some((mean, variance))
+    }
+
+    pub fn elo(&self) -> Option<EloEstimate> {
+        let (mean, variance) = self.game_moments()?;
+        let stderr = (variance / self.games() as f64).sqrt();
+        Some(EloEstimate::from_score(mean, stderr))
+    }
+
+    // Mean and variance of the per-game score averaged over each pair.
+    fn pair_moments(&self) -> Option<(f64, f64)> {
+        let n = self.pairs() as f64;
+        if n == 0.0 {
+            return None;
+        }
+        let value = |i: usize| i as f64 * 0.25;
+        let mean = (0..5)
+            .map(|i| self.pentanomial[i] as f64 * value(i))
+            .sum::<f64>()
+            / n;
+        let variance = (0..5)
+            .map(|i| self.pentanomial[i] as f64 * (value(i) - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        Some((mean, variance))
+    }
+
+    pub fn pentanomial_elo(&self) -> Option<EloEstimate> {
+        let (mean, variance) = self.pair_moments()?;
+        let stderr = (variance / self.pairs() as f64).sqrt();
+        Some(EloEstimate::from_score(mean, stderr))
+    }
+
+    // Normalized Elo: the score excess over its per-game standard deviation,
+    // expressed on the Elo scale, so it does not depend on the draw rate.
+    pub fn normalized_elo(&self) -> Option<f64> {
+        let (mean, variance) = self.pair_moments()?;
+        let sigma = (2.0 * variance).sqrt();
+        if sigma == 0.0 {
+            return None;
+        }
+        Some((mean - 0.5) / sigma * 800.0 / std::f64::consts::LN_10)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub index: u32,
+    // Result from the first engine's point of view.
+    pub score: GameScore,
+    pub pgn: String,
+}
+
+// Progress of a long match, saved after every game so an interrupted run can
+// be resumed. `settings` describes the match so a state is not resumed with
+// different engines.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchState {
+    pub settings: String,
+    pub seed: Option<u64>,
+    pub games: Vec<GameRecord>,
+}
+
+impl MatchState {
+    pub fn new(settings: String, seed: Option<u64>) -> Self {
+        Self {
+            settings,
+            seed,
+            games: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Writes through a temporary file so an interruption never leaves a
+    // truncated state behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(tmp, path)
+    }
+
+    pub fn record(&mut self, index: u32, score: GameScore, pgn: String) {
+        self.games.push(GameRecord { index, score, pgn });
+    }
+
+    pub fn played(&self) -> HashSet<u32> {
+        self.games.iter().map(|game| game.index).collect()
+    }
+
+    pub fn stats(&self) -> MatchStats {
+        let mut stats = MatchStats::new();
+        for game in &self.games {
+            stats.record(game.index, game.score);
+        }
+        stats
+    }
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    AcceptH0,
+    AcceptH1,
+}
+
+// Sequential probability ratio test of H0: elo = elo0 against H1: elo = elo1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    // Lower and upper LLR bounds at which H0 or H1 is accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Log-likelihood ratio under the normal approximation, using pairs once
+    // any are complete and single games before that.
+    pub fn llr(&self, stats: &MatchStats) -> f64 {
+        let (moments, samples) = match stats.pair_moments() {
+            Some(moments) => (moments, stats.pairs()),
+            None => match stats.game_moments() {
+                Some(moments) => (moments, stats.games()),
+                None => return 0.0,
+            },
+        };
+        let (mean, variance) = moments;
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let s0 = elo_to_score(self.elo0);
+        let s1 = elo_to_score(self.elo1);
+        samples as f64 * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn status(&self, stats: &MatchStats) -> SprtStatus {
+        let llr = self.llr(stats);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtStatus::AcceptH0
+        } else if llr >= upper {
+            SprtStatus::AcceptH1
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::play::{Match, PlayerKind};
+
+    #[test]
+    fn elo_from_score() {
+        assert!(score_to_elo(0.5).abs() < 1e-9);
+        assert!((score_to_elo(0.75) - 190.85).abs() < 0.01);
+        assert!((score_to_elo(0.25) + 190.85).abs() < 0.01);
+    }
+
+    #[test]
+    fn opening_suite_pairs_games() {
+        let epd = "# suite\nrnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - id \"e4\";\n\n8/8/8/8/8/8/8/8 w - -\n4k3/8/8/8/8/8/4P3/4K3 w - - bm e4;\n";
+        let suite = OpeningSuite::from_epd(epd);
+        assert_eq!(suite.len(), 2);
+        assert_eq!(suite.game_for(0).to_fen(), suite.game_for(1).to_fen());
+        assert_eq!(suite.game_for(2).current_turn, Color::White);
+        assert_eq!(suite.game_for(4).current_turn, Color::Black);
+        assert_eq!(first_player_color(2), Color::White);
+        assert_eq!(first_player_color(3), Color::Black);
+
+        let pgn = "[Event \"a\"]\n\n1. e4 c5 *\n\n[Event \"b\"]\n\n1. d4 d5 *\n";
+        let suite = OpeningSuite::from_pgn(pgn);
+        assert_eq!(suite.len(), 2);
+        assert_eq!(
+            suite.game_for(3).history[0],
+            ("d2".to_string(), "d4".to_string())
+        );
+        assert!(OpeningSuite::default().game_for(5).history.is_empty());
+        assert_eq!(suite.opening_index(5), Some(0));
+        assert_eq!(OpeningSuite::default().opening_index(5), None);
+
+        let mut totals = SearchTotals::default();
+        assert_eq!(totals.average_depth(), None);
+        totals.add(6, 1000);
+        totals.merge(&SearchTotals {
+            searches: 1,
+            depth: 8,
+            nodes: 3000,
+        });
+        assert_eq!(totals.average_depth(), Some(7.0));
+        assert_eq!(totals.average_nodes(), Some(2000.0));
+    }
+
+    #[test]
+    fn finished_games_export_to_pgn() {
+        let mut game = Game::new();
+        assert_eq!(game_outcome(&mut game, 400), None);
+        for (s, e) in [("f2", "f3"), ("e7", "e5"), ("g2", "g4"), ("d8", "h4")] {
+            assert!(game.make_move(s, e));
+        }
+        let outcome = game_outcome(&mut game, 400).unwrap();
+        assert_eq!(outcome.winner, Some(Color::Black));
+        assert_eq!(outcome.termination, Termination::Checkmate);
+
+        let clocks = [Some(59_000), Some(58_500), None, Some(57_000)];
+        let pgn = game_pgn(
+            &game,
+            3,
+            [("A", "depth 6"), ("B", "depth 4")],
+            &outcome,
+            &clocks,
+        );
+        assert!(pgn.contains("[Round \"3\"]"));
+        assert!(pgn.contains("[WhiteSettings \"depth 6\"]"));
+        assert!(pgn.contains("[Termination \"checkmate\"]"));
+        assert!(pgn.trim_end().ends_with("0-1"));
+        assert!(pgn.contains("1. f3 {[%clk 0:00:59]} 1... e5 {[%clk 0:00:58.5]} 2. g4 Qh4#"));
+
+        let mut game = Game::new();
+        game.make_move("e2", "e4");
+        assert_eq!(
+            game_outcome(&mut game, 1),
+            Some(GameOutcome::draw(Termination::MoveLimit))
+        );
+    }
+
+    #[test]
+    fn adjudication_needs_consecutive_agreement() {
+        let mut game = Game::new();
+        let mut adjudicator = Adjudicator::new(AdjudicationRules {
+            resign_moves: 2,
+            ..AdjudicationRules::default()
+        });
+        // White is losing by both engines' evaluation, until a book move.
+        assert_eq!(adjudicator.record(&game, Color::White, Some(-1200)), None);
+        assert_eq!(adjudicator.record(&game, Color::Black, Some(1500)), None);
+        assert_eq!(adjudicator.record(&game, Color::White, None), None);
+        assert_eq!(adjudicator.record(&game, Color::Black, Some(1500)), None);
+        assert_eq!(adjudicator.record(&game, Color::White, Some(-1100)), None);
+        assert_eq!(adjudicator.record(&game, Color::Black, Some(1100)), None);
+        assert_eq!(
+            adjudicator.record(&game, Color::White, Some(-1000)),
+            Some(GameOutcome {
+                winner: Some(Color::Black),
+                termination: Termination::ResignAdjudication,
+            })
+        );
+
+        adjudicator.reset();
+        adjudicator.rules.draw_moves = 1;
+        adjudicator.rules.draw_after_move = 1;
+        assert_eq!(adjudicator.record(&game, Color::White, Some(0)), None);
+        assert_eq!(adjudicator.record(&game, Color::Black, Some(5)), None);
+        for (s, e) in [("g1", "f3"), ("g8", "f6")] {
+            game.make_move(s, e);
+        }
+        assert_eq!(adjudicator.record(&game, Color::White, Some(-5)), None);
+        assert_eq!(
+            adjudicator.record(&game, Color::Black, Some(5)),
+            Some(GameOutcome::draw(Termination::DrawAdjudication))
+        );
+    }
+
+    #[test]
+    fn engine_config_and_time_control_parse() {
+        let mut config = EngineConfig::new(6);
+        config.set("depth", "8").unwrap();
+        config.set("null_move", "false").unwrap();
+        config.set("lmp_limits", "4,6,9,12").unwrap();
+        config.set("king_safety", "80").unwrap();
+        assert_eq!(config.depth, 8);
+        assert!(!config.params.null_move);
+        assert_eq!(config.params.lmp_limits, [0, 4, 6, 9, 12]);
+        assert_eq!(config.weights.king_safety, 80);
+        assert_eq!(config.params.mcts, None);
+        config.set("mcts_playouts", "5000").unwrap();
+        config.set("mcts", "true").unwrap();
+        assert_eq!(config.params.mcts.map(|m| m.playouts), Some(5000));
+        assert!(config.describe().contains("MCTS 5000 playouts"));
+        config.set("mcts", "false").unwrap();
+        assert_eq!(config.params.mcts, None);
+        assert!(config.set("futility_margins", "1,2").is_err());
+        assert!(config.set("depth", "deep").is_err());
+        assert!(config.set("ponder", "true").is_err());
+
+        assert_eq!("depth".parse(), Ok(TimeControl::Depth));
+        assert_eq!("movetime=250".parse(), Ok(TimeControl::MoveTime(250)));
+        let tc: TimeControl = "10+0.1".parse().unwrap();
+        assert_eq!(
+            tc,
+            TimeControl::Clock {
+                base_ms: 10_000,
+                inc_ms: 100
+            }
+        );
+        assert!("fast".parse::<TimeControl>().is_err());
+
+        let mut clock = GameClock::new(tc);
+        assert!(clock.spend(Color::White, Duration::from_millis(1500)));
+        assert_eq!(clock.remaining_ms(Color::White), 8_600);
+        assert_eq!(clock.time_config(6).btime, Some(10_000));
+        assert_eq!(clock.clock_ms(Color::White), Some(8_600));
+        assert_eq!(
+            GameClock::new(TimeControl::Depth).clock_ms(Color::White),
+            None
+        );
+        clock.record(2, Color::White);
+        assert_eq!(clock.history(), [None, None, Some(8_600)]);
+        assert!(!clock.spend(Color::Black, Duration::from_millis(10_001)));
+        assert_eq!(clock.remaining_ms(Color::Black), 0);
+        assert!(GameClock::new(TimeControl::MoveTime(10)).spend(Color::White, Duration::MAX));
+    }
+
+    #[test]
+    fn matches_are_adjudicated() {
+        let mut engines = [
+            EngineConfig::new(2).build(1, 1 << 10),
+            EngineConfig::new(2).build(1, 1 << 10),
+        ];
+        let referee = engines[0].fork();
+        let mut adjudicator = Adjudicator::new(AdjudicationRules::default());
+        let [white, black] = &mut engines;
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let (white, black) = (PlayerKind::engine_mut(white), PlayerKind::engine_mut(black));
+        let outcome = Match::new(white, black, TimeControl::Depth)
+            .with_game(game)
+            .adjudicate(|game, score| adjudicator.check(game, score, &referee))
+            .play();
+        assert_eq!(outcome.winner, Some(Color::White));
+        assert_eq!(outcome.termination, Termination::Checkmate);
+
+        // Both sides agree White is lost for three moves.
+        adjudicator.reset();
+        let resigned = |score: i32| {
+            PlayerKind::bot(move |game, _| Some((game.legal_moves()[0].clone(), Some(score))))
+        };
+        let mut played = Match::new(resigned(-1000), resigned(1000), TimeControl::Depth)
+            .adjudicate(|game, score| adjudicator.check(game, score, &referee));
+        let outcome = played.play();
+        assert_eq!(outcome.winner, Some(Color::Black));
+        assert_eq!(outcome.termination, Termination::ResignAdjudication);
+        assert_eq!(played.game().history.len(), 6);
+    }
+
+    #[test]
+    fn bare_kings_cannot_win_on_time() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/6K1 w - - 0 1").unwrap();
+        assert_eq!(GameOutcome::timeout(&game, Color::Black).winner, None);
+        assert_eq!(
+            GameOutcome::timeout(&game, Color::White).winner,
+            Some(Color::Black)
+        );
+    }
+
+    #[test]
+    fn sprt_accepts_clear_results() {
+        let sprt = Sprt::new(0.0, 10.0);
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 1e-3 && (upper - 2.944).abs() < 1e-3);
+        assert_eq!(sprt.llr(&MatchStats::new()), 0.0);
+
+        let mut winning = MatchStats::new();
+        let mut losing = MatchStats::new();
+        for i in 0..2000 {
+            let score = match i % 5 {
+                0 | 1 => GameScore::Win,
+                2 | 3 => GameScore::Draw,
+                _ => GameScore::Loss,
+            };
+            winning.record(i, score);
+            let flipped = match score {
+                GameScore::Win => GameScore::Loss,
+                GameScore::Draw => GameScore::Draw,
+                GameScore::Loss => GameScore::Win,
+            };
+            losing.record(i, flipped);
+        }
+        assert_eq!(sprt.status(&winning), SprtStatus::AcceptH1);
+        assert_eq!(sprt.status(&losing), SprtStatus::AcceptH0);
+    }
+
+    #[test]
+    fn match_state_round_trip() {
+        let suite = OpeningSuite::from_pgn(
+            "[Event \"a\"]\n\n1. e4 *\n\n[Event \"b\"]\n\n1. d4 *\n\n[Event \"c\"]\n\n1. c4 *\n",
+        );
+        let order = |suite: &OpeningSuite| {
+            suite
+                .openings
+                .iter()
+                .map(|game| game.history[0].clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            order(&suite.clone().shuffled(7)),
+            order(&suite.clone().shuffled(7))
+        );
+
+        let mut state = MatchState::new("depth 6 vs depth 5".to_string(), Some(7));
+        state.record(1, GameScore::Win, "1. e4 *".to_string());
+        state.record(0, GameScore::Draw, "1. d4 *".to_string());
+        state.record(3, GameScore::Loss, "1. c4 *".to_string());
+        let path = std::env::temp_dir().join(format!("arena_state_{}.json", std::process::id()));
+        state.save(&path).unwrap();
+        let loaded = MatchState::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.played(), HashSet::from([0, 1, 3]));
+        let stats = loaded.stats();
+        assert_eq!((stats.wins, stats.draws, stats.losses), (1, 1, 1));
+        assert_eq!(stats.pentanomial, [0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn trinomial_interval_contains_estimate() {
+        let mut stats = MatchStats::new();
+        for i in 0..100 {
+            let score = match i % 4 {
+                0 | 1 => GameScore::Win,
+                2 => GameScore::Draw,
+                _ => GameScore::Loss,
+            };
+            stats.record(i, score);
+        }
+        assert_eq!((stats.wins, stats.draws, stats.losses), (50, 25, 25));
+        let elo = stats.elo().unwrap();
+        assert!((elo.elo - score_to_elo(0.625)).abs() < 1e-9);
+        assert!(elo.lower < elo.elo && elo.elo < elo.upper);
+        assert!(elo.lower > 0.0);
+    }
+
+    #[test]
+    fn games_are_paired_by_index() {
+        let mut stats = MatchStats::new();
+        stats.record(1, GameScore::Win);
+        stats.record(2, GameScore::Draw);
+        assert_eq!(stats.pairs(), 0);
+        stats.record(0, GameScore::Draw);
+        stats.record(3, GameScore::Draw);
+        assert_eq!(stats.pentanomial, [0, 0, 1, 1, 0]);
+        let pairs = stats.pentanomial_elo().unwrap();
+        assert!((pairs.elo - score_to_elo(0.625)).abs() < 1e-9);
+        assert!(stats.normalized_elo().unwrap() > 0.0);
+    }
+}
@@ -0,0 +1,516 @@
+use chessmind::{
+    arena::{
+        AdjudicationRules, EloEstimate, EngineConfig, GameOutcome, GameScore, MatchState,
+        MatchStats, OpeningSuite, Sprt, SprtStatus, TimeControl, append_pgn, first_player_color,
+        game_pgn, learn_from_game,
+    },
+    game::Game,
+    gamelog::GameLog,
+    pieces::Color,
+    play::{MatchWorker, PlayerKind},
+    rating::Ladder,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc;
+use toml_edit::{DocumentMut, Item, Value};
+
+const WORKER_TABLE_SIZE: usize = 1 << 20;
+
+const USAGE: &str = "Usage: arena_cli [--config FILE] [OPTIONS]
+
+Options:
+  --config FILE           Read settings from a TOML file
+  --games N               Number of games (default 100)
+  --concurrency N         Games played in parallel (default: half the cores)
+  --tc TC                 depth, movetime=<ms> or <base>+<inc> in seconds (default
+                          depth); on a clock, an engine that runs out of time loses
+  --openings FILE         EPD or PGN opening suite
+  --pgn FILE              Append finished games to FILE
+  --state FILE            Save progress to FILE after every game, resuming
+                          from it if it exists
+  --ladder FILE           Rate both engines on the ladder in FILE after the
+                          match and print it
+  --seed N                Shuffle the openings with seed N
+  --sprt ELO0,ELO1[,ALPHA,BETA]
+                          Stop once the SPRT accepts either hypothesis
+  --a KEY=VALUE           Engine A option (name, depth, book, book_file, ...)
+  --b KEY=VALUE           Engine B option
+  --adjudicate KEY=VALUE  Adjudication option (resign_score, draw_moves, ...)
+
+TOML files use the same keys at the top level, plus [engine_a], [engine_b],
+[adjudication] and [sprt] (elo0, elo1, alpha, beta) tables.";
+
+struct MatchConfig {
+    engines: [EngineConfig; 2],
+    time_control: TimeControl,
+    games: u32,
+    concurrency: usize,
+    openings: Option<PathBuf>,
+    pgn: Option<PathBuf>,
+    sprt: Option<Sprt>,
+    adjudication: AdjudicationRules,
+    state: Option<PathBuf>,
+    ladder: Option<PathBuf>,
+    seed: Option<u64>,
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+fn parse_sprt(value: &str) -> Result<Sprt, String> {
+    let values = value
+        .split(',')
+        .map(|v| parse::<f64>("sprt", v))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [elo0, elo1] => Ok(Sprt::new(elo0, elo1)),
+        [elo0, elo1, alpha, beta] => Ok(Sprt {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        }),
+        _ => Err("sprt expects ELO0,ELO1[,ALPHA,BETA]".to_string()),
+    }
+}
+
+// TOML scalars and arrays as the strings accepted on the command line.
+fn value_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.value().clone()),
+        Value::Integer(i) => Some(i.value().to_string()),
+        Value::Float(f) => Some(f.value().to_string()),
+        Value::Boolean(b) => Some(b.value().to_string()),
+        Value::Array(array) => array
+            .iter()
+            .map(value_string)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        _ => None,
+    }
+}
+
+impl MatchConfig {
+    fn new() -> Self {
+        let depth = std::env::var("CHESSMIND_DEPTH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(6);
+        Self {
+            engines: [EngineConfig::new(depth), EngineConfig::new(depth)],
+            time_control: TimeControl::Depth,
+            games: 100,
+            concurrency: (num_cpus::get() / 2).max(1),
+            openings: None,
+            pgn: None,
+            sprt: None,
+            adjudication: AdjudicationRules::default(),
+            state: None,
+            ladder: None,
+            seed: None,
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "games" => self.games = parse(key, value)?,
+            "concurrency" => self.concurrency = parse::<usize>(key, value)?.max(1),
+            "tc" | "time_control" => self.time_control = value.parse()?,
+            "openings" => self.openings = Some(PathBuf::from(value)),
+            "pgn" => self.pgn = Some(PathBuf::from(value)),
+            "sprt" => self.sprt = Some(parse_sprt(value)?),
+            "state" => self.state = Some(PathBuf::from(value)),
+            "ladder" => self.ladder = Some(PathBuf::from(value)),
+            "seed" => self.seed = Some(parse(key, value)?),
+            _ => return Err(format!("unknown option: {}", key)),
+        }
+        Ok(())
+    }
+
+    fn load_toml(&mut self, path: &str) -> Result<(), String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let doc: DocumentMut = text.parse().map_err(|e| format!("{}: {}", path, e))?;
+        for (key, item) in doc.iter() {
+            let Item::Table(table) = item else {
+                let value = item
+                    .as_value()
+                    .and_then(value_string)
+                    .ok_or_else(|| format!("invalid value for {}", key))?;
+                self.set(key, &value)?;
+                continue;
+            };
+            let mut sprt = self.sprt.unwrap_or(Sprt::new(0.0, 5.0));
+            for (option, item) in table.iter() {
+                let value = item
+                    .as_value()
+                    .and_then(value_string)
+                    .ok_or_else(|| format!("invalid value for {}.{}", key, option))?;
+                match key {
+                    "engine_a" => self.engines[0].set(option, &value)?,
+                    "engine_b" => self.engines[1].set(option, &value)?,
+                    "adjudication" => self.adjudication.set(option, &value)?,
+                    "sprt" => match option {
+                        "elo0" => sprt.elo0 = parse(option, &value)?,
+                        "elo1" => sprt.elo1 = parse(option, &value)?,
+                        "alpha" => sprt.alpha = parse(option, &value)?,
+                        "beta" => sprt.beta = parse(option, &value)?,
+                        _ => return Err(format!("unknown sprt option: {}", option)),
+                    },
+                    _ => return Err(format!("unknown table: [{}]", key)),
+                }
+            }
+            if key == "sprt" {
+                self.sprt = Some(sprt);
+            }
+        }
+        Ok(())
+    }
+
+    // Everything that must match for a saved state to be resumed.
+    fn settings(&self) -> String {
+        format!(
+            "A: {} | B: {} | tc: {:?} | openings: {:?} | adjudication: {:?}",
+            self.engines[0].describe(),
+            self.engines[1].describe(),
+            self.time_control,
+            self.openings,
+            self.adjudication
+        )
+    }
+
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = Self::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                return Err(format!("unexpected argument: {}", arg));
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for --{}", flag))?;
+            let option = || {
+                value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--{} expects KEY=VALUE", flag))
+            };
+            match flag {
+                "config" => config.load_toml(value)?,
+                "a" => {
+                    let (key, value) = option()?;
+                    config.engines[0].set(key, value)?;
+                }
+                "b" => {
+                    let (key, value) = option()?;
+                    config.engines[1].set(key, value)?;
+                }
+                "adjudicate" => {
+                    let (key, value) = option()?;
+                    config.adjudication.set(key, value)?;
+                }
+                _ => config.set(flag, value)?,
+            }
+        }
+        Ok(config)
+    }
+}
+
+struct Finished {
+    index: u32,
+    outcome: GameOutcome,
+    game: Game,
+    // Time left after each ply, for `[%clk]` comments.
+    clocks: Vec<Option<u64>>,
+}
+
+// Plays games on `concurrency` threads until all are played or `stop` is set.
+fn spawn_workers(
+    config: &MatchConfig,
+    openings: Arc<OpeningSuite>,
+    played: HashSet<u32>,
+    stop: Arc<AtomicBool>,
+) -> mpsc::Receiver<Finished> {
+    let played = Arc::new(played);
+    let next_game = Arc::new(AtomicU32::new(0));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..config.concurrency {
+        let (stop, next_game, tx, openings, played) = (
+            stop.clone(),
+            next_game.clone(),
+            tx.clone(),
+            openings.clone(),
+            played.clone(),
+        );
+        let configs = config.engines.clone();
+        let time_control = config.time_control;
+        let num_games = config.games;
+        let rules = config.adjudication.clone();
+        std::thread::spawn(move || {
+            let engines = configs.map(|c| c.build(1, WORKER_TABLE_SIZE));
+            let mut worker = MatchWorker::new(engines, rules, next_game, num_games);
+            while !stop.load(Ordering::Relaxed) {
+                let Some(index) = worker.next_index() else {
+                    break;
+                };
+                if played.contains(&index) {
+                    continue;
+                }
+                let first_color = first_player_color(index);
+                let game = openings.game_for(index);
+                let opening_plies = game.history.len();
+                let mut played = worker.start(game, time_control, |engines| {
+                    let [first, second] = engines.each_mut().map(PlayerKind::engine_mut);
+                    if first_color == Color::White {
+                        [first, second]
+                    } else {
+                        [second, first]
+                    }
+                });
+                let outcome = played.play();
+                let clocks = played.clock().history().to_vec();
+                let game = played.into_game();
+                if let Err(e) = learn_from_game(
+                    &worker.engines,
+                    &game,
+                    opening_plies,
+                    first_color,
+                    outcome.winner,
+                ) {
+                    eprintln!("arena_cli: could not save book learning: {}", e);
+                }
+                if tx
+                    .send(Finished {
+                        index,
+                        outcome,
+                        game,
+                        clocks,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+    }
+    rx
+}
+
+fn format_elo(elo: Option<EloEstimate>) -> String {
+    match elo {
+        Some(elo) => format!("{:+.1} [{:+.1}, {:+.1}]", elo.elo, elo.lower, elo.upper),
+        None => "-".to_string(),
+    }
+}
+
+fn format_llr(sprt: &Sprt, stats: &MatchStats) -> String {
+    let (lower, upper) = sprt.bounds();
+    format!("LLR {:.2} [{:.2}, {:.2}]", sprt.llr(stats), lower, upper)
+}
+
+fn game_to_pgn(config: &MatchConfig, finished: &Finished) -> String {
+    let a = ("Engine A", config.engines[0].describe());
+    let b = ("Engine B", config.engines[1].describe());
+    let (white, black) = if first_player_color(finished.index) == Color::White {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    let players = [(white.0, white.1.as_str()), (black.0, black.1.as_str())];
+    game_pgn(
+        &finished.game,
+        finished.index + 1,
+        players,
+        &finished.outcome,
+        &finished.clocks,
+    )
+}
+
+// Loads the saved state, or starts a new one when there is none.
+fn load_state(config: &MatchConfig) -> Result<MatchState, String> {
+    let settings = config.settings();
+    let Some(path) = config.state.as_ref().filter(|p| p.exists()) else {
+        return Ok(MatchState::new(settings, config.seed));
+    };
+    let state = MatchState::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if state.settings != settings {
+        return Err(format!(
+            "{} was saved for a different match:\n  {}",
+            path.display(),
+            state.settings
+        ));
+    }
+    if config.seed.is_some() && config.seed != state.seed {
+        return Err(format!(
+            "{} was saved with seed {:?}",
+            path.display(),
+            state.seed
+        ));
+    }
+    println!(
+        "Resuming from {}: {} games played",
+        path.display(),
+        state.games.len()
+    );
+    Ok(state)
+}
+
+fn run(config: MatchConfig) -> Result<(), String> {
+    let mut state = load_state(&config)?;
+    let mut openings = match &config.openings {
+        Some(path) => OpeningSuite::load(path).map_err(|e| e.to_string())?,
+        None => OpeningSuite::default(),
+    };
+    if let Some(seed) = state.seed {
+        openings = openings.shuffled(seed);
+    }
+    println!("Engine A: {}", config.engines[0].describe());
+    println!("Engine B: {}", config.engines[1].describe());
+    println!(
+        "{} games, {} openings, {} workers",
+        config.games,
+        openings.len(),
+        config.concurrency
+    );
+
+    let game_log = GameLog::from_env().unwrap_or_else(|e| {
+        eprintln!("Could not open the game log: {}", e);
+        None
+    });
+
+    let mut stats = state.stats();
+    let mut sprt_status = config
+        .sprt
+        .map_or(SprtStatus::Continue, |sprt| sprt.status(&stats));
+    let finished_already = sprt_status != SprtStatus::Continue || stats.games() >= config.games;
+    let stop = Arc::new(AtomicBool::new(finished_already));
+    let rx = spawn_workers(&config, Arc::new(openings), state.played(), stop.clone());
+    for finished in rx.iter() {
+        let first_color = first_player_color(finished.index);
+        let score = GameScore::for_player(finished.outcome.winner, first_color);
+        stats.record(finished.index, score);
+        let pgn = game_to_pgn(&config, &finished);
+        if let Some(path) = &config.pgn
+            && let Err(e) = append_pgn(path, &pgn)
+        {
+            eprintln!("Could not write {}: {}", path.display(), e);
+        }
+        if let Some(log) = &game_log
+            && let Err(e) = log.record(&pgn)
+        {
+            eprintln!("Could not log the game in {}: {}", log.dir().display(), e);
+        }
+        state.record(finished.index, score, pgn);
+        if let Some(path) = &config.state
+            && let Err(e) = state.save(path)
+        {
+            eprintln!("Could not write {}: {}", path.display(), e);
+        }
+
+        let mut line = format!(
+            "Game {:>4} (A {}): {} {} | +{} ={} -{} | Elo {}",
+            finished.index + 1,
+            if first_color == Color::White {
+                "white"
+            } else {
+                "black"
+            },
+            finished.outcome.result_str(),
+            finished.outcome.termination.reason(),
+            stats.wins,
+            stats.draws,
+            stats.losses,
+            format_elo(stats.elo()),
+        );
+        if let Some(sprt) = &config.sprt {
+            line.push_str(&format!(" | {}", format_llr(sprt, &stats)));
+            sprt_status = sprt.status(&stats);
+        }
+        println!("{}", line);
+
+        if sprt_status != SprtStatus::Continue || stats.games() >= config.games {
+            stop.store(true, Ordering::Relaxed);
+            break;
+        }
+    }
+
+    println!();
+    println!(
+        "Score of A vs B: {} - {} - {} [{:.3}] {}",
+        stats.wins,
+        stats.losses,
+        stats.draws,
+        stats.score(),
+        stats.games()
+    );
+    println!("Elo: {}", format_elo(stats.elo()));
+    if stats.pairs() > 0 {
+        let [ll, l, d, w, ww] = stats.pentanomial;
+        println!(
+            "Pairs [{} {} {} {} {}]: {}",
+            ll,
+            l,
+            d,
+            w,
+            ww,
+            format_elo(stats.pentanomial_elo())
+        );
+    }
+    if let Some(nelo) = stats.normalized_elo() {
+        println!("nElo: {:+.1}", nelo);
+    }
+    if let Some(sprt) = &config.sprt {
+        let verdict = match sprt_status {
+            SprtStatus::AcceptH0 => "H0 accepted",
+            SprtStatus::AcceptH1 => "H1 accepted",
+            SprtStatus::Continue => "inconclusive",
+        };
+        println!(
+            "SPRT ({:.1}, {:.1}): {} - {}",
+            sprt.elo0,
+            sprt.elo1,
+            format_llr(sprt, &stats),
+            verdict
+        );
+    }
+    if let Some(path) = &config.ladder
+        && !finished_already
+    {
+        update_ladder(path, &config, &stats)?;
+    }
+    Ok(())
+}
+
+// Rates the match on the ladder file and prints the standings.
+fn update_ladder(path: &Path, config: &MatchConfig, stats: &MatchStats) -> Result<(), String> {
+    let mut ladder = Ladder::load(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    ladder.record_match(
+        &config.engines[0].ladder_name(),
+        &config.engines[1].ladder_name(),
+        stats,
+    );
+    ladder
+        .save(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    println!();
+    print!("{}", ladder.table());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let result = MatchConfig::from_args(&args).and_then(run);
+    if let Err(e) = result {
+        eprintln!("arena_cli: {}\n\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}
@@ -1,100 +1,771 @@
 use chessmind::{
+    arena::{
+        AdjudicationRules, Adjudicator, EloEstimate, EngineConfig, GameClock, GameOutcome,
+        GameScore, MatchStats, OpeningSuite, SearchTotals, Sprt, TimeControl, append_pgn,
+        first_player_color, game_outcome, game_pgn, learn_from_game,
+    },
+    board::Board,
     engine::Engine,
     game::Game,
+    gamelog::GameLog,
+    mcts::MctsParams,
+    pgn::format_clock,
     pieces::{Color, Piece, PieceType},
+    play::{MatchWorker, PlayerKind},
+    san::line_to_san,
+    transposition::TABLE_SIZE,
 };
 use eframe::{App, Frame, egui};
 use egui::Color32;
 use num_cpus;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver};
 use std::time::{Duration, Instant};
 
-#[derive(PartialEq)]
+// Games that drag on past this many plies are adjudicated as draws.
+const MAX_PLIES: usize = 400;
+// Each fast-mode worker owns its engine, so keep its table small.
+const WORKER_TABLE_SIZE: usize = 1 << 18;
+const BOARD_REFRESH: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, PartialEq)]
 enum Opponent {
     AiVsAi,
     AiVsRandom,
 }
 
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or(path.to_string(), |n| n.to_string_lossy().into_owned())
+}
+
+// Returns the error of a book file that could not be loaded.
+fn config_ui(config: &mut EngineConfig, ui: &mut egui::Ui) -> Option<String> {
+    let mut error = None;
+    ui.horizontal(|ui| {
+        ui.label("Depth:");
+        ui.add(egui::DragValue::new(&mut config.depth).clamp_range(1..=20));
+    });
+    ui.checkbox(&mut config.params.use_book, "Opening book");
+    ui.horizontal(|ui| {
+        let name = config
+            .book_file
+            .as_deref()
+            .map_or("built-in".into(), file_name);
+        ui.label(format!("Book: {}", name));
+        if ui.small_button("Load...").clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Opening book", &["bin", "txt"])
+                .pick_file()
+        {
+            error = config.set("book_file", &path.to_string_lossy()).err();
+        }
+        if config.book_file.is_some() && ui.small_button("✖").clicked() {
+            config.book_file = None;
+        }
+    });
+    ui.horizontal(|ui| {
+        let name = config
+            .book_learning
+            .as_deref()
+            .map_or("off".into(), file_name);
+        ui.label(format!("Book learning: {}", name));
+        if ui.small_button("Set...").clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Book learning", &["json"])
+                .set_file_name("book_learning.json")
+                .save_file()
+        {
+            error = config.set("book_learning", &path.to_string_lossy()).err();
+        }
+        if config.book_learning.is_some() && ui.small_button("✖").clicked() {
+            config.book_learning = None;
+        }
+    });
+    let book = &mut config.params.book;
+    ui.horizontal(|ui| {
+        ui.label("Book depth:");
+        ui.add(egui::DragValue::new(&mut book.max_ply).clamp_range(0..=255));
+        ui.checkbox(&mut book.weighted, "Weighted");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Min. learned score:");
+        ui.add(
+            egui::DragValue::new(&mut book.min_score)
+                .clamp_range(0..=100)
+                .suffix("%"),
+        );
+    });
+    ui.checkbox(&mut config.params.null_move, "Null-move pruning");
+    ui.checkbox(
+        &mut config.params.late_move_reductions,
+        "Late move reductions",
+    );
+    ui.checkbox(&mut config.params.history_pruning, "History pruning");
+    ui.checkbox(&mut config.params.multi_cut, "Multi-cut");
+    let mut mcts = config.params.mcts.is_some();
+    if ui.checkbox(&mut mcts, "Monte Carlo tree search").changed() {
+        config.params.mcts = mcts.then(MctsParams::default);
+    }
+    if let Some(mcts) = &mut config.params.mcts {
+        ui.horizontal(|ui| {
+            ui.label("Playouts:");
+            ui.add(egui::DragValue::new(&mut mcts.playouts).clamp_range(1..=1_000_000));
+        });
+    }
+    ui.label("Futility margins:");
+    ui.horizontal(|ui| {
+        for margin in &mut config.params.rfp_margins[1..] {
+            ui.add(egui::DragValue::new(margin).clamp_range(0..=1000));
+        }
+    });
+    ui.label("Late move pruning limits:");
+    ui.horizontal(|ui| {
+        for limit in &mut config.params.lmp_limits[1..] {
+            ui.add(egui::DragValue::new(limit).clamp_range(1..=64));
+        }
+    });
+    ui.label("Evaluation weights (%):");
+    egui::Grid::new("weights").show(ui, |ui| {
+        for (name, weight) in [
+            ("Material", &mut config.weights.material),
+            ("Pawn structure", &mut config.weights.pawn_structure),
+            ("Pieces", &mut config.weights.pieces),
+            ("King safety", &mut config.weights.king_safety),
+        ] {
+            ui.label(name);
+            ui.add(egui::DragValue::new(weight).clamp_range(0..=300));
+            ui.end_row();
+        }
+    });
+    if ui.button("Reset").clicked() {
+        *config = EngineConfig::new(config.depth);
+    }
+    error
+}
+
+fn adjudication_ui(rules: &mut AdjudicationRules, ui: &mut egui::Ui) {
+    ui.checkbox(&mut rules.resign, "Resign");
+    ui.horizontal(|ui| {
+        ui.label("Below -");
+        ui.add(egui::DragValue::new(&mut rules.resign_score).clamp_range(100..=10000));
+        ui.label("cp for");
+        ui.add(egui::DragValue::new(&mut rules.resign_moves).clamp_range(1..=50));
+        ui.label("moves");
+    });
+    ui.checkbox(&mut rules.draw, "Draw");
+    ui.horizontal(|ui| {
+        ui.label("Within ±");
+        ui.add(egui::DragValue::new(&mut rules.draw_score).clamp_range(0..=100));
+        ui.label("cp for");
+        ui.add(egui::DragValue::new(&mut rules.draw_moves).clamp_range(1..=50));
+        ui.label("moves");
+    });
+    ui.horizontal(|ui| {
+        ui.label("after move");
+        ui.add(egui::DragValue::new(&mut rules.draw_after_move).clamp_range(0..=200));
+    });
+    ui.checkbox(&mut rules.tablebase, "Tablebases (SYZYGY_PATH)");
+}
+
+// Plots `values` left to right over the vertical `range`, with horizontal
+// reference lines at `guides`.
+fn line_chart(
+    ui: &mut egui::Ui,
+    values: &[f64],
+    range: (f64, f64),
+    guides: &[f64],
+    color: Color32,
+) {
+    let size = egui::vec2(ui.available_width(), 90.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, Color32::from_gray(30));
+    let (low, high) = range;
+    let y = |v: f64| {
+        rect.bottom() - (((v - low) / (high - low)).clamp(0.0, 1.0) as f32) * rect.height()
+    };
+    for guide in guides {
+        painter.hline(rect.x_range(), y(*guide), (1.0, Color32::GRAY));
+    }
+    if values.len() < 2 {
+        return;
+    }
+    let step = rect.width() / (values.len() - 1) as f32;
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| egui::pos2(rect.left() + i as f32 * step, y(*v)))
+        .collect();
+    painter.add(egui::Shape::line(points, (1.5, color)));
+}
+
+// `engines[0]` is player A, whose color alternates; `engines[1]` plays the other side.
+// The mover's evaluation is returned along with its move, and its search
+// effort is added to `totals`.
+fn choose_move(
+    engines: &mut [Engine; 2],
+    game: &mut Game,
+    opponent: Opponent,
+    engine_color: Color,
+    clock: &GameClock,
+    totals: &mut [SearchTotals; 2],
+) -> Option<((String, String), Option<i32>)> {
+    let slot = if game.current_turn == engine_color {
+        0
+    } else if opponent == Opponent::AiVsRandom {
+        let legal = game.legal_moves();
+        return legal.choose(&mut thread_rng()).cloned().map(|m| (m, None));
+    } else {
+        1
+    };
+    search_move(&mut engines[slot], game, clock, &mut totals[slot])
+}
+
+fn search_move(
+    engine: &mut Engine,
+    game: &mut Game,
+    clock: &GameClock,
+    totals: &mut SearchTotals,
+) -> Option<((String, String), Option<i32>)> {
+    let (mv, depth) = engine.best_move_timed(game, &clock.time_config(engine.depth))?;
+    let score = engine.last_score();
+    if score.is_some() {
+        totals.add(depth, engine.last_nodes());
+    }
+    Some((mv, score))
+}
+
+// A fast-mode engine player, which gives the game up once `stop` is set.
+fn fast_player<'a>(
+    engine: &'a mut Engine,
+    totals: &'a mut SearchTotals,
+    stop: &'a AtomicBool,
+) -> PlayerKind<'a> {
+    PlayerKind::bot(move |game, clock| {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        search_move(engine, game, clock, totals)
+    })
+}
+
+enum MatchEvent {
+    Position(Box<Board>),
+    Finished {
+        index: u32,
+        engine_color: Color,
+        outcome: GameOutcome,
+        game: Box<Game>,
+        totals: [SearchTotals; 2],
+    },
+}
+
+struct FastMatch {
+    stop: Arc<AtomicBool>,
+    rx: Receiver<MatchEvent>,
+}
+
+impl FastMatch {
+    fn spawn(
+        configs: [EngineConfig; 2],
+        openings: Arc<OpeningSuite>,
+        opponent: Opponent,
+        rules: AdjudicationRules,
+        time_control: TimeControl,
+        num_games: u32,
+        workers: usize,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let next_game = Arc::new(AtomicU32::new(0));
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..workers.max(1) {
+            let stop = stop.clone();
+            let next_game = next_game.clone();
+            let tx = tx.clone();
+            let configs = configs.clone();
+            let openings = openings.clone();
+            let rules = rules.clone();
+            std::thread::spawn(move || {
+                let engines = configs.map(|c| c.build(1, WORKER_TABLE_SIZE));
+                let mut worker = MatchWorker::new(engines, rules, next_game, num_games);
+                while !stop.load(Ordering::Relaxed) {
+                    let Some(index) = worker.next_index() else {
+                        break;
+                    };
+                    let color = first_player_color(index);
+                    let game = openings.game_for(index);
+                    let opening_plies = game.history.len();
+                    let mut totals = [SearchTotals::default(); 2];
+                    let mut last_refresh = Instant::now();
+                    let [engine_totals, other_totals] = &mut totals;
+                    let mut played = worker
+                        .start(game, time_control, |[engine, other]| {
+                            let player = fast_player(engine, engine_totals, &stop);
+                            let rival = match opponent {
+                                Opponent::AiVsAi => fast_player(other, other_totals, &stop),
+                                Opponent::AiVsRandom => PlayerKind::Random,
+                            };
+                            if color == Color::White {
+                                [player, rival]
+                            } else {
+                                [rival, player]
+                            }
+                        })
+                        .on_move(|game, _, _| {
+                            if last_refresh.elapsed() >= BOARD_REFRESH {
+                                last_refresh = Instant::now();
+                                let board = Box::new(game.board.clone());
+                                let _ = tx.send(MatchEvent::Position(board));
+                            }
+                        });
+                    let outcome = played.play();
+                    let game = played.into_game();
+                    // Games cut short by a stop are not results.
+                    if stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let learners = match opponent {
+                        Opponent::AiVsAi => &worker.engines[..],
+                        Opponent::AiVsRandom => &worker.engines[..1],
+                    };
+                    let _ = learn_from_game(learners, &game, opening_plies, color, outcome.winner);
+                    let finished = MatchEvent::Finished {
+                        index,
+                        engine_color: color,
+                        outcome,
+                        game: Box::new(game),
+                        totals,
+                    };
+                    if tx.send(finished).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Self { stop, rx }
+    }
+}
+
+impl Drop for FastMatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
 pub struct ArenaApp {
-    engine: Engine,
+    configs: [EngineConfig; 2],
+    engines: Option<[Engine; 2]>,
+    adjudicator: Adjudicator,
+    // Set when the last move triggered an adjudication rule.
+    adjudicated: Option<GameOutcome>,
     game: Game,
     opponent: Opponent,
     num_games: u32,
     games_played: u32,
-    wins: u32,
-    draws: u32,
+    stats: MatchStats,
     running: bool,
     last_move: Instant,
     move_delay: Duration,
+    fast: bool,
+    workers: usize,
+    fast_match: Option<FastMatch>,
+    fast_board: Option<Board>,
+    openings: Arc<OpeningSuite>,
+    openings_name: Option<String>,
+    load_error: Option<String>,
+    pgn_path: Option<PathBuf>,
+    pgn_error: Option<String>,
+    time_control: TimeControl,
+    // The time control as typed, and why it does not parse.
+    time_control_text: String,
+    time_control_error: Option<String>,
+    // Clock of the slow-mode game in progress.
+    clock: GameClock,
+    // Where finished games are also appended, from `CHESSMIND_GAME_LOG`.
+    game_log: Option<GameLog>,
+    opening_labels: Vec<String>,
+    sprt: Sprt,
+    // Score and LLR after each game, for the progress charts.
+    progress: Vec<(f64, f64)>,
+    opening_stats: Vec<MatchStats>,
+    total_plies: u64,
+    search_totals: [SearchTotals; 2],
+    // Search effort spent on the slow-mode game in progress.
+    game_totals: [SearchTotals; 2],
 }
 
 impl ArenaApp {
     pub fn new() -> Self {
+        let depth = std::env::var("CHESSMIND_DEPTH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(6);
         Self {
-            engine: {
-                let mut eng = Engine::from_env(6, num_cpus::get());
-                if let Ok(Some(path)) = eng.load_syzygy_from_env() {
-                    println!("Loaded Syzygy tablebases from {}", path);
-                }
-                eng
-            },
+            configs: [EngineConfig::new(depth), EngineConfig::new(depth)],
+            engines: None,
+            adjudicator: Adjudicator::new(AdjudicationRules::default()),
+            adjudicated: None,
             game: Game::new(),
             opponent: Opponent::AiVsAi,
             num_games: 10,
             games_played: 0,
-            wins: 0,
-            draws: 0,
+            stats: MatchStats::new(),
             running: false,
             last_move: Instant::now(),
             move_delay: Duration::from_millis(300),
+            fast: false,
+            workers: (num_cpus::get() / 2).max(1),
+            fast_match: None,
+            fast_board: None,
+            openings: Arc::new(OpeningSuite::default()),
+            openings_name: None,
+            load_error: None,
+            pgn_path: Some(PathBuf::from("arena.pgn")),
+            pgn_error: None,
+            time_control: TimeControl::Depth,
+            time_control_text: "depth".to_string(),
+            time_control_error: None,
+            clock: GameClock::new(TimeControl::Depth),
+            game_log: GameLog::from_env().unwrap_or_else(|e| {
+                println!("Could not open the game log: {}", e);
+                None
+            }),
+            opening_labels: Vec::new(),
+            sprt: Sprt::new(0.0, 5.0),
+            progress: Vec::new(),
+            opening_stats: Vec::new(),
+            total_plies: 0,
+            search_totals: [SearchTotals::default(); 2],
+            game_totals: [SearchTotals::default(); 2],
         }
     }
 
     fn reset(&mut self) {
-        self.game = Game::new();
+        self.game = self.openings.game_for(0);
         self.games_played = 0;
-        self.wins = 0;
-        self.draws = 0;
+        self.stats = MatchStats::new();
+        self.adjudicator.reset();
+        self.adjudicated = None;
+        self.progress.clear();
+        self.opening_stats = vec![MatchStats::new(); self.openings.len()];
+        self.total_plies = 0;
+        self.search_totals = [SearchTotals::default(); 2];
+        self.game_totals = [SearchTotals::default(); 2];
         self.last_move = Instant::now();
+        self.fast_match = None;
+        self.fast_board = None;
+        self.clock = GameClock::new(self.time_control);
+    }
+
+    fn start(&mut self) {
+        self.reset();
+        self.running = true;
+        self.engines = if self.fast {
+            None
+        } else {
+            Some(
+                self.configs
+                    .clone()
+                    .map(|c| c.build(num_cpus::get(), TABLE_SIZE)),
+            )
+        };
+        if self.fast {
+            self.fast_match = Some(FastMatch::spawn(
+                self.configs.clone(),
+                self.openings.clone(),
+                self.opponent,
+                self.adjudicator.rules.clone(),
+                self.time_control,
+                self.num_games,
+                self.workers,
+            ));
+        }
+    }
+
+    fn stop(&mut self) {
+        self.running = false;
+        self.fast_match = None;
+    }
+
+    fn record_result(
+        &mut self,
+        index: u32,
+        engine_color: Color,
+        outcome: GameOutcome,
+        game: &Game,
+        totals: &[SearchTotals; 2],
+    ) {
+        let score = GameScore::for_player(outcome.winner, engine_color);
+        self.stats.record(index, score);
+        self.progress
+            .push((self.stats.score(), self.sprt.llr(&self.stats)));
+        let opening = self.openings.opening_index(index);
+        if let Some(stats) = opening.and_then(|i| self.opening_stats.get_mut(i)) {
+            stats.record(index, score);
+        }
+        let opening_plies = opening.map_or(0, |i| self.openings.openings[i].history.len());
+        self.total_plies += game.history.len().saturating_sub(opening_plies) as u64;
+        for (total, game_total) in self.search_totals.iter_mut().zip(totals) {
+            total.merge(game_total);
+        }
+        self.save_game(index, engine_color, &outcome, game);
+        self.games_played += 1;
+        if self.games_played >= self.num_games {
+            self.stop();
+        }
+    }
+
+    fn player_names(&self) -> [(&'static str, String); 2] {
+        let b = match self.opponent {
+            Opponent::AiVsAi => ("Engine B", self.configs[1].describe()),
+            Opponent::AiVsRandom => ("Random", "random legal moves".to_string()),
+        };
+        [("Engine A", self.configs[0].describe()), b]
+    }
+
+    // Appends the game to the PGN file and the game log, if they are set.
+    fn save_game(&mut self, index: u32, engine_color: Color, outcome: &GameOutcome, game: &Game) {
+        if self.pgn_path.is_none() && self.game_log.is_none() {
+            return;
+        }
+        let [a, b] = self.player_names();
+        let [white, black] = if engine_color == Color::White {
+            [a, b]
+        } else {
+            [b, a]
+        };
+        let players = [(white.0, white.1.as_str()), (black.0, black.1.as_str())];
+        let text = game_pgn(game, index + 1, players, outcome, &[]);
+        let logged = self.game_log.as_ref().and_then(|log| {
+            log.record(&text)
+                .err()
+                .map(|e| format!("Could not log the game in {}: {}", log.dir().display(), e))
+        });
+        let saved = self.pgn_path.as_ref().and_then(|path| {
+            append_pgn(path, &text)
+                .err()
+                .map(|e| format!("Could not write {}: {}", path.display(), e))
+        });
+        self.pgn_error = saved.or(logged);
+    }
+
+    fn choose_pgn_path(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("PGN", &["pgn"])
+            .set_file_name("arena.pgn")
+            .save_file()
+        {
+            self.pgn_path = Some(path);
+            self.pgn_error = None;
+        }
+    }
+
+    fn load_openings(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Openings", &["epd", "pgn"])
+            .pick_file()
+        else {
+            return;
+        };
+        match OpeningSuite::load(&path) {
+            Ok(suite) => {
+                self.opening_labels = suite.openings.iter().map(Self::opening_label).collect();
+                self.openings = Arc::new(suite);
+                self.openings_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+                self.load_error = None;
+                self.game = self.openings.game_for(0);
+            }
+            Err(e) => self.load_error = Some(e.to_string()),
+        }
+    }
+
+    // Moves of a PGN opening, or the position of an EPD one.
+    fn opening_label(game: &Game) -> String {
+        if game.history.is_empty() {
+            return game.to_fen();
+        }
+        line_to_san(&game.start_board, game.start_turn, &game.history).join(" ")
+    }
+
+    fn format_elo(elo: EloEstimate) -> String {
+        format!("{:+.1} [{:+.1}, {:+.1}]", elo.elo, elo.lower, elo.upper)
     }
 
     fn step(&mut self) {
-        let legal = self.game.legal_moves();
-        if legal.is_empty() {
-            if self.game.board.in_check(self.game.current_turn) {
-                if let Some(winner) = self.game.result {
-                    if winner == Color::White {
-                        self.wins += 1;
-                    }
-                }
-            } else {
-                self.draws += 1;
+        let color = first_player_color(self.games_played);
+        let finished = self
+            .adjudicated
+            .take()
+            .or_else(|| game_outcome(&mut self.game, MAX_PLIES))
+            .or_else(|| {
+                let engines = self.engines.as_ref()?;
+                self.adjudicator.tablebase(&self.game, &engines[0])
+            });
+        if let Some(outcome) = finished {
+            if let Some(engines) = &self.engines {
+                let learners = match self.opponent {
+                    Opponent::AiVsAi => &engines[..],
+                    Opponent::AiVsRandom => &engines[..1],
+                };
+                let opening_plies = self.openings.game_for(self.games_played).history.len();
+                let _ = learn_from_game(learners, &self.game, opening_plies, color, outcome.winner);
             }
-            self.games_played += 1;
-            if self.games_played >= self.num_games {
-                self.running = false;
-                return;
+            let game = self.game.clone();
+            let totals = std::mem::take(&mut self.game_totals);
+            self.record_result(self.games_played, color, outcome, &game, &totals);
+            if self.running {
+                self.game = self.openings.game_for(self.games_played);
+                self.adjudicator.reset();
+                self.clock = GameClock::new(self.time_control);
+                if let Some(engines) = &mut self.engines {
+                    engines.iter_mut().for_each(Engine::new_game);
+                }
             }
-            self.game = Game::new();
             return;
         }
 
-        let mv = match self.opponent {
-            Opponent::AiVsAi => self.engine.best_move(&mut self.game),
-            Opponent::AiVsRandom => {
-                if self.game.current_turn == Color::White {
-                    self.engine.best_move(&mut self.game)
-                } else {
-                    let mut rng = thread_rng();
-                    legal.choose(&mut rng).cloned()
+        let Some(engines) = &mut self.engines else {
+            return;
+        };
+        let mover = self.game.current_turn;
+        let start = Instant::now();
+        let Some(((s, e), score)) = choose_move(
+            engines,
+            &mut self.game,
+            self.opponent,
+            color,
+            &self.clock,
+            &mut self.game_totals,
+        ) else {
+            return;
+        };
+        if !self.clock.spend(mover, start.elapsed()) {
+            self.adjudicated = Some(GameOutcome::timeout(&self.game, mover));
+        } else if self.game.make_move(&s, &e) {
+            self.clock.record(self.game.history.len() - 1, mover);
+            self.adjudicated = self.adjudicator.record(&self.game, mover, score);
+        }
+    }
+
+    fn poll_fast_match(&mut self) {
+        let Some(fast_match) = &self.fast_match else {
+            return;
+        };
+        let events: Vec<MatchEvent> = fast_match.rx.try_iter().collect();
+        for event in events {
+            match event {
+                MatchEvent::Position(board) => self.fast_board = Some(*board),
+                MatchEvent::Finished {
+                    index,
+                    engine_color,
+                    outcome,
+                    game,
+                    totals,
+                } => self.record_result(index, engine_color, outcome, &game, &totals),
+            }
+        }
+    }
+
+    fn stats_ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Statistics");
+        let scores: Vec<f64> = self.progress.iter().map(|p| p.0).collect();
+        let llrs: Vec<f64> = self.progress.iter().map(|p| p.1).collect();
+
+        ui.label(format!("Score: {:.1}%", self.stats.score() * 100.0));
+        let (low, high) = scores
+            .iter()
+            .fold((0.45f64, 0.55f64), |(lo, hi), s| (lo.min(*s), hi.max(*s)));
+        line_chart(
+            ui,
+            &scores,
+            (low - 0.02, high + 0.02),
+            &[0.5],
+            Color32::LIGHT_BLUE,
+        );
+
+        let (lower, upper) = self.sprt.bounds();
+        ui.label(format!(
+            "LLR: {:.2} [{:.2}, {:.2}]",
+            self.sprt.llr(&self.stats),
+            lower,
+            upper
+        ));
+        let extent = llrs.iter().fold(upper, |m, llr| m.max(llr.abs())) * 1.1;
+        line_chart(
+            ui,
+            &llrs,
+            (-extent, extent),
+            &[lower, 0.0, upper],
+            Color32::LIGHT_GREEN,
+        );
+        ui.add_enabled_ui(!self.running, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("SPRT elo0");
+                ui.add(egui::DragValue::new(&mut self.sprt.elo0).speed(0.5));
+                ui.label("elo1");
+                ui.add(egui::DragValue::new(&mut self.sprt.elo1).speed(0.5));
+            });
+        });
+
+        ui.separator();
+        if self.games_played > 0 {
+            ui.label(format!(
+                "Average game length: {:.1} plies",
+                self.total_plies as f64 / self.games_played as f64
+            ));
+        }
+        egui::Grid::new("search_totals").show(ui, |ui| {
+            ui.label("");
+            ui.label("Avg depth");
+            ui.label("Avg nodes");
+            ui.end_row();
+            for (name, totals) in ["A", "B"].iter().zip(&self.search_totals) {
+                ui.label(*name);
+                match (totals.average_depth(), totals.average_nodes()) {
+                    (Some(depth), Some(nodes)) => {
+                        ui.label(format!("{:.1}", depth));
+                        ui.label(format!("{:.0}", nodes));
+                    }
+                    _ => {
+                        ui.label("-");
+                        ui.label("-");
+                    }
                 }
+                ui.end_row();
             }
-        };
+        });
 
-        if let Some((s, e)) = mv {
-            self.game.make_move(&s, &e);
+        if self.opening_stats.is_empty() {
+            return;
         }
+        ui.separator();
+        ui.label("Openings (A's results):");
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("openings").striped(true).show(ui, |ui| {
+                ui.label("#");
+                ui.label("Opening");
+                ui.label("+/=/-");
+                ui.label("Score");
+                ui.end_row();
+                for (i, stats) in self.opening_stats.iter().enumerate() {
+                    if stats.games() == 0 {
+                        continue;
+                    }
+                    ui.label((i + 1).to_string());
+                    let label = self.opening_labels.get(i).map_or("", String::as_str);
+                    ui.add(egui::Label::new(label).truncate(true));
+                    ui.label(format!("{}/{}/{}", stats.wins, stats.draws, stats.losses));
+                    ui.label(format!("{:.0}%", stats.score() * 100.0));
+                    ui.end_row();
+                }
+            });
+        });
     }
 
     fn piece_char(piece: &Piece) -> char {
@@ -117,42 +788,170 @@ impl ArenaApp {
 
 impl App for ArenaApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        self.poll_fast_match();
+
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.label(format!("Wins: {}", self.wins));
-                ui.label(format!("Draws: {}", self.draws));
+                ui.label(format!("A wins: {}", self.stats.wins));
+                ui.label(format!("Draws: {}", self.stats.draws));
+                ui.label(format!("B wins: {}", self.stats.losses));
                 ui.label(format!("Total: {}", self.games_played));
-                let wr = if self.games_played > 0 {
-                    self.wins as f32 / self.games_played as f32 * 100.0
-                } else {
-                    0.0
+                ui.label(format!("Score: {:.1}%", self.stats.score() * 100.0));
+                if self.running && self.fast_match.is_none() {
+                    let side = if first_player_color(self.games_played) == Color::White {
+                        "White"
+                    } else {
+                        "Black"
+                    };
+                    ui.separator();
+                    ui.label(format!("A plays {}", side));
+                }
+            });
+            ui.horizontal(|ui| {
+                match self.stats.elo() {
+                    Some(elo) => ui.label(format!("Elo: {} (95%)", Self::format_elo(elo))),
+                    None => ui.label("Elo: -"),
                 };
-                ui.label(format!("Winrate: {:.1}%", wr));
+                if let Some(elo) = self.stats.pentanomial_elo() {
+                    ui.separator();
+                    let [ll, l, d, w, ww] = self.stats.pentanomial;
+                    ui.label(format!(
+                        "Pairs [{} {} {} {} {}]: {}",
+                        ll,
+                        l,
+                        d,
+                        w,
+                        ww,
+                        Self::format_elo(elo)
+                    ));
+                    if let Some(nelo) = self.stats.normalized_elo() {
+                        ui.label(format!("nElo {:+.1}", nelo));
+                    }
+                }
             });
             ui.separator();
             ui.horizontal(|ui| {
                 ui.label("Games:");
                 ui.add(egui::DragValue::new(&mut self.num_games).clamp_range(1..=1000));
-                ui.radio_value(&mut self.opponent, Opponent::AiVsAi, "AI vs AI");
-                ui.radio_value(&mut self.opponent, Opponent::AiVsRandom, "AI vs Random");
+                ui.radio_value(&mut self.opponent, Opponent::AiVsAi, "A vs B");
+                ui.radio_value(&mut self.opponent, Opponent::AiVsRandom, "A vs Random");
+                ui.separator();
+                ui.add_enabled_ui(!self.running, |ui| {
+                    if ui.button("Openings…").clicked() {
+                        self.load_openings();
+                    }
+                    if let Some(name) = &self.openings_name {
+                        ui.label(format!("{} ({} positions)", name, self.openings.len()));
+                        if ui.small_button("✖").clicked() {
+                            self.openings = Arc::new(OpeningSuite::default());
+                            self.opening_labels.clear();
+                            self.openings_name = None;
+                            self.game = Game::new();
+                        }
+                    }
+                    if let Some(err) = &self.load_error {
+                        ui.colored_label(Color32::RED, err);
+                    }
+                    ui.separator();
+                    if ui.button("PGN…").clicked() {
+                        self.choose_pgn_path();
+                    }
+                    if let Some(path) = &self.pgn_path {
+                        let name = path.file_name().unwrap_or(path.as_os_str());
+                        ui.label(name.to_string_lossy());
+                        if ui.small_button("✖").clicked() {
+                            self.pgn_path = None;
+                        }
+                    }
+                    if let Some(err) = &self.pgn_error {
+                        ui.colored_label(Color32::RED, err);
+                    }
+                    ui.separator();
+                    ui.label("Time:");
+                    let field = ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.time_control_text)
+                                .desired_width(70.0),
+                        )
+                        .on_hover_text("depth, movetime=<ms> or <base>+<inc> in seconds");
+                    if field.changed() {
+                        match self.time_control_text.parse() {
+                            Ok(tc) => {
+                                self.time_control = tc;
+                                self.time_control_error = None;
+                            }
+                            Err(e) => self.time_control_error = Some(e),
+                        }
+                    }
+                    if let Some(err) = &self.time_control_error {
+                        ui.colored_label(Color32::RED, err);
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.fast, "Fast mode");
+                    if self.fast {
+                        ui.label("Workers:");
+                        ui.add(
+                            egui::DragValue::new(&mut self.workers)
+                                .clamp_range(1..=num_cpus::get()),
+                        );
+                    }
+                });
                 let button = if self.running { "Stop" } else { "Start" };
                 if ui.button(button).clicked() {
                     if self.running {
-                        self.running = false;
+                        self.stop();
                     } else {
-                        self.reset();
-                        self.running = true;
+                        self.start();
                     }
                 }
             });
         });
 
+        egui::SidePanel::right("stats")
+            .default_width(280.0)
+            .show(ctx, |ui| self.stats_ui(ui));
+
+        egui::SidePanel::left("configs").show(ctx, |ui| {
+            ui.add_enabled_ui(!self.running, |ui| {
+                ui.heading("Engine A");
+                let mut errors = vec![
+                    ui.push_id("a", |ui| config_ui(&mut self.configs[0], ui))
+                        .inner,
+                ];
+                if self.opponent == Opponent::AiVsAi {
+                    ui.separator();
+                    ui.heading("Engine B");
+                    errors.push(
+                        ui.push_id("b", |ui| config_ui(&mut self.configs[1], ui))
+                            .inner,
+                    );
+                }
+                if let Some(err) = errors.into_iter().flatten().next() {
+                    self.load_error = Some(err);
+                }
+                ui.separator();
+                ui.heading("Adjudication");
+                adjudication_ui(&mut self.adjudicator.rules, ui);
+            });
+        });
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            if self.fast_match.is_none()
+                && let Some(white) = self.clock.clock_ms(Color::White)
+            {
+                let black = self.clock.remaining_ms(Color::Black);
+                ui.label(format!(
+                    "White {}   Black {}",
+                    format_clock(white),
+                    format_clock(black)
+                ));
+            }
             let board_size = ui.available_width().min(ui.available_height());
             let square_size = board_size / 8.0;
             let (rect, _) =
                 ui.allocate_exact_size(egui::vec2(board_size, board_size), egui::Sense::hover());
 
+            let board = self.fast_board.as_ref().unwrap_or(&self.game.board);
             let painter = ui.painter();
             for x in 0..8 {
                 for y in 0..8 {
@@ -172,7 +971,7 @@ impl App for ArenaApp {
 
             for x in 0..8 {
                 for y in 0..8 {
-                    if let Some(p) = self.game.board.get_index(x, y) {
+                    if let Some(p) = board.get_index(x, y) {
                         let sq_rect = egui::Rect::from_min_size(
                             egui::pos2(
                                 rect.left() + x as f32 * square_size,
@@ -196,7 +995,8 @@ impl App for ArenaApp {
             }
         });
 
-        if self.running && self.last_move.elapsed() >= self.move_delay {
+        if self.running && self.fast_match.is_none() && self.last_move.elapsed() >= self.move_delay
+        {
             self.step();
             self.last_move = Instant::now();
         }
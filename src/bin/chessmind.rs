@@ -0,0 +1,150 @@
+use chessmind::{
+    engine::{Engine, PvLine, SearchInfo, TimeConfig},
+    game::Game,
+    san::line_to_san,
+};
+
+const USAGE: &str = "Usage: chessmind analyze <FEN|startpos> [OPTIONS]
+
+Options:
+  --movetime T   Search time, e.g. 10s or 500ms (a bare number is milliseconds)
+  --depth N      Search depth (default: CHESSMIND_DEPTH or 10 without --movetime)
+  --multipv N    Number of lines to report (default 1)
+  --uci          Print moves in coordinate notation instead of SAN
+
+Prints one line per completed iteration and line:
+  depth <d> multipv <i> score cp|mate <n> nodes <n> time <ms> pv <moves>
+followed by `bestmove <move>`. Scores are from the side to move.";
+
+struct AnalyzeArgs {
+    game: Game,
+    movetime: Option<u64>,
+    depth: Option<u32>,
+    multipv: usize,
+    uci: bool,
+}
+
+fn parse_duration(text: &str) -> Option<u64> {
+    if let Some(ms) = text.strip_suffix("ms") {
+        return ms.parse().ok();
+    }
+    if let Some(s) = text.strip_suffix('s') {
+        return s.parse::<f64>().ok().map(|s| (s * 1000.0) as u64);
+    }
+    text.parse().ok()
+}
+
+fn parse_args(args: &[String]) -> Result<AnalyzeArgs, String> {
+    let mut args = args.iter();
+    let fen = args.next().ok_or("missing position")?;
+    let game = if fen == "startpos" {
+        Game::new()
+    } else {
+        Game::parse_fen(fen).map_err(|e| format!("invalid FEN {}: {}", fen, e))?
+    };
+    let mut parsed = AnalyzeArgs {
+        game,
+        movetime: None,
+        depth: None,
+        multipv: 1,
+        uci: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--movetime" => {
+                let text = value()?;
+                parsed.movetime =
+                    Some(parse_duration(text).ok_or_else(|| format!("invalid time: {}", text))?);
+            }
+            "--depth" => {
+                let text = value()?;
+                parsed.depth = Some(
+                    text.parse()
+                        .map_err(|_| format!("invalid depth: {}", text))?,
+                );
+            }
+            "--multipv" => {
+                let text = value()?;
+                parsed.multipv = text
+                    .parse()
+                    .map_err(|_| format!("invalid multipv: {}", text))?;
+            }
+            "--uci" => parsed.uci = true,
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn format_moves(game: &Game, moves: &[(String, String)], uci: bool) -> Vec<String> {
+    if uci {
+        return moves.iter().map(|(s, e)| format!("{}{}", s, e)).collect();
+    }
+    line_to_san(&game.board, game.current_turn, moves)
+}
+
+fn format_line(game: &Game, info: &SearchInfo, index: usize, line: &PvLine, uci: bool) -> String {
+    let score = match line.mate_in() {
+        Some(m) => format!("mate {}", m),
+        None => format!("cp {}", line.score),
+    };
+    format!(
+        "depth {} seldepth {} multipv {} score {} nodes {} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        index + 1,
+        score,
+        info.nodes,
+        info.time_ms,
+        format_moves(game, &line.moves, uci).join(" ")
+    )
+}
+
+fn analyze(args: &[String]) -> Result<(), String> {
+    let args = parse_args(args)?;
+    let mut engine = Engine::from_env(10, num_cpus::get());
+    let _ = engine.load_syzygy_from_env();
+    let config = match (args.movetime, args.depth) {
+        (Some(ms), depth) => TimeConfig {
+            depth,
+            ..TimeConfig::fixed_time(ms)
+        },
+        (None, depth) => TimeConfig::fixed_depth(depth.unwrap_or(engine.depth)),
+    };
+
+    let mut game = args.game;
+    if game.legal_moves().is_empty() {
+        return Err("no legal moves in this position".to_string());
+    }
+    let info = engine.analyze(&game, &config, args.multipv, |info| {
+        for (i, line) in info.lines.iter().enumerate() {
+            println!("{}", format_line(&game, info, i, line, args.uci));
+        }
+    });
+    if let Some(best) = info.best().and_then(|line| line.moves.first()) {
+        let best = format_moves(&game, std::slice::from_ref(best), args.uci);
+        println!("bestmove {}", best.join(""));
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("analyze") => analyze(&args[1..]),
+        Some("--help" | "-h" | "help") => {
+            println!("{}", USAGE);
+            return;
+        }
+        Some(command) => Err(format!("unknown command: {}", command)),
+        None => Err("missing command".to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("chessmind: {}\n\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,42 @@
+use chessmind::board::Board;
+use chessmind::eval;
+use std::hint::black_box;
+use std::time::Instant;
+
+// Opening, middlegame and endgame positions, evaluated in turn.
+const POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r1bqk2r/2ppbppp/p1n2n2/1p2p3/4P3/1B3N2/PPPP1PPP/RNBQR1K1 b kq - 1 7",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "2rq1rk1/pp1bppbp/2np1np1/8/3NP3/1BN1BP2/PPPQ2PP/2KR3R b - - 4 11",
+    "r1b2rk1/2q1bppp/p2p1n2/np2p3/3PP3/5N1P/PPBN1PP1/R1BQR1K1 b - - 0 12",
+    "8/5pk1/6p1/3P4/2p2P2/6PK/8/8 w - - 0 45",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/4p3/3b4/8/2B2N2/5PPP/6K1 w - - 0 30",
+];
+
+fn main() {
+    let iterations: usize = std::env::args()
+        .nth(1)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(200_000);
+    let boards: Vec<_> = POSITIONS
+        .iter()
+        .map(|fen| Board::from_fen(fen).expect("valid FEN"))
+        .collect();
+
+    let start = Instant::now();
+    let mut sum = 0i64;
+    for i in 0..iterations {
+        let (board, color) = &boards[i % boards.len()];
+        sum += black_box(eval::evaluate(black_box(board), *color)) as i64;
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{} evaluations in {:?}: {:.1} ns each (checksum {})",
+        iterations,
+        elapsed,
+        elapsed.as_nanos() as f64 / iterations as f64,
+        sum
+    );
+}
@@ -1,12 +1,24 @@
 use chessmind::{
     board::Board,
-    engine::{Engine, TimeConfig},
-    game::Game,
+    coach::{CoachReport, review},
+    eco::{Opening, classify},
+    engine::{Engine, SearchInfo, TimeConfig},
+    eval,
+    game::{DrawClaim, Game, MoveEvent},
+    gamelog::{GameLog, engine_settings},
+    human::HumanStyle,
+    pgn::{GameFile, clock_comment, parse_game_file, to_pgn_annotated},
     pieces::{Color, Piece, PieceType},
+    san::{line_to_san, parse_san},
 };
 use eframe::{App, Frame, egui};
 use egui::Color32;
 use num_cpus;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 #[derive(Clone, Copy, PartialEq)]
@@ -66,6 +78,330 @@ impl TimePreset {
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum BoardTheme {
+    Brown,
+    Green,
+    Blue,
+    Gray,
+}
+
+impl BoardTheme {
+    fn name(&self) -> &'static str {
+        match self {
+            BoardTheme::Brown => "Brown",
+            BoardTheme::Green => "Green",
+            BoardTheme::Blue => "Blue",
+            BoardTheme::Gray => "Gray",
+        }
+    }
+
+    fn colors(&self) -> (Color32, Color32) {
+        match self {
+            BoardTheme::Brown => (
+                Color32::from_rgb(240, 217, 181),
+                Color32::from_rgb(181, 136, 99),
+            ),
+            BoardTheme::Green => (
+                Color32::from_rgb(238, 238, 210),
+                Color32::from_rgb(118, 150, 86),
+            ),
+            BoardTheme::Blue => (
+                Color32::from_rgb(222, 227, 230),
+                Color32::from_rgb(140, 162, 173),
+            ),
+            BoardTheme::Gray => (
+                Color32::from_rgb(200, 200, 200),
+                Color32::from_rgb(120, 120, 120),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PieceSet {
+    Classic,
+    Glyphs,
+}
+
+impl PieceSet {
+    fn name(&self) -> &'static str {
+        match self {
+            PieceSet::Classic => "Classic",
+            PieceSet::Glyphs => "Unicode glyphs",
+        }
+    }
+}
+
+// Which pieces the board draws, for visualisation training. The game itself
+// is unaffected, and moves can still be typed in SAN.
+#[derive(Clone, Copy, PartialEq)]
+enum Visibility {
+    AllPieces,
+    PawnsOnly,
+    Blindfold,
+}
+
+impl Visibility {
+    fn name(&self) -> &'static str {
+        match self {
+            Visibility::AllPieces => "All pieces",
+            Visibility::PawnsOnly => "Only pawns",
+            Visibility::Blindfold => "Blindfold",
+        }
+    }
+
+    fn shows(&self, piece: &Piece) -> bool {
+        match self {
+            Visibility::AllPieces => true,
+            Visibility::PawnsOnly => piece.piece_type == PieceType::Pawn,
+            Visibility::Blindfold => false,
+        }
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    if color == Color::White {
+        Color::Black
+    } else {
+        Color::White
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GameEnd {
+    Checkmate(Color),
+    Stalemate,
+    Repetition,
+    SeventyFiveMoves,
+    Claimed(DrawClaim),
+    InsufficientMaterial,
+    Resignation(Color),
+    Timeout(Color),
+}
+
+impl GameEnd {
+    fn winner(&self) -> Option<Color> {
+        match *self {
+            GameEnd::Checkmate(c) | GameEnd::Resignation(c) | GameEnd::Timeout(c) => Some(c),
+            GameEnd::Stalemate
+            | GameEnd::Repetition
+            | GameEnd::SeventyFiveMoves
+            | GameEnd::Claimed(_)
+            | GameEnd::InsufficientMaterial => None,
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self {
+            GameEnd::Checkmate(_) => "checkmate",
+            GameEnd::Stalemate => "stalemate",
+            GameEnd::Repetition => "fivefold repetition",
+            GameEnd::SeventyFiveMoves => "seventy-five-move rule",
+            GameEnd::Claimed(DrawClaim::Repetition) => "threefold repetition",
+            GameEnd::Claimed(DrawClaim::FiftyMoves) => "fifty-move rule",
+            GameEnd::InsufficientMaterial => "insufficient material",
+            GameEnd::Resignation(_) => "resignation",
+            GameEnd::Timeout(_) => "timeout",
+        }
+    }
+
+    fn result_str(&self) -> &'static str {
+        match self.winner() {
+            Some(Color::White) => "1-0",
+            Some(Color::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+
+    fn summary(&self) -> String {
+        match self.winner() {
+            Some(Color::White) => format!("White wins by {}", self.reason()),
+            Some(Color::Black) => format!("Black wins by {}", self.reason()),
+            None => format!("Draw by {}", self.reason()),
+        }
+    }
+
+    // Games that ended on the board can still be branched from an earlier ply.
+    fn on_board(&self) -> bool {
+        !matches!(
+            self,
+            GameEnd::Resignation(_) | GameEnd::Timeout(_) | GameEnd::Claimed(_)
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SoundKind {
+    Move,
+    Capture,
+    Check,
+    GameEnd,
+}
+
+impl SoundKind {
+    fn for_event(event: MoveEvent) -> Self {
+        if event.check {
+            SoundKind::Check
+        } else if event.capture {
+            SoundKind::Capture
+        } else {
+            SoundKind::Move
+        }
+    }
+
+    // (frequency in Hz, length in ms) of each note, played back to back.
+    #[cfg_attr(not(feature = "sound"), allow(dead_code))]
+    fn notes(&self) -> &'static [(f32, u64)] {
+        match self {
+            SoundKind::Move => &[(440.0, 60)],
+            SoundKind::Capture => &[(330.0, 50), (262.0, 70)],
+            SoundKind::Check => &[(660.0, 70), (880.0, 90)],
+            SoundKind::GameEnd => &[(523.0, 120), (392.0, 120), (262.0, 240)],
+        }
+    }
+}
+
+#[cfg(feature = "sound")]
+struct SoundPlayer {
+    _stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
+}
+
+#[cfg(feature = "sound")]
+impl SoundPlayer {
+    fn new() -> Option<Self> {
+        let (stream, handle) = rodio::OutputStream::try_default().ok()?;
+        Some(Self {
+            _stream: stream,
+            handle,
+        })
+    }
+
+    fn play(&self, kind: SoundKind) {
+        use rodio::Source;
+        let mut offset = 0;
+        for &(freq, ms) in kind.notes() {
+            let note = rodio::source::SineWave::new(freq)
+                .take_duration(Duration::from_millis(ms))
+                .amplify(0.15)
+                .delay(Duration::from_millis(offset));
+            let _ = self.handle.play_raw(note);
+            offset += ms;
+        }
+    }
+}
+
+// Without the `sound` feature the GUI builds without an audio backend and stays silent.
+#[cfg(not(feature = "sound"))]
+struct SoundPlayer;
+
+#[cfg(not(feature = "sound"))]
+impl SoundPlayer {
+    fn new() -> Option<Self> {
+        Some(Self)
+    }
+
+    fn play(&self, _kind: SoundKind) {}
+}
+
+struct Editor {
+    board: Board,
+    turn: Color,
+    brush: Option<Piece>,
+    error: Option<String>,
+}
+
+struct AnalysisWorker {
+    stop: Arc<AtomicBool>,
+    rx: Receiver<SearchInfo>,
+    handle: JoinHandle<()>,
+}
+
+impl AnalysisWorker {
+    fn spawn(engine: &Engine, game: Game) -> Self {
+        let mut engine = engine.fork();
+        let stop = engine.stop_handle();
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            engine.analyze(&game, &TimeConfig::infinite(), 3, |info| {
+                let _ = tx.send(info.clone());
+            });
+        });
+        Self { stop, rx, handle }
+    }
+
+    fn stop(self) {
+        stop_search(&self.stop, self.handle);
+    }
+}
+
+const COACH_DEPTH: u32 = 6;
+
+// Searches the position before the player's last move on a background thread
+// and reports what it gave away.
+struct CoachWorker {
+    stop: Arc<AtomicBool>,
+    rx: Receiver<Option<CoachReport>>,
+    handle: JoinHandle<()>,
+}
+
+impl CoachWorker {
+    fn spawn(engine: &Engine, game: Game) -> Self {
+        let mut engine = engine.fork();
+        let stop = engine.stop_handle();
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let _ = tx.send(review(&mut engine, &game, COACH_DEPTH));
+        });
+        Self { stop, rx, handle }
+    }
+
+    fn stop(self) {
+        stop_search(&self.stop, self.handle);
+    }
+}
+
+struct AiWorker {
+    stop: Arc<AtomicBool>,
+    rx: Receiver<Option<((String, String), u32)>>,
+    handle: JoinHandle<()>,
+    ply: usize,
+    started: Instant,
+}
+
+impl AiWorker {
+    fn spawn(engine: &Engine, mut game: Game, config: TimeConfig) -> Self {
+        let mut engine = engine.fork();
+        let stop = engine.stop_handle();
+        let ply = game.history.len();
+        let (tx, rx) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let _ = tx.send(engine.best_move_timed(&mut game, &config));
+        });
+        Self {
+            stop,
+            rx,
+            handle,
+            ply,
+            started: Instant::now(),
+        }
+    }
+
+    fn stop(self) {
+        stop_search(&self.stop, self.handle);
+    }
+}
+
+fn stop_search(stop: &AtomicBool, handle: JoinHandle<()>) {
+    // The search clears the flag when it starts, so keep raising it until the thread exits.
+    while !handle.is_finished() {
+        stop.store(true, Ordering::Release);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let _ = handle.join();
+}
+
 struct ChessClock {
     white_time_ms: u64,
     black_time_ms: u64,
@@ -165,8 +501,11 @@ pub struct GuiApp {
     vs_ai: bool,
     ai_color: Color,
     dragging: Option<(usize, usize, Piece)>,
+    drag_targets: Vec<(usize, usize, bool)>,
     drag_pos: egui::Pos2,
     last_ai_time: Option<Duration>,
+    ai_worker: Option<AiWorker>,
+    premove: Option<(String, String)>,
 
     clock: ChessClock,
     time_preset: TimePreset,
@@ -174,8 +513,48 @@ pub struct GuiApp {
     custom_increment_secs: u32,
     use_clock: bool,
     game_started: bool,
-
-    flag_winner: Option<Color>,
+    // Time left to the player of each ply, from the clock or a loaded PGN.
+    move_clocks: Vec<Option<u64>>,
+
+    game_end: Option<GameEnd>,
+    show_game_over: bool,
+
+    view_ply: Option<usize>,
+    san_cache: Vec<((String, String), String)>,
+    opening_key: Option<u64>,
+    opening: Option<Opening>,
+
+    flip_board: bool,
+    auto_flip: bool,
+
+    fen_input: String,
+    move_input: String,
+    move_error: Option<String>,
+    load_error: Option<String>,
+    // File name of the loaded opening book, None for the built-in one.
+    book_name: Option<String>,
+    // Where finished games are appended, from `CHESSMIND_GAME_LOG`.
+    game_log: Option<GameLog>,
+
+    board_theme: BoardTheme,
+    piece_set: PieceSet,
+    show_coords: bool,
+    show_heatmap: bool,
+    visibility: Visibility,
+
+    analysis: bool,
+    analysis_worker: Option<AnalysisWorker>,
+    analysis_key: String,
+    analysis_info: Option<SearchInfo>,
+
+    coach: bool,
+    coach_worker: Option<CoachWorker>,
+    coach_report: Option<CoachReport>,
+
+    editor: Option<Editor>,
+
+    sounds: Option<SoundPlayer>,
+    sound_enabled: bool,
 }
 
 impl GuiApp {
@@ -187,13 +566,19 @@ impl GuiApp {
                 if let Ok(Some(path)) = eng.load_syzygy_from_env() {
                     println!("Loaded Syzygy tablebases from {}", path);
                 }
+                if let Ok(Some(path)) = eng.load_book_from_env() {
+                    println!("Loaded opening book from {}", path);
+                }
                 eng
             },
             vs_ai: false,
             ai_color: Color::Black,
             dragging: None,
+            drag_targets: Vec::new(),
             drag_pos: egui::Pos2::ZERO,
             last_ai_time: None,
+            ai_worker: None,
+            premove: None,
 
             clock: ChessClock::new(300, 0), // 5+0 default
             time_preset: TimePreset::Blitz5,
@@ -201,99 +586,1113 @@ impl GuiApp {
             custom_increment_secs: 0,
             use_clock: true,
             game_started: false,
-            flag_winner: None,
+            move_clocks: Vec::new(),
+            game_end: None,
+            show_game_over: false,
+
+            view_ply: None,
+            san_cache: Vec::new(),
+            opening_key: None,
+            opening: None,
+
+            flip_board: false,
+            auto_flip: true,
+
+            fen_input: String::new(),
+            move_input: String::new(),
+            move_error: None,
+            load_error: None,
+            book_name: None,
+            game_log: GameLog::from_env().unwrap_or_else(|e| {
+                println!("Could not open the game log: {}", e);
+                None
+            }),
+
+            board_theme: BoardTheme::Brown,
+            piece_set: PieceSet::Classic,
+            show_coords: true,
+            show_heatmap: false,
+            visibility: Visibility::AllPieces,
+
+            analysis: false,
+            analysis_worker: None,
+            analysis_key: String::new(),
+            analysis_info: None,
+
+            coach: false,
+            coach_worker: None,
+            coach_report: None,
+
+            editor: None,
+
+            sounds: SoundPlayer::new(),
+            sound_enabled: cfg!(feature = "sound"),
+        }
+    }
+
+    fn get_time_config(&mut self) -> TimeConfig {
+        if !self.use_clock || self.time_preset == TimePreset::Unlimited {
+            return TimeConfig::fixed_depth(8);
+        }
+
+        self.clock.update();
+        TimeConfig {
+            wtime: Some(self.clock.white_time_ms),
+            btime: Some(self.clock.black_time_ms),
+            winc: Some(self.clock.increment_ms),
+            binc: Some(self.clock.increment_ms),
+            movestogo: None,
+            depth: None,
+            movetime: None,
+            infinite: false,
+        }
+    }
+
+    fn end_game(&mut self, end: GameEnd) {
+        self.cancel_ai();
+        self.play_sound(SoundKind::GameEnd);
+        self.game_end = Some(end);
+        self.show_game_over = true;
+        self.dragging = None;
+        self.drag_targets.clear();
+        if self.clock.running {
+            self.clock.stop();
+        }
+        // Games loaded or set up without a move played are not logged.
+        if let Some(log) = &self.game_log
+            && self.game_started
+            && let Err(e) = log.record(&self.pgn_text())
+        {
+            self.load_error = Some(format!(
+                "Could not log the game in {}: {}",
+                log.dir().display(),
+                e
+            ));
+        }
+    }
+
+    fn detect_game_end(&mut self) {
+        if self.game_end.is_some() {
+            return;
+        }
+        if let Some(winner) = self.game.result {
+            self.end_game(GameEnd::Checkmate(winner));
+        } else if self.game.is_stalemate() {
+            self.end_game(GameEnd::Stalemate);
+        } else if self.game.is_fivefold_repetition() {
+            self.end_game(GameEnd::Repetition);
+        } else if self.game.is_seventy_five_move_draw() {
+            self.end_game(GameEnd::SeventyFiveMoves);
+        } else if self.game.is_insufficient_material() {
+            self.end_game(GameEnd::InsufficientMaterial);
+        } else if self.use_clock && self.game_started && self.time_preset != TimePreset::Unlimited {
+            if self.clock.is_flagged(Color::White) {
+                self.end_game(GameEnd::Timeout(Color::Black));
+            } else if self.clock.is_flagged(Color::Black) {
+                self.end_game(GameEnd::Timeout(Color::White));
+            }
+        }
+    }
+
+    fn resign(&mut self) {
+        let resigning = if self.ai_enabled() {
+            opposite(self.ai_color)
+        } else {
+            self.game.current_turn
+        };
+        self.end_game(GameEnd::Resignation(opposite(resigning)));
+    }
+
+    // Threefold repetition and the fifty-move rule only end the game when the
+    // player to move asks for it.
+    fn claim_draw(&mut self) {
+        if let Some(claim) = self.game.claim_draw() {
+            self.end_game(GameEnd::Claimed(claim));
+        }
+    }
+
+    fn rematch(&mut self) {
+        if self.vs_ai {
+            self.ai_color = opposite(self.ai_color);
+        }
+        self.restart_game();
+    }
+
+    fn check_ai_move(&mut self) {
+        if self.editor.is_some() {
+            self.cancel_ai();
+            return;
+        }
+        self.detect_game_end();
+        if self.game_end.is_some() || !self.ai_enabled() || self.game.current_turn != self.ai_color
+        {
+            self.cancel_ai();
+            return;
+        }
+
+        let Some(worker) = &self.ai_worker else {
+            let config = self.get_time_config();
+            self.ai_worker = Some(AiWorker::spawn(&self.engine, self.game.clone(), config));
+            return;
+        };
+        if worker.ply != self.game.history.len() {
+            self.cancel_ai();
+            return;
+        }
+        let result = match worker.rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        let Some(worker) = self.ai_worker.take() else {
+            return;
+        };
+        let duration = worker.started.elapsed();
+        let _ = worker.handle.join();
+
+        if let Some(((s, e), depth)) = result {
+            if let Some(event) = self.game.make_move_event(&s, &e) {
+                self.play_sound(SoundKind::for_event(event));
+            }
+            self.last_ai_time = Some(duration);
+
+            if self.use_clock && self.game_started {
+                self.clock.switch(opposite(self.ai_color));
+            }
+            self.record_clock();
+
+            println!("AI move {s}{e} in {:?} (depth {})", duration, depth);
+        }
+        self.detect_game_end();
+
+        // Premoves are only checked for legality once the engine has replied.
+        if let Some((s, e)) = self.premove.take() {
+            self.try_player_move(&s, &e);
+        }
+    }
+
+    fn cancel_ai(&mut self) {
+        if let Some(worker) = self.ai_worker.take() {
+            worker.stop();
+        }
+        self.premove = None;
+    }
+
+    fn ai_enabled(&self) -> bool {
+        self.vs_ai && !self.analysis
+    }
+
+    fn on_player_move(&mut self) {
+        if self.analysis {
+            self.record_clock();
+            return;
+        }
+        if !self.game_started {
+            self.game_started = true;
+            if self.use_clock && self.time_preset != TimePreset::Unlimited {
+                self.clock.start(self.game.current_turn);
+            }
+        } else if self.use_clock && self.time_preset != TimePreset::Unlimited {
+            self.clock.switch(self.game.current_turn);
+        }
+        self.record_clock();
+    }
+
+    // Notes the time left to the player of the last move, once the clock has
+    // added the increment.
+    fn record_clock(&mut self) {
+        let Some(ply) = self.game.history.len().checked_sub(1) else {
+            return;
+        };
+        let timed = self.use_clock
+            && self.time_preset != TimePreset::Unlimited
+            && self.game_started
+            && !self.analysis;
+        let time = timed.then(|| self.clock.get_time(self.game.turn_at(ply)));
+        self.move_clocks.resize(ply, None);
+        self.move_clocks.push(time);
+    }
+
+    // Time each side had left after the first `ply` plies, as recorded.
+    fn recorded_clock(&self, ply: usize, color: Color) -> Option<u64> {
+        self.move_clocks[..ply.min(self.move_clocks.len())]
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|&(i, _)| self.game.turn_at(i) == color)
+            .and_then(|(_, &time)| time)
+    }
+
+    fn takeback(&mut self) {
+        self.cancel_ai();
+        let plies = if self.ai_enabled() && self.game.current_turn != self.ai_color {
+            2
+        } else {
+            1
+        };
+        let mut undone = 0;
+        while undone < plies && self.game.undo_move() {
+            undone += 1;
+        }
+        if undone == 0 {
+            return;
+        }
+        self.move_clocks.truncate(self.game.history.len());
+        let was_over = self.game_end.take().is_some();
+        self.show_game_over = false;
+        self.dragging = None;
+        self.drag_targets.clear();
+        self.view_ply = None;
+        self.engine.new_game();
+        if self.clock.running {
+            self.clock.update();
+            self.clock.active_color = self.game.current_turn;
+        } else if was_over && self.use_clock && self.game_started {
+            self.clock.start(self.game.current_turn);
+        }
+    }
+
+    fn restart_game(&mut self) {
+        self.set_game(Game::new());
+    }
+
+    fn load_fen(&mut self) {
+        match Game::parse_fen(self.fen_input.trim()) {
+            Ok(game) => {
+                self.load_error = None;
+                self.set_game(game);
+            }
+            Err(e) => self.load_error = Some(format!("Invalid FEN: {}", e)),
+        }
+    }
+
+    fn displayed_fen(&self) -> String {
+        match self.view_ply {
+            Some(ply) => self.game.position_at(ply).to_fen(self.game.turn_at(ply)),
+            None => self.game.to_fen(),
+        }
+    }
+
+    fn pgn_tags(&self) -> Vec<(String, String)> {
+        let player = |color: Color| {
+            if self.vs_ai && self.ai_color == color {
+                "Chessmind"
+            } else {
+                "Human"
+            }
+        };
+        let result = self.game_end.map_or("*", |end| end.result_str());
+        let mut tags = vec![
+            ("Event".to_string(), "Chessmind GUI game".to_string()),
+            ("Site".to_string(), "?".to_string()),
+            ("Date".to_string(), "????.??.??".to_string()),
+            ("Round".to_string(), "-".to_string()),
+            ("White".to_string(), player(Color::White).to_string()),
+            ("Black".to_string(), player(Color::Black).to_string()),
+            ("Result".to_string(), result.to_string()),
+        ];
+        if let Some(end) = self.game_end {
+            tags.push(("Termination".to_string(), end.reason().to_string()));
+        }
+        if self.vs_ai {
+            let key = match self.ai_color {
+                Color::White => "WhiteSettings",
+                Color::Black => "BlackSettings",
+            };
+            tags.push((key.to_string(), engine_settings(&self.engine)));
+        }
+        tags
+    }
+
+    fn pgn_text(&self) -> String {
+        let comments: Vec<Option<String>> = self
+            .move_clocks
+            .iter()
+            .take(self.game.history.len())
+            .map(|c| c.map(clock_comment))
+            .collect();
+        to_pgn_annotated(&self.game, &self.pgn_tags(), &comments)
+    }
+
+    fn save_pgn(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PGN", &["pgn"])
+            .set_file_name("game.pgn")
+            .save_file()
+        else {
+            return;
+        };
+        let text = self.pgn_text();
+        self.load_error = std::fs::write(&path, text)
+            .err()
+            .map(|e| format!("Could not save {}: {}", path.display(), e));
+    }
+
+    fn open_pgn(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PGN or FEN", &["pgn", "fen"])
+            .pick_file()
+        else {
+            return;
+        };
+        self.open_file(&path);
+    }
+
+    // Loads a PGN game or a FEN position, whichever the file holds.
+    fn open_file(&mut self, path: &Path) {
+        let text = match std::fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) => {
+                self.load_error = Some(format!("Could not open {}: {}", path.display(), e));
+                return;
+            }
+        };
+        match parse_game_file(&text) {
+            Ok(GameFile::Pgn(pgn)) => {
+                self.load_error = None;
+                self.vs_ai = false;
+                self.set_game(pgn.game);
+                self.move_clocks = pgn.clocks;
+            }
+            Ok(GameFile::Fen(game)) => {
+                self.load_error = None;
+                self.fen_input = game.to_fen();
+                self.set_game(game);
+            }
+            Err(e) => self.load_error = Some(format!("Could not parse {}: {}", path.display(), e)),
+        }
+    }
+
+    // Opens a file dropped onto the window, and darkens the window while one
+    // is dragged over it.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("file_drop"),
+            ));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, Color32::from_black_alpha(180));
+            painter.text(
+                screen.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop a PGN or FEN file to open it",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                Color32::WHITE,
+            );
+        }
+        let dropped = ctx.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            self.open_file(&path);
+        }
+    }
+
+    fn open_book(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Opening book", &["bin", "txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        match self.engine.load_book(&path.to_string_lossy()) {
+            Ok(()) => {
+                self.load_error = None;
+                self.book_name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+            }
+            Err(e) => self.load_error = Some(format!("Could not load {}: {}", path.display(), e)),
+        }
+    }
+
+    fn set_game(&mut self, game: Game) {
+        self.cancel_ai();
+        self.stop_coach();
+        self.game = game;
+        self.san_cache.clear();
+        self.engine.new_game();
+        self.dragging = None;
+        self.drag_targets.clear();
+        self.game_started = false;
+        self.move_clocks.clear();
+        self.game_end = None;
+        self.show_game_over = false;
+        self.view_ply = None;
+
+        if self.time_preset == TimePreset::Custom {
+            self.clock
+                .reset(self.custom_base_mins * 60, self.custom_increment_secs);
+        } else {
+            self.clock.reset(
+                self.time_preset.base_time_secs(),
+                self.time_preset.increment_secs(),
+            );
+        }
+        self.clock.active_color = self.game.current_turn;
+
+        self.check_ai_move();
+    }
+
+    fn try_player_move(&mut self, start: &str, end: &str) {
+        if self.ai_worker.is_some() {
+            if self.view_ply.is_none() && start != end {
+                self.premove = Some((start.to_string(), end.to_string()));
+            }
+            return;
+        }
+        if let Some(game_end) = self.game_end
+            && (self.view_ply.is_none() || !game_end.on_board())
+        {
+            return;
+        }
+        if let Some(ply) = self.view_ply {
+            let mut board = self.game.position_at(ply);
+            if !board.is_legal(start, end, self.game.turn_at(ply)) {
+                return;
+            }
+            self.game.truncate(ply);
+            self.view_ply = None;
+            self.game_end = None;
+        }
+        if let Some(event) = self.game.make_move_event(start, end) {
+            self.play_sound(SoundKind::for_event(event));
+            self.on_player_move();
+            self.review_last_move();
+            self.check_ai_move();
+        }
+    }
+
+    fn stop_coach(&mut self) {
+        if let Some(worker) = self.coach_worker.take() {
+            worker.stop();
+        }
+        self.coach_report = None;
+    }
+
+    fn review_last_move(&mut self) {
+        self.stop_coach();
+        if self.coach && !self.analysis {
+            self.coach_worker = Some(CoachWorker::spawn(&self.engine, self.game.clone()));
+        }
+    }
+
+    fn update_coach(&mut self, ctx: &egui::Context) {
+        let Some(worker) = &self.coach_worker else {
+            return;
+        };
+        match worker.rx.try_recv() {
+            Ok(report) => {
+                self.coach_report = report;
+                if let Some(worker) = self.coach_worker.take() {
+                    let _ = worker.handle.join();
+                }
+            }
+            Err(TryRecvError::Empty) => ctx.request_repaint_after(Duration::from_millis(100)),
+            Err(TryRecvError::Disconnected) => self.coach_worker = None,
+        }
+    }
+
+    fn coach_panel(&self, ui: &mut egui::Ui) {
+        let Some(report) = &self.coach_report else {
+            let text = if self.coach_worker.is_some() {
+                "Reviewing your move…"
+            } else {
+                "Play a move to hear from the coach."
+            };
+            ui.label(text);
+            return;
+        };
+        ui.label(egui::RichText::new(&report.message).strong());
+        if report.loss < 50 {
+            return;
+        }
+        if !report.refutation.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Refutation:");
+                ui.label(egui::RichText::new(report.refutation.join(" ")).monospace());
+            });
+        }
+        ui.horizontal_wrapped(|ui| {
+            for &(name, value) in &report.changes {
+                let color = if value >= 0 {
+                    Color32::from_rgb(60, 170, 80)
+                } else {
+                    Color32::from_rgb(210, 70, 70)
+                };
+                ui.label(
+                    egui::RichText::new(format!("{name} {}", Self::format_score(value, None)))
+                        .monospace()
+                        .color(color),
+                );
+                ui.separator();
+            }
+        });
+    }
+
+    // Plays a move typed in SAN (or long algebraic) on the displayed position.
+    fn enter_move(&mut self) {
+        let text = self.move_input.trim();
+        if text.is_empty() {
+            return;
+        }
+        let mut game = self.game.clone();
+        if let Some(ply) = self.view_ply {
+            game.truncate(ply);
+        }
+        if self.ai_worker.is_some() || (self.ai_enabled() && game.current_turn == self.ai_color) {
+            self.move_error = Some("Not your turn".to_string());
+            return;
+        }
+        let color = game.current_turn;
+        match parse_san(&mut game, text, color) {
+            Ok((start, end)) => {
+                let mv = (start, end);
+                self.try_player_move(&mv.0, &mv.1);
+                if self.view_ply.is_none() && self.game.history.last() == Some(&mv) {
+                    self.move_input.clear();
+                    self.move_error = None;
+                } else {
+                    self.move_error = Some("The move could not be played".to_string());
+                }
+            }
+            Err(e) => self.move_error = Some(e.to_string()),
+        }
+    }
+
+    fn play_sound(&self, kind: SoundKind) {
+        if self.sound_enabled
+            && let Some(sounds) = &self.sounds
+        {
+            sounds.play(kind);
+        }
+    }
+
+    fn legal_targets(board: &Board, x: usize, y: usize, color: Color) -> Vec<(usize, usize, bool)> {
+        let mut board = board.clone();
+        let from = match Board::index_to_algebraic(x, y) {
+            Some(f) => f,
+            None => return Vec::new(),
+        };
+        let mut targets = Vec::new();
+        for to in board.pseudo_legal_moves(&from) {
+            if !board.is_legal(&from, &to, color) {
+                continue;
+            }
+            if let Some((tx, ty)) = Board::algebraic_to_index(&to) {
+                let capture = board.get_index(tx, ty).is_some()
+                    || (board
+                        .get_index(x, y)
+                        .is_some_and(|p| p.piece_type == PieceType::Pawn)
+                        && tx != x);
+                targets.push((tx, ty, capture));
+            }
+        }
+        targets
+    }
+
+    fn displayed_board(&self) -> Board {
+        if let Some(editor) = &self.editor {
+            return editor.board.clone();
+        }
+        match self.view_ply {
+            Some(ply) => self.game.position_at(ply),
+            None => self.game.board.clone(),
+        }
+    }
+
+    fn update_san_cache(&mut self) {
+        let current = self.san_cache.len() == self.game.history.len()
+            && self
+                .san_cache
+                .iter()
+                .zip(self.game.history.iter())
+                .all(|((mv, _), h)| mv == h);
+        if current {
+            return;
+        }
+        self.san_cache = self
+            .game
+            .history
+            .iter()
+            .cloned()
+            .zip(self.game.san_history())
+            .collect();
+    }
+
+    fn update_opening(&mut self) {
+        let key = self.game.board.hash(self.game.current_turn) ^ self.game.history.len() as u64;
+        if self.opening_key != Some(key) {
+            self.opening_key = Some(key);
+            self.opening = classify(&self.game);
+        }
+    }
+
+    fn move_list_panel(&mut self, ui: &mut egui::Ui) {
+        self.update_san_cache();
+        let total = self.san_cache.len();
+        let current = self.view_ply.unwrap_or(total);
+
+        ui.heading("Moves");
+        ui.horizontal(|ui| {
+            if ui.button("⏮").clicked() {
+                self.view_ply = Some(0);
+            }
+            if ui.button("◀").clicked() && current > 0 {
+                self.view_ply = Some(current - 1);
+            }
+            if ui.button("▶").clicked() && current < total {
+                self.view_ply = if current + 1 == total {
+                    None
+                } else {
+                    Some(current + 1)
+                };
+            }
+            if ui.button("⏭").clicked() {
+                self.view_ply = None;
+            }
+        });
+        ui.separator();
+
+        let offset = if self.game.start_turn == Color::Black {
+            1
+        } else {
+            0
+        };
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(self.view_ply.is_none())
+            .show(ui, |ui| {
+                egui::Grid::new("move_list")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        let rows = (total + offset).div_ceil(2);
+                        for row in 0..rows {
+                            ui.label(format!("{}.", row + 1));
+                            for col in 0..2 {
+                                let abs = row * 2 + col;
+                                if abs < offset || abs - offset >= total {
+                                    ui.label("");
+                                    continue;
+                                }
+                                let idx = abs - offset;
+                                let ply = idx + 1;
+                                if ui
+                                    .selectable_label(current == ply, &self.san_cache[idx].1)
+                                    .clicked()
+                                {
+                                    clicked = Some(ply);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(ply) = clicked {
+            self.dragging = None;
+            self.drag_targets.clear();
+            self.view_ply = if ply == total { None } else { Some(ply) };
         }
     }
 
-    fn get_time_config(&mut self) -> TimeConfig {
-        if !self.use_clock || self.time_preset == TimePreset::Unlimited {
-            return TimeConfig::fixed_depth(8);
+    fn flipped(&self) -> bool {
+        let human_black = self.vs_ai && self.ai_color == Color::White;
+        self.flip_board ^ (self.auto_flip && human_black)
+    }
+
+    fn square_rect(
+        rect: egui::Rect,
+        square_size: f32,
+        x: usize,
+        y: usize,
+        flipped: bool,
+    ) -> egui::Rect {
+        let (col, row) = if flipped { (7 - x, y) } else { (x, 7 - y) };
+        egui::Rect::from_min_size(
+            egui::pos2(
+                rect.left() + col as f32 * square_size,
+                rect.top() + row as f32 * square_size,
+            ),
+            egui::vec2(square_size, square_size),
+        )
+    }
+
+    fn square_at(
+        rect: egui::Rect,
+        square_size: f32,
+        pos: egui::Pos2,
+        flipped: bool,
+    ) -> Option<(usize, usize)> {
+        let col = ((pos.x - rect.left()) / square_size).floor() as i32;
+        let row = ((pos.y - rect.top()) / square_size).floor() as i32;
+        if !(0..8).contains(&col) || !(0..8).contains(&row) {
+            return None;
         }
+        let (x, y) = if flipped {
+            (7 - col, row)
+        } else {
+            (col, 7 - row)
+        };
+        Some((x as usize, y as usize))
+    }
 
-        self.clock.update();
-        TimeConfig {
-            wtime: Some(self.clock.white_time_ms),
-            btime: Some(self.clock.black_time_ms),
-            winc: Some(self.clock.increment_ms),
-            binc: Some(self.clock.increment_ms),
-            movestogo: None,
-            depth: None,
-            movetime: None,
-            infinite: false,
+    fn stop_analysis(&mut self) {
+        if let Some(worker) = self.analysis_worker.take() {
+            worker.stop();
         }
+        self.analysis_key.clear();
+        self.analysis_info = None;
     }
 
-    fn check_ai_move(&mut self) {
-        if self.flag_winner.is_some() {
+    fn update_analysis(&mut self, ctx: &egui::Context) {
+        if !self.analysis || self.editor.is_some() {
+            if self.analysis_worker.is_some() {
+                self.stop_analysis();
+            }
             return;
         }
 
-        if self.use_clock && self.game_started && self.time_preset != TimePreset::Unlimited {
-            self.clock.update();
-            if self.clock.is_flagged(Color::White) {
-                self.flag_winner = Some(Color::Black);
-                self.clock.stop();
-                return;
+        let key = format!(
+            "{}|{}",
+            self.view_ply.unwrap_or(self.game.history.len()),
+            self.displayed_fen()
+        );
+        if key != self.analysis_key {
+            self.stop_analysis();
+            let mut game = self.game.clone();
+            if let Some(ply) = self.view_ply {
+                game.truncate(ply);
             }
-            if self.clock.is_flagged(Color::Black) {
-                self.flag_winner = Some(Color::White);
-                self.clock.stop();
-                return;
+            self.analysis_worker = Some(AnalysisWorker::spawn(&self.engine, game));
+            self.analysis_key = key;
+        }
+
+        if let Some(worker) = &self.analysis_worker {
+            while let Ok(info) = worker.rx.try_recv() {
+                self.analysis_info = Some(info);
             }
         }
+        ctx.request_repaint_after(Duration::from_millis(200));
+    }
 
-        if self.vs_ai && self.game.result.is_none() && self.game.current_turn == self.ai_color {
-            let time_config = self.get_time_config();
-            let start = Instant::now();
+    fn format_score(score: i32, mate: Option<i32>) -> String {
+        match mate {
+            Some(m) => format!("#{}", m),
+            None => format!("{:+.2}", score as f32 / 100.0),
+        }
+    }
 
-            if let Some(((s, e), depth)) = self.engine.best_move_timed(&mut self.game, &time_config)
-            {
-                let duration = start.elapsed();
-                self.game.make_move(&s, &e);
-                self.last_ai_time = Some(duration);
+    fn analysis_panel(&self, ui: &mut egui::Ui) {
+        let board = self.displayed_board();
+        let turn = match self.view_ply {
+            Some(ply) => self.game.turn_at(ply),
+            None => self.game.current_turn,
+        };
+        let sign = if turn == Color::White { 1 } else { -1 };
 
-                if self.use_clock && self.game_started {
-                    let next_color = if self.ai_color == Color::White {
-                        Color::Black
-                    } else {
-                        Color::White
-                    };
-                    self.clock.switch(next_color);
-                }
+        let Some(info) = &self.analysis_info else {
+            ui.label("Analyzing…");
+            return;
+        };
+        let Some(best) = info.best() else {
+            ui.label("No legal moves in this position.");
+            return;
+        };
+
+        let white_score = best.score * sign;
+        let white_mate = best.mate_in().map(|m| m * sign);
+        let white_share = match white_mate {
+            Some(m) if m > 0 => 1.0,
+            Some(_) => 0.0,
+            None => 1.0 / (1.0 + (-white_score as f32 / 400.0).exp()),
+        };
+
+        ui.horizontal(|ui| {
+            let (bar, _) = ui.allocate_exact_size(egui::vec2(240.0, 18.0), egui::Sense::hover());
+            let painter = ui.painter();
+            painter.rect_filled(bar, 2.0, Color32::from_gray(40));
+            let mut white = bar;
+            white.set_width(bar.width() * white_share);
+            painter.rect_filled(white, 2.0, Color32::from_gray(235));
+            ui.label(
+                egui::RichText::new(Self::format_score(white_score, white_mate))
+                    .monospace()
+                    .strong(),
+            );
+            ui.separator();
+            let nps = info.nodes * 1000 / info.time_ms.max(1);
+            ui.label(format!(
+                "Depth {}/{}  •  {} nodes  •  {} knps",
+                info.depth,
+                info.seldepth,
+                info.nodes,
+                nps / 1000
+            ));
+        });
 
-                println!("AI move {s}{e} in {:?} (depth {})", duration, depth);
-            }
+        for line in &info.lines {
+            let san = line_to_san(&board, turn, &line.moves).join(" ");
+            let score = Self::format_score(line.score * sign, line.mate_in().map(|m| m * sign));
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("{:>7}", score)).monospace());
+                ui.label(san);
+            });
         }
     }
 
-    fn on_player_move(&mut self) {
-        if !self.game_started {
-            self.game_started = true;
-            if self.use_clock && self.time_preset != TimePreset::Unlimited {
-                self.clock.start(self.game.current_turn);
+    // Green where the piece-square tables favour White, red where they favour
+    // Black, fully tinted from half a pawn.
+    fn heat_color(value: i32) -> Color32 {
+        let alpha = (value.unsigned_abs().min(50) * 150 / 50) as u8;
+        if value >= 0 {
+            Color32::from_rgba_unmultiplied(40, 170, 60, alpha)
+        } else {
+            Color32::from_rgba_unmultiplied(200, 40, 40, alpha)
+        }
+    }
+
+    fn eval_panel(&self, ui: &mut egui::Ui) {
+        let board = self.displayed_board();
+        let breakdown = eval::explain(&board);
+        ui.horizontal(|ui| {
+            ui.label(
+                egui::RichText::new(format!(
+                    "Static eval {}",
+                    Self::format_score(breakdown.total, None)
+                ))
+                .strong(),
+            );
+            if breakdown.endgame.is_some() {
+                ui.label("(recognised endgame)");
             }
-        } else if self.use_clock && self.time_preset != TimePreset::Unlimited {
-            self.clock.switch(self.game.current_turn);
+        });
+        if breakdown.endgame.is_some() {
+            return;
         }
+        let mut terms = breakdown.terms();
+        terms.sort_by_key(|&(_, value)| std::cmp::Reverse(value.abs()));
+        ui.horizontal_wrapped(|ui| {
+            for (name, value) in terms {
+                let color = match value.signum() {
+                    1 => Color32::from_rgb(60, 170, 80),
+                    -1 => Color32::from_rgb(210, 70, 70),
+                    _ => ui.visuals().weak_text_color(),
+                };
+                ui.label(
+                    egui::RichText::new(format!("{name} {}", Self::format_score(value, None)))
+                        .monospace()
+                        .color(color),
+                );
+                ui.separator();
+            }
+        });
     }
 
-    fn restart_game(&mut self) {
-        self.game = Game::new();
+    fn open_editor(&mut self) {
+        let turn = match self.view_ply {
+            Some(ply) => self.game.turn_at(ply),
+            None => self.game.current_turn,
+        };
+        let mut board = self.displayed_board();
+        board.en_passant = None;
         self.dragging = None;
-        self.game_started = false;
-        self.flag_winner = None;
+        self.drag_targets.clear();
+        if self.clock.running {
+            self.clock.stop();
+        }
+        self.editor = Some(Editor {
+            board,
+            turn,
+            brush: Some(Piece {
+                piece_type: PieceType::Pawn,
+                color: Color::White,
+            }),
+            error: None,
+        });
+    }
 
-        if self.time_preset == TimePreset::Custom {
-            self.clock
-                .reset(self.custom_base_mins * 60, self.custom_increment_secs);
+    fn editor_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        ui.heading("Edit position");
+        ui.label("Left click places the selected piece, right click clears a square.");
+        ui.separator();
+
+        for color in [Color::White, Color::Black] {
+            ui.horizontal(|ui| {
+                for piece_type in [
+                    PieceType::King,
+                    PieceType::Queen,
+                    PieceType::Rook,
+                    PieceType::Bishop,
+                    PieceType::Knight,
+                    PieceType::Pawn,
+                ] {
+                    let piece = Piece { piece_type, color };
+                    let image = egui::Image::new(Self::piece_image(&piece))
+                        .fit_to_exact_size(egui::vec2(32.0, 32.0));
+                    if ui
+                        .add(egui::ImageButton::new(image).selected(editor.brush == Some(piece)))
+                        .clicked()
+                    {
+                        editor.brush = Some(piece);
+                    }
+                }
+            });
+        }
+        if ui
+            .selectable_label(editor.brush.is_none(), "🗑 Eraser")
+            .clicked()
+        {
+            editor.brush = None;
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("To move:");
+            ui.radio_value(&mut editor.turn, Color::White, "White");
+            ui.radio_value(&mut editor.turn, Color::Black, "Black");
+        });
+        ui.label("Castling:");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut editor.board.castling[0][0], "O-O");
+            ui.checkbox(&mut editor.board.castling[0][1], "O-O-O");
+            ui.label("White");
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut editor.board.castling[1][0], "O-O");
+            ui.checkbox(&mut editor.board.castling[1][1], "O-O-O");
+            ui.label("Black");
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Clear").clicked() {
+                editor.board.clear();
+            }
+            if ui.button("Start position").clicked() {
+                editor.board.setup_standard();
+                editor.turn = Color::White;
+            }
+        });
+
+        if let Some(err) = &editor.error {
+            ui.colored_label(Color32::RED, err);
+        }
+
+        let mut done = false;
+        let mut cancel = false;
+        ui.horizontal(|ui| {
+            done = ui.button("✔ Done").clicked();
+            cancel = ui.button("Cancel").clicked();
+        });
+        if cancel {
+            self.editor = None;
+        } else if done {
+            match editor.board.validate(editor.turn) {
+                Ok(()) => {
+                    let game = Game::from_board(editor.board.clone(), editor.turn);
+                    self.editor = None;
+                    self.set_game(game);
+                }
+                Err(e) => editor.error = Some(format!("Invalid position: {}", e)),
+            }
+        }
+    }
+
+    fn game_over_dialog(&mut self, ctx: &egui::Context) {
+        let Some(end) = self.game_end else {
+            return;
+        };
+        if !self.show_game_over {
+            return;
+        }
+        let mut open = true;
+        let mut rematch = false;
+        let mut export = false;
+        let rematch_label = if self.vs_ai {
+            "Rematch (swap colors)"
         } else {
-            self.clock.reset(
-                self.time_preset.base_time_secs(),
-                self.time_preset.increment_secs(),
-            );
+            "Rematch"
+        };
+        egui::Window::new("Game over")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading(end.result_str());
+                    ui.label(end.summary());
+                });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    rematch = ui.button(rematch_label).clicked();
+                    export = ui.button("Export PGN…").clicked();
+                });
+            });
+        if !open {
+            self.show_game_over = false;
+        }
+        if export {
+            self.save_pgn();
         }
+        if rematch {
+            self.rematch();
+        }
+    }
 
-        self.check_ai_move();
+    fn piece_image(piece: &Piece) -> egui::ImageSource<'static> {
+        match (piece.piece_type, piece.color) {
+            (PieceType::King, Color::White) => egui::include_image!("../../assets/pieces/wK.svg"),
+            (PieceType::Queen, Color::White) => egui::include_image!("../../assets/pieces/wQ.svg"),
+            (PieceType::Rook, Color::White) => egui::include_image!("../../assets/pieces/wR.svg"),
+            (PieceType::Bishop, Color::White) => egui::include_image!("../../assets/pieces/wB.svg"),
+            (PieceType::Knight, Color::White) => egui::include_image!("../../assets/pieces/wN.svg"),
+            (PieceType::Pawn, Color::White) => egui::include_image!("../../assets/pieces/wP.svg"),
+            (PieceType::King, Color::Black) => egui::include_image!("../../assets/pieces/bK.svg"),
+            (PieceType::Queen, Color::Black) => egui::include_image!("../../assets/pieces/bQ.svg"),
+            (PieceType::Rook, Color::Black) => egui::include_image!("../../assets/pieces/bR.svg"),
+            (PieceType::Bishop, Color::Black) => egui::include_image!("../../assets/pieces/bB.svg"),
+            (PieceType::Knight, Color::Black) => egui::include_image!("../../assets/pieces/bN.svg"),
+            (PieceType::Pawn, Color::Black) => egui::include_image!("../../assets/pieces/bP.svg"),
+        }
+    }
+
+    fn draw_piece(ui: &egui::Ui, piece_set: PieceSet, rect: egui::Rect, piece: &Piece) {
+        match piece_set {
+            PieceSet::Classic => {
+                egui::Image::new(Self::piece_image(piece)).paint_at(ui, rect);
+            }
+            PieceSet::Glyphs => {
+                let font = egui::FontId::proportional(rect.height() * 0.8);
+                let (fill, outline) = if piece.color == Color::White {
+                    (Color32::WHITE, Color32::BLACK)
+                } else {
+                    (Color32::BLACK, Color32::WHITE)
+                };
+                let glyph = Self::piece_char(piece);
+                let painter = ui.painter();
+                let w = (rect.height() * 0.02).max(1.0);
+                for (dx, dy) in [(-w, 0.0), (w, 0.0), (0.0, -w), (0.0, w)] {
+                    painter.text(
+                        rect.center() + egui::vec2(dx, dy),
+                        egui::Align2::CENTER_CENTER,
+                        glyph,
+                        font.clone(),
+                        outline,
+                    );
+                }
+                painter.text(
+                    rect.center(),
+                    egui::Align2::CENTER_CENTER,
+                    glyph,
+                    font,
+                    fill,
+                );
+            }
+        }
     }
 
     fn piece_char(piece: &Piece) -> char {
@@ -320,16 +1719,178 @@ impl App for GuiApp {
             ctx.request_repaint();
         }
 
+        self.handle_dropped_files(ctx);
         self.check_ai_move();
+        if self.ai_worker.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(20));
+        }
 
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open PGN or FEN…").clicked() {
+                        ui.close_menu();
+                        self.open_pgn();
+                    }
+                    if ui.button("Save PGN…").clicked() {
+                        ui.close_menu();
+                        self.save_pgn();
+                    }
+                });
+                ui.menu_button("Engine", |ui| {
+                    ui.checkbox(&mut self.engine.params.use_book, "Opening book");
+                    let book = &mut self.engine.params.book;
+                    ui.horizontal(|ui| {
+                        ui.label("Book depth (plies):");
+                        ui.add(egui::DragValue::new(&mut book.max_ply).clamp_range(0..=255));
+                    });
+                    ui.checkbox(&mut book.weighted, "Vary book moves by weight");
+                    ui.horizontal(|ui| {
+                        ui.label("Min. learned score:");
+                        ui.add(
+                            egui::DragValue::new(&mut book.min_score)
+                                .clamp_range(0..=100)
+                                .suffix("%"),
+                        );
+                    });
+                    ui.label(format!(
+                        "Book: {}",
+                        self.book_name.as_deref().unwrap_or("built-in")
+                    ));
+                    if ui.button("Load opening book…").clicked() {
+                        ui.close_menu();
+                        self.open_book();
+                    }
+                    ui.separator();
+                    let mut human = self.engine.params.human.is_some();
+                    if ui.checkbox(&mut human, "Human-like play").changed() {
+                        self.engine.params.human = human.then(HumanStyle::default);
+                    }
+                    if let Some(style) = &mut self.engine.params.human {
+                        ui.horizontal(|ui| {
+                            ui.label("Depth:");
+                            ui.add(egui::DragValue::new(&mut style.depth).clamp_range(1..=8));
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Temperature:");
+                            ui.add(
+                                egui::DragValue::new(&mut style.temperature)
+                                    .clamp_range(1..=500)
+                                    .suffix(" cp"),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Mistakes:");
+                            ui.add(
+                                egui::DragValue::new(&mut style.mistake_rate)
+                                    .clamp_range(0..=100)
+                                    .suffix("%"),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Blunders:");
+                            ui.add(
+                                egui::DragValue::new(&mut style.blunder_rate)
+                                    .clamp_range(0..=100)
+                                    .suffix("%"),
+                            );
+                        });
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    ui.label("Board");
+                    for theme in [
+                        BoardTheme::Brown,
+                        BoardTheme::Green,
+                        BoardTheme::Blue,
+                        BoardTheme::Gray,
+                    ] {
+                        ui.radio_value(&mut self.board_theme, theme, theme.name());
+                    }
+                    ui.separator();
+                    ui.label("Pieces");
+                    for set in [PieceSet::Classic, PieceSet::Glyphs] {
+                        ui.radio_value(&mut self.piece_set, set, set.name());
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.show_coords, "Coordinates");
+                    ui.checkbox(&mut self.show_heatmap, "Evaluation heatmap");
+                    ui.separator();
+                    ui.label("Show");
+                    for visibility in [
+                        Visibility::AllPieces,
+                        Visibility::PawnsOnly,
+                        Visibility::Blindfold,
+                    ] {
+                        ui.radio_value(&mut self.visibility, visibility, visibility.name());
+                    }
+                    ui.separator();
+                    if ui.checkbox(&mut self.coach, "Coach").changed() && !self.coach {
+                        self.stop_coach();
+                    }
+                    ui.add_enabled(
+                        cfg!(feature = "sound") && self.sounds.is_some(),
+                        egui::Checkbox::new(&mut self.sound_enabled, "Sound effects"),
+                    )
+                    .on_disabled_hover_text("Build with `--features sound` to enable audio");
+                });
+            });
+
+            ui.separator();
+
             ui.horizontal(|ui| {
                 if ui.button("🔄 Restart").clicked() {
                     self.restart_game();
                 }
 
+                let can_takeback =
+                    self.game_end.is_none_or(|end| end.on_board()) && !self.game.history.is_empty();
+                if ui
+                    .add_enabled(can_takeback, egui::Button::new("↩ Takeback"))
+                    .clicked()
+                {
+                    self.takeback();
+                }
+
+                if ui
+                    .add_enabled(self.game_end.is_none(), egui::Button::new("🏳 Resign"))
+                    .clicked()
+                {
+                    self.resign();
+                }
+
+                // Only the player to move may claim.
+                let ai_to_move = self.ai_enabled() && self.game.current_turn == self.ai_color;
+                let claim = self
+                    .game
+                    .can_claim_draw()
+                    .filter(|_| self.game_end.is_none() && self.editor.is_none() && !ai_to_move);
+                if let Some(claim) = claim {
+                    let hint = match claim {
+                        DrawClaim::Repetition => "The position has occurred three times",
+                        DrawClaim::FiftyMoves => "50 moves without a capture or pawn move",
+                    };
+                    if ui.button("½ Claim draw").on_hover_text(hint).clicked() {
+                        self.claim_draw();
+                    }
+                }
+
+                if ui
+                    .add_enabled(self.editor.is_none(), egui::Button::new("✏ Edit"))
+                    .clicked()
+                {
+                    self.open_editor();
+                }
+
                 ui.separator();
 
+                if ui.checkbox(&mut self.analysis, "🔍 Analysis").changed()
+                    && self.analysis
+                    && self.clock.running
+                {
+                    self.clock.stop();
+                }
+
                 ui.checkbox(&mut self.vs_ai, "Play vs AI");
 
                 if self.vs_ai {
@@ -337,6 +1898,11 @@ impl App for GuiApp {
                     ui.radio_value(&mut self.ai_color, Color::White, "White");
                     ui.radio_value(&mut self.ai_color, Color::Black, "Black");
                 }
+
+                ui.separator();
+
+                ui.checkbox(&mut self.flip_board, "Flip board");
+                ui.checkbox(&mut self.auto_flip, "Auto-flip");
             });
 
             ui.separator();
@@ -407,25 +1973,52 @@ impl App for GuiApp {
 
             ui.separator();
             ui.horizontal(|ui| {
-                if let Some(winner) = self.flag_winner {
-                    let winner_str = if winner == Color::White {
-                        "White"
-                    } else {
-                        "Black"
-                    };
-                    ui.label(
-                        egui::RichText::new(format!("⏱ {} wins on time!", winner_str))
-                            .color(Color32::RED)
-                            .strong(),
-                    );
-                } else if let Some(res) = self.game.result {
-                    let winner_str = if res == Color::White {
-                        "White"
-                    } else {
-                        "Black"
-                    };
+                ui.label("FEN:");
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut self.fen_input)
+                        .desired_width(360.0)
+                        .hint_text("Paste a FEN to set up a position"),
+                );
+                if input.changed() {
+                    self.load_error = None;
+                }
+                let enter = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Load FEN").clicked() || enter {
+                    self.load_fen();
+                }
+                if ui.button("📋 Copy FEN").clicked() {
+                    let fen = self.displayed_fen();
+                    ui.output_mut(|o| o.copied_text = fen);
+                }
+                if let Some(err) = &self.load_error {
+                    ui.colored_label(Color32::RED, err);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Move:");
+                let input = ui.add(
+                    egui::TextEdit::singleline(&mut self.move_input)
+                        .desired_width(120.0)
+                        .hint_text("e.g. Nf3"),
+                );
+                if input.changed() {
+                    self.move_error = None;
+                }
+                let enter = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Play").clicked() || enter {
+                    self.enter_move();
+                    input.request_focus();
+                }
+                if let Some(err) = &self.move_error {
+                    ui.colored_label(Color32::RED, err);
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if let Some(end) = self.game_end {
                     ui.label(
-                        egui::RichText::new(format!("♚ {} wins by checkmate!", winner_str))
+                        egui::RichText::new(format!("{} ({})", end.summary(), end.result_str()))
                             .color(Color32::GOLD)
                             .strong(),
                     );
@@ -437,25 +2030,122 @@ impl App for GuiApp {
                     };
                     ui.label(format!("Turn: {}", turn_str));
 
-                    if let Some(t) = self.last_ai_time {
+                    if self.ai_worker.is_some() {
+                        ui.separator();
+                        ui.label("AI thinking…");
+                        if let Some((s, e)) = &self.premove {
+                            ui.label(format!("(premove {}{})", s, e));
+                        }
+                    } else if let Some(t) = self.last_ai_time {
                         ui.separator();
                         ui.label(format!("AI: {:.2?}", t));
                     }
                 }
+
+                self.update_opening();
+                if let Some(opening) = self.opening {
+                    ui.separator();
+                    ui.label(format!("{}: {}", opening.eco, opening.name));
+                }
+            });
+        });
+
+        self.game_over_dialog(ctx);
+        self.update_analysis(ctx);
+
+        if self.analysis {
+            egui::TopBottomPanel::bottom("analysis").show(ctx, |ui| {
+                self.analysis_panel(ui);
+            });
+        }
+
+        self.update_coach(ctx);
+        if self.coach {
+            egui::TopBottomPanel::bottom("coach").show(ctx, |ui| {
+                self.coach_panel(ui);
+            });
+        }
+
+        if self.show_heatmap {
+            egui::TopBottomPanel::bottom("evaluation").show(ctx, |ui| {
+                self.eval_panel(ui);
+            });
+        }
+
+        if self.editor.is_some() {
+            egui::SidePanel::left("editor")
+                .resizable(false)
+                .show(ctx, |ui| {
+                    self.editor_panel(ui);
+                });
+        }
+
+        egui::SidePanel::right("moves")
+            .resizable(false)
+            .min_width(180.0)
+            .show(ctx, |ui| {
+                self.move_list_panel(ui);
             });
+
+        let board = self.displayed_board();
+        let last_move = match self.view_ply {
+            _ if self.editor.is_some() => None,
+            Some(ply) => ply
+                .checked_sub(1)
+                .and_then(|i| self.game.history.get(i))
+                .map(|(s, e)| (s.as_str(), e.as_str())),
+            None => self.game.last_move(),
+        }
+        .and_then(|(s, e)| {
+            Some((
+                Board::algebraic_to_index(s)?,
+                Board::algebraic_to_index(e.get(0..2)?)?,
+            ))
+        });
+        let premove = self.premove.as_ref().and_then(|(s, e)| {
+            Some((Board::algebraic_to_index(s)?, Board::algebraic_to_index(e)?))
         });
+        let side_to_move = match (&self.editor, self.view_ply) {
+            (Some(editor), _) => editor.turn,
+            (None, Some(ply)) => self.game.turn_at(ply),
+            (None, None) => self.game.current_turn,
+        };
+        let visibility = if self.editor.is_some() {
+            Visibility::AllPieces
+        } else {
+            self.visibility
+        };
+        // The heatmap would give away where the hidden pieces stand.
+        let heatmap = (self.show_heatmap && visibility == Visibility::AllPieces)
+            .then(|| eval::Evaluator::new(&board).pst_map());
+        let checked_king = if board.in_check_fast(side_to_move) {
+            board.find_king(side_to_move)
+        } else {
+            None
+        };
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 let available_height = ui.available_height();
                 let board_size = ui.available_width().min(available_height) * 0.85;
                 let square_size = board_size / 8.0;
+                let flipped = self.flipped();
+
+                let margin = if self.show_coords {
+                    square_size * 0.35
+                } else {
+                    0.0
+                };
 
                 ui.vertical(|ui| {
-                    let (rect, response) = ui.allocate_exact_size(
-                        egui::vec2(board_size, board_size),
+                    let (outer, response) = ui.allocate_exact_size(
+                        egui::vec2(board_size + margin, board_size + margin),
                         egui::Sense::click_and_drag(),
                     );
+                    let rect = egui::Rect::from_min_size(
+                        outer.min + egui::vec2(margin, 0.0),
+                        egui::vec2(board_size, board_size),
+                    );
 
                     if let Some(pos) = ctx.input(|i| i.pointer.interact_pos()) {
                         self.drag_pos = pos;
@@ -465,30 +2155,17 @@ impl App for GuiApp {
                         || (self.dragging.is_some() && !ctx.input(|i| i.pointer.any_down()));
 
                     if drag_released {
-                        if let Some((sx, sy, piece)) = self.dragging.take() {
-                            if rect.contains(self.drag_pos) {
-                                let fx =
-                                    ((self.drag_pos.x - rect.left()) / square_size).floor() as i32;
-                                let fy = 7
-                                    - ((self.drag_pos.y - rect.top()) / square_size).floor() as i32;
-                                if fx >= 0 && fx < 8 && fy >= 0 && fy < 8 {
-                                    if let (Some(start), Some(end)) = (
-                                        Board::index_to_algebraic(sx, sy),
-                                        Board::index_to_algebraic(fx as usize, fy as usize),
-                                    ) {
-                                        if !self.game.make_move(&start, &end) {
-                                            self.game.board.set_index(sx, sy, Some(piece));
-                                        } else {
-                                            self.on_player_move();
-                                            self.check_ai_move();
-                                        }
-                                    }
-                                } else {
-                                    self.game.board.set_index(sx, sy, Some(piece));
-                                }
-                            } else {
-                                self.game.board.set_index(sx, sy, Some(piece));
-                            }
+                        self.drag_targets.clear();
+                        if let Some((sx, sy, _)) = self.dragging.take()
+                            && rect.contains(self.drag_pos)
+                            && let Some((fx, fy)) =
+                                Self::square_at(rect, square_size, self.drag_pos, flipped)
+                            && let (Some(start), Some(end)) = (
+                                Board::index_to_algebraic(sx, sy),
+                                Board::index_to_algebraic(fx, fy),
+                            )
+                        {
+                            self.try_player_move(&start, &end);
                         }
                     }
 
@@ -496,78 +2173,132 @@ impl App for GuiApp {
 
                     for x in 0..8 {
                         for y in 0..8 {
-                            let sq_rect = egui::Rect::from_min_size(
-                                egui::pos2(
-                                    rect.left() + x as f32 * square_size,
-                                    rect.top() + (7 - y) as f32 * square_size,
-                                ),
-                                egui::vec2(square_size, square_size),
-                            );
-                            let light = Color32::from_rgb(240, 217, 181);
-                            let dark = Color32::from_rgb(181, 136, 99);
+                            let sq_rect = Self::square_rect(rect, square_size, x, y, flipped);
+                            let (light, dark) = self.board_theme.colors();
                             let color = if (x + y) % 2 == 0 { light } else { dark };
                             painter.rect_filled(sq_rect, 0.0, color);
+                            if let Some(map) = &heatmap {
+                                painter.rect_filled(sq_rect, 0.0, Self::heat_color(map[y * 8 + x]));
+                            }
+                            if let Some((from, to)) = last_move
+                                && ((x, y) == from || (x, y) == to)
+                            {
+                                painter.rect_filled(
+                                    sq_rect,
+                                    0.0,
+                                    Color32::from_rgba_unmultiplied(205, 210, 60, 110),
+                                );
+                            }
+                            if let Some((from, to)) = premove
+                                && ((x, y) == from || (x, y) == to)
+                            {
+                                painter.rect_filled(
+                                    sq_rect,
+                                    0.0,
+                                    Color32::from_rgba_unmultiplied(60, 110, 200, 120),
+                                );
+                            }
+                            if checked_king == Some((x, y)) {
+                                painter.rect_filled(
+                                    sq_rect,
+                                    0.0,
+                                    Color32::from_rgba_unmultiplied(220, 30, 30, 150),
+                                );
+                            }
+                        }
+                    }
+
+                    if self.show_coords {
+                        let label_font = egui::FontId::proportional(margin * 0.75);
+                        let label_color = ui.visuals().text_color();
+                        for i in 0..8 {
+                            let file_rect = Self::square_rect(rect, square_size, i, 0, flipped);
+                            painter.text(
+                                egui::pos2(file_rect.center().x, rect.bottom() + margin * 0.5),
+                                egui::Align2::CENTER_CENTER,
+                                (b'a' + i as u8) as char,
+                                label_font.clone(),
+                                label_color,
+                            );
+                            let rank_rect = Self::square_rect(rect, square_size, 0, i, flipped);
+                            painter.text(
+                                egui::pos2(outer.left() + margin * 0.5, rank_rect.center().y),
+                                egui::Align2::CENTER_CENTER,
+                                i + 1,
+                                label_font.clone(),
+                                label_color,
+                            );
+                        }
+                    }
+
+                    let marker = Color32::from_rgba_unmultiplied(20, 85, 30, 110);
+                    for &(x, y, capture) in &self.drag_targets {
+                        let center = Self::square_rect(rect, square_size, x, y, flipped).center();
+                        if capture {
+                            painter.circle_stroke(
+                                center,
+                                square_size * 0.45,
+                                egui::Stroke::new(square_size * 0.08, marker),
+                            );
+                        } else {
+                            painter.circle_filled(center, square_size * 0.15, marker);
                         }
                     }
 
                     for x in 0..8 {
                         for y in 0..8 {
-                            let piece_opt = self.game.board.get_index(x, y);
-                            if let Some(p) = piece_opt {
+                            let piece_opt = board.get_index(x, y);
+                            if let Some(p) = piece_opt
+                                && visibility.shows(&p)
+                            {
                                 if let Some((dx, dy, _)) = self.dragging {
                                     if dx == x && dy == y {
                                         continue;
                                     }
                                 }
-                                let sq_rect = egui::Rect::from_min_size(
-                                    egui::pos2(
-                                        rect.left() + x as f32 * square_size,
-                                        rect.top() + (7 - y) as f32 * square_size,
-                                    ),
-                                    egui::vec2(square_size, square_size),
-                                );
-                                painter.text(
-                                    sq_rect.center(),
-                                    egui::Align2::CENTER_CENTER,
-                                    Self::piece_char(&p),
-                                    egui::FontId::proportional(square_size * 0.8),
-                                    if p.color == Color::White {
-                                        egui::Color32::WHITE
-                                    } else {
-                                        egui::Color32::BLACK
-                                    },
-                                );
+                                let sq_rect = Self::square_rect(rect, square_size, x, y, flipped);
+                                Self::draw_piece(ui, self.piece_set, sq_rect, &p);
                             }
                         }
                     }
 
-                    if response.drag_started() {
-                        if rect.contains(self.drag_pos) {
-                            let fx =
-                                ((self.drag_pos.x - rect.left()) / square_size).floor() as usize;
-                            let fy =
-                                7 - ((self.drag_pos.y - rect.top()) / square_size).floor() as usize;
-
-                            if fx < 8 && fy < 8 {
-                                if let Some(p) = self.game.board.get_index(fx, fy) {
-                                    self.dragging = Some((fx, fy, p));
-                                }
-                            }
+                    if let Some(editor) = &mut self.editor {
+                        let pointer = response.interact_pointer_pos();
+                        if let Some(pos) = pointer
+                            && (response.clicked() || response.secondary_clicked())
+                            && let Some((x, y)) = Self::square_at(rect, square_size, pos, flipped)
+                        {
+                            let piece = if response.secondary_clicked()
+                                || editor.board.get_index(x, y) == editor.brush
+                            {
+                                None
+                            } else {
+                                editor.brush
+                            };
+                            editor.board.set_index(x, y, piece);
+                            editor.error = None;
                         }
+                    } else if response.secondary_clicked() {
+                        self.premove = None;
+                    } else if response.drag_started()
+                        && self.game_end.is_none_or(|end| end.on_board())
+                        && rect.contains(self.drag_pos)
+                        && let Some((fx, fy)) =
+                            Self::square_at(rect, square_size, self.drag_pos, flipped)
+                        && let Some(p) = board.get_index(fx, fy)
+                        && visibility.shows(&p)
+                        && !(self.ai_enabled() && p.color == self.ai_color)
+                    {
+                        self.dragging = Some((fx, fy, p));
+                        self.drag_targets = Self::legal_targets(&board, fx, fy, p.color);
                     }
 
                     if let Some((_sx, _sy, p)) = self.dragging {
-                        painter.text(
+                        let drag_rect = egui::Rect::from_center_size(
                             self.drag_pos,
-                            egui::Align2::CENTER_CENTER,
-                            Self::piece_char(&p),
-                            egui::FontId::proportional(square_size * 0.8),
-                            if p.color == Color::White {
-                                egui::Color32::WHITE
-                            } else {
-                                egui::Color32::BLACK
-                            },
+                            egui::vec2(square_size, square_size),
                         );
+                        Self::draw_piece(ui, self.piece_set, drag_rect, &p);
                     }
                 });
 
@@ -577,8 +2308,14 @@ impl App for GuiApp {
                     ui.set_min_width(120.0);
 
                     self.clock.update();
-
-                    let black_time = self.clock.black_time_ms;
+                    // Stepping through a game, or one loaded before it is
+                    // played on, shows the times recorded for that ply.
+                    let review = self
+                        .view_ply
+                        .or((!self.game_started).then_some(self.game.history.len()));
+                    let recorded = |color| review.and_then(|ply| self.recorded_clock(ply, color));
+
+                    let black_time = recorded(Color::Black).unwrap_or(self.clock.black_time_ms);
                     let black_active =
                         self.clock.running && self.clock.active_color == Color::Black;
 
@@ -617,7 +2354,7 @@ impl App for GuiApp {
 
                     ui.add_space(20.0);
 
-                    let white_time = self.clock.white_time_ms;
+                    let white_time = recorded(Color::White).unwrap_or(self.clock.white_time_ms);
                     let white_active =
                         self.clock.running && self.clock.active_color == Color::White;
 
@@ -654,13 +2391,17 @@ fn main() {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([700.0, 550.0])
-            .with_min_inner_size([500.0, 400.0]),
+            .with_min_inner_size([500.0, 400.0])
+            .with_drag_and_drop(true),
         ..Default::default()
     };
     eframe::run_native(
         "Chessmind",
         options,
-        Box::new(|_cc| Box::new(GuiApp::new())),
+        Box::new(|cc| {
+            egui_extras::install_image_loaders(&cc.egui_ctx);
+            Box::new(GuiApp::new())
+        }),
     )
     .unwrap();
 }
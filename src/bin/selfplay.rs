@@ -0,0 +1,282 @@
+use chessmind::{
+    arena::{AdjudicationRules, EngineConfig, GameOutcome, TimeControl, append_pgn},
+    eco::{Opening, book_exits},
+    engine::{Engine, TimeConfig, mate_distance},
+    game::Game,
+    pgn::to_pgn_annotated,
+    pieces::Color,
+    play::{MatchWorker, PlayerKind},
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, mpsc};
+
+const WORKER_TABLE_SIZE: usize = 1 << 20;
+
+const USAGE: &str = "Usage: selfplay [OPTIONS]
+
+Plays fast engine-vs-itself games from randomized book exits and writes them
+as PGN, with each engine move annotated by its evaluation as {score/depth}.
+
+Options:
+  --games N          Number of games (default 100)
+  --depth N          Search depth per move (default 5)
+  --random-plies N   Random moves played after the book exit (default 4)
+  --seed N           Seed of the opening randomization (default: random)
+  --concurrency N    Games played in parallel (default: half the cores)
+  --pgn FILE         PGN output, appended to (default selfplay.pgn)
+  --labels FILE      Also append one `fen | score | result` line per
+                     evaluated position: score in centipawns and result
+                     (1.0, 0.5 or 0.0) both from White's point of view";
+
+struct Options {
+    games: u32,
+    depth: u32,
+    random_plies: usize,
+    seed: u64,
+    concurrency: usize,
+    pgn: PathBuf,
+    labels: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        games: 100,
+        depth: 5,
+        random_plies: 4,
+        seed: rand::thread_rng().r#gen(),
+        concurrency: (num_cpus::get() / 2).max(1),
+        pgn: PathBuf::from("selfplay.pgn"),
+        labels: None,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", arg))?;
+        let invalid = || format!("invalid value for {}: {}", arg, value);
+        match arg.as_str() {
+            "--games" => options.games = value.parse().map_err(|_| invalid())?,
+            "--depth" => options.depth = value.parse().map_err(|_| invalid())?,
+            "--random-plies" => options.random_plies = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--concurrency" => {
+                options.concurrency = value.parse::<usize>().map_err(|_| invalid())?.max(1)
+            }
+            "--pgn" => options.pgn = PathBuf::from(value),
+            "--labels" => options.labels = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+// A book exit followed by a few random moves, so games rarely repeat.
+fn random_start(
+    exits: &[(Opening, Game)],
+    random_plies: usize,
+    rng: &mut StdRng,
+) -> (Opening, Game) {
+    let (opening, mut game) = exits.choose(rng).cloned().expect("book has openings");
+    for _ in 0..random_plies {
+        let legal = game.legal_moves();
+        let Some((start, end)) = legal.choose(rng) else {
+            break;
+        };
+        game.make_move(start, end);
+    }
+    // Random moves may have walked into a finished position.
+    if game.legal_moves().is_empty() {
+        game.undo_move();
+    }
+    (opening, game)
+}
+
+struct SelfPlayGame {
+    index: u32,
+    opening: Opening,
+    game: Game,
+    // Number of plies played before the engine took over.
+    start_plies: usize,
+    // White-relative score and depth of every engine move.
+    evals: Vec<Option<(i32, u32)>>,
+    outcome: GameOutcome,
+}
+
+impl SelfPlayGame {
+    fn result_value(&self) -> &'static str {
+        match self.outcome.winner {
+            Some(Color::White) => "1.0",
+            Some(Color::Black) => "0.0",
+            None => "0.5",
+        }
+    }
+
+    fn pgn(&self, depth: u32) -> String {
+        let mut comments = vec![None; self.start_plies];
+        comments.extend(self.evals.iter().map(|eval| {
+            eval.map(|(score, depth)| match mate_distance(score) {
+                Some(m) => format!("#{}/{}", m, depth),
+                None => format!("{:+.2}/{}", score as f32 / 100.0, depth),
+            })
+        }));
+        let tags = [
+            ("Event", "Chessmind self-play".to_string()),
+            ("Round", (self.index + 1).to_string()),
+            ("White", format!("Chessmind depth {}", depth)),
+            ("Black", format!("Chessmind depth {}", depth)),
+            ("Result", self.outcome.result_str().to_string()),
+            ("ECO", self.opening.eco.to_string()),
+            ("Opening", self.opening.name.to_string()),
+            ("Termination", self.outcome.termination.reason().to_string()),
+        ]
+        .map(|(key, value)| (key.to_string(), value));
+        to_pgn_annotated(&self.game, &tags, &comments)
+    }
+
+    // Positions searched by the engine with their labels; mate scores are
+    // left out as they say little about the evaluation.
+    fn labels(&self) -> Vec<String> {
+        let result = self.result_value();
+        self.evals
+            .iter()
+            .enumerate()
+            .filter_map(|(i, eval)| {
+                let (score, _) = (*eval)?;
+                if mate_distance(score).is_some() {
+                    return None;
+                }
+                let ply = self.start_plies + i;
+                let fen = self.game.position_at(ply).to_fen(self.game.turn_at(ply));
+                Some(format!("{} | {} | {}", fen, score, result))
+            })
+            .collect()
+    }
+}
+
+fn spawn_workers(options: &Options) -> mpsc::Receiver<SelfPlayGame> {
+    let exits = Arc::new(book_exits());
+    let next_game = Arc::new(AtomicU32::new(0));
+    let (tx, rx) = mpsc::channel();
+    let mut config = EngineConfig::new(options.depth);
+    config.params.use_book = false;
+    for _ in 0..options.concurrency {
+        let (exits, next_game, tx, config) =
+            (exits.clone(), next_game.clone(), tx.clone(), config.clone());
+        let (games, seed, random_plies) = (options.games, options.seed, options.random_plies);
+        std::thread::spawn(move || {
+            let engine = config.build(1, WORKER_TABLE_SIZE);
+            let rules = AdjudicationRules::default();
+            let mut worker = MatchWorker::new([engine.clone(), engine], rules, next_game, games);
+            while let Some(index) = worker.next_index() {
+                let mut rng = StdRng::seed_from_u64(seed.wrapping_add(index as u64));
+                let (opening, game) = random_start(&exits, random_plies, &mut rng);
+                let start_plies = game.history.len();
+                let mut side_evals = [Vec::new(), Vec::new()];
+                let [white_evals, black_evals] = side_evals.each_mut();
+                let mut played = worker.start(game, TimeControl::Depth, |[white, black]| {
+                    [player(white, white_evals), player(black, black_evals)]
+                });
+                let outcome = played.play();
+                let game = played.into_game();
+                let [white_evals, black_evals] = side_evals;
+                let mut evals: Vec<_> = white_evals.into_iter().chain(black_evals).collect();
+                evals.sort_by_key(|&(ply, _)| ply);
+                let evals = evals.into_iter().map(|(_, eval)| eval).collect();
+                let finished = SelfPlayGame {
+                    index,
+                    opening,
+                    game,
+                    start_plies,
+                    evals,
+                    outcome,
+                };
+                if tx.send(finished).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    rx
+}
+
+// One side of a self-play game, noting the white-relative evaluation and
+// depth of each of its moves by ply.
+fn player<'a>(
+    engine: &'a mut Engine,
+    evals: &'a mut Vec<(usize, Option<(i32, u32)>)>,
+) -> PlayerKind<'a> {
+    PlayerKind::bot(move |game, _| {
+        let color = game.current_turn;
+        let (mv, depth) = engine.best_move_timed(game, &TimeConfig::fixed_depth(engine.depth))?;
+        let eval = engine.last_score().map(|score| {
+            let white = if color == Color::White { score } else { -score };
+            (white, depth)
+        });
+        evals.push((game.history.len(), eval));
+        Some((mv, engine.last_score()))
+    })
+}
+
+fn run(options: Options) -> Result<(), String> {
+    println!(
+        "{} games at depth {}, seed {}, writing {}",
+        options.games,
+        options.depth,
+        options.seed,
+        options.pgn.display()
+    );
+    let mut labels = match &options.labels {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            Some(BufWriter::new(file))
+        }
+        None => None,
+    };
+    let mut positions = 0;
+    for (done, game) in spawn_workers(&options).iter().enumerate() {
+        append_pgn(&options.pgn, &game.pgn(options.depth))
+            .map_err(|e| format!("{}: {}", options.pgn.display(), e))?;
+        if let Some(out) = &mut labels {
+            for line in game.labels() {
+                writeln!(out, "{}", line).map_err(|e| e.to_string())?;
+                positions += 1;
+            }
+            out.flush().map_err(|e| e.to_string())?;
+        }
+        println!(
+            "Game {:>4}/{}: {} {} ({}, {} plies)",
+            done + 1,
+            options.games,
+            game.outcome.result_str(),
+            game.outcome.termination.reason(),
+            game.opening.eco,
+            game.game.history.len()
+        );
+    }
+    if options.labels.is_some() {
+        println!("{} labelled positions written", positions);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(e) = parse_args(&args).and_then(run) {
+        eprintln!("selfplay: {}\n\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}
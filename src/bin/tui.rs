@@ -0,0 +1,419 @@
+use chessmind::{
+    board::Board,
+    engine::{Engine, TimeConfig, mate_distance},
+    game::{DrawClaim, Game},
+    pieces::{Color, Piece, PieceType},
+    san::{line_to_san, parse_san},
+};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout},
+    style::{Color as TermColor, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+};
+use std::io::{self, IsTerminal};
+
+const HELP: &str = "Enter moves in SAN (Nf3, exd5, O-O) or coordinates (g1f3).
+Commands: undo, new, flip, draw (claim a draw), go (engine plays the side to move), fen, help, quit (or Ctrl-C)";
+
+// Rank label plus eight three-column squares, inside the block's borders.
+const BOARD_WIDTH: u16 = 2 + 8 * 3 + 2;
+// Eight ranks and the file labels, inside the block's borders.
+const BOARD_HEIGHT: u16 = 9 + 2;
+
+fn piece_char(piece: &Piece) -> char {
+    match piece.piece_type {
+        PieceType::King => '♚',
+        PieceType::Queen => '♛',
+        PieceType::Rook => '♜',
+        PieceType::Bishop => '♝',
+        PieceType::Knight => '♞',
+        PieceType::Pawn => '♟',
+    }
+}
+
+fn format_score(score: i32) -> String {
+    match mate_distance(score) {
+        Some(m) => format!("#{}", m),
+        None => format!("{:+.2}", score as f32 / 100.0),
+    }
+}
+
+struct Tui {
+    game: Game,
+    engine: Engine,
+    engine_color: Option<Color>,
+    flipped: bool,
+    // Last engine evaluation from White's point of view, with its depth.
+    eval: Option<(i32, u32)>,
+    message: String,
+    // The line being typed.
+    input: String,
+}
+
+impl Tui {
+    fn new(engine: Engine, engine_color: Option<Color>) -> Self {
+        Self {
+            game: Game::new(),
+            engine,
+            engine_color,
+            flipped: engine_color == Some(Color::White),
+            eval: None,
+            message: HELP.to_string(),
+            input: String::new(),
+        }
+    }
+
+    fn square_span(&self, board: &Board, x: usize, y: usize) -> Span<'static> {
+        let piece = board.get_index(x, y);
+        let symbol = piece.map_or(' ', |p| piece_char(&p));
+        let name = Board::index_to_algebraic(x, y).unwrap_or_default();
+        let last_move = self
+            .game
+            .last_move()
+            .is_some_and(|(s, e)| s == name || e == name);
+        let background = match (last_move, (x + y).is_multiple_of(2)) {
+            (true, _) => 143,
+            (false, true) => 94,
+            (false, false) => 180,
+        };
+        let foreground = match piece.map(|p| p.color) {
+            Some(Color::White) => TermColor::White,
+            _ => TermColor::Black,
+        };
+        Span::styled(
+            format!(" {} ", symbol),
+            Style::new()
+                .fg(foreground)
+                .bg(TermColor::Indexed(background)),
+        )
+    }
+
+    fn board_lines(&self) -> Vec<Line<'static>> {
+        let board = &self.game.board;
+        let ranks: Vec<usize> = if self.flipped {
+            (0..8).collect()
+        } else {
+            (0..8).rev().collect()
+        };
+        let files: Vec<usize> = if self.flipped {
+            (0..8).rev().collect()
+        } else {
+            (0..8).collect()
+        };
+        let mut lines: Vec<Line> = ranks
+            .iter()
+            .map(|&y| {
+                let mut spans = vec![Span::raw(format!("{} ", y + 1))];
+                spans.extend(files.iter().map(|&x| self.square_span(board, x, y)));
+                Line::from(spans)
+            })
+            .collect();
+        let labels: String = files
+            .iter()
+            .map(|&x| format!(" {} ", (b'a' + x as u8) as char))
+            .collect();
+        lines.push(Line::raw(format!("  {}", labels)));
+        lines
+    }
+
+    // The moves in SAN, one numbered pair per line.
+    fn move_lines(&self) -> Vec<Line<'static>> {
+        let game = &self.game;
+        let sans = line_to_san(&game.start_board, game.start_turn, &game.history);
+        let offset = usize::from(game.start_turn == Color::Black);
+        let mut lines = Vec::new();
+        let mut i = 0;
+        while i < sans.len() {
+            let number = (i + offset) / 2 + 1;
+            if i == 0 && offset == 1 {
+                lines.push(Line::raw(format!("{:>3}. ...     {}", number, sans[0])));
+                i += 1;
+                continue;
+            }
+            let black = sans.get(i + 1).map_or("", String::as_str);
+            lines.push(Line::raw(format!("{:>3}. {:<8}{}", number, sans[i], black)));
+            i += 2;
+        }
+        lines
+    }
+
+    fn game_over(&mut self) -> Option<String> {
+        if self.game.legal_moves().is_empty() {
+            return Some(match self.game.result {
+                Some(Color::White) => "1-0, White wins by checkmate".to_string(),
+                Some(Color::Black) => "0-1, Black wins by checkmate".to_string(),
+                None => "1/2-1/2, stalemate".to_string(),
+            });
+        }
+        match self.game.draw {
+            Some(DrawClaim::Repetition) => {
+                return Some("1/2-1/2, threefold repetition claimed".to_string());
+            }
+            Some(DrawClaim::FiftyMoves) => {
+                return Some("1/2-1/2, fifty-move rule claimed".to_string());
+            }
+            None => {}
+        }
+        if self.game.is_fivefold_repetition() {
+            return Some("1/2-1/2, fivefold repetition".to_string());
+        }
+        if self.game.is_seventy_five_move_draw() {
+            return Some("1/2-1/2, seventy-five-move rule".to_string());
+        }
+        if self.game.is_insufficient_material() {
+            return Some("1/2-1/2, insufficient material".to_string());
+        }
+        None
+    }
+
+    fn status_lines(&mut self) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+        if let Some((score, depth)) = self.eval {
+            lines.push(Line::raw(format!(
+                "Eval: {} (depth {})",
+                format_score(score),
+                depth
+            )));
+        }
+        match self.game_over() {
+            Some(result) => lines.push(Line::raw(format!("Game over: {}", result))),
+            None => {
+                let side = if self.game.current_turn == Color::White {
+                    "White"
+                } else {
+                    "Black"
+                };
+                lines.push(Line::raw(format!("{} to move", side)));
+                match self.game.can_claim_draw() {
+                    Some(DrawClaim::Repetition) => {
+                        lines.push(Line::raw("Threefold repetition: type draw to claim"));
+                    }
+                    Some(DrawClaim::FiftyMoves) => {
+                        lines.push(Line::raw("Fifty-move rule: type draw to claim"));
+                    }
+                    None => {}
+                }
+            }
+        }
+        lines.extend(self.message.lines().map(|l| Line::raw(l.to_string())));
+        lines
+    }
+
+    // Board and move list on top, the status below them and the input line
+    // at the bottom.
+    fn draw(&mut self, frame: &mut Frame) {
+        let [top, status, input] = Layout::vertical([
+            Constraint::Length(BOARD_HEIGHT),
+            Constraint::Min(3),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+        let [board, moves] =
+            Layout::horizontal([Constraint::Length(BOARD_WIDTH), Constraint::Min(16)]).areas(top);
+
+        frame.render_widget(
+            Paragraph::new(self.board_lines()).block(Block::bordered().title("Board")),
+            board,
+        );
+        // Only the latest moves that fit.
+        let mut move_lines = self.move_lines();
+        let skip = move_lines
+            .len()
+            .saturating_sub(moves.height.saturating_sub(2) as usize);
+        frame.render_widget(
+            Paragraph::new(move_lines.split_off(skip)).block(Block::bordered().title("Moves")),
+            moves,
+        );
+        frame.render_widget(
+            Paragraph::new(self.status_lines())
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered()),
+            status,
+        );
+        let prompt = format!("> {}", self.input);
+        let cursor = (input.x + 1 + prompt.chars().count() as u16, input.y + 1);
+        frame.render_widget(
+            Paragraph::new(prompt).block(Block::bordered().title("Move")),
+            input,
+        );
+        frame.set_cursor_position(cursor);
+    }
+
+    fn engine_to_move(&mut self) -> bool {
+        self.engine_color == Some(self.game.current_turn) && self.game_over().is_none()
+    }
+
+    fn engine_move(&mut self) {
+        let color = self.game.current_turn;
+        let config = TimeConfig::fixed_depth(self.engine.depth);
+        let Some(((start, end), depth)) = self.engine.best_move_timed(&mut self.game, &config)
+        else {
+            self.message = "The engine found no move".to_string();
+            return;
+        };
+        let san = line_to_san(&self.game.board, color, &[(start.clone(), end.clone())]);
+        self.game.make_move(&start, &end);
+        if let Some(score) = self.engine.last_score() {
+            let white_score = if color == Color::White { score } else { -score };
+            self.eval = Some((white_score, depth));
+        }
+        self.message = format!("Engine played {}", san.join(""));
+    }
+
+    // Accepts coordinates (e2e4, e7e8n) or SAN.
+    fn parse_move(&mut self, input: &str) -> Option<(String, String)> {
+        let legal = self.game.legal_moves();
+        let is_legal = |(start, end): &(String, String)| {
+            legal
+                .iter()
+                .any(|(s, e)| s == start && e.get(..2) == end.get(..2))
+        };
+        if matches!(input.len(), 4 | 5) && input.is_char_boundary(2) && input.is_ascii() {
+            let mv = (input[..2].to_string(), input[2..].to_string());
+            if is_legal(&mv) {
+                return Some(mv);
+            }
+        }
+        let color = self.game.current_turn;
+        parse_san(&mut self.game, input, color)
+            .ok()
+            .filter(is_legal)
+    }
+
+    // Takes back the last move, and the engine's reply before it when playing
+    // against the engine.
+    fn undo(&mut self) {
+        let plies = if self
+            .engine_color
+            .is_some_and(|c| c != self.game.current_turn)
+        {
+            2
+        } else {
+            1
+        };
+        let mut undone = 0;
+        while undone < plies && self.game.undo_move() {
+            undone += 1;
+        }
+        self.engine.new_game();
+        self.eval = None;
+        self.message = match undone {
+            0 => "Nothing to undo".to_string(),
+            _ => format!("Took back {} ply", undone),
+        };
+    }
+
+    // Returns false once the user quits.
+    fn handle(&mut self, input: &str) -> bool {
+        self.message.clear();
+        match input {
+            "" => {}
+            "quit" | "q" | "exit" => return false,
+            "help" | "?" => self.message = HELP.to_string(),
+            "undo" | "u" => self.undo(),
+            "flip" => self.flipped = !self.flipped,
+            "fen" => self.message = self.game.to_fen(),
+            "new" => {
+                self.game = Game::new();
+                self.engine.new_game();
+                self.eval = None;
+            }
+            "go" => self.engine_color = Some(self.game.current_turn),
+            "draw" if self.game_over().is_none() => {
+                if self.game.claim_draw().is_none() {
+                    self.message = "No draw to claim".to_string();
+                }
+            }
+            _ if self.game_over().is_some() => {
+                self.message = "The game is over: undo or new".to_string();
+            }
+            _ => match self.parse_move(input) {
+                Some((start, end)) => {
+                    self.game.make_move(&start, &end);
+                }
+                None => self.message = format!("Illegal or unknown move: {}", input),
+            },
+        }
+        true
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, tui: &mut Tui) -> io::Result<()> {
+    loop {
+        if tui.engine_to_move() {
+            tui.message = "thinking...".to_string();
+            terminal.draw(|frame| tui.draw(frame))?;
+            tui.engine_move();
+        }
+        terminal.draw(|frame| tui.draw(frame))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char(c) => tui.input.push(c),
+            KeyCode::Backspace => {
+                tui.input.pop();
+            }
+            KeyCode::Esc => tui.input.clear(),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut tui.input);
+                if !tui.handle(line.trim()) {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    let mut engine_color = Some(Color::Black);
+    let mut depth = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--engine" => {
+                engine_color = match args.next().as_deref() {
+                    Some("white") => Some(Color::White),
+                    Some("black") => Some(Color::Black),
+                    Some("none") => None,
+                    _ => {
+                        eprintln!("--engine expects white, black or none");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--depth" => depth = args.next().and_then(|d| d.parse().ok()),
+            _ => {
+                eprintln!("Usage: tui [--engine white|black|none] [--depth N]");
+                std::process::exit(1);
+            }
+        }
+    }
+    if !io::stdout().is_terminal() {
+        eprintln!("tui needs a terminal");
+        std::process::exit(1);
+    }
+    let mut engine = Engine::from_env(6, num_cpus::get());
+    if let Some(depth) = depth {
+        engine.depth = depth;
+    }
+    let _ = engine.load_syzygy_from_env();
+
+    let mut tui = Tui::new(engine, engine_color);
+    // Raw mode and the alternate screen, restored on exit and on panic.
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut tui);
+    ratatui::restore();
+    if let Err(e) = result {
+        eprintln!("terminal error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,20 +1,27 @@
 use chessmind::{
-    engine::{Engine, TimeConfig},
+    engine::{Engine, SearchInfo, TimeConfig},
     game::Game,
+    gamelog::{GameLog, engine_settings},
+    pgn::{game_result, to_pgn},
     pieces::Color,
-    san::parse_san,
+    san::{line_to_san, parse_san},
+    uci::{self, UciCommand},
 };
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
 use num_cpus;
 use serde::Deserialize;
 use serde_json;
 use std::env;
-use std::time::Instant;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio_tungstenite::{accept_async, tungstenite::Message};
 
 fn is_coordinate(mv: &str) -> bool {
-    mv.len() >= 4
+    matches!(mv.len(), 4 | 5)
         && mv.as_bytes()[0].is_ascii_lowercase()
         && mv.as_bytes()[1].is_ascii_digit()
         && mv.as_bytes()[2].is_ascii_lowercase()
@@ -116,6 +123,55 @@ enum ClientMsg {
 
     #[serde(rename = "newgame")]
     NewGame,
+
+    // Searches a position without touching the game being played.
+    #[serde(rename = "analyze")]
+    Analyze {
+        fen: String,
+        #[serde(default)]
+        depth: Option<u32>,
+        #[serde(default)]
+        movetime: Option<u64>,
+        #[serde(default)]
+        multipv: Option<usize>,
+    },
+
+    // Loads a Polyglot or text book, turns the book on or off and/or starts
+    // learning from the results of its moves.
+    #[serde(rename = "book")]
+    Book {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        enabled: Option<bool>,
+        // File keeping the results of the book moves played.
+        #[serde(default)]
+        learning: Option<String>,
+    },
+}
+
+// PGN of a game the engine played as `color` against the client at `addr`.
+fn game_record(game: &Game, color: Color, engine: &Engine, addr: std::net::SocketAddr) -> String {
+    let (white, black) = match color {
+        Color::White => ("Chessmind", "Opponent"),
+        Color::Black => ("Opponent", "Chessmind"),
+    };
+    let settings = match color {
+        Color::White => "WhiteSettings",
+        Color::Black => "BlackSettings",
+    };
+    let tags = [
+        ("Event", "Chessmind WebSocket game".to_string()),
+        ("Site", addr.to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", "-".to_string()),
+        ("White", white.to_string()),
+        ("Black", black.to_string()),
+        ("Result", game_result(game).to_string()),
+        (settings, engine_settings(engine)),
+    ]
+    .map(|(key, value)| (key.to_string(), value));
+    to_pgn(game, &tags)
 }
 
 #[tokio::main]
@@ -125,21 +181,48 @@ async fn main() {
     let listener = TcpListener::bind(&addr).await.expect("bind");
     println!("WebSocket server on ws://{}", addr);
     println!("Supports time control: wtime, btime, winc, binc, movestogo, depth, movetime");
+    let engine = Arc::new(shared_engine());
     while let Ok((stream, addr)) = listener.accept().await {
         println!("Client connected: {}", addr);
-        tokio::spawn(handle_conn(stream, addr));
+        tokio::spawn(handle_conn(stream, addr, engine.clone()));
     }
 }
 
-async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr) {
-    let ws_stream = accept_async(stream).await.expect("ws accept");
-    let (mut write, mut read) = ws_stream.split();
-
-    let mut game = Game::new();
+// The engine every connection forks, set up once from the environment so the
+// tablebases, book and transposition table are loaded a single time.
+fn shared_engine() -> Engine {
     let mut engine = Engine::from_env(6, num_cpus::get());
     if let Ok(Some(path)) = engine.load_syzygy_from_env() {
         println!("Loaded Syzygy tablebases from {}", path);
     }
+    match engine.load_book_from_env() {
+        Ok(Some(path)) => println!("Loaded opening book from {}", path),
+        Ok(None) => {}
+        Err(e) => println!("Could not load opening book: {}", e),
+    }
+    if let Ok(path) = env::var("CHESSMIND_BOOK_LEARNING") {
+        match engine.enable_book_learning(&path) {
+            Ok(()) => println!("Learning book results in {}", path),
+            Err(e) => println!("Could not open book learning {}: {}", path, e),
+        }
+    }
+    engine
+}
+
+async fn handle_conn(
+    stream: tokio::net::TcpStream,
+    addr: std::net::SocketAddr,
+    shared: Arc<Engine>,
+) {
+    let ws_stream = accept_async(stream).await.expect("ws accept");
+    let (mut write, mut read) = ws_stream.split();
+
+    let mut game = Game::new();
+    let mut engine = shared.fork();
+    let game_log = GameLog::from_env().unwrap_or_else(|e| {
+        println!("Could not open the game log: {}", e);
+        None
+    });
 
     let mut my_color: Option<Color> = None;
     let mut last_len: usize = 0;
@@ -153,6 +236,13 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
             let txt = msg.to_text().unwrap();
             println!("Received from {}: {}", addr, txt);
 
+            // A client opening with `uci` speaks plain UCI from then on.
+            if txt.trim() == "uci" {
+                println!("{} switched to UCI", addr);
+                run_uci(&mut write, &mut read, engine).await;
+                break;
+            }
+
             if let Ok(data) = serde_json::from_str::<ClientMsg>(txt) {
                 match data {
                     ClientMsg::Color { color } => {
@@ -193,13 +283,20 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
 
                         let mov = mov.replace('+', "").replace('#', "");
                         if is_coordinate(&mov) {
-                            game.make_move(&mov[0..2], &mov[2..4]);
+                            game.make_move(&mov[0..2], &mov[2..]);
                             last_len += 1;
                         } else {
                             let color = game.current_turn;
-                            if let Some((s, e)) = parse_san(&mut game, &mov, color) {
-                                game.make_move(&s, &e);
-                                last_len += 1;
+                            match parse_san(&mut game, &mov, color) {
+                                Ok((s, e)) => {
+                                    game.make_move(&s, &e);
+                                    last_len += 1;
+                                }
+                                Err(e) => {
+                                    println!("Could not read move: {}", e);
+                                    let msg = serde_json::json!({ "error": e.to_string() });
+                                    let _ = write.send(Message::Text(msg.to_string())).await;
+                                }
                             }
                         }
                     }
@@ -214,6 +311,7 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
                         }
 
                         game = Game::new();
+                        let mut error = None;
                         for entry in &moves {
                             let mv = entry.mov.replace('+', "").replace('#', "");
                             let color = if entry.color.to_lowercase().starts_with('w') {
@@ -222,12 +320,25 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
                                 Color::Black
                             };
                             if is_coordinate(&mv) {
-                                game.make_move(&mv[0..2], &mv[2..4]);
-                            } else if let Some((s, e)) = parse_san(&mut game, &mv, color) {
-                                game.make_move(&s, &e);
+                                game.make_move(&mv[0..2], &mv[2..]);
+                            } else {
+                                match parse_san(&mut game, &mv, color) {
+                                    Ok((s, e)) => {
+                                        game.make_move(&s, &e);
+                                    }
+                                    Err(e) => {
+                                        error = Some(e);
+                                        break;
+                                    }
+                                }
                             }
                         }
                         last_len = moves.len();
+                        if let Some(e) = error {
+                            println!("Could not read move list: {}", e);
+                            let msg = serde_json::json!({ "error": e.to_string() });
+                            let _ = write.send(Message::Text(msg.to_string())).await;
+                        }
                     }
 
                     ClientMsg::Go { time } => {
@@ -248,9 +359,13 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
                             game.make_move(&s, &e);
                             last_len += 1;
                             let msg = serde_json::json!({
-                                "next_move": format!("{}{}", s, e),
-                                "time_ms": start_time.elapsed().as_millis()
-                            })
+                                        "next_move": format!("{}{}", s, e),
+                            "san": game.san_history().last(),
+                            "moves": game.san_movetext(),
+                                    "san": game.san_history().last(),
+                                    "moves": game.san_movetext(),
+                                        "time_ms": start_time.elapsed().as_millis()
+                                    })
                             .to_string();
                             let _ = write.send(Message::Text(msg)).await;
                         }
@@ -276,20 +391,75 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
                         println!("New game started");
                         continue;
                     }
+
+                    ClientMsg::Analyze {
+                        fen,
+                        depth,
+                        movetime,
+                        multipv,
+                    } => {
+                        let reply = analyze(&mut engine, &fen, depth, movetime, multipv);
+                        let _ = write.send(Message::Text(reply.to_string())).await;
+                        continue;
+                    }
+
+                    ClientMsg::Book {
+                        path,
+                        enabled,
+                        learning,
+                    } => {
+                        let mut reply = serde_json::json!({});
+                        if let Some(path) = path {
+                            match engine.load_book(&path) {
+                                Ok(()) => println!("Loaded opening book from {}", path),
+                                Err(e) => {
+                                    println!("Could not load opening book {}: {}", path, e);
+                                    reply["error"] = e.to_string().into();
+                                }
+                            }
+                        }
+                        if let Some(enabled) = enabled {
+                            engine.set_book_enabled(enabled);
+                        }
+                        if let Some(path) = learning
+                            && let Err(e) = engine.enable_book_learning(&path)
+                        {
+                            println!("Could not open book learning {}: {}", path, e);
+                            reply["error"] = e.to_string().into();
+                        }
+                        reply["book"] = engine.params.use_book.into();
+                        let _ = write.send(Message::Text(reply.to_string())).await;
+                        continue;
+                    }
                 }
             } else if is_coordinate(txt) {
-                game.make_move(&txt[0..2], &txt[2..4]);
+                game.make_move(&txt[0..2], &txt[2..]);
                 last_len += 1;
             } else {
                 let color = game.current_turn;
-                if let Some((s, e)) = parse_san(&mut game, txt, color) {
-                    game.make_move(&s, &e);
-                    last_len += 1;
+                match parse_san(&mut game, txt, color) {
+                    Ok((s, e)) => {
+                        game.make_move(&s, &e);
+                        last_len += 1;
+                    }
+                    Err(e) => {
+                        println!("Could not read move: {}", e);
+                        let msg = serde_json::json!({ "error": e.to_string() });
+                        let _ = write.send(Message::Text(msg.to_string())).await;
+                    }
                 }
             }
 
             if let Some(color) = my_color {
                 if let Some(res) = game.result {
+                    if let Err(e) = engine.learn_from_game(&game, 0, color, Some(res)) {
+                        println!("Could not save book learning: {}", e);
+                    }
+                    if let Some(log) = &game_log
+                        && let Err(e) = log.record(&game_record(&game, color, &engine, addr))
+                    {
+                        println!("Could not log the game in {}: {}", log.dir().display(), e);
+                    }
                     let result = if res == Color::White {
                         "white"
                     } else {
@@ -327,6 +497,8 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
                     last_len += 1;
                     let msg = serde_json::json!({
                         "next_move": format!("{}{}", s, e),
+                    "san": game.san_history().last(),
+                    "moves": game.san_movetext(),
                         "time_ms": start_time.elapsed().as_millis()
                     })
                     .to_string();
@@ -337,3 +509,190 @@ async fn handle_conn(stream: tokio::net::TcpStream, addr: std::net::SocketAddr)
     }
     println!("Client disconnected: {}", addr);
 }
+
+// Longest search an `analyze` message may ask for.
+const MAX_ANALYSIS_MS: u64 = 60_000;
+
+fn analyze(
+    engine: &mut Engine,
+    fen: &str,
+    depth: Option<u32>,
+    movetime: Option<u64>,
+    multipv: Option<usize>,
+) -> serde_json::Value {
+    let game = match Game::parse_fen(fen) {
+        Ok(game) => game,
+        Err(e) => return serde_json::json!({ "error": format!("invalid FEN {}: {}", fen, e) }),
+    };
+    let config = match movetime {
+        Some(ms) => TimeConfig {
+            depth,
+            ..TimeConfig::fixed_time(ms.min(MAX_ANALYSIS_MS))
+        },
+        None => TimeConfig {
+            movetime: Some(MAX_ANALYSIS_MS),
+            ..TimeConfig::fixed_depth(depth.unwrap_or(engine.depth))
+        },
+    };
+    let info = engine.analyze(&game, &config, multipv.unwrap_or(1).clamp(1, 256), |_| {});
+    let lines: Vec<serde_json::Value> = info
+        .lines
+        .iter()
+        .map(|line| {
+            serde_json::json!({
+                "score": line.score,
+                "mate": line.mate_in(),
+                "moves": line.moves.iter().map(|(s, e)| format!("{}{}", s, e)).collect::<Vec<_>>(),
+                "san": line_to_san(&game.board, game.current_turn, &line.moves),
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "analysis": {
+            "fen": fen,
+            "best_move": info.best().and_then(|l| l.moves.first()).map(|(s, e)| format!("{}{}", s, e)),
+            "depth": info.depth,
+            "seldepth": info.seldepth,
+            "nodes": info.nodes,
+            "time_ms": info.time_ms,
+            "lines": lines,
+        }
+    })
+}
+
+struct UciSearch {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl UciSearch {
+    async fn stop(self) {
+        // The search clears the flag when it starts, so keep raising it until
+        // the thread exits.
+        while !self.handle.is_finished() {
+            self.stop.store(true, Ordering::Release);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let _ = self.handle.await;
+    }
+}
+
+// Answers UCI commands, one or more lines per message, with one message per
+// output line. Searches run on a blocking thread streaming `info` lines, so
+// `stop` and `isready` are handled while they think.
+async fn run_uci<W, R, E>(write: &mut W, read: &mut R, mut engine: Engine)
+where
+    W: Sink<Message> + Unpin,
+    R: Stream<Item = Result<Message, E>> + Unpin,
+{
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let mut game = Game::new();
+    let mut multipv = 1;
+    let mut chess960 = false;
+    let mut search: Option<UciSearch> = None;
+    let mut pending = Some("uci".to_string());
+
+    loop {
+        let text = match pending.take() {
+            Some(text) => text,
+            None => tokio::select! {
+                Some(line) = rx.recv() => {
+                    let _ = write.send(Message::Text(line)).await;
+                    continue;
+                }
+                msg = read.next() => match msg {
+                    Some(Ok(msg)) if msg.is_text() => msg.to_text().unwrap_or_default().to_string(),
+                    Some(Ok(_)) => continue,
+                    _ => break,
+                },
+            },
+        };
+
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut replies: Vec<String> = Vec::new();
+            match uci::parse_command(line) {
+                Ok(UciCommand::Uci) => {
+                    replies.extend(uci::ID.iter().chain(&uci::OPTIONS).map(|l| l.to_string()));
+                    replies.push("uciok".to_string());
+                }
+                Ok(UciCommand::IsReady) => replies.push("readyok".to_string()),
+                Ok(UciCommand::NewGame) => {
+                    if let Some(search) = search.take() {
+                        search.stop().await;
+                    }
+                    game = Game::new();
+                    engine.new_game();
+                }
+                Ok(UciCommand::Position(position)) => game = *position,
+                Ok(UciCommand::SetOption { name, value }) => {
+                    if name.eq_ignore_ascii_case("multipv") {
+                        match value.parse::<usize>() {
+                            Ok(n) => multipv = n.clamp(1, 256),
+                            Err(_) => {
+                                replies.push(format!("info string invalid MultiPV {}", value))
+                            }
+                        }
+                    } else if name.eq_ignore_ascii_case("uci_chess960") {
+                        chess960 = value.eq_ignore_ascii_case("true");
+                    } else {
+                        replies.push(format!("info string unknown option {}", name));
+                    }
+                }
+                Ok(UciCommand::Go(config)) => {
+                    if let Some(search) = search.take() {
+                        search.stop().await;
+                    }
+                    let mut engine = engine.fork();
+                    let game = game.clone();
+                    let tx = tx.clone();
+                    let stop = engine.stop_handle();
+                    let handle = tokio::task::spawn_blocking(move || {
+                        let notation = |info: &SearchInfo| {
+                            if chess960 {
+                                uci::chess960_info(&game, info)
+                            } else {
+                                info.clone()
+                            }
+                        };
+                        let info = engine.analyze(&game, &config, multipv, |info| {
+                            let info = notation(info);
+                            for (i, line) in info.lines.iter().enumerate() {
+                                let _ = tx.send(uci::info_line(&info, i, line));
+                            }
+                        });
+                        let _ = tx.send(uci::bestmove_line(&notation(&info)));
+                    });
+                    search = Some(UciSearch { stop, handle });
+                }
+                Ok(UciCommand::Eval) => replies.extend(
+                    engine
+                        .eval_string(&game.board, game.current_turn)
+                        .lines()
+                        .map(str::to_string),
+                ),
+                Ok(UciCommand::Stop) => {
+                    if let Some(search) = search.take() {
+                        search.stop().await;
+                    }
+                }
+                Ok(UciCommand::Quit) => {
+                    if let Some(search) = search.take() {
+                        search.stop().await;
+                    }
+                    return;
+                }
+                Err(e) => replies.push(format!("info string {}", e)),
+            }
+            // Search output already queued goes out first.
+            let queued = std::iter::from_fn(|| rx.try_recv().ok());
+            for reply in queued.collect::<Vec<_>>().into_iter().chain(replies) {
+                if write.send(Message::Text(reply)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+    if let Some(search) = search.take() {
+        search.stop().await;
+    }
+}
@@ -1,27 +1,111 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 use core::option::Option::None;
 
+use crate::material::{self, MaterialKey};
 use crate::pieces::{Color, Piece, PieceType};
 use crate::transposition::ZOBRIST;
 use crate::types::{Move, UndoState};
 
-#[derive(Clone)]
-pub struct MoveState {
-    pub start: (usize, usize),
-    pub end: (usize, usize),
-    pub captured: Option<Piece>,
-    pub captured_sq: Option<(usize, usize)>,
-    pub prev_en_passant: Option<(usize, usize)>,
-    pub prev_castling: [[bool; 2]; 2],
-    pub rook_move: Option<((usize, usize), (usize, usize))>,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PositionError {
+    MissingKing(Color),
+    TooManyKings(Color),
+    PawnOnBackRank,
+    OpponentInCheck,
+    InvalidCastling,
+}
+
+impl core::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let side = |c: &Color| if *c == Color::White { "White" } else { "Black" };
+        match self {
+            PositionError::MissingKing(c) => write!(f, "{} has no king", side(c)),
+            PositionError::TooManyKings(c) => write!(f, "{} has more than one king", side(c)),
+            PositionError::PawnOnBackRank => {
+                write!(f, "pawns cannot stand on the first or last rank")
+            }
+            PositionError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            PositionError::InvalidCastling => {
+                write!(
+                    f,
+                    "castling rights need the king and rook on their original squares"
+                )
+            }
+        }
+    }
 }
 
+impl core::error::Error for PositionError {}
+
+// Why a FEN string could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenError {
+    Empty,
+    // The placement field has this many ranks instead of 8.
+    RankCount(usize),
+    InvalidPiece(char),
+    // The rank, numbered from 8 down to 1, does not cover exactly 8 squares.
+    RankLength(usize),
+    SideToMove(String),
+    Castling(char),
+    EnPassant(String),
+    Clock(String),
+    // Well formed, but not a position a game can be played from.
+    Position(PositionError),
+}
+
+impl core::fmt::Display for FenError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "empty FEN"),
+            FenError::RankCount(n) => write!(f, "expected 8 ranks, found {}", n),
+            FenError::InvalidPiece(ch) => write!(f, "invalid piece '{}'", ch),
+            FenError::RankLength(rank) => write!(f, "rank {} does not have 8 squares", rank),
+            FenError::SideToMove(side) => write!(f, "invalid side to move \"{}\"", side),
+            FenError::Castling(ch) => write!(f, "invalid castling right '{}'", ch),
+            FenError::EnPassant(square) => write!(f, "invalid en passant square \"{}\"", square),
+            FenError::Clock(value) => write!(f, "invalid move counter \"{}\"", value),
+            FenError::Position(error) => write!(f, "illegal position: {}", error),
+        }
+    }
+}
+
+impl core::error::Error for FenError {}
+
+// Mailbox code of a piece, `color_idx * 6 + piece_index`, or `EMPTY`.
+pub const EMPTY: u8 = 12;
+
+#[inline(always)]
+fn piece_code(piece: Piece) -> u8 {
+    (color_idx(piece.color) * 6 + piece_index(piece.piece_type)) as u8
+}
+
+#[inline(always)]
+fn decode_piece(code: u8) -> Option<Piece> {
+    (code != EMPTY).then(|| Piece {
+        piece_type: PIECE_TYPES[(code % 6) as usize],
+        color: if code < 6 { Color::White } else { Color::Black },
+    })
+}
+
+// The bitboards are the position; the mailbox only answers "what stands on
+// this square" without scanning them.
 #[derive(Clone)]
 pub struct Board {
-    pub squares: [[Option<Piece>; 8]; 8],
+    pub mailbox: [u8; 64],
     pub bitboards: [[u64; 6]; 2],
     pub hash: u64,
+    pub material_key: MaterialKey,
     pub en_passant: Option<(usize, usize)>,
     pub castling: [[bool; 2]; 2],
+    // Files of the rooks each side castles with, king's side first: the
+    // corners, or wherever a Chess960 FEN put them.
+    pub castle_rooks: [[u8; 2]; 2],
+    // Plies since the last capture or pawn move, and the FEN move number.
+    pub halfmove_clock: u16,
+    pub fullmove: u16,
 }
 
 pub fn color_idx(color: Color) -> usize {
@@ -31,6 +115,16 @@ pub fn color_idx(color: Color) -> usize {
     }
 }
 
+// Piece types in bitboard order, the inverse of `piece_index`.
+pub const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
 pub fn piece_index(pt: PieceType) -> usize {
     match pt {
         PieceType::Pawn => 0,
@@ -42,28 +136,102 @@ pub fn piece_index(pt: PieceType) -> usize {
     }
 }
 
-fn sq_mask(x: usize, y: usize) -> u64 {
-    1u64 << (y * 8 + x)
-}
+// Rook files of standard chess, king's side first.
+pub const STANDARD_ROOKS: [[u8; 2]; 2] = [[7, 0], [7, 0]];
+
+// a1, c1, ..., b2, d2, ...
+pub const DARK_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
 
 impl Board {
     pub fn new() -> Self {
         Self {
-            squares: [[None; 8]; 8],
+            mailbox: [EMPTY; 64],
             bitboards: [[0u64; 6]; 2],
             hash: 0,
+            material_key: 0,
             en_passant: None,
             castling: [[true, true], [true, true]],
+            castle_rooks: STANDARD_ROOKS,
+            halfmove_clock: 0,
+            fullmove: 1,
         }
     }
 
-    pub fn setup_standard(&mut self) {
-        self.hash = 0;
-        for y in 0..8 {
-            for x in 0..8 {
-                self.squares[y][x] = None;
+    pub fn empty() -> Self {
+        let mut board = Self::new();
+        board.castling = [[false, false], [false, false]];
+        board
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::empty();
+    }
+
+    // The same position with the colors swapped: mirrored top to bottom,
+    // White's pieces and rights given to Black and the other way round. The
+    // side to move has to be swapped with it.
+    pub fn flipped(&self) -> Board {
+        let mut board = Self::empty();
+        for sq in 0..64 {
+            let code = self.mailbox[sq];
+            if code != EMPTY {
+                board.put_code(sq ^ 56, (code + 6) % 12);
+            }
+        }
+        board.castling = [self.castling[1], self.castling[0]];
+        board.castle_rooks = [self.castle_rooks[1], self.castle_rooks[0]];
+        board.en_passant = self.en_passant.map(|(x, y)| (x, 7 - y));
+        board.halfmove_clock = self.halfmove_clock;
+        board.fullmove = self.fullmove;
+        board
+    }
+
+    pub fn validate(&self, turn: Color) -> Result<(), PositionError> {
+        for color in [Color::White, Color::Black] {
+            match self.bitboards[color_idx(color)][piece_index(PieceType::King)].count_ones() {
+                0 => return Err(PositionError::MissingKing(color)),
+                1 => {}
+                _ => return Err(PositionError::TooManyKings(color)),
             }
         }
+        const BACK_RANKS: u64 = 0xff00_0000_0000_00ff;
+        let pawns = self.bitboards[0][piece_index(PieceType::Pawn)]
+            | self.bitboards[1][piece_index(PieceType::Pawn)];
+        if pawns & BACK_RANKS != 0 {
+            return Err(PositionError::PawnOnBackRank);
+        }
+        let opponent = if turn == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+        if self.in_check_fast(opponent) {
+            return Err(PositionError::OpponentInCheck);
+        }
+        for (cidx, color, rank) in [(0, Color::White, 0), (1, Color::Black, 7)] {
+            let has = |x: usize, pt: PieceType| {
+                self.get_index(x, rank)
+                    == Some(Piece {
+                        piece_type: pt,
+                        color,
+                    })
+            };
+            let king = (0..8).find(|&x| has(x, PieceType::King));
+            for side in 0..2 {
+                let file = self.castle_rooks[cidx][side] as usize;
+                let beside = king.is_some_and(|k| if side == 0 { file > k } else { file < k });
+                if self.castling[cidx][side] && !(beside && has(file, PieceType::Rook)) {
+                    return Err(PositionError::InvalidCastling);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn setup_standard(&mut self) {
+        self.hash = 0;
+        self.material_key = 0;
+        self.mailbox = [EMPTY; 64];
         self.bitboards = [[0u64; 6]; 2];
         let back = [
             PieceType::Rook,
@@ -111,27 +279,45 @@ impl Board {
         }
         self.en_passant = None;
         self.castling = [[true, true], [true, true]];
+        self.castle_rooks = STANDARD_ROOKS;
+        self.halfmove_clock = 0;
+        self.fullmove = 1;
     }
 
     pub fn set_index(&mut self, x: usize, y: usize, piece: Option<Piece>) {
-        let mask = sq_mask(x, y);
-        if let Some(old) = self.squares[y][x] {
-            let c = color_idx(old.color);
-            let p = piece_index(old.piece_type);
-            self.bitboards[c][p] &= !mask;
-            self.hash ^= ZOBRIST[c][p][y * 8 + x];
+        self.put_code(y * 8 + x, piece.map_or(EMPTY, piece_code));
+    }
+
+    // Empties `sq`, returning the code of the piece that stood there.
+    #[inline(always)]
+    fn take_code(&mut self, sq: usize) -> u8 {
+        let code = self.mailbox[sq];
+        if code != EMPTY {
+            let (c, p) = ((code / 6) as usize, (code % 6) as usize);
+            self.bitboards[c][p] &= !(1u64 << sq);
+            self.hash ^= ZOBRIST[c][p][sq];
+            self.material_key -= material::unit(c, p);
+            self.mailbox[sq] = EMPTY;
         }
-        self.squares[y][x] = piece;
-        if let Some(pce) = piece {
-            let c = color_idx(pce.color);
-            let p = piece_index(pce.piece_type);
-            self.bitboards[c][p] |= mask;
-            self.hash ^= ZOBRIST[c][p][y * 8 + x];
+        code
+    }
+
+    // Places the piece `code` on `sq`, replacing whatever stood there.
+    #[inline(always)]
+    fn put_code(&mut self, sq: usize, code: u8) {
+        self.take_code(sq);
+        if code != EMPTY {
+            let (c, p) = ((code / 6) as usize, (code % 6) as usize);
+            self.bitboards[c][p] |= 1u64 << sq;
+            self.hash ^= ZOBRIST[c][p][sq];
+            self.material_key += material::unit(c, p);
+            self.mailbox[sq] = code;
         }
     }
 
+    #[inline(always)]
     pub fn get_index(&self, x: usize, y: usize) -> Option<Piece> {
-        self.squares[y][x]
+        decode_piece(self.mailbox[y * 8 + x])
     }
 
     pub fn get(&self, pos: &str) -> Option<Piece> {
@@ -151,111 +337,45 @@ impl Board {
         }
     }
 
-    pub fn make_move_state(&mut self, start: &str, end: &str) -> Option<MoveState> {
+    // `end` may carry a promotion letter ("e8n"); pawns reaching the last
+    // rank without one promote to a queen.
+    pub fn make_move_state(&mut self, start: &str, end: &str) -> Option<UndoState> {
         let (sx, sy) = Self::algebraic_to_index(start)?;
-        let (ex, ey) = Self::algebraic_to_index(end)?;
-        let piece = self.get_index(sx, sy)?;
-        let captured = self.get_index(ex, ey);
-        let mut captured_sq = if captured.is_some() {
-            Some((ex, ey))
-        } else {
-            None
-        };
-        let prev_ep = self.en_passant;
-        let prev_castling = self.castling;
-        let mut rook_move = None;
-
-        let cidx = color_idx(piece.color);
-        match piece.piece_type {
-            PieceType::King => {
-                self.castling[cidx] = [false, false];
-                if (sx as isize - ex as isize).abs() == 2 {
-                    if ex == 6 {
-                        rook_move = Some(((7, sy), (5, sy)));
-                        let rook = self.get_index(7, sy);
-                        self.set_index(5, sy, rook);
-                        self.set_index(7, sy, None);
-                    } else if ex == 2 {
-                        rook_move = Some(((0, sy), (3, sy)));
-                        let rook = self.get_index(0, sy);
-                        self.set_index(3, sy, rook);
-                        self.set_index(0, sy, None);
-                    }
-                }
-            }
-            PieceType::Rook => {
-                if sx == 0 {
-                    self.castling[cidx][1] = false;
-                }
-                if sx == 7 {
-                    self.castling[cidx][0] = false;
-                }
-            }
-            _ => {}
-        }
+        let (dest, promo) = Self::split_promotion(end)?;
+        let (ex, ey) = Self::algebraic_to_index(dest)?;
+        self.make_move_sq((sy * 8 + sx) as u8, (ey * 8 + ex) as u8, promo)
+    }
 
-        self.en_passant = None;
-        if piece.piece_type == PieceType::Pawn {
-            let dir_y: isize = if piece.color == Color::White { 1 } else { -1 };
-            if (sy as isize + 2 * dir_y) as usize == ey
-                && sx == ex
-                && self.get_index(ex, ey).is_none()
-            {
-                self.en_passant = Some((sx, (sy as isize + dir_y) as usize));
-            }
-            if let Some((epx, epy)) = prev_ep {
-                if ex == epx && ey == epy && self.get_index(ex, ey).is_none() {
-                    let cap_y = if piece.color == Color::White {
-                        ey - 1
-                    } else {
-                        ey + 1
-                    };
-                    let cap = self.get_index(ex, cap_y);
-                    self.set_index(ex, cap_y, None);
-                    self.set_index(ex, ey, Some(piece));
-                    self.set_index(sx, sy, None);
-                    captured_sq = Some((ex, cap_y));
-                    return Some(MoveState {
-                        start: (sx, sy),
-                        end: (ex, ey),
-                        captured: cap,
-                        captured_sq,
-                        prev_en_passant: prev_ep,
-                        prev_castling,
-                        rook_move,
-                    });
-                }
-            }
+    // Plays a move given by square indices, see `encode_move`. Returns `None`
+    // when `from` is empty.
+    pub fn make_move_sq(
+        &mut self,
+        from: u8,
+        to: u8,
+        promo: Option<PieceType>,
+    ) -> Option<UndoState> {
+        if self.mailbox[from as usize] == EMPTY {
+            return None;
         }
+        Some(self.make(self.encode_move(from, to, promo)))
+    }
 
-        self.set_index(ex, ey, Some(piece));
-        self.set_index(sx, sy, None);
-
-        Some(MoveState {
-            start: (sx, sy),
-            end: (ex, ey),
-            captured,
-            captured_sq,
-            prev_en_passant: prev_ep,
-            prev_castling,
-            rook_move,
-        })
+    pub fn unmake_move(&mut self, state: UndoState) {
+        self.unmake(state.mv, state);
     }
 
-    pub fn unmake_move(&mut self, state: MoveState) {
-        let moving = self.get_index(state.end.0, state.end.1);
-        self.set_index(state.start.0, state.start.1, moving);
-        self.set_index(state.end.0, state.end.1, None);
-        if let Some((cx, cy)) = state.captured_sq {
-            self.set_index(cx, cy, state.captured);
-        }
-        if let Some(((rsx, rsy), (rex, rey))) = state.rook_move {
-            let rook = self.get_index(rex, rey);
-            self.set_index(rsx, rsy, rook);
-            self.set_index(rex, rey, None);
-        }
-        self.en_passant = state.prev_en_passant;
-        self.castling = state.prev_castling;
+    // Splits a destination such as "e8" or "e8n" into the square and the
+    // promotion piece named by the suffix.
+    pub fn split_promotion(end: &str) -> Option<(&str, Option<PieceType>)> {
+        let promo = match end.get(2..) {
+            Some("") => None,
+            Some("n" | "N") => Some(PieceType::Knight),
+            Some("b" | "B") => Some(PieceType::Bishop),
+            Some("r" | "R") => Some(PieceType::Rook),
+            Some("q" | "Q") => Some(PieceType::Queen),
+            _ => return None,
+        };
+        Some((end.get(0..2)?, promo))
     }
 
     pub fn algebraic_to_index(pos: &str) -> Option<(usize, usize)> {
@@ -379,23 +499,10 @@ impl Board {
                         }
                     }
                 }
-                let rank = if color == Color::White { 0 } else { 7 };
-                let cidx = color_idx(color);
-                if self.castling[cidx][0]
-                    && self.get_index(5, rank).is_none()
-                    && self.get_index(6, rank).is_none()
-                {
-                    if let Some(s) = Self::index_to_algebraic(6, rank) {
-                        moves.push(s);
-                    }
-                }
-                if self.castling[cidx][1]
-                    && self.get_index(1, rank).is_none()
-                    && self.get_index(2, rank).is_none()
-                    && self.get_index(3, rank).is_none()
-                {
-                    if let Some(s) = Self::index_to_algebraic(2, rank) {
-                        moves.push(s);
+                for kingside in [true, false] {
+                    if self.can_castle(color, kingside) {
+                        let castle = self.castle_move(color, kingside);
+                        moves.push(self.move_to_strings(castle).1);
                     }
                 }
             }
@@ -496,35 +603,8 @@ impl Board {
         false
     }
 
-    pub fn in_check(&mut self, color: Color) -> bool {
-        let king_sq = self.find_king(color);
-        if king_sq.is_none() {
-            return false;
-        }
-        let k = king_sq.unwrap();
-        let opp = if color == Color::White {
-            Color::Black
-        } else {
-            Color::White
-        };
-        for y in 0..8 {
-            for x in 0..8 {
-                if let Some(p) = self.get_index(x, y) {
-                    if p.color == opp {
-                        if let Some(pos) = Self::index_to_algebraic(x, y) {
-                            for m in self.pseudo_legal_moves(&pos) {
-                                if let Some((tx, ty)) = Self::algebraic_to_index(&m) {
-                                    if tx == k.0 && ty == k.1 {
-                                        return true;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        false
+    pub fn in_check(&self, color: Color) -> bool {
+        self.in_check_fast(color)
     }
 
     pub fn find_king(&self, color: Color) -> Option<(usize, usize)> {
@@ -541,57 +621,141 @@ impl Board {
     }
 
     pub fn is_legal(&mut self, start: &str, end: &str, color: Color) -> bool {
-        let (sx, sy) = match Self::algebraic_to_index(start) {
-            Some(v) => v,
-            None => return false,
+        match (
+            Self::algebraic_to_index(start),
+            Self::split_promotion(end).and_then(|(dest, _)| Self::algebraic_to_index(dest)),
+        ) {
+            (Some((sx, sy)), Some((ex, ey))) => {
+                self.is_legal_sq((sy * 8 + sx) as u8, (ey * 8 + ex) as u8, color)
+            }
+            _ => false,
+        }
+    }
+
+    // Whether `color` may castle on the king's (`kingside`) or queen's side
+    // right now: the right is kept, king and rook stand on their original
+    // squares, nothing but the two of them stands on the squares they cross
+    // or land on, and the king is not in check and does not pass through or
+    // land on an attacked square. In Chess960 the rook may have been covering
+    // the king's destination, so the played move is still checked for that.
+    pub fn can_castle(&self, color: Color, kingside: bool) -> bool {
+        let cidx = color_idx(color);
+        let rank: u8 = if color == Color::White { 0 } else { 56 };
+        let king_bb = self.pieces_of(color, PieceType::King);
+        let rook = rank + self.castle_rooks[cidx][if kingside { 0 } else { 1 }];
+        if !self.castling[cidx][if kingside { 0 } else { 1 }]
+            || king_bb & (0xff << rank) == 0
+            || self.piece_at_sq(rook) != Some((PieceType::Rook, color))
+        {
+            return false;
+        }
+        let king = king_bb.trailing_zeros() as u8;
+        let (king_to, rook_to) = if kingside {
+            (rank + 6, rank + 5)
+        } else {
+            (rank + 2, rank + 3)
         };
-        let (ex, ey) = match Self::algebraic_to_index(end) {
-            Some(v) => v,
-            None => return false,
+        let span = |a: u8, b: u8| a.min(b)..=a.max(b);
+        let opp = if color == Color::White {
+            Color::Black
+        } else {
+            Color::White
         };
+        span(king, king_to)
+            .chain(span(rook, rook_to))
+            .all(|sq| sq == king || sq == rook || self.piece_at_sq(sq).is_none())
+            && span(king, king_to).all(|sq| !self.is_square_attacked_by(sq, opp))
+    }
 
-        let piece = match self.get_index(sx, sy) {
-            Some(p) => p,
-            None => return false,
-        };
-        if piece.color != color {
-            return false;
+    // The castling move of `color` to the king's or queen's side, which
+    // moves the king to the g- or c-file. Only meaningful while the right is
+    // kept.
+    pub fn castle_move(&self, color: Color, kingside: bool) -> Move {
+        let king = self.pieces_of(color, PieceType::King).trailing_zeros() as u8;
+        let rank = king / 8 * 8;
+        if kingside {
+            Move::new(king, rank + 6, Move::FLAG_KING_CASTLE)
+        } else {
+            Move::new(king, rank + 2, Move::FLAG_QUEEN_CASTLE)
         }
+    }
 
-        if let Some(dest) = self.get_index(ex, ey) {
-            if dest.color == color {
-                return false;
-            }
+    // Where the rook castling with `mv` starts and ends.
+    pub fn castle_rook(&self, mv: Move) -> (u8, u8) {
+        let rank = mv.from_sq() / 8 * 8;
+        let cidx = if rank == 0 { 0 } else { 1 };
+        if mv.flags() == Move::FLAG_KING_CASTLE {
+            (rank + self.castle_rooks[cidx][0], rank + 5)
+        } else {
+            (rank + self.castle_rooks[cidx][1], rank + 3)
         }
+    }
 
-        let is_castle =
-            piece.piece_type == PieceType::King && (sx as isize - ex as isize).abs() == 2;
-        if is_castle {
-            if self.in_check(color) {
+    // Whether `color` may play the pseudo-legal move `from`-`to` without
+    // leaving its king in check, castling through check included.
+    pub fn is_legal_sq(&mut self, from: u8, to: u8, color: Color) -> bool {
+        match self.piece_at_sq(from) {
+            Some((_, c)) if c == color => {}
+            _ => return false,
+        }
+        let mv = self.encode_move(from, to, None);
+        if mv.is_castle() {
+            if !self.can_castle(color, mv.flags() == Move::FLAG_KING_CASTLE) {
                 return false;
             }
-            let step = if ex > sx { 1 } else { -1 };
-            let opp = if color == Color::White {
-                Color::Black
-            } else {
-                Color::White
-            };
-            let mut x = sx as isize + step;
-            while x != ex as isize {
-                if self.square_attacked(x as usize, sy, opp) {
-                    return false;
-                }
-                x += step;
+        } else if let Some((_, c)) = self.piece_at_sq(to)
+            && c == color
+        {
+            return false;
+        }
+        self.keeps_king_safe(mv, color)
+    }
+
+    // Whether playing `mv` leaves the king of `color` out of check.
+    pub fn keeps_king_safe(&mut self, mv: Move, color: Color) -> bool {
+        let state = self.make(mv);
+        let check = self.in_check_fast(color);
+        self.unmake(mv, state);
+        !check
+    }
+
+    // `mv` as the squares the string moves use, with a promotion letter.
+    // Castling is the king's two-square move when king and rook start on
+    // the squares of standard chess, and the king taking its own rook
+    // otherwise: in Chess960 the king's destination may be its own square
+    // or one a plain king move also reaches.
+    pub fn move_to_strings(&self, mv: Move) -> (String, String) {
+        let name = |sq: u8| Self::index_to_algebraic((sq % 8) as usize, (sq / 8) as usize);
+        let mut to = mv.to_sq();
+        if mv.is_castle() {
+            let (rook, _) = self.castle_rook(mv);
+            if mv.from_sq() % 8 != 4 || !matches!(rook % 8, 0 | 7) {
+                to = rook;
             }
         }
+        let mut end = name(to).unwrap_or_default();
+        if let Some(pt) = mv.promotion_piece() {
+            end.push(match pt {
+                PieceType::Knight => 'n',
+                PieceType::Bishop => 'b',
+                PieceType::Rook => 'r',
+                _ => 'q',
+            });
+        }
+        (name(mv.from_sq()).unwrap_or_default(), end)
+    }
 
-        if let Some(state) = self.make_move_state(start, end) {
-            let check = self.in_check(color);
-            self.unmake_move(state);
-            !check
-        } else {
-            false
+    // UCI text of `mv`. Under the Chess960 convention (`UCI_Chess960`)
+    // castling is always written as the king taking its own rook: "e1h1"
+    // for O-O.
+    pub fn move_to_uci(&self, mv: Move, chess960: bool) -> String {
+        let (from, mut to) = self.move_to_strings(mv);
+        if chess960 && mv.is_castle() {
+            let (rook, _) = self.castle_rook(mv);
+            to = Self::index_to_algebraic((rook % 8) as usize, (rook / 8) as usize)
+                .unwrap_or_default();
         }
+        format!("{}{}", from, to)
     }
 
     pub fn all_legal_moves(&mut self, color: Color) -> Vec<(String, String)> {
@@ -642,17 +806,9 @@ impl Board {
         res
     }
 
+    // Captures, queen promotions and en passant.
     pub fn capture_moves_fast(&mut self, color: Color) -> Vec<(String, String)> {
-        self.all_legal_moves_fast(color)
-            .into_iter()
-            .filter(|(_, e)| {
-                if let Some((ex, ey)) = Board::algebraic_to_index(e) {
-                    self.get_index(ex, ey).is_some()
-                } else {
-                    false
-                }
-            })
-            .collect()
+        crate::movegen::generate_captures(self, color)
     }
 
     pub fn piece_count(&self, piece_type: PieceType) -> usize {
@@ -689,11 +845,22 @@ impl Board {
     }
 
     pub fn piece_count_all(&self) -> usize {
-        self.bitboards
-            .iter()
-            .flat_map(|b| b.iter())
-            .map(|bb| bb.count_ones() as usize)
-            .sum()
+        material::piece_count(self.material_key) as usize
+    }
+
+    // Neither side can checkmate: too little material, or only one bishop
+    // each with both on squares of the same color.
+    pub fn insufficient_material(&self) -> bool {
+        let key = self.material_key;
+        if material::is_insufficient(key) {
+            return true;
+        }
+        let bishops = material::unit(0, 2) + material::unit(1, 2);
+        if key != material::unit(0, 5) + material::unit(1, 5) + bishops {
+            return false;
+        }
+        let on_dark = |color| self.pieces_of(color, PieceType::Bishop) & DARK_SQUARES != 0;
+        on_dark(Color::White) == on_dark(Color::Black)
     }
 
     pub fn to_fen(&self, turn: Color) -> String {
@@ -732,18 +899,34 @@ impl Board {
         fen.push(' ');
         fen.push(if turn == Color::White { 'w' } else { 'b' });
         fen.push(' ');
+        // X-FEN: KQkq name the outermost rook on their side of the king, the
+        // rook's file any other one.
         let mut castle = String::new();
-        if self.castling[0][0] {
-            castle.push('K');
-        }
-        if self.castling[0][1] {
-            castle.push('Q');
-        }
-        if self.castling[1][0] {
-            castle.push('k');
-        }
-        if self.castling[1][1] {
-            castle.push('q');
+        for (cidx, color, rank) in [(0, Color::White, 0), (1, Color::Black, 7)] {
+            for (side, letter) in [(0, 'k'), (1, 'q')] {
+                if !self.castling[cidx][side] {
+                    continue;
+                }
+                let file = self.castle_rooks[cidx][side] as usize;
+                let outside = if side == 0 { file + 1..8 } else { 0..file };
+                let outermost = !outside.into_iter().any(|x| {
+                    self.get_index(x, rank)
+                        == Some(Piece {
+                            piece_type: PieceType::Rook,
+                            color,
+                        })
+                });
+                let ch = if outermost {
+                    letter
+                } else {
+                    (b'a' + file as u8) as char
+                };
+                castle.push(if cidx == 0 {
+                    ch.to_ascii_uppercase()
+                } else {
+                    ch
+                });
+            }
         }
         if castle.is_empty() {
             castle.push('-');
@@ -759,21 +942,202 @@ impl Board {
         } else {
             fen.push('-');
         }
-        fen.push_str(" 0 1");
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove));
         fen
     }
 
+    pub fn from_fen(fen: &str) -> Option<(Self, Color)> {
+        Self::parse_fen(fen).ok()
+    }
+
+    // Position and side to move of a FEN. Only the placement is required: the
+    // other fields default to White to move, no castling, no en passant
+    // square and the clocks of a fresh game.
+    pub fn parse_fen(fen: &str) -> Result<(Self, Color), FenError> {
+        let mut parts = fen.split_whitespace();
+        let placement = parts.next().ok_or(FenError::Empty)?;
+        let mut board = Board::new();
+        board.castling = [[false, false], [false, false]];
+
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let y = 7 - i;
+            let mut x = 0;
+            for ch in rank.chars() {
+                if let Some(d) = ch.to_digit(10) {
+                    x += d as usize;
+                    continue;
+                }
+                let piece_type = match ch.to_ascii_lowercase() {
+                    'p' => PieceType::Pawn,
+                    'n' => PieceType::Knight,
+                    'b' => PieceType::Bishop,
+                    'r' => PieceType::Rook,
+                    'q' => PieceType::Queen,
+                    'k' => PieceType::King,
+                    _ => return Err(FenError::InvalidPiece(ch)),
+                };
+                let color = if ch.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                if x >= 8 {
+                    return Err(FenError::RankLength(y + 1));
+                }
+                board.set_index(x, y, Some(Piece { piece_type, color }));
+                x += 1;
+            }
+            if x != 8 {
+                return Err(FenError::RankLength(y + 1));
+            }
+        }
+
+        let turn = match parts.next().unwrap_or("w") {
+            "w" => Color::White,
+            "b" => Color::Black,
+            side => return Err(FenError::SideToMove(side.to_string())),
+        };
+
+        for ch in parts.next().unwrap_or("-").chars() {
+            if ch == '-' {
+                continue;
+            }
+            let (cidx, color, rank) = if ch.is_ascii_uppercase() {
+                (0, Color::White, 0)
+            } else {
+                (1, Color::Black, 7)
+            };
+            let holds = |x: usize, piece_type| {
+                board.get_index(x, rank) == Some(Piece { piece_type, color })
+            };
+            let king = (0..8).find(|&x| holds(x, PieceType::King));
+            let (side, file) = match ch.to_ascii_lowercase() {
+                // X-FEN: the outermost rook on that side of the king.
+                'k' => (
+                    0,
+                    (king.unwrap_or(0)..8)
+                        .rev()
+                        .find(|&x| holds(x, PieceType::Rook)),
+                ),
+                'q' => (
+                    1,
+                    (0..king.unwrap_or(8)).find(|&x| holds(x, PieceType::Rook)),
+                ),
+                // Shredder-FEN names the rook's file.
+                file @ 'a'..='h' => {
+                    let file = (file as u8 - b'a') as usize;
+                    let kingside = king.map_or(file >= 4, |k| file > k);
+                    (if kingside { 0 } else { 1 }, Some(file))
+                }
+                _ => return Err(FenError::Castling(ch)),
+            };
+            // A right whose rook is gone can never be used, so it is dropped.
+            if let Some(x) = file.filter(|&x| holds(x, PieceType::Rook)) {
+                board.castling[cidx][side] = true;
+                board.castle_rooks[cidx][side] = x as u8;
+            }
+        }
+
+        match parts.next().unwrap_or("-") {
+            "-" => {}
+            ep => {
+                let square = Self::algebraic_to_index(ep)
+                    .filter(|&(_, y)| y == 2 || y == 5)
+                    .ok_or_else(|| FenError::EnPassant(ep.to_string()))?;
+                board.en_passant = Some(square);
+            }
+        }
+        let clock = |value: Option<&str>, default: u16| -> Result<u16, FenError> {
+            value.map_or(Ok(default), |n| {
+                n.parse().map_err(|_| FenError::Clock(n.to_string()))
+            })
+        };
+        board.halfmove_clock = clock(parts.next(), 0)?;
+        board.fullmove = clock(parts.next(), 1)?;
+
+        Ok((board, turn))
+    }
+
+    // The flagged move from `from` to `to` in this position. Pawns reaching
+    // the last rank promote to `promo`, a queen when it is not set.
+    pub fn encode_move(&self, from: u8, to: u8, promo: Option<PieceType>) -> Move {
+        let capture = self.mailbox[to as usize] != EMPTY;
+        let ep_target = self.en_passant.map(|(x, y)| (y * 8 + x) as u8);
+        let flags = match self.piece_at_sq(from) {
+            Some((PieceType::Pawn, _)) => {
+                if to / 8 == 0 || to / 8 == 7 {
+                    return Move::promotion(from, to, promo.unwrap_or(PieceType::Queen), capture);
+                }
+                if from.abs_diff(to) == 16 {
+                    Move::FLAG_DOUBLE_PUSH
+                } else if ep_target == Some(to) && from % 8 != to % 8 {
+                    Move::FLAG_EP_CAPTURE
+                } else if capture {
+                    Move::FLAG_CAPTURE
+                } else {
+                    Move::FLAG_NORMAL
+                }
+            }
+            // Castling is the king's two-square move from the e-file or the
+            // king taking the rook it castles with.
+            Some((PieceType::King, color)) => {
+                let cidx = color_idx(color);
+                let rank = from / 8 * 8;
+                let takes_rook = |side: usize| {
+                    to == rank + self.castle_rooks[cidx][side]
+                        && self.piece_at_sq(to) == Some((PieceType::Rook, color))
+                };
+                let two_squares = from % 8 == 4 && from.abs_diff(to) == 2;
+                if takes_rook(0) || two_squares && to > from {
+                    return Move::new(from, rank + 6, Move::FLAG_KING_CASTLE);
+                } else if takes_rook(1) || two_squares {
+                    return Move::new(from, rank + 2, Move::FLAG_QUEEN_CASTLE);
+                } else if capture {
+                    Move::FLAG_CAPTURE
+                } else {
+                    Move::FLAG_NORMAL
+                }
+            }
+            _ if capture => Move::FLAG_CAPTURE,
+            _ => Move::FLAG_NORMAL,
+        };
+        Move::new(from, to, flags)
+    }
+
+    // Plays `mv` by its flags (castling, en passant, double push, promotion)
+    // for the side owning the piece on its origin square.
     #[inline]
-    pub fn make_move_fast(&mut self, mv: Move, color: Color) -> UndoState {
-        let from_sq = mv.from_sq();
-        let to_sq = mv.to_sq();
-        let from_x = (from_sq % 8) as usize;
-        let from_y = (from_sq / 8) as usize;
-        let to_x = (to_sq % 8) as usize;
-        let to_y = (to_sq / 8) as usize;
+    pub fn make(&mut self, mv: Move) -> UndoState {
+        let color = if self.mailbox[mv.from_sq() as usize] < 6 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        self.make_move_fast(mv, color)
+    }
 
-        let piece = self.get_index(from_x, from_y).unwrap();
-        let captured = self.get_index(to_x, to_y);
+    // Takes back `mv`, played by `make` with `state` as its result.
+    #[inline]
+    pub fn unmake(&mut self, mv: Move, state: UndoState) {
+        debug_assert!(state.mv == mv);
+        let color = if self.mailbox[mv.to_sq() as usize] < 6 {
+            Color::White
+        } else {
+            Color::Black
+        };
+        self.unmake_move_fast(state, color);
+    }
+
+    #[inline]
+    pub fn make_move_fast(&mut self, mv: Move, color: Color) -> UndoState {
+        let from = mv.from_sq() as usize;
+        let to = mv.to_sq() as usize;
+        let from_x = from % 8;
+        let cidx = color_idx(color);
 
         let prev_ep = self
             .en_passant
@@ -781,91 +1145,83 @@ impl Board {
             .unwrap_or(UndoState::NO_EP);
         let prev_castling = self.pack_castling();
         let prev_hash = self.hash;
-
+        let prev_halfmove = self.halfmove_clock;
+
+        let moving = self.take_code(from);
+        // The rook is lifted before anything lands: in Chess960 it may stand
+        // on the king's destination.
+        let castle_rook = mv.is_castle().then(|| {
+            let (rook_from, rook_to) = self.castle_rook(mv);
+            (self.take_code(rook_from as usize), rook_to as usize)
+        });
+        let captured = self.mailbox[to];
         let mut captured_piece_idx = UndoState::NO_CAPTURE;
-        let mut captured_sq = to_sq;
+        let mut captured_sq = to as u8;
 
         if mv.is_ep() {
-            let cap_y = if color == Color::White {
-                to_y - 1
+            let cap = if color == Color::White {
+                to - 8
             } else {
-                to_y + 1
+                to + 8
             };
-            captured_sq = (cap_y * 8 + to_x) as u8;
-            let cap_piece = self.get_index(to_x, cap_y).unwrap();
-            captured_piece_idx = piece_index(cap_piece.piece_type) as u8;
-            self.set_index(to_x, cap_y, None);
-        } else if let Some(cap) = captured {
-            captured_piece_idx = piece_index(cap.piece_type) as u8;
+            captured_sq = cap as u8;
+            let code = self.take_code(cap);
+            if code != EMPTY {
+                captured_piece_idx = code % 6;
+            }
+        } else if captured != EMPTY {
+            captured_piece_idx = captured % 6;
         }
 
-        let cidx = color_idx(color);
-        match piece.piece_type {
-            PieceType::King => {
+        if moving.is_multiple_of(6) || captured_piece_idx != UndoState::NO_CAPTURE {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if color == Color::Black {
+            self.fullmove += 1;
+        }
+
+        match (moving % 6) as usize {
+            5 => {
                 self.castling[cidx] = [false, false];
             }
-            PieceType::Rook => {
-                if from_x == 0 {
-                    self.castling[cidx][1] = false; // Queen-side
-                }
-                if from_x == 7 {
-                    self.castling[cidx][0] = false; // King-side
+            3 => {
+                let rank = cidx * 56;
+                for side in 0..2 {
+                    if from == rank + self.castle_rooks[cidx][side] as usize {
+                        self.castling[cidx][side] = false;
+                    }
                 }
             }
             _ => {}
         }
 
-        if captured.is_some() {
+        if captured != EMPTY {
             let opp = 1 - cidx;
-            if to_x == 0 && (to_y == 0 || to_y == 7) {
-                let rank = if opp == 0 { 0 } else { 7 };
-                if to_y == rank {
-                    self.castling[opp][1] = false;
-                }
-            }
-            if to_x == 7 && (to_y == 0 || to_y == 7) {
-                let rank = if opp == 0 { 0 } else { 7 };
-                if to_y == rank {
-                    self.castling[opp][0] = false;
+            let rank = opp * 56;
+            for side in 0..2 {
+                if to == rank + self.castle_rooks[opp][side] as usize {
+                    self.castling[opp][side] = false;
                 }
             }
         }
 
         self.en_passant = None;
 
-        if mv.is_castle() {
-            let rank = from_y;
-            if mv.flags() == Move::FLAG_KING_CASTLE {
-                let rook = self.get_index(7, rank);
-                self.set_index(5, rank, rook);
-                self.set_index(7, rank, None);
-            } else {
-                let rook = self.get_index(0, rank);
-                self.set_index(3, rank, rook);
-                self.set_index(0, rank, None);
-            }
+        if let Some((rook, rook_to)) = castle_rook {
+            self.put_code(rook_to, rook);
         }
 
         if mv.is_double_push() {
-            let ep_y = if color == Color::White {
-                from_y + 1
-            } else {
-                from_y - 1
-            };
-            self.en_passant = Some((from_x, ep_y));
+            self.en_passant = Some((from_x, (from + to) / 16));
         }
 
-        let moving_piece = if let Some(promo_type) = mv.promotion_piece() {
-            Piece {
-                piece_type: promo_type,
-                color,
-            }
-        } else {
-            piece
+        let placed = match mv.promotion_piece() {
+            Some(promo_type) => (cidx * 6 + piece_index(promo_type)) as u8,
+            None => moving,
         };
-
-        self.set_index(to_x, to_y, Some(moving_piece));
-        self.set_index(from_x, from_y, None);
+        self.put_code(to, placed);
 
         UndoState {
             mv,
@@ -874,59 +1230,36 @@ impl Board {
             prev_ep,
             prev_castling,
             prev_hash,
+            prev_halfmove,
         }
     }
 
     #[inline]
     pub fn unmake_move_fast(&mut self, state: UndoState, color: Color) {
         let mv = state.mv;
-        let from_sq = mv.from_sq();
-        let to_sq = mv.to_sq();
-        let from_x = (from_sq % 8) as usize;
-        let from_y = (from_sq / 8) as usize;
-        let to_x = (to_sq % 8) as usize;
-        let to_y = (to_sq / 8) as usize;
-
-        let mut moving_piece = self.get_index(to_x, to_y).unwrap();
+        let from = mv.from_sq() as usize;
+        let to = mv.to_sq() as usize;
+        let cidx = color_idx(color);
 
-        if mv.is_promotion() {
-            moving_piece.piece_type = PieceType::Pawn;
+        if mv.is_castle() {
+            // Both pieces are lifted first, as either may go back to where
+            // the other one landed.
+            let (rook_from, rook_to) = self.castle_rook(mv);
+            let king = self.take_code(to);
+            let rook = self.take_code(rook_to as usize);
+            self.put_code(from, king);
+            self.put_code(rook_from as usize, rook);
+        } else {
+            let mut moving = self.take_code(to);
+            if mv.is_promotion() {
+                moving = (cidx * 6) as u8;
+            }
+            self.put_code(from, moving);
         }
 
-        self.set_index(from_x, from_y, Some(moving_piece));
-        self.set_index(to_x, to_y, None);
-
         if state.has_capture() {
-            let cap_sq = state.captured_sq;
-            let cap_x = (cap_sq % 8) as usize;
-            let cap_y = (cap_sq / 8) as usize;
-            let opp_color = if color == Color::White {
-                Color::Black
-            } else {
-                Color::White
-            };
-            let cap_type = Self::piece_type_from_idx(state.captured as usize);
-            self.set_index(
-                cap_x,
-                cap_y,
-                Some(Piece {
-                    piece_type: cap_type,
-                    color: opp_color,
-                }),
-            );
-        }
-
-        if mv.is_castle() {
-            let rank = from_y;
-            if mv.flags() == Move::FLAG_KING_CASTLE {
-                let rook = self.get_index(5, rank);
-                self.set_index(7, rank, rook);
-                self.set_index(5, rank, None);
-            } else {
-                let rook = self.get_index(3, rank);
-                self.set_index(0, rank, rook);
-                self.set_index(3, rank, None);
-            }
+            let opp = 1 - cidx;
+            self.put_code(state.captured_sq as usize, opp as u8 * 6 + state.captured);
         }
 
         self.en_passant = if state.prev_ep == UndoState::NO_EP {
@@ -938,6 +1271,10 @@ impl Board {
         self.unpack_castling(state.prev_castling);
 
         self.hash = state.prev_hash;
+        self.halfmove_clock = state.prev_halfmove;
+        if color == Color::Black {
+            self.fullmove -= 1;
+        }
     }
 
     #[inline(always)]
@@ -967,111 +1304,72 @@ impl Board {
     }
 
     #[inline(always)]
-    fn piece_type_from_idx(idx: usize) -> PieceType {
-        match idx {
-            0 => PieceType::Pawn,
-            1 => PieceType::Knight,
-            2 => PieceType::Bishop,
-            3 => PieceType::Rook,
-            4 => PieceType::Queen,
-            _ => PieceType::King,
-        }
-    }
-
-    #[inline(always)]
-    pub fn all_pieces(&self, color: Color) -> u64 {
+    pub fn occupied_by(&self, color: Color) -> u64 {
         let cidx = color_idx(color);
         self.bitboards[cidx].iter().fold(0, |a, &b| a | b)
     }
 
     #[inline(always)]
     pub fn occupied(&self) -> u64 {
-        self.all_pieces(Color::White) | self.all_pieces(Color::Black)
+        self.occupied_by(Color::White) | self.occupied_by(Color::Black)
     }
 
+    #[inline(always)]
+    pub fn pieces_of(&self, color: Color, piece_type: PieceType) -> u64 {
+        self.bitboards[color_idx(color)][piece_index(piece_type)]
+    }
+
+    // Every piece of `color` with its square index, pawns first.
+    pub fn pieces(&self, color: Color) -> impl Iterator<Item = (u8, Piece)> + '_ {
+        PIECE_TYPES.into_iter().flat_map(move |piece_type| {
+            let piece = Piece { piece_type, color };
+            let mut bb = self.pieces_of(color, piece_type);
+            core::iter::from_fn(move || {
+                if bb == 0 {
+                    return None;
+                }
+                let sq = bb.trailing_zeros() as u8;
+                bb &= bb - 1;
+                Some((sq, piece))
+            })
+        })
+    }
+
+    // Attack lookups from `sq`: a piece of `by_color` attacks it when the
+    // same piece standing on `sq` would attack that piece's square.
     #[inline]
     pub fn is_square_attacked_by(&self, sq: u8, by_color: Color) -> bool {
-        let cidx = color_idx(by_color);
-        let sq_bb = 1u64 << sq;
-        let occ = self.occupied();
+        use crate::movegen::piece_attacks;
 
-        let pawn_attacks = if by_color == Color::White {
-            let pawns = self.bitboards[cidx][0];
-            ((pawns & !0x0101010101010101) << 7) | ((pawns & !0x8080808080808080) << 9)
+        let sq = sq as usize;
+        let occ = self.occupied();
+        let defender = if by_color == Color::White {
+            Color::Black
         } else {
-            let pawns = self.bitboards[cidx][0];
-            ((pawns & !0x8080808080808080) >> 7) | ((pawns & !0x0101010101010101) >> 9)
+            Color::White
         };
-        if (pawn_attacks & sq_bb) != 0 {
-            return true;
-        }
-
-        let knights = self.bitboards[cidx][1];
-        if (crate::movegen::KNIGHT_TABLE[sq as usize] & knights) != 0 {
-            return true;
-        }
-
-        let kings = self.bitboards[cidx][5];
-        if (crate::movegen::KING_TABLE[sq as usize] & kings) != 0 {
-            return true;
-        }
-
-        let bishops_queens = self.bitboards[cidx][2] | self.bitboards[cidx][4];
-        let rooks_queens = self.bitboards[cidx][3] | self.bitboards[cidx][4];
-
-        if self.diagonal_attacks(sq, occ) & bishops_queens != 0 {
-            return true;
-        }
-
-        if self.straight_attacks(sq, occ) & rooks_queens != 0 {
-            return true;
-        }
-
-        false
+        let pieces = |pt| self.pieces_of(by_color, pt);
+        let queens = pieces(PieceType::Queen);
+
+        piece_attacks(PieceType::Pawn, defender, sq, occ) & pieces(PieceType::Pawn) != 0
+            || piece_attacks(PieceType::Knight, defender, sq, occ) & pieces(PieceType::Knight) != 0
+            || piece_attacks(PieceType::King, defender, sq, occ) & pieces(PieceType::King) != 0
+            || piece_attacks(PieceType::Bishop, defender, sq, occ)
+                & (pieces(PieceType::Bishop) | queens)
+                != 0
+            || piece_attacks(PieceType::Rook, defender, sq, occ)
+                & (pieces(PieceType::Rook) | queens)
+                != 0
     }
 
-    #[inline]
-    fn diagonal_attacks(&self, sq: u8, occ: u64) -> u64 {
-        let x = (sq % 8) as isize;
-        let y = (sq / 8) as isize;
-        let mut attacks = 0u64;
-
-        for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
-            let mut nx = x + dx;
-            let mut ny = y + dy;
-            while nx >= 0 && nx < 8 && ny >= 0 && ny < 8 {
-                let idx = (ny * 8 + nx) as usize;
-                attacks |= 1u64 << idx;
-                if (occ & (1u64 << idx)) != 0 {
-                    break;
-                }
-                nx += dx;
-                ny += dy;
-            }
-        }
-        attacks
+    // Pieces of `color` pinned to their king.
+    pub fn pinned(&self, color: Color) -> u64 {
+        crate::movegen::pinned(self, color)
     }
 
-    #[inline]
-    fn straight_attacks(&self, sq: u8, occ: u64) -> u64 {
-        let x = (sq % 8) as isize;
-        let y = (sq / 8) as isize;
-        let mut attacks = 0u64;
-
-        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
-            let mut nx = x + dx;
-            let mut ny = y + dy;
-            while nx >= 0 && nx < 8 && ny >= 0 && ny < 8 {
-                let idx = (ny * 8 + nx) as usize;
-                attacks |= 1u64 << idx;
-                if (occ & (1u64 << idx)) != 0 {
-                    break;
-                }
-                nx += dx;
-                ny += dy;
-            }
-        }
-        attacks
+    // Pieces of either color shielding the king of `color` from an enemy slider.
+    pub fn blockers_for_king(&self, color: Color) -> u64 {
+        crate::movegen::blockers_for_king(self, color)
     }
 
     #[inline]
@@ -1092,18 +1390,14 @@ impl Board {
 
     #[inline(always)]
     pub fn piece_at_sq(&self, sq: u8) -> Option<(PieceType, Color)> {
-        let x = (sq % 8) as usize;
-        let y = (sq / 8) as usize;
-        self.get_index(x, y).map(|p| (p.piece_type, p.color))
+        decode_piece(self.mailbox[sq as usize]).map(|p| (p.piece_type, p.color))
     }
 
     #[inline(always)]
     pub fn piece_type_idx_at(&self, sq: u8) -> usize {
-        let x = (sq % 8) as usize;
-        let y = (sq / 8) as usize;
-        match self.get_index(x, y) {
-            Some(p) => piece_index(p.piece_type),
-            None => 6,
+        match self.mailbox[sq as usize] {
+            EMPTY => 6,
+            code => (code % 6) as usize,
         }
     }
 }
@@ -1111,6 +1405,7 @@ impl Board {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::Square;
 
     fn setup_board() -> Board {
         let mut board = Board::new();
@@ -1118,6 +1413,50 @@ mod tests {
         board
     }
 
+    #[test]
+    fn test_validate_positions() {
+        let board = setup_board();
+        assert_eq!(board.validate(Color::White), Ok(()));
+
+        let mut board = Board::empty();
+        assert_eq!(
+            board.validate(Color::White),
+            Err(PositionError::MissingKing(Color::White))
+        );
+        let king = |color| {
+            Some(Piece {
+                piece_type: PieceType::King,
+                color,
+            })
+        };
+        board.set("e1", king(Color::White));
+        board.set("e8", king(Color::Black));
+        assert_eq!(board.validate(Color::White), Ok(()));
+
+        board.castling[0][0] = true;
+        assert_eq!(
+            board.validate(Color::White),
+            Err(PositionError::InvalidCastling)
+        );
+        board.castling[0][0] = false;
+
+        board.set(
+            "e7",
+            Some(Piece {
+                piece_type: PieceType::Rook,
+                color: Color::White,
+            }),
+        );
+        assert_eq!(
+            board.validate(Color::White),
+            Err(PositionError::OpponentInCheck)
+        );
+        assert_eq!(board.validate(Color::Black), Ok(()));
+
+        board.clear();
+        assert_eq!(board.piece_count_all(), 0);
+    }
+
     #[test]
     fn test_make_unmake_normal_move() {
         let mut board = setup_board();
@@ -1145,8 +1484,8 @@ mod tests {
         let hash_before_capture = board.hash;
 
         let state = board.make_move_state("e4", "d5").unwrap();
-        assert!(state.captured.is_some());
-        assert_eq!(state.captured.unwrap().piece_type, PieceType::Pawn);
+        assert!(state.has_capture());
+        assert_eq!(state.captured, piece_index(PieceType::Pawn) as u8);
 
         board.unmake_move(state);
         assert!(board.get("e4").is_some());
@@ -1207,6 +1546,46 @@ mod tests {
         assert!(board.get("a1").is_some());
     }
 
+    #[test]
+    fn test_make_unmake_chess960_castling() {
+        // King f1 with its rook on h1: the rook lands where the king stood.
+        let (mut board, _) = Board::from_fen("4k3/8/8/8/8/8/8/5K1R w H - 0 1").unwrap();
+        assert_eq!(board.castle_rooks[0], [7, 0]);
+        let moves = board.all_legal_moves(Color::White);
+        assert!(moves.contains(&("f1".to_string(), "h1".to_string())));
+        let state = board.make_move_state("f1", "h1").unwrap();
+        assert_eq!(board.to_fen(Color::Black), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        board.unmake_move(state);
+        assert_eq!(board.to_fen(Color::White), "4k3/8/8/8/8/8/8/5K1R w K - 0 1");
+
+        // King b1 and rook a1: the king goes to c1, the rook over it to d1.
+        let (mut board, _) = Board::from_fen("4k3/8/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+        let state = board.make_move_state("b1", "a1").unwrap();
+        assert_eq!(board.to_fen(Color::Black), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        board.unmake_move(state);
+        assert_eq!(board.to_fen(Color::White), "4k3/8/8/8/8/8/8/RK6 w Q - 0 1");
+
+        // King g1 castling short stays where it is.
+        let (mut board, _) = Board::from_fen("4k3/8/8/8/8/8/8/6KR w K - 0 1").unwrap();
+        let castle = board.castle_move(Color::White, true);
+        assert_eq!((castle.from_sq(), castle.to_sq()), (6, 6));
+        assert_eq!(board.move_to_uci(castle, false), "g1h1");
+        let state = board.make(castle);
+        assert_eq!(board.to_fen(Color::Black), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        board.unmake(castle, state);
+        assert_eq!(board.to_fen(Color::White), "4k3/8/8/8/8/8/8/6KR w K - 0 1");
+
+        // Castling on c1 with the rook on b1 would uncover the a1 queen.
+        let (mut board, _) = Board::from_fen("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+        assert!(board.can_castle(Color::White, false));
+        assert!(!board.is_legal("c1", "b1", Color::White));
+
+        // A rook moving off, or taken on, its square ends that side's right.
+        let (mut board, _) = Board::from_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1").unwrap();
+        board.make_move_state("g1", "g8").unwrap();
+        assert_eq!(board.castling, [[false, true], [false, true]]);
+    }
+
     #[test]
     fn test_make_unmake_en_passant() {
         let mut board = setup_board();
@@ -1325,6 +1704,40 @@ mod tests {
         assert!(!board.in_check(Color::Black));
     }
 
+    #[test]
+    fn test_flipped_swaps_colors() {
+        let (board, _) = Board::from_fen("r3k2r/8/8/2pP4/8/8/8/R3K3 w Qkq c6 0 1").unwrap();
+        let flipped = board.flipped();
+        assert_eq!(
+            flipped.to_fen(Color::Black),
+            "r3k3/8/8/8/2Pp4/8/8/R3K2R b KQq c3 0 1"
+        );
+        assert_eq!(flipped.flipped().hash, board.hash);
+        assert_eq!(
+            flipped.material_key,
+            crate::material::mirror(board.material_key)
+        );
+    }
+
+    #[test]
+    fn test_in_check_by_every_piece_type() {
+        for (fen, white, black) in [
+            ("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1", true, false),
+            ("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1", false, false),
+            ("4k3/8/8/8/8/5n2/8/4K3 w - - 0 1", true, false),
+            ("4k3/8/8/8/1b6/8/8/4K3 w - - 0 1", true, false),
+            ("4k3/8/8/8/1b6/8/3P4/4K3 w - - 0 1", false, false),
+            ("4k3/8/8/8/8/8/8/r3K3 w - - 0 1", true, false),
+            ("4k3/8/8/8/4Q3/8/8/4K3 b - - 0 1", false, true),
+            ("4k3/5P2/8/8/8/8/8/4K3 b - - 0 1", false, true),
+            ("4k3/3P4/8/8/8/8/8/4K3 b - - 0 1", false, true),
+        ] {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            assert_eq!(board.in_check(Color::White), white, "{fen}");
+            assert_eq!(board.in_check(Color::Black), black, "{fen}");
+        }
+    }
+
     #[test]
     fn test_is_legal_blocks_king_in_check() {
         let mut board = Board::new();
@@ -1369,7 +1782,103 @@ mod tests {
         board.setup_standard();
 
         let fen = board.to_fen(Color::White);
-        assert!(fen.starts_with("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR"));
+        assert_eq!(
+            fen,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        board.make_move_state("e2", "e4").unwrap();
+        assert_eq!(
+            board.to_fen(Color::Black),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        let (shredder, _) = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAh - 0 1").unwrap();
+        assert_eq!(
+            shredder.to_fen(Color::White),
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQk - 0 1"
+        );
+
+        // X-FEN: a rook with another one outside it is named by its file.
+        let fen = "1r2k1rr/8/8/8/8/8/8/1R2K1RR w GQgq - 0 1";
+        let (chess960, turn) = Board::from_fen("1r2k1rr/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1").unwrap();
+        assert_eq!(chess960.castle_rooks, [[6, 1], [6, 1]]);
+        assert_eq!(chess960.to_fen(turn), fen);
+        assert_eq!(
+            Board::from_fen(fen).unwrap().0.castle_rooks,
+            [[6, 1], [6, 1]]
+        );
+        assert_eq!(chess960.validate(turn), Ok(()));
+        assert_eq!(
+            chess960.flipped().to_fen(Color::Black),
+            "1r2k1rr/8/8/8/8/8/8/1R2K1RR b GQgq - 0 1"
+        );
+
+        let (king_moved, turn) = Board::from_fen("4k3/8/8/8/8/8/7K/R1R5 w K - 0 1").unwrap();
+        assert_eq!(
+            king_moved.validate(turn),
+            Err(PositionError::InvalidCastling)
+        );
+    }
+
+    #[test]
+    fn test_parse_fen_restores_the_position() {
+        let fen = "r3k2r/1P4p1/8/3pP3/8/8/P7/R3K2R b Kq d6 12 40";
+        let (board, turn) = Board::parse_fen(fen).unwrap();
+        assert_eq!(turn, Color::Black);
+        assert_eq!(board.castling, [[true, false], [false, true]]);
+        assert_eq!(board.en_passant, Some((3, 5)));
+        assert_eq!((board.halfmove_clock, board.fullmove), (12, 40));
+        assert_eq!(board.to_fen(turn), fen);
+
+        let (bare, turn) = Board::parse_fen("4k3/8/8/8/8/8/8/4K3").unwrap();
+        assert_eq!(turn, Color::White);
+        assert_eq!(bare.to_fen(turn), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        // Rights whose rook is missing are dropped.
+        let (rookless, turn) = Board::parse_fen("r3k3/8/8/8/8/8/8/4K2R w KQkqC - 0 1").unwrap();
+        assert_eq!(rookless.castling, [[true, false], [false, true]]);
+        assert_eq!(rookless.to_fen(turn), "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1");
+    }
+
+    #[test]
+    fn test_parse_fen_errors() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        let cases = [
+            ("", FenError::Empty),
+            ("8/8/8 w - - 0 1", FenError::RankCount(3)),
+            (
+                "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::InvalidPiece('x'),
+            ),
+            (
+                "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::RankLength(7),
+            ),
+            (
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::RankLength(6),
+            ),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(Board::parse_fen(fen).err(), Some(error), "{}", fen);
+        }
+        let fields = [
+            ("x KQkq - 0 1", FenError::SideToMove("x".to_string())),
+            ("w KQxq - 0 1", FenError::Castling('x')),
+            ("w KQkq e4 0 1", FenError::EnPassant("e4".to_string())),
+            ("w KQkq z9 0 1", FenError::EnPassant("z9".to_string())),
+            ("w KQkq - -3 1", FenError::Clock("-3".to_string())),
+            ("w KQkq - 0 one", FenError::Clock("one".to_string())),
+        ];
+        for (rest, error) in fields {
+            let fen = format!("{} {}", start, rest);
+            assert_eq!(Board::parse_fen(&fen).err(), Some(error), "{}", fen);
+            assert!(Board::from_fen(&fen).is_none());
+        }
+        assert_eq!(
+            FenError::RankLength(7).to_string(),
+            "rank 7 does not have 8 squares"
+        );
     }
 
     #[test]
@@ -1402,4 +1911,163 @@ mod tests {
         assert!(board.get("e4").is_none());
         assert_eq!(board.hash, original_hash);
     }
+
+    #[test]
+    fn test_piece_iterator_and_occupancy() {
+        let (board, _) = Board::from_fen("4k3/8/8/8/8/8/3PP3/R3K1N1 w Q - 0 1").unwrap();
+        let white: Vec<(u8, PieceType)> = board
+            .pieces(Color::White)
+            .map(|(sq, p)| (sq, p.piece_type))
+            .collect();
+        assert_eq!(
+            white,
+            vec![
+                (11, PieceType::Pawn),
+                (12, PieceType::Pawn),
+                (6, PieceType::Knight),
+                (0, PieceType::Rook),
+                (4, PieceType::King),
+            ]
+        );
+        assert!(
+            board
+                .pieces(Color::Black)
+                .all(|(_, p)| p.color == Color::Black)
+        );
+        assert_eq!(board.pieces(Color::Black).count(), 1);
+        assert_eq!(
+            board.pieces_of(Color::White, PieceType::Pawn),
+            (1 << 11) | (1 << 12)
+        );
+        assert_eq!(board.occupied_by(Color::Black), 1 << 60);
+        assert_eq!(
+            board.occupied(),
+            board.occupied_by(Color::White) | board.occupied_by(Color::Black)
+        );
+        assert_eq!(board.occupied().count_ones(), 6);
+    }
+
+    #[test]
+    fn test_square_moves_match_string_moves() {
+        let (mut board, color) =
+            Board::from_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1")
+                .unwrap();
+        for from in 0..64u8 {
+            for to in 0..64u8 {
+                let (fs, ts) = (Square::to_algebraic(from), Square::to_algebraic(to));
+                assert_eq!(
+                    board.is_legal_sq(from, to, color),
+                    board.is_legal(&fs, &ts, color),
+                    "{}{}",
+                    fs,
+                    ts
+                );
+            }
+        }
+
+        // b2xa1 promoting to a knight, then back.
+        let hash = board.hash;
+        let state = board.make_move_sq(9, 0, Some(PieceType::Knight)).unwrap();
+        assert_eq!(board.get("a1").unwrap().piece_type, PieceType::Knight);
+        assert_eq!(state.captured, piece_index(PieceType::Rook) as u8);
+        board.unmake_move(state);
+        assert_eq!(board.get("b2").unwrap().piece_type, PieceType::Pawn);
+        assert_eq!(board.get("a1").unwrap().piece_type, PieceType::Rook);
+        assert_eq!(board.hash, hash);
+
+        // A promotion piece is ignored for moves that do not promote.
+        let state = board.make_move_sq(27, 35, Some(PieceType::Queen));
+        assert!(state.is_none());
+        let state = board.make_move_sq(26, 34, Some(PieceType::Queen)).unwrap();
+        assert_eq!(state.mv.promotion_piece(), None);
+        assert_eq!(board.get("c5").unwrap().piece_type, PieceType::Pawn);
+    }
+
+    fn assert_mailbox_matches_bitboards(board: &Board) {
+        for sq in 0..64 {
+            let expected =
+                (0..12).find(|&code| board.bitboards[code / 6][code % 6] & (1u64 << sq) != 0);
+            assert_eq!(
+                board.mailbox[sq],
+                expected.map_or(EMPTY, |code| code as u8),
+                "square {}",
+                sq
+            );
+        }
+    }
+
+    #[test]
+    fn test_mailbox_follows_make_and_unmake() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let (mut board, color) = Board::from_fen(fen).unwrap();
+        let before = board.mailbox;
+        let mut list = crate::types::MoveList::new();
+        crate::movegen::generate_moves_fast(&mut board, color, &mut list);
+        for i in 0..list.len() {
+            let mv = list.get(i).unwrap();
+            let state = board.make_move_fast(mv, color);
+            assert_mailbox_matches_bitboards(&board);
+            board.unmake_move_fast(state, color);
+            assert_eq!(board.mailbox, before);
+        }
+    }
+
+    #[test]
+    fn test_encode_and_make_flagged_moves() {
+        let (mut board, _) =
+            Board::from_fen("r3k2r/1P4p1/8/3pP3/8/8/P7/R3K2R w KQkq d6 0 1").unwrap();
+        let cases = [
+            ("e1", "g1", Move::FLAG_KING_CASTLE),
+            ("e1", "c1", Move::FLAG_QUEEN_CASTLE),
+            ("e5", "d6", Move::FLAG_EP_CAPTURE),
+            ("a2", "a4", Move::FLAG_DOUBLE_PUSH),
+            ("a2", "a3", Move::FLAG_NORMAL),
+            ("b7", "b8", Move::FLAG_PROMO_QUEEN),
+            ("b7", "a8", Move::FLAG_PROMO_QUEEN_CAP),
+            ("a1", "a8", Move::FLAG_CAPTURE),
+        ];
+        let fen = board.to_fen(Color::White);
+        for (from, to, flags) in cases {
+            let (from, to) = (algebraic_sq(from), algebraic_sq(to));
+            let mv = board.encode_move(from, to, None);
+            assert_eq!(mv.flags(), flags, "{:?}", mv);
+
+            let state = board.make(mv);
+            assert_mailbox_matches_bitboards(&board);
+            board.unmake(mv, state);
+            assert_eq!(board.to_fen(Color::White), fen);
+        }
+
+        let knight = board.encode_move(49, 57, Some(PieceType::Knight));
+        let state = board.make(knight);
+        assert_eq!(board.get("b8").unwrap().piece_type, PieceType::Knight);
+        assert_eq!(board.en_passant, None);
+        board.unmake(knight, state);
+        assert_eq!(board.to_fen(Color::White), fen);
+    }
+
+    fn algebraic_sq(name: &str) -> u8 {
+        let (x, y) = Board::algebraic_to_index(name).unwrap();
+        (y * 8 + x) as u8
+    }
+
+    #[test]
+    fn test_move_clocks_follow_make_and_unmake() {
+        let (mut board, _) = Board::from_fen("4k3/8/8/3p4/8/8/4P3/R3K3 b Q - 7 30").unwrap();
+        let quiet = board.make_move_state("e8", "e7").unwrap();
+        assert_eq!((board.halfmove_clock, board.fullmove), (8, 31));
+        let rook = board.make_move_state("a1", "a5").unwrap();
+        assert_eq!((board.halfmove_clock, board.fullmove), (9, 31));
+        let push = board.make_move_state("d5", "d4").unwrap();
+        assert_eq!((board.halfmove_clock, board.fullmove), (0, 32));
+        assert!(board.to_fen(Color::White).ends_with(" - 0 32"));
+
+        board.unmake_move(push);
+        board.unmake_move(rook);
+        board.unmake_move(quiet);
+        assert_eq!(
+            board.to_fen(Color::Black),
+            "4k3/8/8/3p4/8/8/4P3/R3K3 b Q - 7 30"
+        );
+    }
 }
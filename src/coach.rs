@@ -0,0 +1,261 @@
+use crate::board::{Board, color_idx, piece_index};
+use crate::engine::{Engine, PvLine, TimeConfig};
+use crate::eval;
+use crate::game::Game;
+use crate::pieces::{Color, PieceType};
+use crate::san::line_to_san;
+use crate::types::MAX_MOVES;
+
+// Moves losing less than this are not worth a comment.
+const INACCURACY: i32 = 50;
+// Smallest change of an evaluation term that gets reported.
+const TERM_CHANGE: i32 = 30;
+
+// What the coach has to say about the last move of a game. Scores are in
+// centipawns from the point of view of the player who made the move.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoachReport {
+    pub played: String,
+    // The engine's choice, when it differs from the move played.
+    pub best: Option<String>,
+    pub loss: i32,
+    // The opponent's best answer and its continuation, in SAN.
+    pub refutation: Vec<String>,
+    // Evaluation terms that moved by the end of the refutation, largest
+    // first.
+    pub changes: Vec<(&'static str, i32)>,
+    // The most valuable piece the refutation wins, with the move taking it.
+    pub lost_piece: Option<(PieceType, String)>,
+    pub message: String,
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+fn piece_name(pt: PieceType) -> &'static str {
+    match pt {
+        PieceType::Pawn => "pawn",
+        PieceType::Knight => "knight",
+        PieceType::Bishop => "bishop",
+        PieceType::Rook => "rook",
+        PieceType::Queen => "queen",
+        PieceType::King => "king",
+    }
+}
+
+fn count(board: &Board, color: Color, pt: PieceType) -> u32 {
+    board.bitboards[color_idx(color)][piece_index(pt)].count_ones()
+}
+
+// Compares coordinate moves, a bare promotion square meaning a queen.
+fn same_move(a: &(String, String), b: &(String, String)) -> bool {
+    let promotion = |end: &str| {
+        Board::split_promotion(end).map(|(dest, promo)| {
+            (
+                dest.to_string(),
+                promo.unwrap_or(PieceType::Queen) == PieceType::Queen,
+            )
+        })
+    };
+    a.0 == b.0 && promotion(&a.1) == promotion(&b.1)
+}
+
+// Finds the first move of `line` (played from `board` by `color`) after which
+// `victim` has fewer pieces than at the start, and the piece it took. Of the
+// pieces lost by the end of the line, the most valuable is named.
+fn first_loss(
+    board: &Board,
+    color: Color,
+    line: &[(String, String)],
+    victim: Color,
+) -> Option<(PieceType, usize)> {
+    const TYPES: [PieceType; 5] = [
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Pawn,
+    ];
+    let mut end = board.clone();
+    for (start, to) in line {
+        end.make_move_state(start, to)?;
+    }
+    let pt = TYPES
+        .into_iter()
+        .find(|&pt| count(&end, victim, pt) < count(board, victim, pt))?;
+
+    let mut board = board.clone();
+    let mut color = color;
+    let before = count(&board, victim, pt);
+    for (i, (start, to)) in line.iter().enumerate() {
+        board.make_move_state(start, to)?;
+        if color != victim && count(&board, victim, pt) < before {
+            return Some((pt, i));
+        }
+        color = opposite(color);
+    }
+    None
+}
+
+fn pawns(score: i32) -> String {
+    format!("{:.2}", score as f32 / 100.0)
+}
+
+// Searches the position before the last move of `game` to `depth` and
+// explains what the move gave away: the engine's preferred move, the line
+// refuting the one played, and which evaluation terms it damages.
+pub fn review(engine: &mut Engine, game: &Game, depth: u32) -> Option<CoachReport> {
+    let played = game.history.last()?.clone();
+    let ply = game.history.len() - 1;
+    let mover = game.turn_at(ply);
+    let mut before = game.clone();
+    before.truncate(ply);
+
+    let config = TimeConfig {
+        depth: Some(depth.max(1)),
+        ..TimeConfig::default()
+    };
+    let info = engine.analyze(&before, &config, MAX_MOVES, |_| {});
+    let best = info.best()?;
+    let line: &PvLine = info
+        .lines
+        .iter()
+        .find(|l| l.moves.first().is_some_and(|m| same_move(m, &played)))?;
+    let loss = (best.score - line.score).max(0);
+
+    let board = &before.board;
+    let sans = line_to_san(board, mover, &line.moves);
+    let played_san = sans.first()?.clone();
+    let best_san = if same_move(&best.moves[0], &played) {
+        None
+    } else {
+        line_to_san(board, mover, &best.moves[..1]).pop()
+    };
+    let refutation: Vec<String> = sans[1..].to_vec();
+
+    let mut end = board.clone();
+    for (start, to) in &line.moves {
+        end.make_move_state(start, to)?;
+    }
+    let sign = if mover == Color::White { 1 } else { -1 };
+    let start_terms = eval::explain(board).terms();
+    let end_terms = eval::explain(&end).terms();
+    let mut changes: Vec<(&'static str, i32)> = start_terms
+        .iter()
+        .zip(end_terms.iter())
+        .map(|(&(name, a), &(_, b))| (name, (b - a) * sign))
+        .filter(|&(_, delta)| delta.abs() >= TERM_CHANGE)
+        .collect();
+    changes.sort_by_key(|&(_, delta)| std::cmp::Reverse(delta.abs()));
+
+    let material = changes
+        .iter()
+        .find(|&&(name, _)| name == "material")
+        .map_or(0, |&(_, delta)| delta);
+    // Index in `refutation` of the move winning the piece.
+    let lost = if material < 0 {
+        first_loss(board, mover, &line.moves, mover)
+            .map(|(pt, i)| (pt, i - 1))
+            .filter(|&(_, i)| i < refutation.len())
+    } else {
+        None
+    };
+
+    let message = if loss < INACCURACY {
+        match best_san {
+            None => format!("{played_san} is the best move."),
+            Some(_) => format!("{played_san} is fine."),
+        }
+    } else {
+        let mut message = match lost {
+            Some((pt, 0)) => format!(
+                "{played_san}? You lose a {} to {}.",
+                piece_name(pt),
+                refutation[0]
+            ),
+            Some((pt, i)) => format!(
+                "{played_san}? You lose a {} after {}.",
+                piece_name(pt),
+                refutation[..=i].join(" ")
+            ),
+            None => match changes.iter().find(|&&(_, delta)| delta < 0) {
+                Some(&(name, delta)) if !refutation.is_empty() => format!(
+                    "{played_san}? After {} your {name} drops by {}.",
+                    refutation.join(" "),
+                    pawns(-delta)
+                ),
+                _ => format!("{played_san}? It costs {} pawns.", pawns(loss)),
+            },
+        };
+        if let Some(best) = &best_san {
+            message.push_str(&format!(" {best} was better."));
+        }
+        message
+    };
+
+    Some(CoachReport {
+        played: played_san,
+        best: best_san,
+        loss,
+        changes,
+        lost_piece: lost.map(|(pt, i)| (pt, refutation[i].clone())),
+        refutation,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_a_hanging_piece() {
+        let mut game =
+            Game::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2").unwrap();
+        let mut engine = Engine::new(4);
+        assert!(game.make_move("g1", "h3"));
+        // The queen walks into the knight.
+        assert!(game.make_move("d8", "g5"));
+        let report = review(&mut engine, &game, 4).unwrap();
+        assert_eq!(report.played, "Qg5");
+        assert!(report.loss >= 300, "{report:?}");
+        assert_eq!(report.refutation.first().map(String::as_str), Some("Nxg5"));
+        assert_eq!(
+            report.lost_piece,
+            Some((PieceType::Queen, "Nxg5".to_string()))
+        );
+        assert!(
+            report
+                .changes
+                .iter()
+                .any(|&(name, d)| name == "material" && d < -300)
+        );
+        assert!(
+            report.message.contains("lose a queen to Nxg5"),
+            "{}",
+            report.message
+        );
+        assert!(report.best.is_some());
+    }
+
+    #[test]
+    fn praises_the_best_move() {
+        // Taking the hanging queen is the only good move.
+        let mut game =
+            Game::from_fen("rnb1kbnr/pppp1ppp/8/4p1q1/4P3/7N/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        assert!(game.make_move("h3", "g5"));
+        let mut engine = Engine::new(4);
+        let report = review(&mut engine, &game, 3).unwrap();
+        assert_eq!(report.played, "Nxg5");
+        assert_eq!(report.best, None);
+        assert_eq!(report.loss, 0);
+        assert_eq!(report.lost_piece, None);
+        assert!(report.message.contains("best move"));
+        assert!(review(&mut engine, &Game::new(), 3).is_none());
+    }
+}
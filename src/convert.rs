@@ -0,0 +1,226 @@
+// Conversions to and from shakmaty's types for tablebase probing, Polyglot
+// keys and differential tests. They map bitboards and square indices directly
+// (a1 is 0 and h8 is 63 in both crates) instead of going through FEN or UCI
+// text.
+use crate::board::{Board, color_idx, piece_index};
+use crate::game::Game;
+use crate::pieces::{Color, PieceType};
+use crate::types::Move;
+use shakmaty::{
+    Bitboard, ByColor, ByRole, CastlingMode, Chess, FromSetup, PositionError, Role, Setup, Square,
+};
+use std::num::NonZeroU32;
+
+pub fn square(sq: u8) -> Square {
+    Square::new(u32::from(sq & 63))
+}
+
+pub fn from_square(sq: Square) -> u8 {
+    sq.to_u32() as u8
+}
+
+pub fn color(color: Color) -> shakmaty::Color {
+    match color {
+        Color::White => shakmaty::Color::White,
+        Color::Black => shakmaty::Color::Black,
+    }
+}
+
+pub fn from_color(color: shakmaty::Color) -> Color {
+    match color {
+        shakmaty::Color::White => Color::White,
+        shakmaty::Color::Black => Color::Black,
+    }
+}
+
+pub fn role(pt: PieceType) -> Role {
+    match pt {
+        PieceType::Pawn => Role::Pawn,
+        PieceType::Knight => Role::Knight,
+        PieceType::Bishop => Role::Bishop,
+        PieceType::Rook => Role::Rook,
+        PieceType::Queen => Role::Queen,
+        PieceType::King => Role::King,
+    }
+}
+
+pub fn from_role(role: Role) -> PieceType {
+    match role {
+        Role::Pawn => PieceType::Pawn,
+        Role::Knight => PieceType::Knight,
+        Role::Bishop => PieceType::Bishop,
+        Role::Rook => PieceType::Rook,
+        Role::Queen => PieceType::Queen,
+        Role::King => PieceType::King,
+    }
+}
+
+// The piece placement alone.
+impl From<&Board> for shakmaty::Board {
+    fn from(board: &Board) -> Self {
+        let side = |c: Color| Bitboard(board.bitboards[color_idx(c)].iter().fold(0, |a, b| a | b));
+        let by_role = ByRole::new_with(|r| {
+            let pt = piece_index(from_role(r));
+            Bitboard(board.bitboards[0][pt] | board.bitboards[1][pt])
+        });
+        let by_color = ByColor::new_with(|c| side(from_color(c)));
+        // Our bitboards never overlap.
+        shakmaty::Board::try_from_bitboards(by_role, by_color)
+            .unwrap_or_else(|_| shakmaty::Board::empty())
+    }
+}
+
+// The whole position with `turn` to move, for shakmaty to validate.
+pub fn setup(board: &Board, turn: Color) -> Setup {
+    let mut castling_rights = Bitboard::EMPTY;
+    for (c, rank) in [(Color::White, 0), (Color::Black, 56)] {
+        let cidx = color_idx(c);
+        for side in 0..2 {
+            if board.castling[cidx][side] {
+                castling_rights.add(square(rank + board.castle_rooks[cidx][side]));
+            }
+        }
+    }
+    Setup {
+        board: board.into(),
+        promoted: Bitboard::EMPTY,
+        pockets: None,
+        turn: color(turn),
+        castling_rights,
+        ep_square: board.en_passant.map(|(x, y)| square((y * 8 + x) as u8)),
+        remaining_checks: None,
+        halfmoves: u32::from(board.halfmove_clock),
+        fullmoves: NonZeroU32::new(u32::from(board.fullmove)).unwrap_or(NonZeroU32::MIN),
+    }
+}
+
+// Chess960 castling rights are read as such. The error carries the whole
+// rejected position, so it is boxed.
+pub fn to_chess(board: &Board, turn: Color) -> Result<Chess, Box<PositionError<Chess>>> {
+    let setup = setup(board, turn);
+    let mode = CastlingMode::detect(&setup);
+    Chess::from_setup(setup, mode).map_err(Box::new)
+}
+
+impl TryFrom<&Game> for Chess {
+    type Error = Box<PositionError<Chess>>;
+
+    fn try_from(game: &Game) -> Result<Self, Self::Error> {
+        to_chess(&game.board, game.current_turn)
+    }
+}
+
+// shakmaty's move for `mv` played on `board`, which tells the moving and
+// captured pieces.
+pub fn to_move(board: &Board, mv: Move) -> Option<shakmaty::Move> {
+    let (from, to) = (square(mv.from_sq()), square(mv.to_sq()));
+    if mv.is_ep() {
+        return Some(shakmaty::Move::EnPassant { from, to });
+    }
+    if mv.is_castle() {
+        return Some(shakmaty::Move::Castle {
+            king: from,
+            rook: square(board.castle_rook(mv).0),
+        });
+    }
+    let (pt, _) = board.piece_at_sq(mv.from_sq())?;
+    Some(shakmaty::Move::Normal {
+        role: role(pt),
+        from,
+        capture: board.piece_at_sq(mv.to_sq()).map(|(pt, _)| role(pt)),
+        to,
+        promotion: mv.promotion_piece().map(role),
+    })
+}
+
+// Our encoding of a shakmaty move; drops have none.
+pub fn from_move(mv: &shakmaty::Move) -> Option<Move> {
+    Some(match *mv {
+        shakmaty::Move::Normal {
+            role,
+            from,
+            capture,
+            to,
+            promotion,
+        } => {
+            let (from, to) = (from_square(from), from_square(to));
+            match promotion {
+                Some(promotion) => {
+                    Move::promotion(from, to, from_role(promotion), capture.is_some())
+                }
+                None if capture.is_some() => Move::capture(from, to),
+                None if role == Role::Pawn && from.abs_diff(to) == 16 => {
+                    Move::new(from, to, Move::FLAG_DOUBLE_PUSH)
+                }
+                None => Move::normal(from, to),
+            }
+        }
+        shakmaty::Move::EnPassant { from, to } => {
+            Move::new(from_square(from), from_square(to), Move::FLAG_EP_CAPTURE)
+        }
+        shakmaty::Move::Castle { king, rook } => {
+            let (king, rook) = (from_square(king), from_square(rook));
+            let rank = king / 8 * 8;
+            if rook > king {
+                Move::new(king, rank + 6, Move::FLAG_KING_CASTLE)
+            } else {
+                Move::new(king, rank + 2, Move::FLAG_QUEEN_CASTLE)
+            }
+        }
+        shakmaty::Move::Put { .. } => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::generate_moves_fast;
+    use crate::types::MoveList;
+    use shakmaty::Position;
+
+    const FENS: [&str; 4] = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "4k3/8/8/q2pP2K/8/8/8/8 w - d6 0 1",
+    ];
+
+    #[test]
+    fn positions_match_fen() {
+        for fen in FENS {
+            let game = Game::from_fen(fen).unwrap();
+            let pos = Chess::try_from(&game).unwrap();
+            let text = shakmaty::fen::Fen::from_position(&pos, shakmaty::EnPassantMode::Always);
+            assert_eq!(text.to_string(), fen);
+        }
+        assert!(to_chess(&Board::new(), Color::White).is_err());
+        for sq in [0, 7, 36, 63] {
+            assert_eq!(from_square(square(sq)), sq);
+        }
+    }
+
+    #[test]
+    fn moves_match_the_legal_moves() {
+        for fen in FENS {
+            let (mut board, turn) = Board::from_fen(fen).unwrap();
+            let pos = to_chess(&board, turn).unwrap();
+            let mut theirs: Vec<u16> = pos
+                .legal_moves()
+                .iter()
+                .map(|m| from_move(m).unwrap().0)
+                .collect();
+            let mut list = MoveList::new();
+            generate_moves_fast(&mut board, turn, &mut list);
+            let mut ours: Vec<u16> = list.iter().map(|m| m.0).collect();
+            theirs.sort_unstable();
+            ours.sort_unstable();
+            assert_eq!(ours, theirs, "{}", fen);
+
+            for &mv in list.iter() {
+                let converted = to_move(&board, mv).unwrap();
+                assert!(pos.is_legal(converted), "{} in {}", mv.to_algebraic(), fen);
+                assert_eq!(from_move(&converted), Some(mv));
+            }
+        }
+    }
+}
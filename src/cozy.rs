@@ -0,0 +1,206 @@
+// Conversions to and from cozy-chess's types, for book probers, perft tools
+// and datasets built on that crate. Squares are indexed the same way in both
+// crates (a1 is 0 and h8 is 63); castling is the king taking its own rook
+// there, the encoding `Board::encode_move` already reads.
+use crate::board::{Board, color_idx};
+use crate::game::Game;
+use crate::pieces::{Color, Piece, PieceType};
+use crate::types::Move;
+use cozy_chess::{BoardBuilder, BoardBuilderError, CastleRights, File, Square};
+
+pub fn square(sq: u8) -> Square {
+    Square::index(usize::from(sq & 63))
+}
+
+pub fn from_square(sq: Square) -> u8 {
+    sq as u8
+}
+
+pub fn color(color: Color) -> cozy_chess::Color {
+    match color {
+        Color::White => cozy_chess::Color::White,
+        Color::Black => cozy_chess::Color::Black,
+    }
+}
+
+pub fn from_color(color: cozy_chess::Color) -> Color {
+    match color {
+        cozy_chess::Color::White => Color::White,
+        cozy_chess::Color::Black => Color::Black,
+    }
+}
+
+pub fn piece(pt: PieceType) -> cozy_chess::Piece {
+    match pt {
+        PieceType::Pawn => cozy_chess::Piece::Pawn,
+        PieceType::Knight => cozy_chess::Piece::Knight,
+        PieceType::Bishop => cozy_chess::Piece::Bishop,
+        PieceType::Rook => cozy_chess::Piece::Rook,
+        PieceType::Queen => cozy_chess::Piece::Queen,
+        PieceType::King => cozy_chess::Piece::King,
+    }
+}
+
+pub fn from_piece(piece: cozy_chess::Piece) -> PieceType {
+    match piece {
+        cozy_chess::Piece::Pawn => PieceType::Pawn,
+        cozy_chess::Piece::Knight => PieceType::Knight,
+        cozy_chess::Piece::Bishop => PieceType::Bishop,
+        cozy_chess::Piece::Rook => PieceType::Rook,
+        cozy_chess::Piece::Queen => PieceType::Queen,
+        cozy_chess::Piece::King => PieceType::King,
+    }
+}
+
+// The whole position with `turn` to move, for cozy-chess to validate. Its
+// halfmove clock stops at 255.
+pub fn to_board(board: &Board, turn: Color) -> Result<cozy_chess::Board, BoardBuilderError> {
+    let mut builder = BoardBuilder::empty();
+    for (sq, p) in board.pieces(Color::White).chain(board.pieces(Color::Black)) {
+        builder.board[sq as usize] = Some((piece(p.piece_type), color(p.color)));
+    }
+    builder.side_to_move = color(turn);
+    for c in [Color::White, Color::Black] {
+        let cidx = color_idx(c);
+        let file = |side: usize| {
+            board.castling[cidx][side]
+                .then(|| File::index(usize::from(board.castle_rooks[cidx][side])))
+        };
+        builder.castle_rights[cidx] = CastleRights {
+            short: file(0),
+            long: file(1),
+        };
+    }
+    builder.en_passant = board.en_passant.map(|(x, y)| square((y * 8 + x) as u8));
+    builder.halfmove_clock = board.halfmove_clock.min(u16::from(u8::MAX)) as u8;
+    builder.fullmove_number = board.fullmove;
+    builder.build()
+}
+
+// Our board and side to move for a cozy-chess position.
+pub fn from_board(position: &cozy_chess::Board) -> (Board, Color) {
+    let builder = BoardBuilder::from_board(position);
+    let mut board = Board::empty();
+    for (sq, entry) in builder.board.iter().enumerate() {
+        if let Some((p, c)) = *entry {
+            board.set_index(
+                sq % 8,
+                sq / 8,
+                Some(Piece {
+                    piece_type: from_piece(p),
+                    color: from_color(c),
+                }),
+            );
+        }
+    }
+    for (cidx, rights) in builder.castle_rights.iter().enumerate() {
+        for (side, file) in [(0, rights.short), (1, rights.long)] {
+            if let Some(file) = file {
+                board.castling[cidx][side] = true;
+                board.castle_rooks[cidx][side] = file as u8;
+            }
+        }
+    }
+    board.en_passant = builder.en_passant.map(|sq| {
+        let sq = from_square(sq) as usize;
+        (sq % 8, sq / 8)
+    });
+    board.halfmove_clock = u16::from(builder.halfmove_clock);
+    board.fullmove = builder.fullmove_number;
+    (board, from_color(builder.side_to_move))
+}
+
+impl TryFrom<&Game> for cozy_chess::Board {
+    type Error = BoardBuilderError;
+
+    fn try_from(game: &Game) -> Result<Self, Self::Error> {
+        to_board(&game.board, game.current_turn)
+    }
+}
+
+// cozy-chess's move for `mv` played on `board`.
+pub fn to_move(board: &Board, mv: Move) -> cozy_chess::Move {
+    let to = if mv.is_castle() {
+        board.castle_rook(mv).0
+    } else {
+        mv.to_sq()
+    };
+    cozy_chess::Move {
+        from: square(mv.from_sq()),
+        to: square(to),
+        promotion: mv.promotion_piece().map(piece),
+    }
+}
+
+// Our flagged move for a cozy-chess move played on `board`.
+pub fn from_move(board: &Board, mv: cozy_chess::Move) -> Move {
+    board.encode_move(
+        from_square(mv.from),
+        from_square(mv.to),
+        mv.promotion.map(from_piece),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::generate_moves_fast;
+    use crate::types::MoveList;
+
+    const FENS: [&str; 5] = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "1r2k1rr/8/8/8/8/8/8/1R2K1RR w GBgb - 0 1",
+    ];
+
+    #[test]
+    fn positions_round_trip() {
+        for fen in FENS {
+            let (board, turn) = Board::from_fen(fen).unwrap();
+            let position = to_board(&board, turn).unwrap();
+            let (back, back_turn) = from_board(&position);
+            assert_eq!(back_turn, turn);
+            assert_eq!(back.to_fen(back_turn), board.to_fen(turn), "{}", fen);
+            assert_eq!(back.hash(turn), board.hash(turn));
+        }
+        let game = Game::new();
+        let position = cozy_chess::Board::try_from(&game).unwrap();
+        assert_eq!(position, cozy_chess::Board::default());
+        assert!(to_board(&Board::new(), Color::White).is_err());
+        for sq in [0, 7, 36, 63] {
+            assert_eq!(from_square(square(sq)), sq);
+        }
+    }
+
+    #[test]
+    fn moves_match_the_legal_moves() {
+        for fen in FENS {
+            let (mut board, turn) = Board::from_fen(fen).unwrap();
+            let position = to_board(&board, turn).unwrap();
+            let mut theirs = Vec::new();
+            position.generate_moves(|moves| {
+                theirs.extend(moves.into_iter().map(|m| from_move(&board, m).0));
+                false
+            });
+            let mut list = MoveList::new();
+            generate_moves_fast(&mut board, turn, &mut list);
+            let mut ours: Vec<u16> = list.iter().map(|m| m.0).collect();
+            theirs.sort_unstable();
+            ours.sort_unstable();
+            assert_eq!(ours, theirs, "{}", fen);
+
+            for &mv in list.iter() {
+                let converted = to_move(&board, mv);
+                assert!(
+                    position.is_legal(converted),
+                    "{} in {}",
+                    mv.to_algebraic(),
+                    fen
+                );
+                assert_eq!(from_move(&board, converted), mv);
+            }
+        }
+    }
+}
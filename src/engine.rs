@@ -1,15 +1,22 @@
 use crate::board::Board; // Removed color_idx, UndoState
+use crate::eval::EvalWeights;
 use crate::game::Game;
-use crate::opening::book_move;
-use crate::pieces::{Color, Piece, PieceType};
+use crate::human::HumanStyle;
+use crate::mcts::{MctsEvaluator, MctsParams};
+use crate::opening::{BookLearning, BookOptions, OpeningBook};
+use crate::pieces::{Color, PieceType};
 use crate::transposition::{Bound, TABLE_SIZE, TTEntry, Table};
 use crate::types::{Move, mvv_lva_score}; // Import Move, mvv_lva_score
-use shakmaty::{CastlingMode, Chess, fen::Fen};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+#[cfg(feature = "syzygy")]
+use shakmaty::Chess;
+#[cfg(feature = "syzygy")]
 use shakmaty_syzygy::{Tablebase, Wdl};
 use std::collections::HashMap;
 use std::env;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 #[derive(Clone, Debug, Default)]
@@ -51,12 +58,52 @@ impl TimeConfig {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct PvLine {
+    pub score: i32,
+    pub moves: Vec<(String, String)>,
+}
+
+impl PvLine {
+    pub fn mate_in(&self) -> Option<i32> {
+        mate_distance(self.score)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchInfo {
+    pub depth: u32,
+    // Deepest ply reached so far, quiescence and extensions included.
+    pub seldepth: u32,
+    pub nodes: u64,
+    pub time_ms: u64,
+    pub lines: Vec<PvLine>,
+}
+
+impl SearchInfo {
+    pub fn best(&self) -> Option<&PvLine> {
+        self.lines.first()
+    }
+}
+
+pub fn mate_distance(score: i32) -> Option<i32> {
+    if score.abs() < MATE_VALUE - MAX_PLY as i32 {
+        return None;
+    }
+    let moves = (MATE_VALUE - score.abs() + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
 #[allow(dead_code)]
 struct TimeManager {
     start_time: Instant,
     allocated_time_ms: u64,
     max_time_ms: u64,
+    // The allotted time as scaled by the search so far, capped by `max_time_ms`.
+    limit_ms: AtomicU64,
     in_crisis: bool,
+    // Whether the time comes from a clock, so a move may be played early.
+    clock: bool,
     stop_flag: Arc<AtomicBool>,
     node_count: Arc<AtomicU64>,
 }
@@ -71,7 +118,9 @@ impl TimeManager {
                 start_time: Instant::now(),
                 allocated_time_ms: movetime,
                 max_time_ms: movetime,
+                limit_ms: AtomicU64::new(movetime),
                 in_crisis: false,
+                clock: false,
                 stop_flag,
                 node_count,
             };
@@ -82,7 +131,9 @@ impl TimeManager {
                 start_time: Instant::now(),
                 allocated_time_ms: u64::MAX,
                 max_time_ms: u64::MAX,
+                limit_ms: AtomicU64::new(u64::MAX),
                 in_crisis: false,
+                clock: false,
                 stop_flag,
                 node_count,
             };
@@ -105,7 +156,9 @@ impl TimeManager {
             start_time: Instant::now(),
             allocated_time_ms: allocated,
             max_time_ms: max_time,
+            limit_ms: AtomicU64::new(allocated),
             in_crisis,
+            clock: true,
             stop_flag,
             node_count,
         }
@@ -171,7 +224,7 @@ impl TimeManager {
         }
 
         let elapsed = self.start_time.elapsed().as_millis() as u64;
-        elapsed >= self.allocated_time_ms
+        elapsed >= self.limit_ms.load(Ordering::Relaxed)
     }
 
     #[allow(dead_code)]
@@ -181,7 +234,6 @@ impl TimeManager {
         elapsed >= self.max_time_ms
     }
 
-    #[allow(dead_code)]
     fn elapsed_ms(&self) -> u64 {
         self.start_time.elapsed().as_millis() as u64
     }
@@ -205,35 +257,236 @@ impl TimeManager {
         self.node_count.load(Ordering::Relaxed)
     }
 
+    // An easy move is played once this share of the allotted time is used.
+    fn easy_move_done(&self) -> bool {
+        self.clock && self.elapsed_ms() >= self.allocated_time_ms / EASY_MOVE_DIVISOR
+    }
+
     fn should_continue_iterating(&self) -> bool {
         if self.stop_flag.load(Ordering::Relaxed) {
             return false;
         }
         let elapsed = self.start_time.elapsed().as_millis() as u64;
-        elapsed < self.allocated_time_ms / 2
+        elapsed < self.limit_ms.load(Ordering::Relaxed) / 2
+    }
+
+    // Sets the limit to `percent` of the allotted time when playing on a clock.
+    fn scale(&self, percent: u64) {
+        if self.clock {
+            let limit = (self.allocated_time_ms * percent / 100).clamp(10, self.max_time_ms);
+            self.limit_ms.store(limit, Ordering::Relaxed);
+        }
+    }
+}
+
+// Best root move and score across iterations, for the time decisions.
+#[derive(Default)]
+struct RootStats {
+    best: Option<Move>,
+    score: i32,
+    // Completed iterations in a row that kept `best`.
+    stable: u32,
+    // Change of the score over the last iteration.
+    delta: i32,
+    // One bit per iteration, newest lowest, set when the best move changed.
+    changes: u8,
+}
+
+impl RootStats {
+    fn update(&mut self, best: Move, score: i32) {
+        let changed = self.best.is_some() && self.best != Some(best);
+        self.changes = (self.changes << 1) | changed as u8;
+        if self.best == Some(best) {
+            self.stable += 1;
+            self.delta = score - self.score;
+        } else {
+            self.stable = 0;
+            self.delta = 0;
+        }
+        self.best = Some(best);
+        self.score = score;
+    }
+
+    // Share of the allotted time worth spending, in percent: more while the
+    // best move keeps changing or the score falls, less once the move has
+    // held for many iterations.
+    fn time_percent(&self) -> u64 {
+        let changes = (self.changes & 0x0f).count_ones() as u64;
+        let mut percent = 100 + 25 * changes;
+        if self.delta < -SCORE_DROP_MARGIN {
+            percent += 50;
+        } else if self.stable >= STABLE_ITERATIONS {
+            percent = 50;
+        }
+        percent
+    }
+
+    // A recapture on the square the opponent just captured on that has held
+    // for a few iterations with a steady score.
+    fn easy_move(&self, recapture_sq: Option<u8>) -> bool {
+        self.best.is_some_and(|m| {
+            m.is_capture()
+                && Some(m.to_sq()) == recapture_sq
+                && self.stable >= EASY_MOVE_STABLE
+                && self.delta.abs() <= EASY_MOVE_MARGIN
+        })
     }
 }
 
-const RFP_MARGIN: [i32; 4] = [0, 150, 250, 350];
+const EASY_MOVE_STABLE: u32 = 4;
+const EASY_MOVE_MARGIN: i32 = 30;
+const EASY_MOVE_DIVISOR: u64 = 10;
+const SCORE_DROP_MARGIN: i32 = 30;
+const STABLE_ITERATIONS: u32 = 6;
 const HLP_THRESHOLD: u32 = 3;
 const HLP_BASE: i32 = -50;
-const LMP_LIMITS: [usize; 5] = [0, 5, 7, 10, 14];
 const MATE_VALUE: i32 = 10000;
+// Ahead of killers and every non-losing capture but the biggest ones.
+const QUEEN_PROMOTION_BONUS: i32 = 20_000;
 const MAX_PLY: usize = 128;
+const MAX_MOVES: usize = 256;
 const MAX_DEPTH: u32 = 64;
+// Multi-cut: at cut nodes this deep, if `MC_CUTS` of the first `MC_MOVES`
+// moves fail high at `MC_REDUCTION` plies less, the node is pruned.
+const MC_DEPTH: u32 = 6;
+const MC_MOVES: usize = 6;
+const MC_CUTS: usize = 3;
+const MC_REDUCTION: u32 = 3;
+const IIR_DEPTH: u32 = 4;
+const COUNTER_MOVE_BONUS: i32 = 8_000;
+// History a quiet move needs to be reduced one ply less, or lacks to be
+// reduced one ply more.
+const LMR_HISTORY_DIVISOR: i32 = 512;
+
+// What a node is expected to do: PV nodes are searched with an open window,
+// cut nodes should fail high and all nodes fail low.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NodeType {
+    Pv,
+    Cut,
+    All,
+}
+
+impl NodeType {
+    // The expected type of a child searched with a null window.
+    fn child(self) -> NodeType {
+        match self {
+            NodeType::Pv | NodeType::All => NodeType::Cut,
+            NodeType::Cut => NodeType::All,
+        }
+    }
+}
+
+// The window one MultiPV line is searched with, and the root moves already
+// given as better lines.
+struct RootWindow<'a> {
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    excluded: &'a [Move],
+}
 
+// Configuration and the tables shared by every search: the transposition
+// table, tablebases and book sit behind `Arc`s, so an `Arc<Engine>` can be
+// handed to several threads, each searching with its own `fork`.
 pub struct Engine {
     pub depth: u32,
     pub threads: usize,
+    // The thread count asked for, which `threads` goes back to when the
+    // reproducible mode is turned off.
+    requested_threads: usize,
+    pub params: SearchParams,
+    pub weights: EvalWeights,
     tt: Table,
+    #[cfg(feature = "syzygy")]
+    tb: Option<Arc<Tablebase<Chess>>>,
+    book: Arc<OpeningBook>,
+    learning: Option<Arc<Mutex<BookLearning>>>,
+    stop_flag: Arc<AtomicBool>,
+    search: SearchWorker,
+}
+
+// State one search builds up as it runs: move ordering heuristics, the game
+// history for repetitions, and what it reports once it is done.
+#[derive(Clone)]
+struct SearchWorker {
     killers: Vec<[Option<Move>; 2]>,
     quiet_history: [[i32; 64]; 64],
     capture_history: [[i32; 64]; 64],
     cont_history: HashMap<(u16, u16), i32>,
-    tb: Option<Arc<Tablebase<Chess>>>,
-    stop_flag: Arc<AtomicBool>,
+    // The quiet move that last refuted each move, by its from and to squares.
+    counter_moves: [[Option<Move>; 64]; 64],
+    // Latest score of each root move in the current search, which orders the
+    // root moves of the next iteration.
+    root_scores: Vec<(Move, i32)>,
     time_manager: Option<Arc<TimeManager>>,
+    // Positions of the game followed by those on the current search path.
     search_history: Vec<u64>,
+    // For each ply of the current path, the earliest place in
+    // `search_history` a repetition below that node went back to.
+    rep_floors: Vec<usize>,
+    // Score of the last search, from the side to move's point of view.
+    last_score: Option<i32>,
+    last_nodes: u64,
+    // Deepest ply reached by the current or last search.
+    seldepth: usize,
+}
+
+impl Default for SearchWorker {
+    fn default() -> Self {
+        Self {
+            killers: vec![[None, None]; MAX_PLY],
+            quiet_history: [[0; 64]; 64],
+            capture_history: [[0; 64]; 64],
+            cont_history: HashMap::new(),
+            counter_moves: [[None; 64]; 64],
+            root_scores: Vec::new(),
+            time_manager: None,
+            search_history: Vec::new(),
+            rep_floors: Vec::new(),
+            last_score: None,
+            last_nodes: 0,
+            seldepth: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchParams {
+    pub use_book: bool,
+    pub book: BookOptions,
+    // Play weak, human-like moves instead of the best one.
+    pub human: Option<HumanStyle>,
+    // Pick moves with the Monte Carlo tree searcher instead of alpha-beta.
+    pub mcts: Option<MctsParams>,
+    pub null_move: bool,
+    pub history_pruning: bool,
+    pub late_move_reductions: bool,
+    pub multi_cut: bool,
+    pub rfp_margins: [i32; 4],
+    pub lmp_limits: [usize; 5],
+    // Reproducible play: book sampling and human-style picks draw from this
+    // seed mixed with the position, and the engine keeps to one thread, so a
+    // depth-limited search gives the same move every time.
+    pub seed: Option<u64>,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            use_book: true,
+            book: BookOptions::default(),
+            human: None,
+            mcts: None,
+            null_move: true,
+            history_pruning: true,
+            late_move_reductions: true,
+            multi_cut: true,
+            rfp_margins: [0, 150, 250, 350],
+            lmp_limits: [0, 5, 7, 10, 14],
+            seed: None,
+        }
+    }
 }
 
 impl Clone for Engine {
@@ -241,15 +494,16 @@ impl Clone for Engine {
         Self {
             depth: self.depth,
             threads: self.threads,
+            requested_threads: self.requested_threads,
+            params: self.params.clone(),
+            weights: self.weights,
             tt: self.tt.clone(), // Arc clone - shares the table!
-            killers: self.killers.clone(),
-            quiet_history: self.quiet_history,     // Array copy
-            capture_history: self.capture_history, // Array copy
-            cont_history: self.cont_history.clone(),
+            #[cfg(feature = "syzygy")]
             tb: self.tb.clone(),
+            book: self.book.clone(),
+            learning: self.learning.clone(),
             stop_flag: self.stop_flag.clone(),
-            time_manager: self.time_manager.clone(),
-            search_history: self.search_history.clone(),
+            search: self.search.clone(),
         }
     }
 }
@@ -267,20 +521,41 @@ impl Engine {
         Self {
             depth,
             threads,
+            requested_threads: threads,
+            params: SearchParams::default(),
+            weights: EvalWeights::default(),
             tt: Table::new(table_size.max(1)),
-            killers: vec![[None, None]; MAX_PLY],
-            quiet_history: [[0; 64]; 64],
-            capture_history: [[0; 64]; 64],
-            cont_history: HashMap::new(),
+            #[cfg(feature = "syzygy")]
             tb: None,
+            book: Arc::new(OpeningBook::default()),
+            learning: None,
             stop_flag: Arc::new(AtomicBool::new(false)),
-            time_manager: None,
-            search_history: Vec::new(),
+            search: SearchWorker::default(),
         }
     }
 
     pub fn set_threads(&mut self, threads: usize) {
-        self.threads = threads;
+        self.requested_threads = threads;
+        self.threads = if self.params.seed.is_some() {
+            1
+        } else {
+            threads
+        };
+    }
+
+    // Turns the reproducible mode of `SearchParams::seed` on or off.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.params.seed = seed;
+        self.set_threads(self.requested_threads);
+    }
+
+    // Random numbers for the choices made in `game`: fixed by the seed and
+    // the position when there is one.
+    fn rng(&self, game: &Game) -> StdRng {
+        match self.params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed ^ game.board.hash(game.current_turn)),
+            None => StdRng::from_entropy(),
+        }
     }
 
     pub fn from_env(default_depth: u32, default_threads: usize) -> Self {
@@ -296,9 +571,15 @@ impl Engine {
             .ok()
             .and_then(|v| v.parse::<usize>().ok())
             .unwrap_or(TABLE_SIZE);
-        Self::with_threads_and_table(depth, threads, tt_size)
+        let seed = env::var("CHESSMIND_SEED")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok());
+        let mut engine = Self::with_threads_and_table(depth, threads, tt_size);
+        engine.set_seed(seed);
+        engine
     }
 
+    #[cfg(feature = "syzygy")]
     pub fn load_syzygy(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let mut tb = Tablebase::new();
         tb.add_directory(path)?;
@@ -306,6 +587,11 @@ impl Engine {
         Ok(())
     }
 
+    #[cfg(not(feature = "syzygy"))]
+    pub fn load_syzygy(&mut self, _path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Err("chessmind was built without the `syzygy` feature".into())
+    }
+
     pub fn load_syzygy_from_env(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
         if let Ok(path) = env::var("SYZYGY_PATH") {
             self.load_syzygy(&path)?;
@@ -314,70 +600,103 @@ impl Engine {
         Ok(None)
     }
 
-    pub fn stop(&self) {
-        self.stop_flag.store(true, Ordering::Release);
+    // Replaces the built-in book with a Polyglot `.bin` or text book and
+    // enables it.
+    pub fn load_book(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.book = Arc::new(OpeningBook::load(path)?);
+        self.params.use_book = true;
+        Ok(())
     }
 
-    fn reset_stop(&mut self) {
-        self.stop_flag = Arc::new(AtomicBool::new(false));
+    pub fn load_book_from_env(&mut self) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        if let Ok(path) = env::var("CHESSMIND_BOOK") {
+            self.load_book(&path)?;
+            return Ok(Some(path));
+        }
+        Ok(None)
     }
 
-    #[inline(always)]
-    fn piece_value(t: PieceType) -> i32 {
-        crate::types::PieceValues::value(t)
+    pub fn set_book_enabled(&mut self, enabled: bool) {
+        self.params.use_book = enabled;
+    }
+
+    pub fn book(&self) -> &OpeningBook {
+        &self.book
+    }
+
+    // Skips book moves that keep losing according to the results kept in
+    // `path`, which is shared with every other engine learning there.
+    pub fn enable_book_learning(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.learning = Some(BookLearning::shared(path)?);
+        Ok(())
     }
 
-    fn string_to_move(&self, board: &Board, s: &str, e: &str) -> Move {
-        let (sx, sy) = Board::algebraic_to_index(s).unwrap();
-        let (ex, ey) = Board::algebraic_to_index(e).unwrap();
-        let from = (sy * 8 + sx) as u8;
-        let to = (ey * 8 + ex) as u8;
+    pub fn disable_book_learning(&mut self) {
+        self.learning = None;
+    }
 
-        let piece = board.get_index(sx, sy).unwrap();
-        let captured = board.get_index(ex, ey);
+    // Records the book moves `color` played from `from_ply` on and saves the
+    // results. Does nothing unless book learning is enabled.
+    pub fn learn_from_game(
+        &self,
+        game: &Game,
+        from_ply: usize,
+        color: Color,
+        winner: Option<Color>,
+    ) -> std::io::Result<()> {
+        let Some(learning) = &self.learning else {
+            return Ok(());
+        };
+        let mut learning = learning.lock().unwrap();
+        learning.record(&self.book, game, from_ply, color, winner);
+        learning.save()
+    }
 
-        let is_capture = captured.is_some();
-        let mut flags = Move::FLAG_NORMAL;
+    pub fn stop(&self) {
+        self.stop_flag.store(true, Ordering::Release);
+    }
 
-        match piece.piece_type {
-            PieceType::Pawn => {
-                let diff_y = (ey as isize - sy as isize).abs();
-                let diff_x = (ex as isize - sx as isize).abs();
+    pub fn new_game(&mut self) {
+        self.search = SearchWorker {
+            last_score: self.search.last_score,
+            last_nodes: self.search.last_nodes,
+            seldepth: self.search.seldepth,
+            ..SearchWorker::default()
+        };
+    }
 
-                if ey == 0 || ey == 7 {
-                    if is_capture {
-                        flags = Move::FLAG_PROMO_QUEEN_CAP;
-                    } else {
-                        flags = Move::FLAG_PROMO_QUEEN;
-                    }
-                } else if diff_y == 2 && diff_x == 0 {
-                    flags = Move::FLAG_DOUBLE_PUSH;
-                } else if diff_x != 0 && !is_capture {
-                    flags = Move::FLAG_EP_CAPTURE;
-                } else if is_capture {
-                    flags = Move::FLAG_CAPTURE;
-                }
-            }
-            PieceType::King => {
-                let diff_x = (ex as isize - sx as isize).abs();
-                if diff_x == 2 {
-                    if ex > sx {
-                        flags = Move::FLAG_KING_CASTLE;
-                    } else {
-                        flags = Move::FLAG_QUEEN_CASTLE;
-                    }
-                } else if is_capture {
-                    flags = Move::FLAG_CAPTURE;
-                }
-            }
-            _ => {
-                if is_capture {
-                    flags = Move::FLAG_CAPTURE;
-                }
-            }
+    // An engine for another thread, sharing this one's configuration,
+    // transposition table, tablebases and book but with a stop flag and
+    // search state of its own, so nothing per-search is copied and stopping
+    // one search leaves the others running.
+    pub fn fork(&self) -> Self {
+        Self {
+            depth: self.depth,
+            threads: self.threads,
+            requested_threads: self.requested_threads,
+            params: self.params.clone(),
+            weights: self.weights,
+            tt: self.tt.clone(),
+            #[cfg(feature = "syzygy")]
+            tb: self.tb.clone(),
+            book: self.book.clone(),
+            learning: self.learning.clone(),
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            search: SearchWorker::default(),
         }
+    }
+
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop_flag.clone()
+    }
+
+    fn reset_stop(&mut self) {
+        self.stop_flag.store(false, Ordering::Release);
+    }
 
-        Move::new(from, to, flags)
+    #[inline(always)]
+    fn piece_value(t: PieceType) -> i32 {
+        crate::types::PieceValues::value(t)
     }
 
     fn generate_legal_moves(&self, board: &mut Board, color: Color) -> crate::types::MoveList {
@@ -386,84 +705,134 @@ impl Engine {
         list
     }
 
+    // Static score for `color` to move, from its own point of view.
     #[inline(always)]
-    fn evaluate(board: &Board, color: Color) -> i32 {
-        crate::eval::evaluate(board, color)
+    fn evaluate(&self, board: &Board, color: Color) -> i32 {
+        crate::eval::evaluate_weighted(board, color, &self.weights)
     }
 
-    fn cheapest_attacker(
-        board: &mut Board,
-        color: Color,
-        tx: usize,
-        ty: usize,
-    ) -> Option<((usize, usize), Piece)> {
-        let target = Board::index_to_algebraic(tx, ty)?;
-        let mut best: Option<((usize, usize), Piece)> = None;
-        for y in 0..8 {
-            for x in 0..8 {
-                if let Some(p) = board.get_index(x, y) {
-                    if p.color == color {
-                        if let Some(from) = Board::index_to_algebraic(x, y) {
-                            if board.pseudo_legal_moves(&from).iter().any(|m| m == &target)
-                                && board.is_legal(&from, &target, color)
-                            {
-                                if best.as_ref().map_or(true, |(_, bp)| {
-                                    Self::piece_value(p.piece_type)
-                                        < Self::piece_value(bp.piece_type)
-                                }) {
-                                    best = Some(((x, y), p));
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    // Pieces of `color` pinned away from `target`, which cannot join an
+    // exchange there.
+    fn pinned_off_line(board: &Board, color: Color, target: usize) -> u64 {
+        let pinned = board.pinned(color);
+        if pinned == 0 {
+            return 0;
         }
-        best
-    }
-
-    fn see_rec(&self, board: &mut Board, color: Color, tx: usize, ty: usize) -> i32 {
-        if let Some(((sx, sy), _piece)) = Self::cheapest_attacker(board, color, tx, ty) {
-            let from = Board::index_to_algebraic(sx, sy).unwrap();
-            let to = Board::index_to_algebraic(tx, ty).unwrap();
-            if let Some(state) = board.make_move_state(&from, &to) {
-                let captured_val = state
-                    .captured
-                    .map_or(0, |p| Self::piece_value(p.piece_type));
-                let gain = captured_val - self.see_rec(board, opposite(color), tx, ty);
-                board.unmake_move(state);
-                return gain.max(0);
+        let king_sq = board.pieces_of(color, PieceType::King).trailing_zeros() as usize;
+        let mut off_line = 0u64;
+        let mut bb = pinned;
+        while bb != 0 {
+            let sq = bb.trailing_zeros() as usize;
+            if crate::movegen::LINE[king_sq][sq] & (1u64 << target) == 0 {
+                off_line |= 1u64 << sq;
             }
+            bb &= bb - 1;
         }
-        0
+        off_line
     }
 
+    // Swap-list SEE: both sides capture on the target with their least
+    // valuable attacker, each free to stop when going on would lose. Removing
+    // a capturer from the occupancy uncovers the sliders behind it (x-rays).
     #[inline(always)]
     fn static_exchange_eval(&self, board: &Board, mv: Move) -> i32 {
-        let sx = (mv.from_sq() % 8) as usize;
-        let sy = (mv.from_sq() / 8) as usize;
-        let ex = (mv.to_sq() % 8) as usize;
-        let ey = (mv.to_sq() / 8) as usize;
+        use crate::movegen::{attackers, piece_attacks};
 
-        if board.get_index(sx, sy).is_none() {
+        let Some((piece_type, color)) = board.piece_at_sq(mv.from_sq()) else {
             return 0; // Should not happen for legal moves
-        }
-
-        let mut b = board.clone(); // Clone is expensive! Use with caution.
-
-        let color = board.get_index(sx, sy).unwrap().color;
-
-        let undo = b.make_move_fast(mv, color);
-
-        let captured_val = if undo.has_capture() {
-            crate::types::PieceValues::value_by_idx(undo.captured as usize)
+        };
+        let target = mv.to_sq() as usize;
+        let victim = if mv.is_ep() {
+            crate::types::PieceValues::PAWN
         } else {
-            0
+            board
+                .piece_at_sq(mv.to_sq())
+                .map_or(0, |(victim, _)| Self::piece_value(victim))
         };
 
-        let gain = captured_val - self.see_rec(&mut b, opposite(color), ex, ey);
-        b.unmake_move_fast(undo, color);
-        return gain;
+        let mut occ = board.occupied() & !(1u64 << mv.from_sq());
+        if mv.is_ep() {
+            occ &= !(1u64 << (mv.from_sq() / 8 * 8 + mv.to_sq() % 8));
+        }
+        let usable = !(Self::pinned_off_line(board, Color::White, target)
+            | Self::pinned_off_line(board, Color::Black, target));
+        let queens = board.bitboards[0][4] | board.bitboards[1][4];
+        let diagonal = board.bitboards[0][2] | board.bitboards[1][2] | queens;
+        let straight = board.bitboards[0][3] | board.bitboards[1][3] | queens;
+        let mut attacking = (attackers(board, target, Color::White, occ)
+            | attackers(board, target, Color::Black, occ))
+            & occ
+            & usable;
+
+        let mut gain = [0i32; 32];
+        gain[0] = victim;
+        let mut depth = 0;
+        let mut side = opposite(color);
+        let mut on_target = Self::piece_value(piece_type);
+        while depth < gain.len() - 1 {
+            let own = attacking & board.occupied_by(side);
+            let Some((sq, pt)) = crate::board::PIECE_TYPES.iter().find_map(|&pt| {
+                let bb = own & board.pieces_of(side, pt);
+                (bb != 0).then(|| (bb.trailing_zeros() as usize, pt))
+            }) else {
+                break;
+            };
+            depth += 1;
+            gain[depth] = on_target - gain[depth - 1];
+            occ &= !(1u64 << sq);
+            if matches!(pt, PieceType::Pawn | PieceType::Bishop | PieceType::Queen) {
+                attacking |= piece_attacks(PieceType::Bishop, side, target, occ) & diagonal;
+            }
+            if matches!(pt, PieceType::Rook | PieceType::Queen) {
+                attacking |= piece_attacks(PieceType::Rook, side, target, occ) & straight;
+            }
+            attacking &= occ & usable;
+            on_target = Self::piece_value(pt);
+            side = opposite(side);
+        }
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    fn counter_move(&self, prev: Option<&Move>) -> Option<Move> {
+        prev.and_then(|p| self.search.counter_moves[p.from_sq() as usize][p.to_sq() as usize])
+    }
+
+    // Reduction for the late quiet move `mv`: none for killers and counter
+    // moves, otherwise `lmr_value` shifted by the move's history, one ply
+    // less at PV nodes, whose line is kept, and one more at cut nodes, where
+    // a late move is least likely to matter.
+    fn lmr_reduction(
+        &self,
+        depth: u32,
+        idx: usize,
+        mv: Move,
+        ply: usize,
+        prev: Option<&Move>,
+        node: NodeType,
+    ) -> u32 {
+        let killer = self
+            .search
+            .killers
+            .get(ply)
+            .is_some_and(|k| k.contains(&Some(mv)));
+        if killer || self.counter_move(prev) == Some(mv) {
+            return 0;
+        }
+        let mut history = self.search.quiet_history[mv.from_sq() as usize][mv.to_sq() as usize];
+        if let Some(pmv) = prev {
+            history += *self.search.cont_history.get(&(pmv.0, mv.0)).unwrap_or(&0);
+        }
+        let mut r = Self::lmr_value(depth, idx) as i32 - history / LMR_HISTORY_DIVISOR;
+        match node {
+            NodeType::Pv => r -= 1,
+            NodeType::Cut => r += 1,
+            NodeType::All => {}
+        }
+        r.clamp(0, depth as i32 - 1) as u32
     }
 
     #[inline(always)]
@@ -483,17 +852,127 @@ impl Engine {
         r as u32
     }
 
+    // Score of the last `best_move` search, or `None` for book moves.
+    pub fn last_score(&self) -> Option<i32> {
+        self.search.last_score
+    }
+
+    // Nodes searched by the last `best_move` search.
+    pub fn last_nodes(&self) -> u64 {
+        self.search.last_nodes
+    }
+
+    // Selective depth of the last `best_move` search: the deepest ply it
+    // reached, quiescence and extensions included.
+    pub fn last_seldepth(&self) -> u32 {
+        self.search.seldepth as u32
+    }
+
+    // Tablebase score of the position for `color` to move, if it is covered.
+    pub fn probe_tablebase(&self, board: &Board, color: Color) -> Option<i32> {
+        self.probe_syzygy(board, color, 0)
+    }
+
+    // A board diagram, the FEN, Zobrist key and game phase, and the static
+    // evaluation term by term, for debugging a misjudged position. Terms are
+    // White-relative in centipawns; the last line is the score the search
+    // uses, with the engine's weights and the tempo bonus.
+    pub fn eval_string(&self, board: &Board, color: Color) -> String {
+        let border = "  +---+---+---+---+---+---+---+---+\n";
+        let mut out = String::from(border);
+        for y in (0..8).rev() {
+            out.push_str(&format!("{} |", y + 1));
+            for x in 0..8 {
+                let symbol = board.piece_at_sq(y * 8 + x).map_or(' ', |(piece, c)| {
+                    let symbol = match piece {
+                        PieceType::Pawn => 'p',
+                        PieceType::Knight => 'n',
+                        PieceType::Bishop => 'b',
+                        PieceType::Rook => 'r',
+                        PieceType::Queen => 'q',
+                        PieceType::King => 'k',
+                    };
+                    if c == Color::White {
+                        symbol.to_ascii_uppercase()
+                    } else {
+                        symbol
+                    }
+                });
+                out.push_str(&format!(" {} |", symbol));
+            }
+            out.push('\n');
+            out.push_str(border);
+        }
+        out.push_str("    a   b   c   d   e   f   g   h\n\n");
+
+        let breakdown = crate::eval::explain(board);
+        out.push_str(&format!("Fen: {}\n", board.to_fen(color)));
+        out.push_str(&format!("Key: {:016X}\n", board.hash(color)));
+        out.push_str(&format!(
+            "Phase: {} ({} is a middlegame, 0 an endgame)\n\n",
+            breakdown.phase,
+            crate::types::Phase::TOTAL_PHASE
+        ));
+
+        if let Some(score) = breakdown.endgame {
+            out.push_str(&format!("Recognised endgame: {}\n", score));
+        } else {
+            let row = |name: &str, white: String, black: String, total: String| {
+                format!("{:<16}|{:>14} |{:>14} |{:>14}\n", name, white, black, total)
+            };
+            let pair = |s: crate::eval::Score| format!("{:>6} {:>6}", s.mg(), s.eg());
+            out.push_str(&row(
+                "Term",
+                "White MG EG".into(),
+                "Black MG EG".into(),
+                "Net tapered".into(),
+            ));
+            out.push_str(&format!("{}\n", "-".repeat(64)));
+            let terms = [
+                breakdown.material,
+                breakdown.pst,
+                breakdown.pawn_structure,
+                breakdown.mobility,
+                breakdown.pieces,
+                breakdown.king_safety,
+            ];
+            for ((name, net), term) in breakdown.terms().into_iter().zip(terms) {
+                out.push_str(&row(
+                    name,
+                    pair(term.white),
+                    pair(term.black),
+                    net.to_string(),
+                ));
+            }
+            out.push_str(&format!("{}\n", "-".repeat(64)));
+            out.push_str(&row(
+                "Total",
+                String::new(),
+                String::new(),
+                breakdown.total.to_string(),
+            ));
+        }
+        let score = self.evaluate(board, color);
+        let white = if color == Color::White { score } else { -score };
+        out.push_str(&format!(
+            "\nEvaluation: {} (White), {} for the side to move\n",
+            white, score
+        ));
+        out
+    }
+
+    #[cfg(not(feature = "syzygy"))]
+    fn probe_syzygy(&self, _board: &Board, _color: Color, _ply: usize) -> Option<i32> {
+        None
+    }
+
+    #[cfg(feature = "syzygy")]
     fn probe_syzygy(&self, board: &Board, color: Color, ply: usize) -> Option<i32> {
         let tb = self.tb.as_ref()?;
-        if board.piece_count_all() > tb.max_pieces() {
+        if crate::material::piece_count(board.material_key) as usize > tb.max_pieces() {
             return None;
         }
-        let fen = board.to_fen(color);
-        let pos: Chess = fen
-            .parse::<Fen>()
-            .ok()?
-            .into_position(CastlingMode::Standard)
-            .ok()?;
+        let pos = crate::convert::to_chess(board, color).ok()?;
         let wdl = tb.probe_wdl(&pos).ok()?.after_zeroing();
         Some(match wdl {
             Wdl::Win | Wdl::CursedWin => MATE_VALUE - ply as i32,
@@ -502,6 +981,40 @@ impl Engine {
         })
     }
 
+    fn root_score(&self, mv: Move) -> Option<i32> {
+        self.search
+            .root_scores
+            .iter()
+            .find(|&&(m, _)| m == mv)
+            .map(|&(_, score)| score)
+    }
+
+    fn record_root_score(&mut self, mv: Move, score: i32) {
+        match self.search.root_scores.iter_mut().find(|(m, _)| *m == mv) {
+            Some(entry) => entry.1 = score,
+            None => self.search.root_scores.push((mv, score)),
+        }
+    }
+
+    // Root moves come hash move first, then by their score in the previous
+    // iteration, ties and unsearched moves falling back to the usual ordering
+    // with the game's last move as the one to counter.
+    fn order_root_moves(
+        &self,
+        board: &Board,
+        moves: &mut [Move],
+        tt_best: Option<Move>,
+        prev: Option<&Move>,
+    ) {
+        moves.sort_by_cached_key(|&m| {
+            std::cmp::Reverse((
+                tt_best == Some(m),
+                self.root_score(m),
+                self.move_score(board, m, 0, prev),
+            ))
+        });
+    }
+
     fn move_score(&self, board: &Board, mv: Move, ply: usize, prev: Option<&Move>) -> i32 {
         let mut score = 0;
         let capture = mv.is_capture();
@@ -509,7 +1022,7 @@ impl Engine {
         let to = mv.to_sq() as usize;
 
         if capture {
-            score += self.capture_history[from][to];
+            score += self.search.capture_history[from][to];
 
             let victim_idx = if mv.is_ep() {
                 0 // Pawn
@@ -529,8 +1042,8 @@ impl Engine {
                 score -= 1000;
             }
         } else {
-            score += self.quiet_history[from][to];
-            if let Some(k) = self.killers.get(ply) {
+            score += self.search.quiet_history[from][to];
+            if let Some(k) = self.search.killers.get(ply) {
                 if let Some(m) = &k[0] {
                     if m.0 == mv.0 {
                         score += 10_000;
@@ -542,10 +1055,17 @@ impl Engine {
                     }
                 }
             }
+            if self.counter_move(prev) == Some(mv) {
+                score += COUNTER_MOVE_BONUS;
+            }
+        }
+
+        if mv.promotion_piece() == Some(PieceType::Queen) {
+            score += QUEEN_PROMOTION_BONUS;
         }
 
         if let Some(pmv) = prev {
-            score += *self.cont_history.get(&(pmv.0, mv.0)).unwrap_or(&0);
+            score += *self.search.cont_history.get(&(pmv.0, mv.0)).unwrap_or(&0);
         }
         score
     }
@@ -555,7 +1075,7 @@ impl Engine {
         if self.stop_flag.load(Ordering::Relaxed) {
             return true;
         }
-        if let Some(tm) = &self.time_manager {
+        if let Some(tm) = &self.search.time_manager {
             let count = tm.node_count.fetch_add(1, Ordering::Relaxed);
             if count & 2047 == 0 {
                 if tm.should_stop() {
@@ -579,8 +1099,25 @@ impl Engine {
         if self.should_stop() {
             return 0;
         }
+        self.search.seldepth = self.search.seldepth.max(ply);
+
+        // In check there is no standing pat: every evasion is searched, and
+        // having none is mate.
+        let in_check = board.in_check_fast(color);
+        if in_check && ply < MAX_PLY {
+            let moves = self.generate_legal_moves(board, color);
+            if moves.is_empty() {
+                return -MATE_VALUE + ply as i32;
+            }
+            let mut scored: Vec<(i32, Move)> = moves
+                .iter()
+                .map(|m| (self.move_score(board, *m, ply, None), *m))
+                .collect();
+            scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+            return self.quiescence_moves(board, color, &scored, alpha, beta, ply);
+        }
 
-        let stand_pat = Self::evaluate(board, color);
+        let stand_pat = self.evaluate(board, color);
 
         if stand_pat >= beta {
             return beta;
@@ -595,31 +1132,31 @@ impl Engine {
             return alpha;
         }
 
-        let list = self.generate_legal_moves(board, color);
-        let mut capt_list = crate::types::MoveList::new();
-        for m in list.iter() {
-            if m.is_capture() {
-                capt_list.push(*m);
-            }
-        }
-        let mut moves = capt_list;
-
-        let len = moves.len();
-        if len > 1 {
-            let slice = moves.as_mut_slice();
-            slice.sort_by(|a, b| {
-                let score_a = self.move_score(board, *a, ply, None);
-                let score_b = self.move_score(board, *b, ply, None);
-                score_b.cmp(&score_a)
-            });
-        }
-
-        for m in moves.iter() {
-            let see_value = self.static_exchange_eval(board, *m);
-            if see_value < 0 {
-                continue;
-            }
+        let mut moves = crate::types::MoveList::new();
+        crate::movegen::generate_captures_fast(board, color, &mut moves);
+
+        // Captures that lose material by SEE are dropped before they are
+        // scored.
+        let mut scored: Vec<(i32, Move)> = moves
+            .iter()
+            .filter(|m| self.static_exchange_eval(board, **m) >= 0)
+            .map(|m| (self.move_score(board, *m, ply, None), *m))
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.quiescence_moves(board, color, &scored, alpha, beta, ply)
+    }
 
+    // Searches `moves`, best first, below a quiescence node.
+    fn quiescence_moves(
+        &mut self,
+        board: &mut Board,
+        color: Color,
+        moves: &[(i32, Move)],
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+    ) -> i32 {
+        for (_, m) in moves {
             let undo = board.make_move_fast(*m, color);
 
             let score = -self.quiescence(board, opposite(color), -beta, -alpha, ply + 1);
@@ -645,23 +1182,29 @@ impl Engine {
         &mut self,
         board: &mut Board,
         color: Color,
-        depth: u32,
+        mut depth: u32,
         mut alpha: i32,
         mut beta: i32,
         ply: usize,
         prev_move: Option<Move>,
-        _use_iir: bool,
+        node: NodeType,
     ) -> i32 {
         if self.should_stop() {
             return 0;
         }
+        self.search.seldepth = self.search.seldepth.max(ply);
 
         if ply > 0 {
             let current_hash = board.hash(color);
-            for &h in &self.search_history {
-                if h == current_hash {
-                    return 0; // Draw by repetition
+            let history = &self.search.search_history;
+            if let Some(i) = history.iter().position(|&h| h == current_hash) {
+                for floor in self.search.rep_floors.iter_mut().take(ply) {
+                    *floor = (*floor).min(i);
                 }
+                return 0; // Draw by repetition
+            }
+            if board.insufficient_material() {
+                return 0;
             }
         }
 
@@ -680,9 +1223,21 @@ impl Engine {
         let alpha_orig = alpha;
         let hash = board.hash(color); // board.hash is u64 (Zobrist)
         let mut tt_best: Option<Move> = None;
+        // Where this position first appears in the history; repetitions of
+        // anything earlier make the score depend on the path.
+        let history = &self.search.search_history;
+        let node_index = history
+            .iter()
+            .position(|&h| h == hash)
+            .unwrap_or(history.len());
+        if self.search.rep_floors.len() <= ply {
+            self.search.rep_floors.resize(ply + 1, usize::MAX);
+        }
+        self.search.rep_floors[ply] = usize::MAX;
 
         if let Some(entry) = self.tt.get(hash) {
-            if entry.depth >= depth {
+            // No cutoffs at the root, which must always produce a move.
+            if ply > 0 && entry.depth >= depth {
                 match entry.bound {
                     Bound::Exact => return entry.value,
                     Bound::Lower => alpha = alpha.max(entry.value),
@@ -692,33 +1247,45 @@ impl Engine {
                     return entry.value;
                 }
             }
-            if let Some((fs, ts)) = entry.best {
-                let f_str =
-                    Board::index_to_algebraic((fs % 8) as usize, (fs / 8) as usize).unwrap();
-                let t_str =
-                    Board::index_to_algebraic((ts % 8) as usize, (ts / 8) as usize).unwrap();
-                tt_best = Some(self.string_to_move(board, &f_str, &t_str));
-            }
+            tt_best = entry.best;
         }
 
         if let Some(tb_val) = self.probe_syzygy(board, color, ply) {
             return tb_val;
         }
 
+        // Internal iterative reduction: without a hash move the ordering is
+        // poor, so search shallower and let the next iteration fill the
+        // table. Deep cut nodes, expected to fail high on any decent move,
+        // lose two plies; all nodes keep their depth.
+        if tt_best.is_none() && ply > 0 && depth >= IIR_DEPTH {
+            depth -= match node {
+                NodeType::Pv => 1,
+                NodeType::Cut if depth >= 2 * IIR_DEPTH => 2,
+                NodeType::Cut => 1,
+                NodeType::All => 0,
+            };
+        }
+
         if depth == 0 {
             return self.quiescence(board, color, alpha, beta, ply);
         }
 
-        let in_check = board.in_check(color);
+        let in_check = board.in_check_fast(color);
 
         if depth <= 3 && !in_check {
-            let eval = Self::evaluate(board, color);
-            if eval - RFP_MARGIN[depth as usize] >= beta {
+            let eval = self.evaluate(board, color);
+            if eval - self.params.rfp_margins[depth as usize] >= beta {
                 return eval;
             }
         }
 
-        let can_null = !in_check && board.piece_count_total(color) > 3 && depth >= 3;
+        // Null moves are unsound in zugzwang, so the side to move needs a piece
+        // besides its pawns; pawn endings are never pruned this way.
+        let can_null = self.params.null_move
+            && !in_check
+            && crate::material::has_non_pawn_material(board.material_key, color)
+            && depth >= 3;
         if can_null {
             let r = if depth > 6 { 3 } else { 2 };
             let ep = board.en_passant; // Backup EP
@@ -732,7 +1299,7 @@ impl Engine {
                 -beta + 1,
                 ply + 1,
                 None, // Prev move is null
-                false,
+                NodeType::All,
             );
             board.en_passant = ep; // Restore EP
 
@@ -750,7 +1317,7 @@ impl Engine {
                         beta,
                         ply,
                         prev_move,
-                        false,
+                        NodeType::Cut,
                     );
                     if verify >= beta {
                         return beta;
@@ -771,22 +1338,59 @@ impl Engine {
 
         let moves_slice = moves_list.as_mut_slice();
 
-        let mut scores = [0i32; 256];
-        for (i, m) in moves_slice.iter().enumerate() {
-            scores[i] = self.move_score(board, *m, ply, prev_move.as_ref());
-            if let Some(ttm) = tt_best {
-                if m.0 == ttm.0 {
+        if ply == 0 {
+            self.order_root_moves(board, moves_slice, tt_best, prev_move.as_ref());
+        } else {
+            let mut scores = [0i32; 256];
+            for (i, m) in moves_slice.iter().enumerate() {
+                scores[i] = self.move_score(board, *m, ply, prev_move.as_ref());
+                if tt_best.is_some_and(|ttm| m.0 == ttm.0) {
                     scores[i] = 1_000_000;
                 }
             }
+
+            let len = moves_slice.len();
+            for i in 0..len {
+                for j in 0..len - 1 - i {
+                    if scores[j] < scores[j + 1] {
+                        scores.swap(j, j + 1);
+                        moves_slice.swap(j, j + 1);
+                    }
+                }
+            }
         }
 
-        let len = moves_slice.len();
-        for i in 0..len {
-            for j in 0..len - 1 - i {
-                if scores[j] < scores[j + 1] {
-                    scores.swap(j, j + 1);
-                    moves_slice.swap(j, j + 1);
+        if self.params.multi_cut
+            && node == NodeType::Cut
+            && ply > 0
+            && depth >= MC_DEPTH
+            && !in_check
+            && beta.abs() < MATE_VALUE - MAX_PLY as i32
+        {
+            let mut cuts = 0;
+            for m in moves_slice.iter().take(MC_MOVES) {
+                let undo = board.make_move_fast(*m, color);
+                self.search.search_history.push(hash);
+                let score = -self.pvs(
+                    board,
+                    opposite(color),
+                    depth - 1 - MC_REDUCTION,
+                    -beta,
+                    -beta + 1,
+                    ply + 1,
+                    Some(*m),
+                    NodeType::All,
+                );
+                self.search.search_history.pop();
+                board.unmake_move_fast(undo, color);
+                if self.stop_flag.load(Ordering::Relaxed) {
+                    return 0;
+                }
+                if score >= beta {
+                    cuts += 1;
+                    if cuts >= MC_CUTS {
+                        return beta;
+                    }
                 }
             }
         }
@@ -796,14 +1400,27 @@ impl Engine {
 
         for (idx, m) in moves_slice.iter().enumerate() {
             let capture = m.is_capture();
-
-            if !in_check && !capture && depth <= 4 && idx >= LMP_LIMITS[depth as usize] {
+            // Promotions, underpromotions included, are never pruned or reduced.
+            let quiet = !capture && !m.is_promotion();
+
+            if ply > 0
+                && !in_check
+                && quiet
+                && depth <= 4
+                && idx >= self.params.lmp_limits[depth as usize]
+            {
                 continue;
             }
-            if skip_quiets && !capture {
+            if skip_quiets && quiet {
                 continue;
             }
-            if !in_check && !capture && depth <= HLP_THRESHOLD && idx > 0 {
+            if self.params.history_pruning
+                && ply > 0
+                && !in_check
+                && quiet
+                && depth <= HLP_THRESHOLD
+                && idx > 0
+            {
                 let s = self.move_score(board, *m, ply, prev_move.as_ref());
                 if s < HLP_BASE {
                     skip_quiets = true;
@@ -811,21 +1428,33 @@ impl Engine {
                 }
             }
 
+            let gives_check = crate::movegen::gives_check(board, *m, color);
             let undo = board.make_move_fast(*m, color);
-            let gives_check = board.in_check_fast(opposite(color)); // Fast check
+            self.search.search_history.push(hash);
 
             let mut new_depth = depth - 1;
             if gives_check && depth < MAX_DEPTH - 1 {
                 new_depth = new_depth.saturating_add(1);
             }
 
-            if depth > 2 && !capture && !in_check && !gives_check && idx >= 3 {
-                let r = Self::lmr_value(depth, idx + 1);
+            if self.params.late_move_reductions
+                && depth > 2
+                && quiet
+                && !in_check
+                && !gives_check
+                && idx >= 3
+            {
+                let r = self.lmr_reduction(depth, idx + 1, *m, ply, prev_move.as_ref(), node);
                 new_depth = new_depth.saturating_sub(r);
             }
 
             let mut score;
             if idx == 0 {
+                let child = if node == NodeType::Pv {
+                    NodeType::Pv
+                } else {
+                    node.child()
+                };
                 score = -self.pvs(
                     board,
                     opposite(color),
@@ -834,7 +1463,7 @@ impl Engine {
                     -alpha,
                     ply + 1,
                     Some(*m),
-                    true,
+                    child,
                 );
             } else {
                 score = -self.pvs(
@@ -845,7 +1474,7 @@ impl Engine {
                     -alpha,
                     ply + 1,
                     Some(*m),
-                    true,
+                    node.child(),
                 );
                 if score > alpha && score < beta {
                     score = -self.pvs(
@@ -856,27 +1485,35 @@ impl Engine {
                         -alpha,
                         ply + 1,
                         Some(*m),
-                        true,
+                        NodeType::Pv,
                     );
                 }
             }
 
+            self.search.search_history.pop();
             board.unmake_move_fast(undo, color);
 
             if self.stop_flag.load(Ordering::Relaxed) {
                 return 0;
             }
+            if ply == 0 {
+                self.record_root_score(*m, score);
+            }
 
             if score >= beta {
                 if !capture {
-                    if self.killers.len() <= ply {
-                        self.killers.resize(ply + 1, [None, None]);
+                    if self.search.killers.len() <= ply {
+                        self.search.killers.resize(ply + 1, [None, None]);
                     }
-                    let k = &mut self.killers[ply];
+                    let k = &mut self.search.killers[ply];
                     if k[0] != Some(*m) {
                         k[1] = k[0];
                         k[0] = Some(*m);
                     }
+                    if let Some(pmv) = prev_move {
+                        self.search.counter_moves[pmv.from_sq() as usize][pmv.to_sq() as usize] =
+                            Some(*m);
+                    }
                 }
 
                 let from = m.from_sq() as usize;
@@ -884,24 +1521,17 @@ impl Engine {
                 let bonus = (depth * depth) as i32;
 
                 if capture {
-                    self.capture_history[from][to] += bonus;
+                    self.search.capture_history[from][to] += bonus;
                 } else {
-                    self.quiet_history[from][to] += bonus;
+                    self.search.quiet_history[from][to] += bonus;
                 }
 
                 if let Some(pmv) = prev_move {
-                    *self.cont_history.entry((pmv.0, m.0)).or_insert(0) += bonus;
+                    *self.search.cont_history.entry((pmv.0, m.0)).or_insert(0) += bonus;
                 }
 
-                self.tt.store(
-                    hash,
-                    TTEntry {
-                        depth,
-                        value: beta,
-                        bound: Bound::Lower,
-                        best: Some((from as u8, to as u8)),
-                    },
-                );
+                let path_dependent = self.search.rep_floors[ply] < node_index;
+                self.store_node(hash, depth, beta, Bound::Lower, Some(*m), path_dependent);
 
                 return beta;
             } else {
@@ -909,9 +1539,9 @@ impl Engine {
                 let to = m.to_sq() as usize;
                 let penalty = (depth * depth) as i32;
                 if capture {
-                    self.capture_history[from][to] -= penalty;
+                    self.search.capture_history[from][to] -= penalty;
                 } else {
-                    self.quiet_history[from][to] -= penalty;
+                    self.search.quiet_history[from][to] -= penalty;
                 }
             }
 
@@ -927,19 +1557,38 @@ impl Engine {
             Bound::Exact
         };
 
-        let best_idx = best_move.map(|m| (m.from_sq(), m.to_sq()));
+        let path_dependent = self.search.rep_floors[ply] < node_index;
+        self.store_node(hash, depth, alpha, bound, best_move, path_dependent);
 
+        alpha
+    }
+
+    // A score that leans on a repetition of a position played before this
+    // node only holds on the path that led here, so the table keeps its move
+    // but no bound to cut on. Upper bounds never cut, and none is above mate.
+    fn store_node(
+        &self,
+        hash: u64,
+        depth: u32,
+        value: i32,
+        bound: Bound,
+        best: Option<Move>,
+        path_dependent: bool,
+    ) {
+        let (value, bound) = if path_dependent {
+            (MATE_VALUE, Bound::Upper)
+        } else {
+            (value, bound)
+        };
         self.tt.store(
             hash,
             TTEntry {
                 depth,
-                value: alpha,
+                value,
                 bound,
-                best: best_idx,
+                best,
             },
         );
-
-        alpha
     }
 
     pub fn best_move_timed(
@@ -949,21 +1598,123 @@ impl Engine {
     ) -> Option<((String, String), u32)> {
         self.reset_stop();
         self.tt.next_age();
+        self.search.last_score = None;
+        self.search.last_nodes = 0;
+        self.search.seldepth = 0;
 
-        if let Some(book_mv) = book_move(&game.history, &game.board, game.current_turn) {
+        // Ended games have no move, and a forced reply needs no search.
+        let color = game.current_turn;
+        let moves = self.generate_legal_moves(&mut game.board, color);
+        if game.result.is_some() || moves.is_empty() {
+            return None;
+        }
+        if let [only] = moves.as_slice() {
+            let only = *only;
+            self.search.last_score = Some(self.forced_move_score(game, only));
+            return Some((game.board.move_to_strings(only), 0));
+        }
+
+        if self.params.use_book
+            && let Some(book_mv) = self.book_move(game)
+        {
             return Some((book_mv, 0));
         }
 
+        if let Some(style) = self.params.human {
+            return self.human_move(game, config, &style);
+        }
+
+        if let Some(params) = self.params.mcts {
+            return self.mcts_move(game, config, &params);
+        }
+
         let max_depth = config.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
         let time_manager = TimeManager::new(config, game.current_turn, self.stop_flag.clone());
-        self.time_manager = Some(Arc::new(time_manager));
+        self.search.time_manager = Some(Arc::new(time_manager));
 
         let result = self.best_move_single(game, max_depth);
 
-        self.time_manager = None;
+        self.search.last_nodes = self.search.time_manager.as_ref().map_or(0, |tm| tm.nodes());
+        self.search.time_manager = None;
         result
     }
 
+    // Score of a forced move from a quiescence search of the reply, so
+    // adjudication keeps seeing scores through forced sequences.
+    fn forced_move_score(&mut self, game: &Game, mv: Move) -> i32 {
+        let mut board = game.board.clone();
+        let color = game.current_turn;
+        board.make_move_fast(mv, color);
+        -self.quiescence(&mut board, opposite(color), -MATE_VALUE, MATE_VALUE, 1)
+    }
+
+    // Scores every legal move at the style's depth and lets the style pick one.
+    fn human_move(
+        &mut self,
+        game: &Game,
+        config: &TimeConfig,
+        style: &HumanStyle,
+    ) -> Option<((String, String), u32)> {
+        let depth = config.depth.unwrap_or(style.depth).min(style.depth).max(1);
+        let config = TimeConfig {
+            depth: Some(depth),
+            ..config.clone()
+        };
+        let info = self.analyze(game, &config, MAX_MOVES, |_| {});
+        self.search.last_nodes = info.nodes;
+        let line = style.choose(&info.lines, &mut self.rng(game))?;
+        self.search.last_score = Some(line.score);
+        Some((line.moves[0].clone(), info.depth))
+    }
+
+    // Leaves are valued by the quiescence search and moves ranked by the
+    // static evaluation, both under the engine's weights.
+    fn mcts_move(
+        &mut self,
+        game: &Game,
+        config: &TimeConfig,
+        params: &MctsParams,
+    ) -> Option<((String, String), u32)> {
+        struct Evaluator<'a>(&'a mut Engine);
+
+        impl MctsEvaluator for Evaluator<'_> {
+            fn value(&mut self, board: &mut Board, color: Color) -> Option<i32> {
+                let score = self.0.quiescence(board, color, -MATE_VALUE, MATE_VALUE, 0);
+                (!self.0.stop_flag.load(Ordering::Relaxed)).then_some(score)
+            }
+
+            fn prior(&mut self, board: &Board, color: Color) -> i32 {
+                self.0.evaluate(board, color)
+            }
+        }
+
+        let color = game.current_turn;
+        let time_manager = TimeManager::new(config, color, self.stop_flag.clone());
+        self.search.time_manager = Some(Arc::new(time_manager));
+        let result = crate::mcts::search(
+            &game.board,
+            color,
+            &game.hash_history,
+            params,
+            &mut Evaluator(self),
+        );
+        self.search.last_nodes = self.search.time_manager.as_ref().map_or(0, |tm| tm.nodes());
+        self.search.time_manager = None;
+        let result = result?;
+        self.search.last_score = Some(result.score);
+        Some((game.board.move_to_strings(result.best), result.depth))
+    }
+
+    fn book_move(&self, game: &Game) -> Option<(String, String)> {
+        let learning = self.learning.as_ref().map(|l| l.lock().unwrap());
+        self.book.choose(
+            game,
+            learning.as_deref(),
+            &self.params.book,
+            &mut self.rng(game),
+        )
+    }
+
     pub fn best_move(&mut self, game: &mut Game) -> Option<(String, String)> {
         let config = TimeConfig::fixed_depth(self.depth);
         self.best_move_timed(game, &config).map(|(m, _)| m)
@@ -980,14 +1731,20 @@ impl Engine {
         let mut guess = 0;
         let mut best_move: Option<Move> = None;
         let mut reached_depth = 0;
+        let mut stats = RootStats::default();
+        let last_move = game.states.last().map(|s| s.mv);
+        let recapture_sq = last_move.filter(|m| m.is_capture()).map(|m| m.to_sq());
 
-        self.search_history = game.hash_history.clone();
+        self.search.search_history = game.hash_history.clone();
+        self.search.rep_floors.clear();
+        self.search.root_scores.clear();
 
         for d in 1..=max_depth {
-            if let Some(ref tm) = self.time_manager {
-                if !tm.should_continue_iterating() && d > 1 {
-                    break;
-                }
+            if let Some(ref tm) = self.search.time_manager
+                && !tm.should_continue_iterating()
+                && d > 1
+            {
+                break;
             }
 
             let mut alpha = -100000;
@@ -1004,7 +1761,16 @@ impl Engine {
 
                 let mut board = game.board.clone();
 
-                let score = self.pvs(&mut board, color, d, alpha, beta, 0, None, true);
+                let score = self.pvs(
+                    &mut board,
+                    color,
+                    d,
+                    alpha,
+                    beta,
+                    0,
+                    last_move,
+                    NodeType::Pv,
+                );
 
                 if self.stop_flag.load(Ordering::Relaxed) {
                     break;
@@ -1022,12 +1788,8 @@ impl Engine {
                 guess = score;
 
                 if let Some(entry) = self.tt.get(root_hash) {
-                    if let Some((fs, ts)) = entry.best {
-                        let f_str = Board::index_to_algebraic((fs % 8) as usize, (fs / 8) as usize)
-                            .unwrap();
-                        let t_str = Board::index_to_algebraic((ts % 8) as usize, (ts / 8) as usize)
-                            .unwrap();
-                        best_move = Some(self.string_to_move(&game.board, &f_str, &t_str));
+                    if entry.best.is_some() {
+                        best_move = entry.best;
                     }
                 }
                 break;
@@ -1036,29 +1798,212 @@ impl Engine {
             if self.stop_flag.load(Ordering::Relaxed) {
                 break;
             }
+            if let Some(m) = best_move {
+                stats.update(m, guess);
+            }
+            if let Some(tm) = &self.search.time_manager {
+                tm.scale(stats.time_percent());
+            }
+            if stats.easy_move(recapture_sq)
+                && self
+                    .search
+                    .time_manager
+                    .as_ref()
+                    .is_some_and(|tm| tm.easy_move_done())
+            {
+                break;
+            }
         }
 
-        best_move.map(|m| {
-            let f =
-                Board::index_to_algebraic((m.from_sq() % 8) as usize, (m.from_sq() / 8) as usize)
-                    .unwrap();
-            let t = Board::index_to_algebraic((m.to_sq() % 8) as usize, (m.to_sq() / 8) as usize)
-                .unwrap();
-            let mut t_string = t;
-            if m.is_promotion() {
-                if let Some(pt) = m.promotion_piece() {
-                    let c = match pt {
-                        PieceType::Queen => 'q',
-                        PieceType::Rook => 'r',
-                        PieceType::Bishop => 'b',
-                        PieceType::Knight => 'n',
-                        _ => 'q',
-                    };
-                    t_string.push(c);
+        if best_move.is_some() {
+            self.search.last_score = Some(guess);
+        }
+        best_move.map(|m| (game.board.move_to_strings(m), reached_depth))
+    }
+
+    fn search_root(
+        &mut self,
+        board: &mut Board,
+        color: Color,
+        window: RootWindow,
+        prev: Option<Move>,
+    ) -> Option<(i32, Move)> {
+        let RootWindow {
+            depth,
+            mut alpha,
+            beta,
+            excluded,
+        } = window;
+        let tt_best = self.tt.get(board.hash(color)).and_then(|e| e.best);
+        let mut moves: Vec<Move> = self
+            .generate_legal_moves(board, color)
+            .iter()
+            .filter(|m| !excluded.contains(m))
+            .copied()
+            .collect();
+        self.order_root_moves(board, &mut moves, tt_best, prev.as_ref());
+
+        let mut best = None;
+        for (idx, &m) in moves.iter().enumerate() {
+            let undo = board.make_move_fast(m, color);
+            let mut score;
+            if idx == 0 {
+                score = -self.pvs(
+                    board,
+                    opposite(color),
+                    depth - 1,
+                    -beta,
+                    -alpha,
+                    1,
+                    Some(m),
+                    NodeType::Pv,
+                );
+            } else {
+                score = -self.pvs(
+                    board,
+                    opposite(color),
+                    depth - 1,
+                    -alpha - 1,
+                    -alpha,
+                    1,
+                    Some(m),
+                    NodeType::Cut,
+                );
+                if score > alpha && score < beta {
+                    score = -self.pvs(
+                        board,
+                        opposite(color),
+                        depth - 1,
+                        -beta,
+                        -alpha,
+                        1,
+                        Some(m),
+                        NodeType::Pv,
+                    );
                 }
             }
-            ((f, t_string), reached_depth)
-        })
+            board.unmake_move_fast(undo, color);
+
+            if self.stop_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            self.record_root_score(m, score);
+            if score > alpha || best.is_none() {
+                alpha = alpha.max(score);
+                best = Some((score, m));
+            }
+        }
+        best
+    }
+
+    fn extract_pv(
+        &mut self,
+        board: &Board,
+        color: Color,
+        first: Move,
+        max_len: usize,
+    ) -> Vec<Move> {
+        let mut board = board.clone();
+        let mut color = color;
+        let mut seen = vec![board.hash(color)];
+        let mut pv = vec![first];
+        board.make_move_fast(first, color);
+        color = opposite(color);
+        while pv.len() < max_len {
+            let hash = board.hash(color);
+            if seen.contains(&hash) {
+                break;
+            }
+            seen.push(hash);
+            let Some(best) = self.tt.get(hash).and_then(|e| e.best) else {
+                break;
+            };
+            let Some(m) = self
+                .generate_legal_moves(&mut board, color)
+                .iter()
+                .find(|&&m| m == best)
+                .copied()
+            else {
+                break;
+            };
+            board.make_move_fast(m, color);
+            pv.push(m);
+            color = opposite(color);
+        }
+        pv
+    }
+
+    pub fn analyze<F: FnMut(&SearchInfo)>(
+        &mut self,
+        game: &Game,
+        config: &TimeConfig,
+        multipv: usize,
+        mut on_info: F,
+    ) -> SearchInfo {
+        self.reset_stop();
+        self.tt.next_age();
+
+        let color = game.current_turn;
+        let max_depth = config.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+        let time_manager = TimeManager::new(config, color, self.stop_flag.clone());
+        let start = time_manager.start_time;
+        self.search.time_manager = Some(Arc::new(time_manager));
+        self.search.search_history = game.hash_history.clone();
+        self.search.rep_floors.clear();
+        self.search.root_scores.clear();
+        self.search.seldepth = 0;
+        let last_move = game.states.last().map(|s| s.mv);
+
+        let mut info = SearchInfo::default();
+        for depth in 1..=max_depth {
+            if let Some(ref tm) = self.search.time_manager
+                && !tm.should_continue_iterating()
+                && depth > 1
+            {
+                break;
+            }
+
+            let mut board = game.board.clone();
+            let mut excluded = Vec::new();
+            let mut lines = Vec::new();
+            while excluded.len() < multipv.max(1) {
+                let window = RootWindow {
+                    depth,
+                    alpha: -100000,
+                    beta: 100000,
+                    excluded: &excluded,
+                };
+                let Some((score, m)) = self.search_root(&mut board, color, window, last_move)
+                else {
+                    break;
+                };
+                if self.stop_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                excluded.push(m);
+                let pv = self.extract_pv(&board, color, m, depth as usize);
+                lines.push(PvLine {
+                    score,
+                    moves: pv.into_iter().map(|m| board.move_to_strings(m)).collect(),
+                });
+            }
+            if self.stop_flag.load(Ordering::Relaxed) || lines.is_empty() {
+                break;
+            }
+            lines.sort_by_key(|l| std::cmp::Reverse(l.score));
+
+            info = SearchInfo {
+                depth,
+                seldepth: (self.search.seldepth as u32).max(depth),
+                nodes: self.search.time_manager.as_ref().map_or(0, |tm| tm.nodes()),
+                time_ms: start.elapsed().as_millis() as u64,
+                lines,
+            };
+            on_info(&info);
+        }
+
+        self.search.time_manager = None;
+        info
     }
 }
 
@@ -1073,6 +2018,7 @@ fn opposite(c: Color) -> Color {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pieces::Piece;
 
     fn setup_game() -> Game {
         Game::new()
@@ -1196,6 +2142,238 @@ mod tests {
         assert_eq!(to, "a8", "Rook should deliver mate on a8");
     }
 
+    #[test]
+    fn test_finds_knight_promotion_fork() {
+        // d8=N+ forks king and queen; d8=Q only trades queens.
+        let mut game = Game::from_fen("8/1q1P1k2/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let mut engine = Engine::new(4);
+        let config = TimeConfig::fixed_depth(4);
+        let ((from, to), _) = engine.best_move_timed(&mut game, &config).unwrap();
+        assert_eq!((from.as_str(), to.as_str()), ("d7", "d8n"));
+    }
+
+    #[test]
+    fn test_hash_move_keeps_promotion_piece() {
+        let (board, _) = Board::from_fen("8/1q1P1k2/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let engine = Engine::new(1);
+        let mv = Move::promotion(51, 59, PieceType::Knight, false);
+        engine.tt.store(
+            1,
+            TTEntry {
+                depth: 1,
+                value: 0,
+                bound: Bound::Exact,
+                best: Some(mv),
+            },
+        );
+        assert_eq!(engine.tt.get(1).and_then(|e| e.best), Some(mv));
+        assert_eq!(
+            engine.move_score(
+                &board,
+                Move::promotion(51, 59, PieceType::Queen, false),
+                0,
+                None
+            ),
+            QUEEN_PROMOTION_BONUS
+        );
+    }
+
+    #[test]
+    fn test_multi_cut_keeps_tactics() {
+        assert_eq!(NodeType::Pv.child(), NodeType::Cut);
+        assert_eq!(NodeType::Cut.child(), NodeType::All);
+        assert_eq!(NodeType::All.child(), NodeType::Cut);
+
+        for multi_cut in [true, false] {
+            let mut game = Game::from_fen("8/1q1P1k2/8/8/8/8/8/K7 w - - 0 1").unwrap();
+            let mut engine = Engine::new(6);
+            engine.params.use_book = false;
+            engine.params.multi_cut = multi_cut;
+            let ((from, to), _) = engine
+                .best_move_timed(&mut game, &TimeConfig::fixed_depth(6))
+                .unwrap();
+            assert_eq!((from.as_str(), to.as_str()), ("d7", "d8n"));
+        }
+    }
+
+    #[test]
+    fn test_root_moves_follow_previous_scores() {
+        let game = Game::new();
+        let board = game.board.clone();
+        let mut engine = Engine::new(4);
+        let prev = Move::normal(52, 36); // e7-e5, as if Black had just moved
+        let nf3 = Move::normal(6, 21);
+        let nc3 = Move::normal(1, 18);
+        let e3 = Move::normal(12, 20);
+        let d3 = Move::normal(11, 19);
+        engine.search.counter_moves[52][36] = Some(d3);
+        engine.record_root_score(nc3, 30);
+        engine.record_root_score(e3, 10);
+        engine.record_root_score(nc3, 40);
+        assert_eq!(engine.root_score(nc3), Some(40));
+        assert_eq!(engine.search.root_scores.len(), 2);
+
+        let mut moves: Vec<Move> = engine
+            .generate_legal_moves(&mut board.clone(), Color::White)
+            .iter()
+            .copied()
+            .collect();
+        engine.order_root_moves(&board, &mut moves, Some(nf3), Some(&prev));
+        assert_eq!(&moves[..4], &[nf3, nc3, e3, d3]);
+
+        let mut game = Game::new();
+        engine.params.use_book = false;
+        let result = engine.best_move_timed(&mut game, &TimeConfig::fixed_depth(3));
+        assert!(result.is_some());
+        assert_eq!(engine.search.root_scores.len(), 20);
+    }
+
+    #[test]
+    fn test_lmr_follows_history() {
+        let mut engine = Engine::new(4);
+        let prev = Move::normal(12, 28); // e2-e4
+        let mv = Move::normal(6, 21); // g1-f3
+        let base = Engine::lmr_value(10, 20);
+        assert!(base > 1);
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::All),
+            base
+        );
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::Pv),
+            base - 1
+        );
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::Cut),
+            base + 1
+        );
+
+        engine.search.quiet_history[6][21] = LMR_HISTORY_DIVISOR;
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::All),
+            base - 1
+        );
+        engine.search.quiet_history[6][21] = -LMR_HISTORY_DIVISOR;
+        engine
+            .search
+            .cont_history
+            .insert((prev.0, mv.0), -LMR_HISTORY_DIVISOR);
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::All),
+            base + 2
+        );
+        engine.search.quiet_history[6][21] = -100 * LMR_HISTORY_DIVISOR;
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::All),
+            9
+        );
+
+        engine.search.killers[3][1] = Some(mv);
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::All),
+            0
+        );
+        engine.search.killers[3][1] = None;
+        engine.search.counter_moves[12][28] = Some(mv);
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, Some(&prev), NodeType::Cut),
+            0
+        );
+        assert_eq!(engine.lmr_reduction(10, 20, mv, 3, None, NodeType::All), 9);
+        engine.search.quiet_history[6][21] = 0;
+        assert_eq!(
+            engine.lmr_reduction(10, 20, mv, 3, None, NodeType::Pv),
+            base - 1
+        );
+    }
+
+    #[test]
+    fn test_quiescence_searches_evasions_in_check() {
+        let mut engine = Engine::new(1);
+        // Mated on the back rank: no standing pat on a lost position.
+        let (mut board, color) = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/r5K1 w - - 0 1").unwrap();
+        let score = engine.quiescence(&mut board, color, -MATE_VALUE, MATE_VALUE, 2);
+        assert_eq!(score, -MATE_VALUE + 2);
+
+        // The knight checks and forks: the king has to move and the queen
+        // is lost, whatever the material count says.
+        let (mut board, color) = Board::from_fen("k7/8/8/4Q3/8/3n4/7P/4K3 w - - 0 1").unwrap();
+        assert!(engine.evaluate(&board, color) > 400);
+        let score = engine.quiescence(&mut board, color, -MATE_VALUE, MATE_VALUE, 0);
+        assert!(score < 0, "{}", score);
+    }
+
+    #[test]
+    fn test_repetition_scores_keep_out_of_the_table() {
+        let fen = "4k3/8/8/8/8/8/8/Q3K3 w - - 0 1";
+        let (board, color) = Board::from_fen(fen).unwrap();
+        let search = |history: Vec<u64>| {
+            let mut engine = Engine::new(1);
+            engine.search.search_history = history.clone();
+            let mut board = board.clone();
+            let score = engine.pvs(
+                &mut board,
+                color,
+                2,
+                -MATE_VALUE,
+                MATE_VALUE,
+                1,
+                None,
+                NodeType::Pv,
+            );
+            assert_eq!(engine.search.search_history, history);
+            (score, engine.tt.get(board.hash(color)).unwrap())
+        };
+
+        let (score, entry) = search(Vec::new());
+        assert!(score > 500);
+        assert!(matches!(entry.bound, Bound::Exact));
+        assert_eq!(entry.value, score);
+
+        // Qa2 goes back to a position of the game: that draw holds on this
+        // path only, so the score is not kept as a bound.
+        let mut after = board.clone();
+        after.make_move_fast(board.encode_move(0, 8, None), color);
+        let (score, entry) = search(vec![after.hash(opposite(color))]);
+        assert!(score > 500);
+        assert!(matches!(entry.bound, Bound::Upper));
+        assert_eq!(entry.value, MATE_VALUE);
+        assert!(entry.best.is_some());
+    }
+
+    #[test]
+    fn test_quiescence_skips_losing_captures() {
+        let mut engine = Engine::new(1);
+        let (mut board, color) = Board::from_fen("4k3/8/8/2p5/3p4/8/8/3QK3 w - - 0 1").unwrap();
+        let stand_pat = engine.evaluate(&board, color);
+        let score = engine.quiescence(&mut board, color, -MATE_VALUE, MATE_VALUE, 0);
+        assert_eq!(score, stand_pat);
+
+        let (mut board, color) = Board::from_fen("4k3/8/8/8/3p4/8/8/3QK3 w - - 0 1").unwrap();
+        let stand_pat = engine.evaluate(&board, color);
+        let score = engine.quiescence(&mut board, color, -MATE_VALUE, MATE_VALUE, 0);
+        assert!(score > stand_pat);
+    }
+
+    #[test]
+    fn test_see_counts_xray_attackers() {
+        let see = |fen: &str, from: u8, to: u8| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            Engine::new(1).static_exchange_eval(&board, board.encode_move(from, to, None))
+        };
+        // Re2xe5: the e1 rook behind it recaptures, the e8 rook does not pay.
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4R1K1 w - - 0 1", 12, 36), 100);
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/6K1 w - - 0 1", 12, 36), -400);
+        // Bb3xd5 Nxd5 Qxd5: the a2 queen backs up the bishop.
+        assert_eq!(see("6k1/8/5n2/3p4/8/1B6/Q7/6K1 w - - 0 1", 17, 35), 90);
+        assert_eq!(see("6k1/8/5n2/3p4/8/1B6/8/6K1 w - - 0 1", 17, 35), -230);
+        // The d6 pawn defends e5 and the x-ray does not help against it.
+        assert_eq!(see("4r1k1/8/3p4/4p3/8/8/4R3/4R1K1 w - - 0 1", 12, 36), -400);
+        // A knight pinned to its king cannot recapture.
+        assert_eq!(see("4k3/3n4/8/1Q2p3/8/8/8/4R1K1 w - - 0 1", 4, 36), 100);
+        assert_eq!(see("4k3/3n4/8/4p3/8/8/8/4R1K1 w - - 0 1", 4, 36), -400);
+    }
+
     #[test]
     fn test_fixed_depth_search() {
         let mut game = setup_game();
@@ -1207,6 +2385,24 @@ mod tests {
         assert!(result.is_some(), "Should return a move");
     }
 
+    #[test]
+    fn test_eval_string_shows_position_and_terms() {
+        let engine = Engine::new(1);
+        let game = Game::from_fen("4k3/1p6/8/8/8/8/3PP3/R3K3 b Q - 0 1").unwrap();
+        let text = engine.eval_string(&game.board, Color::Black);
+        assert!(text.contains("1 | R |   |   |   | K |   |   |   |"));
+        assert!(text.contains("8 |   |   |   |   | k |   |   |   |"));
+        assert!(text.contains("Fen: 4k3/1p6/8/8/8/8/3PP3/R3K3 b Q - 0 1"));
+        let key = format!("Key: {:016X}", game.board.hash(Color::Black));
+        assert!(text.contains(&key));
+        for term in ["material", "piece-square", "king safety", "Total"] {
+            assert!(text.lines().any(|l| l.starts_with(term)), "{}", term);
+        }
+        let score = engine.evaluate(&game.board, Color::Black);
+        assert!(text.contains(&format!("{} for the side to move", score)));
+        assert!(score < 0);
+    }
+
     #[test]
     fn test_engine_evaluates_material() {
         let mut game = Game::new();
@@ -1234,7 +2430,7 @@ mod tests {
             }),
         );
 
-        let eval = Engine::evaluate(&game.board, Color::White);
+        let eval = Engine::new(1).evaluate(&game.board, Color::White);
         assert!(
             eval > 800,
             "White up a queen should have high eval, got {}",
@@ -1254,6 +2450,206 @@ mod tests {
         assert!(infinite.infinite);
     }
 
+    #[test]
+    fn test_easy_move_needs_a_stable_recapture() {
+        let recapture = Move::capture(27, 36);
+        let mut stats = RootStats::default();
+        for score in [100, 110, 105, 100] {
+            stats.update(recapture, score);
+            assert!(!stats.easy_move(Some(36)));
+        }
+        stats.update(recapture, 95);
+        assert!(stats.easy_move(Some(36)));
+        assert!(!stats.easy_move(Some(35)));
+        assert!(!stats.easy_move(None));
+
+        stats.update(recapture, 20);
+        assert!(!stats.easy_move(Some(36)));
+        stats.update(Move::normal(12, 28), 20);
+        assert_eq!(stats.stable, 0);
+        assert!(!stats.easy_move(Some(28)));
+
+        let mut game = Game::from_fen("7k/8/8/8/8/8/6q1/7K w - - 0 1").unwrap();
+        let mut engine = Engine::new(1);
+        engine.params.use_book = false;
+        let config = TimeConfig {
+            wtime: Some(60_000),
+            btime: Some(60_000),
+            ..TimeConfig::new()
+        };
+        let ((from, to), _) = engine.best_move_timed(&mut game, &config).unwrap();
+        assert_eq!((from.as_str(), to.as_str()), ("h1", "g2"));
+    }
+
+    #[test]
+    fn test_terminal_and_forced_roots_skip_the_search() {
+        let mut engine = Engine::new(20);
+        engine.params.use_book = false;
+        let config = TimeConfig::fixed_time(60_000);
+        // Checkmated, then stalemated.
+        for fen in [
+            "7k/6Q1/6K1/8/8/8/8/8 b - - 0 1",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        ] {
+            let mut game = Game::from_fen(fen).unwrap();
+            assert_eq!(engine.best_move_timed(&mut game, &config), None);
+        }
+        let mut game = Game::new();
+        game.result = Some(Color::Black);
+        assert_eq!(engine.best_move_timed(&mut game, &config), None);
+
+        // The king's only move takes the queen, and is played without a search.
+        let mut game = Game::from_fen("7k/8/8/8/8/8/6q1/7K w - - 0 1").unwrap();
+        let start = Instant::now();
+        let result = engine.best_move_timed(&mut game, &config);
+        assert!(start.elapsed().as_millis() < 1000);
+        assert_eq!(result, Some((("h1".to_string(), "g2".to_string()), 0)));
+        assert_eq!(engine.last_nodes(), 0);
+        // Only the two kings are left.
+        assert_eq!(engine.last_score(), Some(0));
+    }
+
+    #[test]
+    fn test_time_follows_best_move_stability() {
+        let (a, b) = (Move::normal(12, 28), Move::normal(11, 27));
+        let mut stats = RootStats::default();
+        stats.update(a, 20);
+        assert_eq!(stats.time_percent(), 100);
+        stats.update(b, 20);
+        stats.update(a, 20);
+        assert_eq!(stats.time_percent(), 150);
+        stats.update(a, -40);
+        assert_eq!(stats.time_percent(), 200);
+        for _ in 0..6 {
+            stats.update(a, -40);
+        }
+        assert_eq!(stats.time_percent(), 50);
+
+        let config = TimeConfig {
+            wtime: Some(60_000),
+            ..TimeConfig::new()
+        };
+        let tm = TimeManager::new(&config, Color::White, Arc::new(AtomicBool::new(false)));
+        let limit = |tm: &TimeManager| tm.limit_ms.load(Ordering::Relaxed);
+        assert_eq!(limit(&tm), tm.allocated_time_ms);
+        tm.scale(150);
+        assert_eq!(limit(&tm), tm.allocated_time_ms * 3 / 2);
+        tm.scale(10_000);
+        assert_eq!(limit(&tm), tm.max_time_ms);
+
+        let fixed = TimeManager::new(
+            &TimeConfig::fixed_time(500),
+            Color::White,
+            Arc::new(AtomicBool::new(false)),
+        );
+        fixed.scale(200);
+        assert_eq!(limit(&fixed), 500);
+    }
+
+    #[test]
+    fn test_human_mode_plays_legal_moves() {
+        let mut game = setup_game();
+        let mut engine = Engine::new(6);
+        engine.params.use_book = false;
+        engine.params.human = Some(HumanStyle {
+            depth: 2,
+            ..HumanStyle::default()
+        });
+        for _ in 0..4 {
+            let ((from, to), depth) = engine
+                .best_move_timed(&mut game, &TimeConfig::fixed_depth(6))
+                .unwrap();
+            assert_eq!(depth, 2);
+            assert!(game.make_move(&from, &to));
+        }
+    }
+
+    #[test]
+    fn test_seed_makes_choices_reproducible() {
+        let mut engine = Engine::with_threads(6, 8);
+        engine.set_seed(Some(42));
+        assert_eq!(engine.threads, 1);
+        engine.set_seed(None);
+        assert_eq!(engine.threads, 8);
+        engine.set_seed(Some(42));
+        engine.set_threads(4);
+        assert_eq!(engine.threads, 1);
+        engine.set_seed(None);
+        assert_eq!(engine.threads, 4);
+
+        // The same seed replays the same game, book and human-style picks
+        // included, while other seeds sample other book moves.
+        let play = |seed: u64| {
+            let mut engine = Engine::new(6);
+            engine.set_seed(Some(seed));
+            engine.params.book.weighted = true;
+            engine.params.human = Some(HumanStyle {
+                depth: 2,
+                temperature: 200,
+                ..HumanStyle::default()
+            });
+            let mut game = setup_game();
+            for _ in 0..6 {
+                let ((from, to), _) = engine
+                    .best_move_timed(&mut game, &TimeConfig::fixed_depth(2))
+                    .unwrap();
+                assert!(game.make_move(&from, &to));
+            }
+            game.history
+        };
+        assert_eq!(play(7), play(7));
+        let openings: std::collections::HashSet<_> =
+            (0..20).map(|seed| play(seed)[0].clone()).collect();
+        assert!(openings.len() > 1);
+    }
+
+    #[test]
+    fn test_mcts_mode_plays_legal_moves() {
+        let mut engine = Engine::new(6);
+        engine.params.use_book = false;
+        engine.params.mcts = Some(MctsParams {
+            playouts: 500,
+            ..MctsParams::default()
+        });
+        let mut game = setup_game();
+        for _ in 0..4 {
+            let ((from, to), _) = engine
+                .best_move_timed(&mut game, &TimeConfig::fixed_depth(6))
+                .unwrap();
+            assert!(game.make_move(&from, &to));
+        }
+
+        // Time limits stop the playouts early.
+        engine.params.mcts = Some(MctsParams {
+            playouts: u32::MAX,
+            ..MctsParams::default()
+        });
+        let mut game = Game::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let start = Instant::now();
+        let (mv, _) = engine
+            .best_move_timed(&mut game, &TimeConfig::fixed_time(200))
+            .unwrap();
+        assert!(start.elapsed().as_millis() < 1000);
+        assert_eq!(mv, ("d2".to_string(), "d5".to_string()));
+        assert!(engine.last_nodes() > 0);
+    }
+
+    #[test]
+    fn test_seldepth_counts_quiescence() {
+        // Captures on d5 and e4 keep the quiescence search going past depth 2.
+        let fen = "4k3/8/2n1r3/3p4/4p3/3P1N2/8/4K2R w - - 0 1";
+        let mut engine = Engine::new(2);
+        engine.params.use_book = false;
+        let mut game = Game::from_fen(fen).unwrap();
+        engine.best_move_timed(&mut game, &TimeConfig::fixed_depth(2));
+        assert!(engine.last_seldepth() > 2, "{}", engine.last_seldepth());
+
+        let info = engine.analyze(&game, &TimeConfig::fixed_depth(3), 1, |info| {
+            assert!(info.seldepth >= info.depth);
+        });
+        assert!(info.seldepth > 3, "{}", info.seldepth);
+    }
+
     #[test]
     fn test_engine_cloning() {
         let engine = Engine::new(5);
@@ -1348,4 +2744,154 @@ mod tests {
 
         assert!(result.is_some());
     }
+
+    #[test]
+    fn test_last_score() {
+        let mut engine = Engine::new(2);
+        let mut game = Game::new();
+        engine.best_move(&mut game).unwrap();
+        assert_eq!(engine.last_score(), None);
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/QQ2K3 w - - 0 1").unwrap();
+        engine.best_move(&mut game).unwrap();
+        assert!(engine.last_score().unwrap() > 1000);
+        assert!(engine.last_nodes() > 0);
+    }
+
+    #[test]
+    fn test_load_book() {
+        let path = std::env::temp_dir().join(format!("book_{}.txt", std::process::id()));
+        std::fs::write(&path, "g1f3 d7d5\n").unwrap();
+        let mut engine = Engine::new(2);
+        engine.set_book_enabled(false);
+        engine.load_book(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut game = Game::new();
+        let (mv, depth) = engine
+            .best_move_timed(&mut game, &TimeConfig::fixed_depth(2))
+            .unwrap();
+        assert_eq!((mv, depth), (("g1".into(), "f3".into()), 0));
+        engine.set_book_enabled(false);
+        assert!(
+            engine
+                .best_move_timed(&mut game, &TimeConfig::fixed_depth(2))
+                .unwrap()
+                .1
+                > 0
+        );
+        assert!(engine.load_book("missing.bin").is_err());
+    }
+
+    #[test]
+    fn test_search_params_and_weights() {
+        let mut engine = Engine::new(2);
+        let mut game = Game::new();
+        assert_eq!(
+            engine
+                .best_move_timed(&mut game, &TimeConfig::fixed_depth(2))
+                .unwrap()
+                .1,
+            0
+        );
+
+        engine.params = SearchParams {
+            use_book: false,
+            null_move: false,
+            history_pruning: false,
+            late_move_reductions: false,
+            ..SearchParams::default()
+        };
+        assert!(
+            engine
+                .best_move_timed(&mut game, &TimeConfig::fixed_depth(2))
+                .unwrap()
+                .1
+                > 0
+        );
+
+        let mut game = Game::from_fen("7k/6pp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        engine.weights.king_safety = 0;
+        let ((from, to), _) = engine
+            .best_move_timed(&mut game, &TimeConfig::fixed_depth(2))
+            .unwrap();
+        assert_eq!((from.as_str(), to.as_str()), ("a1", "a8"));
+    }
+
+    #[test]
+    fn test_analyze_multipv() {
+        let game = setup_game();
+        let mut engine = Engine::new(3);
+        let mut depths = Vec::new();
+        let info = engine.analyze(&game, &TimeConfig::fixed_depth(3), 3, |i| {
+            depths.push(i.depth)
+        });
+
+        assert_eq!(depths, vec![1, 2, 3]);
+        assert_eq!(info.lines.len(), 3);
+        assert!(info.lines.windows(2).all(|w| w[0].score >= w[1].score));
+        let firsts: Vec<_> = info.lines.iter().map(|l| l.moves[0].clone()).collect();
+        assert_ne!(firsts[0], firsts[1]);
+        assert_ne!(firsts[1], firsts[2]);
+    }
+
+    #[test]
+    fn test_analyze_reports_mate() {
+        let game = Game::from_fen("7k/6pp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut engine = Engine::new(3);
+        let info = engine.analyze(&game, &TimeConfig::fixed_depth(3), 1, |_| {});
+        let best = info.best().unwrap();
+        assert_eq!(best.moves[0], ("a1".to_string(), "a8".to_string()));
+        assert_eq!(best.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn test_external_stop_handle() {
+        let game = setup_game();
+        let mut engine = Engine::new(3);
+        let stop = engine.stop_handle();
+        let worker =
+            std::thread::spawn(move || engine.analyze(&game, &TimeConfig::infinite(), 1, |_| {}));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        stop.store(true, Ordering::Release);
+        let info = worker.join().unwrap();
+        assert!(info.depth >= 1);
+    }
+
+    #[test]
+    fn test_forks_share_tables_but_not_searches() {
+        let mut shared = Engine::new(3);
+        shared.params.use_book = false;
+        let shared = Arc::new(shared);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let searches: Vec<_> = (0..2)
+            .map(|_| {
+                let shared = shared.clone();
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    let mut engine = shared.fork();
+                    tx.send(engine.stop_handle()).unwrap();
+                    engine.analyze(&setup_game(), &TimeConfig::infinite(), 1, |_| {})
+                })
+            })
+            .collect();
+        let stops: Vec<_> = rx.iter().take(2).collect();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        // Stopping the shared engine leaves its forks searching.
+        shared.stop();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(searches.iter().all(|s| !s.is_finished()));
+        for stop in stops {
+            stop.store(true, Ordering::Release);
+        }
+        // Positions the forks searched are in the shared table.
+        for search in searches {
+            let info = search.join().unwrap();
+            let (start, end) = &info.best().unwrap().moves[0];
+            let mut game = setup_game();
+            assert!(game.make_move(start, end));
+            assert!(shared.tt.get(game.board.hash(game.current_turn)).is_some());
+        }
+        assert_eq!(shared.search.root_scores.len(), 0);
+    }
 }
@@ -0,0 +1,309 @@
+use crate::{
+    board::{FenError, PositionError},
+    game::Game,
+    san::{SanError, move_to_san, parse_san},
+};
+
+// One EPD line: a position given by the first four FEN fields, and the
+// opcodes test suites and tuning sets describe it with.
+#[derive(Clone)]
+pub struct EpdRecord {
+    pub game: Game,
+    // `bm` and `am`: the moves to find and the moves to avoid.
+    pub best_moves: Vec<(String, String)>,
+    pub avoid_moves: Vec<(String, String)>,
+    pub id: Option<String>,
+    // `ce`: centipawn evaluation from the side to move.
+    pub centipawns: Option<i32>,
+    // `dm`: mate in this many moves.
+    pub mate: Option<i32>,
+    // Any other opcode with its operands, in the order they were read.
+    pub other: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpdError {
+    Fen(FenError),
+    Position(PositionError),
+    // An operand that does not fit its opcode, such as a non-numeric `ce`.
+    Operand { opcode: String, operand: String },
+    // A `bm` or `am` move that could not be read in the position.
+    Move { opcode: String, error: SanError },
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::Fen(e) => write!(f, "invalid position: {}", e),
+            EpdError::Position(e) => write!(f, "illegal position: {}", e),
+            EpdError::Operand { opcode, operand } => {
+                write!(f, "invalid operand \"{}\" for {}", operand, opcode)
+            }
+            EpdError::Move { opcode, error } => write!(f, "{}: {}", opcode, error),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+// Opcodes and their operands, split on semicolons and whitespace outside of
+// double quotes. Quoted operands lose their quotes.
+fn operations(text: &str) -> Vec<(String, Vec<String>)> {
+    let mut ops = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    for ch in text.chars().chain([';']) {
+        match ch {
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+                continue;
+            }
+            c if quoted => {
+                current.push(c);
+                continue;
+            }
+            c if c.is_whitespace() || c == ';' => {
+                if !current.is_empty() || was_quoted {
+                    words.push(std::mem::take(&mut current));
+                }
+                was_quoted = false;
+                if c == ';' && !words.is_empty() {
+                    let opcode = words.remove(0);
+                    ops.push((opcode, std::mem::take(&mut words)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    ops
+}
+
+fn number<T: std::str::FromStr>(opcode: &str, operands: &[String]) -> Result<T, EpdError> {
+    let operand = operands.first().map_or("", String::as_str);
+    operand.parse().map_err(|_| EpdError::Operand {
+        opcode: opcode.to_string(),
+        operand: operand.to_string(),
+    })
+}
+
+pub fn parse_epd(line: &str) -> Result<EpdRecord, EpdError> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim();
+    while fields.len() < 4 && !rest.is_empty() {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    let ops = operations(rest);
+
+    // `hmvc` and `fmvn` stand in for the FEN clock fields.
+    let mut clocks = [0u16, 1u16];
+    for (opcode, operands) in &ops {
+        match opcode.as_str() {
+            "hmvc" => clocks[0] = number(opcode, operands)?,
+            "fmvn" => clocks[1] = number(opcode, operands)?,
+            _ => {}
+        }
+    }
+    let fen = format!("{} {} {}", fields.join(" "), clocks[0], clocks[1]);
+    let game = Game::parse_fen(&fen).map_err(|e| match e {
+        FenError::Position(e) => EpdError::Position(e),
+        e => EpdError::Fen(e),
+    })?;
+
+    let mut record = EpdRecord::new(game);
+    for (opcode, operands) in ops {
+        match opcode.as_str() {
+            "bm" | "am" => {
+                let mut moves = Vec::new();
+                for san in &operands {
+                    let mut game = record.game.clone();
+                    let color = game.current_turn;
+                    let mv = parse_san(&mut game, san, color).map_err(|error| EpdError::Move {
+                        opcode: opcode.clone(),
+                        error,
+                    })?;
+                    moves.push(mv);
+                }
+                if opcode == "bm" {
+                    record.best_moves = moves;
+                } else {
+                    record.avoid_moves = moves;
+                }
+            }
+            "id" => record.id = Some(operands.join(" ")),
+            "ce" => record.centipawns = Some(number(&opcode, &operands)?),
+            "dm" => record.mate = Some(number(&opcode, &operands)?),
+            "hmvc" | "fmvn" => {}
+            _ => record.other.push((opcode, operands)),
+        }
+    }
+    Ok(record)
+}
+
+// Every record of an EPD file. Blank lines and `#` comments are skipped, as
+// are records that fail to parse.
+pub fn parse_epd_collection(text: &str) -> Vec<EpdRecord> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| parse_epd(line).ok())
+        .collect()
+}
+
+fn quote(operand: &str) -> String {
+    if operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';') {
+        format!("\"{}\"", operand)
+    } else {
+        operand.to_string()
+    }
+}
+
+impl EpdRecord {
+    // A record of the position alone, to which opcodes can be added.
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            id: None,
+            centipawns: None,
+            mate: None,
+            other: Vec::new(),
+        }
+    }
+
+    pub fn is_best_move(&self, start: &str, end: &str) -> bool {
+        self.best_moves.iter().any(|(s, e)| s == start && e == end)
+    }
+
+    pub fn is_avoid_move(&self, start: &str, end: &str) -> bool {
+        self.avoid_moves.iter().any(|(s, e)| s == start && e == end)
+    }
+
+    fn san_moves(&self, moves: &[(String, String)]) -> String {
+        let board = &self.game.board;
+        let color = self.game.current_turn;
+        moves
+            .iter()
+            .map(|(s, e)| move_to_san(board, s, e, color).unwrap_or_else(|| format!("{}{}", s, e)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // The record as one EPD line, with its moves in SAN. Clocks other than
+    // those of a fresh game are kept as `hmvc` and `fmvn`.
+    pub fn to_epd(&self) -> String {
+        let fen = self.game.to_fen();
+        let mut line = fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+        let mut op = |opcode: &str, operands: String| {
+            line.push_str(&format!(" {} {};", opcode, operands));
+        };
+        if !self.best_moves.is_empty() {
+            op("bm", self.san_moves(&self.best_moves));
+        }
+        if !self.avoid_moves.is_empty() {
+            op("am", self.san_moves(&self.avoid_moves));
+        }
+        if let Some(ce) = self.centipawns {
+            op("ce", ce.to_string());
+        }
+        if let Some(dm) = self.mate {
+            op("dm", dm.to_string());
+        }
+        if let Some(id) = &self.id {
+            op("id", format!("\"{}\"", id));
+        }
+        let board = &self.game.board;
+        if board.halfmove_clock != 0 || board.fullmove != 1 {
+            op("hmvc", board.halfmove_clock.to_string());
+            op("fmvn", board.fullmove.to_string());
+        }
+        for (opcode, operands) in &self.other {
+            let operands: Vec<String> = operands.iter().map(|o| quote(o)).collect();
+            line.push_str(&format!(" {}", opcode));
+            if !operands.is_empty() {
+                line.push_str(&format!(" {}", operands.join(" ")));
+            }
+            line.push(';');
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::Color;
+
+    fn mv(start: &str, end: &str) -> (String, String) {
+        (start.to_string(), end.to_string())
+    }
+
+    #[test]
+    fn parses_test_suite_records() {
+        let line = "1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - bm Qd1+; id \"BK.01\";";
+        let record = parse_epd(line).unwrap();
+        assert_eq!(record.game.current_turn, Color::Black);
+        assert_eq!(record.best_moves, vec![mv("d6", "d1")]);
+        assert!(record.is_best_move("d6", "d1"));
+        assert_eq!(record.id.as_deref(), Some("BK.01"));
+        assert_eq!(record.to_epd(), line);
+
+        let line = "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - \
+                    am Nxc6 Bb5; ce -15; dm 3; c0 \"from a book\"; noop;";
+        let record = parse_epd(line).unwrap();
+        assert_eq!(record.avoid_moves, vec![mv("d4", "c6"), mv("f1", "b5")]);
+        assert!(record.best_moves.is_empty());
+        assert_eq!(record.centipawns, Some(-15));
+        assert_eq!(record.mate, Some(3));
+        assert_eq!(
+            record.other,
+            vec![
+                ("c0".to_string(), vec!["from a book".to_string()]),
+                ("noop".to_string(), vec![]),
+            ]
+        );
+        assert_eq!(record.to_epd(), line);
+    }
+
+    #[test]
+    fn clocks_and_errors() {
+        let record = parse_epd("4k3/8/8/8/8/8/4P3/4K3 w - - hmvc 7; fmvn 40;").unwrap();
+        assert_eq!(record.game.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 7 40");
+        assert_eq!(
+            parse_epd(&record.to_epd()).unwrap().game.to_fen(),
+            record.game.to_fen()
+        );
+        assert_eq!(
+            EpdRecord::new(Game::new()).to_epd(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"
+        );
+
+        assert!(matches!(
+            parse_epd("8/8/8/8 w - - bm e4;"),
+            Err(EpdError::Fen(_))
+        ));
+        assert!(matches!(
+            parse_epd("8/8/8/8/8/8/8/8 w - -"),
+            Err(EpdError::Position(_))
+        ));
+        assert!(matches!(
+            parse_epd("4k3/8/8/8/8/8/4P3/4K3 w - - bm e5;"),
+            Err(EpdError::Move { .. })
+        ));
+        assert_eq!(
+            parse_epd("4k3/8/8/8/8/8/4P3/4K3 w - - ce high;").err(),
+            Some(EpdError::Operand {
+                opcode: "ce".to_string(),
+                operand: "high".to_string()
+            })
+        );
+
+        let text = "# suite\n4k3/8/8/8/8/8/4P3/4K3 w - - bm e4;\n\n8/8/8/8/8/8/8/8 w - -\n";
+        assert_eq!(parse_epd_collection(text).len(), 1);
+    }
+}
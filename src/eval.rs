@@ -1,10 +1,18 @@
-use crate::board::{Board, color_idx};
-use crate::pieces::Color;
+use crate::board::{Board, DARK_SQUARES, PIECE_TYPES, color_idx, piece_index};
+use crate::material::{self, MaterialKey};
+use crate::movegen::{pawn_attacks, piece_attacks};
+use crate::pieces::{Color, PieceType};
 use crate::types::{Phase, Square};
 
 #[derive(Copy, Clone, Default, Eq, PartialEq)]
 pub struct Score(i32);
 
+impl core::fmt::Debug for Score {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Score({}, {})", self.mg(), self.eg())
+    }
+}
+
 impl Score {
     pub const ZERO: Score = Score(0);
 
@@ -36,7 +44,86 @@ impl Score {
     }
 }
 
-impl std::ops::Add for Score {
+impl Score {
+    #[inline(always)]
+    pub fn scale(self, percent: i32) -> Score {
+        Score::new(
+            (self.mg() * percent / 100) as i16,
+            (self.eg() * percent / 100) as i16,
+        )
+    }
+}
+
+// Per-term evaluation weights in percent, 100 being the built-in tuning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalWeights {
+    pub material: i32,
+    pub pawn_structure: i32,
+    pub pieces: i32,
+    pub king_safety: i32,
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        Self {
+            material: 100,
+            pawn_structure: 100,
+            pieces: 100,
+            king_safety: 100,
+        }
+    }
+}
+
+// One evaluation term for each side, from that side's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TermScores {
+    pub white: Score,
+    pub black: Score,
+}
+
+impl TermScores {
+    // White's advantage on this term.
+    pub fn net(self) -> Score {
+        self.white - self.black
+    }
+}
+
+// The evaluation of a position term by term, as found by
+// `Evaluator::explain` with the built-in weights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvalBreakdown {
+    pub material: TermScores,
+    pub pst: TermScores,
+    pub pawn_structure: TermScores,
+    pub mobility: TermScores,
+    // Bishop pair, rooks, knight outposts and pinned pieces.
+    pub pieces: TermScores,
+    pub king_safety: TermScores,
+    // Blend between the middlegame (`Phase::TOTAL_PHASE`) and endgame (0)
+    // halves of every score.
+    pub phase: i32,
+    // Exact score of a recognised endgame, which overrides the terms.
+    pub endgame: Option<i32>,
+    // White-relative evaluation, tempo excluded.
+    pub total: i32,
+}
+
+impl EvalBreakdown {
+    // Each term's name with White's tapered advantage.
+    pub fn terms(&self) -> [(&'static str, i32); 6] {
+        [
+            ("material", self.material),
+            ("piece-square", self.pst),
+            ("pawn structure", self.pawn_structure),
+            ("mobility", self.mobility),
+            ("pieces", self.pieces),
+            ("king safety", self.king_safety),
+        ]
+        .map(|(name, term)| (name, term.net().taper(self.phase)))
+    }
+}
+
+impl core::ops::Add for Score {
     type Output = Score;
     #[inline(always)]
     fn add(self, rhs: Score) -> Score {
@@ -44,7 +131,7 @@ impl std::ops::Add for Score {
     }
 }
 
-impl std::ops::Sub for Score {
+impl core::ops::Sub for Score {
     type Output = Score;
     #[inline(always)]
     fn sub(self, rhs: Score) -> Score {
@@ -52,7 +139,7 @@ impl std::ops::Sub for Score {
     }
 }
 
-impl std::ops::Neg for Score {
+impl core::ops::Neg for Score {
     type Output = Score;
     #[inline(always)]
     fn neg(self) -> Score {
@@ -60,21 +147,21 @@ impl std::ops::Neg for Score {
     }
 }
 
-impl std::ops::AddAssign for Score {
+impl core::ops::AddAssign for Score {
     #[inline(always)]
     fn add_assign(&mut self, rhs: Score) {
         self.0 += rhs.0;
     }
 }
 
-impl std::ops::SubAssign for Score {
+impl core::ops::SubAssign for Score {
     #[inline(always)]
     fn sub_assign(&mut self, rhs: Score) {
         self.0 -= rhs.0;
     }
 }
 
-impl std::ops::Mul<i32> for Score {
+impl core::ops::Mul<i32> for Score {
     type Output = Score;
     #[inline(always)]
     fn mul(self, rhs: i32) -> Score {
@@ -190,16 +277,20 @@ const PASSED_PAWN_BONUS_EG: [i16; 8] = [0, 10, 20, 40, 70, 120, 200, 0];
 
 const CONNECTED_PASSED_BONUS: Score = Score::new(10, 20);
 
+// Endgame weight, by rank, of the kings' distances to the square in front of
+// a passed pawn: the enemy king's counts five times, our own twice.
+const PASSED_KING_PROXIMITY: [i16; 8] = [0, 0, 0, 1, 2, 3, 4, 0];
+
 const DOUBLED_PAWN_PENALTY: Score = Score::new(10, 15);
 
 const ISOLATED_PAWN_PENALTY: Score = Score::new(15, 10);
 
 const BACKWARD_PAWN_PENALTY: Score = Score::new(10, 8);
 
-#[allow(dead_code)]
 const MOBILITY_BONUS_MG: [i16; 6] = [0, 4, 3, 2, 1, 0]; // P, N, B, R, Q, K
-#[allow(dead_code)]
 const MOBILITY_BONUS_EG: [i16; 6] = [0, 4, 3, 3, 2, 0];
+// Typical number of safe squares, which scores neither bonus nor penalty.
+const MOBILITY_BASELINE: [i16; 6] = [0, 4, 6, 7, 13, 0];
 
 const PAWN_SHELTER_PENALTY: Score = Score::new(20, 5);
 
@@ -207,8 +298,19 @@ const KING_OPEN_FILE_PENALTY: Score = Score::new(30, 0);
 
 const KING_SEMI_OPEN_FILE_PENALTY: Score = Score::new(15, 0);
 
+// Enemy pawn advancing on a file next to the king, by its rank counted from
+// the king's side. Halved when one of our pawns blocks it, doubled when the
+// kings stand on opposite wings and the attacker has nothing to lose.
+const PAWN_STORM_PENALTY: [i16; 8] = [0, 0, 30, 20, 10, 0, 0, 0];
+
 const BISHOP_PAIR_BONUS: Score = Score::new(30, 50);
 
+// Per own pawn on the bishop's square color short of (or beyond) this many, so
+// a good bishop gains and a bad one loses. Fixed pawns cost the extra penalty.
+const BISHOP_PAWNS_BASELINE: i32 = 3;
+const BISHOP_PAWN_COLOR: Score = Score::new(4, 6);
+const BISHOP_FIXED_PAWN_PENALTY: Score = Score::new(4, 4);
+
 const ROOK_OPEN_FILE_BONUS: Score = Score::new(20, 10);
 
 const ROOK_SEMI_OPEN_FILE_BONUS: Score = Score::new(10, 5);
@@ -217,8 +319,113 @@ const ROOK_ON_7TH_BONUS: Score = Score::new(20, 30);
 
 const KNIGHT_OUTPOST_BONUS: Score = Score::new(25, 15);
 
+const PINNED_PIECE_PENALTY: Score = Score::new(20, 10);
+
+// White's starting squares of each piece type; Black's are the mirror image.
+const HOME_SQUARES: [u64; 6] = [0xFF00, 0x42, 0x24, 0x81, 0x08, 0x10];
+
+// Per knight or bishop still at home once this many moves are played.
+const DEVELOPMENT_MOVES: u16 = 8;
+const UNDEVELOPED_MINOR_PENALTY: Score = Score::new(15, 0);
+
+// Until this move, per minor piece still at home while the queen is out, and
+// per tempo spent moving a piece that had already left home: the moves
+// played minus the pieces that have left their squares, past a small slack.
+const OPENING_MOVES: u16 = 12;
+const EARLY_QUEEN_PENALTY: Score = Score::new(8, 0);
+const WASTED_TEMPO_SLACK: i32 = 1;
+const MAX_WASTED_TEMPI: i32 = 4;
+const WASTED_TEMPO_PENALTY: Score = Score::new(10, 0);
+
 const TEMPO_BONUS: i32 = 15;
 
+// Bonus for a won endgame, so the search heads for it and then makes progress.
+const KNOWN_WIN: i32 = 1000;
+
+// Endgames the general evaluation misjudges, found by material key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Endgame {
+    // Neither side can force mate.
+    Draw,
+    // Bishop and knight against a bare king: mate only in a bishop corner.
+    Kbnk,
+}
+
+const ENDGAME_SIGNATURES: [(&str, Endgame); 8] = [
+    ("KvK", Endgame::Draw),
+    ("KNvK", Endgame::Draw),
+    ("KBvK", Endgame::Draw),
+    ("KNNvK", Endgame::Draw),
+    ("KNvKN", Endgame::Draw),
+    ("KBvKN", Endgame::Draw),
+    ("KBvKB", Endgame::Draw),
+    ("KBNvK", Endgame::Kbnk),
+];
+
+// Each endgame with its stronger side, registered for both colors.
+static ENDGAMES: [(MaterialKey, Endgame, Color); 16] = {
+    let mut table = [(0, Endgame::Draw, Color::White); 16];
+    let mut i = 0;
+    while i < ENDGAME_SIGNATURES.len() {
+        let (signature, endgame) = ENDGAME_SIGNATURES[i];
+        let Some(key) = material::from_signature(signature) else {
+            panic!("bad endgame signature");
+        };
+        table[2 * i] = (key, endgame, Color::White);
+        table[2 * i + 1] = (material::mirror(key), endgame, Color::Black);
+        i += 1;
+    }
+    table
+};
+
+fn find_endgame(key: MaterialKey) -> Option<(Endgame, Color)> {
+    ENDGAMES
+        .iter()
+        .find(|&&(k, _, _)| k == key)
+        .map(|&(_, endgame, strong)| (endgame, strong))
+}
+
+const FILE_A: u64 = 0x0101010101010101;
+
+// The files next to `file`.
+#[inline(always)]
+fn adjacent_files(file: usize) -> u64 {
+    let files = FILE_A << file;
+    ((files << 1) & !FILE_A) | ((files >> 1) & !(FILE_A << 7))
+}
+
+// The ranks strictly in front of `rank`, seen from `color`.
+#[inline(always)]
+fn ranks_ahead(color: Color, rank: usize) -> u64 {
+    match color {
+        Color::White => (!0u64).checked_shl(8 * (rank as u32 + 1)).unwrap_or(0),
+        Color::Black => (1u64 << (8 * rank)) - 1,
+    }
+}
+
+#[inline]
+fn king_distance(a: usize, b: usize) -> i32 {
+    let files = (a % 8) as i32 - (b % 8) as i32;
+    let ranks = (a / 8) as i32 - (b / 8) as i32;
+    files.abs().max(ranks.abs())
+}
+
+// 0 in the four center squares, 6 in the corners.
+#[inline]
+fn center_distance(sq: usize) -> i32 {
+    let (file, rank) = ((sq % 8) as i32, (sq / 8) as i32);
+    (3 - file).max(file - 4) + (3 - rank).max(rank - 4)
+}
+
+// A White-relative score seen from `color`.
+fn relative(white_score: i32, color: Color) -> i32 {
+    if color == Color::White {
+        white_score
+    } else {
+        -white_score
+    }
+}
+
 #[allow(dead_code)]
 pub struct Evaluator<'a> {
     board: &'a Board,
@@ -230,8 +437,8 @@ pub struct Evaluator<'a> {
 
 impl<'a> Evaluator<'a> {
     pub fn new(board: &'a Board) -> Self {
-        let white_pieces: u64 = board.bitboards[0].iter().fold(0, |a, b| a | b);
-        let black_pieces: u64 = board.bitboards[1].iter().fold(0, |a, b| a | b);
+        let white_pieces = board.occupied_by(Color::White);
+        let black_pieces = board.occupied_by(Color::Black);
 
         let phase = Self::calculate_phase(board);
 
@@ -256,54 +463,188 @@ impl<'a> Evaluator<'a> {
     }
 
     pub fn evaluate(&self, color: Color) -> i32 {
+        self.evaluate_weighted(color, &EvalWeights::default())
+    }
+
+    // Every term is computed from White's point of view; the result is
+    // relative to `color`, the side to move, as negamax expects: positive
+    // when `color` stands better. The tempo bonus goes to the side to move,
+    // so a position and its color-flipped copy with the other side to move
+    // evaluate the same. Recognised endgames are exact and get no tempo.
+    pub fn evaluate_weighted(&self, color: Color, weights: &EvalWeights) -> i32 {
+        if let Some(score) = self.eval_endgame() {
+            return relative(score, color);
+        }
+
         let mut score = Score::ZERO;
 
-        score += self.eval_material_and_pst();
+        score += self.eval_material_and_pst().scale(weights.material);
 
-        score += self.eval_pawn_structure();
+        score += self.eval_pawn_structure().scale(weights.pawn_structure);
 
-        score += self.eval_pieces();
+        score += self.eval_pieces().scale(weights.pieces);
 
-        score += self.eval_king_safety();
+        score += self.eval_king_safety().scale(weights.king_safety);
 
-        let tapered = score.taper(self.phase);
+        relative(score.taper(self.phase), color) + TEMPO_BONUS
+    }
 
-        let final_score = if color == Color::White {
-            tapered + TEMPO_BONUS
-        } else {
-            -tapered + TEMPO_BONUS
+    pub fn explain(&self) -> EvalBreakdown {
+        let per_side = |term: fn(&Self, Color) -> Score| TermScores {
+            white: term(self, Color::White),
+            black: term(self, Color::Black),
+        };
+        let (white_material, white_pst) = self.eval_material_for_color(Color::White);
+        let (black_material, black_pst) = self.eval_material_for_color(Color::Black);
+        let white_pawns = self.board.pieces_of(Color::White, PieceType::Pawn);
+        let black_pawns = self.board.pieces_of(Color::Black, PieceType::Pawn);
+
+        let mut breakdown = EvalBreakdown {
+            material: TermScores {
+                white: white_material,
+                black: black_material,
+            },
+            pst: TermScores {
+                white: white_pst,
+                black: black_pst,
+            },
+            pawn_structure: TermScores {
+                white: self.eval_pawns_for_color(Color::White, white_pawns, black_pawns),
+                black: self.eval_pawns_for_color(Color::Black, black_pawns, white_pawns),
+            },
+            mobility: per_side(Self::eval_mobility),
+            pieces: per_side(Self::eval_piece_terms),
+            king_safety: per_side(Self::eval_king_safety_for_color),
+            phase: self.phase,
+            endgame: self.eval_endgame(),
+            total: 0,
         };
+        breakdown.total = breakdown.endgame.unwrap_or_else(|| {
+            let b = &breakdown;
+            (b.material.net()
+                + b.pst.net()
+                + b.pawn_structure.net()
+                + b.mobility.net()
+                + b.pieces.net()
+                + b.king_safety.net())
+            .taper(self.phase)
+        });
+        breakdown
+    }
 
-        final_score
+    // Tapered piece-square bonus of the piece on each square, White-relative
+    // like `explain`: positive where White's piece stands well or Black's
+    // stands badly. Empty squares are 0.
+    pub fn pst_map(&self) -> [i32; 64] {
+        let mut map = [0i32; 64];
+        for (color, sign) in [(Color::White, 1), (Color::Black, -1)] {
+            for (sq, piece) in self.board.pieces(color) {
+                let pt = piece_index(piece.piece_type);
+                let table_sq = if color == Color::White {
+                    sq as usize
+                } else {
+                    Square::flip(sq) as usize
+                };
+                map[sq as usize] =
+                    sign * Score::new(PST_MG[pt][table_sq], PST_EG[pt][table_sq]).taper(self.phase);
+            }
+        }
+        map
     }
 
-    fn eval_material_and_pst(&self) -> Score {
-        let mut score = Score::ZERO;
+    // White-relative score of a recognised endgame: the table above, or a bare
+    // king against a rook or queen, to be driven to the edge and mated.
+    fn eval_endgame(&self) -> Option<i32> {
+        let key = self.board.material_key;
+        let (endgame, strong) = match find_endgame(key) {
+            Some(entry) => entry,
+            None => {
+                let strong = [Color::White, Color::Black].into_iter().find(|&c| {
+                    let weak = if c == Color::White {
+                        Color::Black
+                    } else {
+                        Color::White
+                    };
+                    material::side_count(key, weak) == 1
+                        && material::count(key, c, PieceType::Rook)
+                            + material::count(key, c, PieceType::Queen)
+                            > 0
+                })?;
+                return Some(self.eval_kxk(strong, None));
+            }
+        };
+        match endgame {
+            Endgame::Draw => Some(0),
+            Endgame::Kbnk => {
+                let bishop = self.board.pieces_of(strong, PieceType::Bishop);
+                Some(self.eval_kxk(strong, Some(bishop & DARK_SQUARES != 0)))
+            }
+        }
+    }
 
-        for pt in 0..6 {
-            let mut bb = self.board.bitboards[0][pt];
-            while bb != 0 {
-                let sq = bb.trailing_zeros() as usize;
-                score += Score::new(
-                    MATERIAL_MG[pt] + PST_MG[pt][sq],
-                    MATERIAL_EG[pt] + PST_EG[pt][sq],
-                );
-                bb &= bb - 1;
+    // Bare king of the weak side against the strong side's material. The weak
+    // king is pushed to the edge, or with `dark_corner` set to a corner of
+    // that color, and the strong king brought next to it.
+    fn eval_kxk(&self, strong: Color, dark_corner: Option<bool>) -> i32 {
+        let weak = if strong == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+        let strong_king = self
+            .board
+            .pieces_of(strong, PieceType::King)
+            .trailing_zeros() as usize;
+        let weak_king = self.board.pieces_of(weak, PieceType::King).trailing_zeros() as usize;
+        let material: i32 = self
+            .board
+            .pieces(strong)
+            .map(|(_, p)| MATERIAL_EG[piece_index(p.piece_type)] as i32)
+            .sum();
+        let edge = match dark_corner {
+            // a1 and h8 are dark, a8 and h1 light.
+            Some(dark) => {
+                let corners = if dark { [0, 63] } else { [7, 56] };
+                let nearest = corners.map(|c| king_distance(weak_king, c));
+                20 * (7 - nearest[0].min(nearest[1]))
             }
+            None => 20 * center_distance(weak_king),
+        };
+        let closeness = 10 * (7 - king_distance(strong_king, weak_king));
+        let score = KNOWN_WIN + material + edge + closeness;
+        if strong == Color::White {
+            score
+        } else {
+            -score
+        }
+    }
+
+    fn eval_material_and_pst(&self) -> Score {
+        let (white_material, white_pst) = self.eval_material_for_color(Color::White);
+        let (black_material, black_pst) = self.eval_material_for_color(Color::Black);
+        white_material + white_pst - black_material - black_pst
+    }
 
-            let mut bb = self.board.bitboards[1][pt];
+    // Piece values and piece-square bonuses of `color`, apart.
+    fn eval_material_for_color(&self, color: Color) -> (Score, Score) {
+        let mut material = Score::ZERO;
+        let mut pst = Score::ZERO;
+        for pt in 0..6 {
+            let mut bb = self.board.bitboards[color_idx(color)][pt];
+            let count = bb.count_ones() as i32;
+            material += Score::new(MATERIAL_MG[pt], MATERIAL_EG[pt]) * count;
             while bb != 0 {
                 let sq = bb.trailing_zeros() as usize;
-                let flipped = Square::flip(sq as u8) as usize;
-                score -= Score::new(
-                    MATERIAL_MG[pt] + PST_MG[pt][flipped],
-                    MATERIAL_EG[pt] + PST_EG[pt][flipped],
-                );
+                let sq = if color == Color::White {
+                    sq
+                } else {
+                    Square::flip(sq as u8) as usize
+                };
+                pst += Score::new(PST_MG[pt][sq], PST_EG[pt][sq]);
                 bb &= bb - 1;
             }
         }
-
-        score
+        (material, pst)
     }
 
     fn eval_pawn_structure(&self) -> Score {
@@ -331,24 +672,18 @@ impl<'a> Evaluator<'a> {
                 7 - Square::rank(sq) as usize
             };
 
-            let file_mask = 0x0101010101010101u64 << file;
+            let file_mask = FILE_A << file;
             let pawns_on_file = (own_pawns & file_mask).count_ones();
             if pawns_on_file > 1 {
                 score -= DOUBLED_PAWN_PENALTY;
             }
 
-            let adjacent_files = match file {
-                0 => 0x0202020202020202u64,
-                7 => 0x4040404040404040u64,
-                _ => (0x0101010101010101u64 << (file - 1)) | (0x0101010101010101u64 << (file + 1)),
-            };
-            if (own_pawns & adjacent_files) == 0 {
+            if (own_pawns & adjacent_files(file)) == 0 {
                 score -= ISOLATED_PAWN_PENALTY;
             }
 
             if self.is_passed_pawn(sq, color, enemy_pawns) {
-                let bonus = Score::new(PASSED_PAWN_BONUS_MG[rank], PASSED_PAWN_BONUS_EG[rank]);
-                score += bonus;
+                score += self.eval_passed_pawn(sq, color, rank);
 
                 if self.has_adjacent_pawn(sq, color, own_pawns) {
                     score += CONNECTED_PASSED_BONUS;
@@ -365,138 +700,205 @@ impl<'a> Evaluator<'a> {
         score
     }
 
-    fn is_passed_pawn(&self, sq: u8, color: Color, enemy_pawns: u64) -> bool {
-        let file = Square::file(sq) as usize;
-        let rank = Square::rank(sq) as usize;
+    // A passed pawn's bonus is halved when the square in front of it is
+    // occupied and cut by a quarter when the enemy holds a square of its path.
+    // In the endgame it grows as the enemy king is farther from that square
+    // than our own.
+    fn eval_passed_pawn(&self, sq: u8, color: Color, rank: usize) -> Score {
+        let mut mg = PASSED_PAWN_BONUS_MG[rank] as i32;
+        let mut eg = PASSED_PAWN_BONUS_EG[rank] as i32;
+        let file_mask = 0x0101010101010101u64 << Square::file(sq);
+        let (stop, path) = if color == Color::White {
+            (sq as usize + 8, file_mask & (!0u64 << (sq + 8).min(63)))
+        } else {
+            (sq as usize - 8, file_mask & (!0u64 >> (71 - sq as u32)))
+        };
+        let enemy = if color == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+        let enemy_pieces = if color == Color::White {
+            self.black_pieces
+        } else {
+            self.white_pieces
+        };
 
-        let mut mask = 0u64;
+        let mut held = path & enemy_pieces != 0;
+        let mut bb = path;
+        while bb != 0 && !held {
+            held = self
+                .board
+                .is_square_attacked_by(bb.trailing_zeros() as u8, enemy);
+            bb &= bb - 1;
+        }
+        if self.occupied & (1u64 << stop) != 0 {
+            mg /= 2;
+            eg /= 2;
+        } else if held {
+            mg = mg * 3 / 4;
+            eg = eg * 3 / 4;
+        }
 
-        match color {
-            Color::White => {
-                for r in (rank + 1)..8 {
-                    for f in file.saturating_sub(1)..=(file + 1).min(7) {
-                        mask |= 1u64 << (r * 8 + f);
-                    }
-                }
-            }
-            Color::Black => {
-                for r in 0..rank {
-                    for f in file.saturating_sub(1)..=(file + 1).min(7) {
-                        mask |= 1u64 << (r * 8 + f);
-                    }
-                }
-            }
+        let own_king = self.board.pieces_of(color, PieceType::King);
+        let enemy_king = self.board.pieces_of(enemy, PieceType::King);
+        if own_king != 0 && enemy_king != 0 {
+            let theirs = king_distance(enemy_king.trailing_zeros() as usize, stop);
+            let ours = king_distance(own_king.trailing_zeros() as usize, stop);
+            eg += PASSED_KING_PROXIMITY[rank] as i32 * (5 * theirs - 2 * ours);
         }
+        Score::new(mg as i16, eg as i16)
+    }
 
-        (enemy_pawns & mask) == 0
+    fn is_passed_pawn(&self, sq: u8, color: Color, enemy_pawns: u64) -> bool {
+        let file = Square::file(sq) as usize;
+        let span =
+            (adjacent_files(file) | FILE_A << file) & ranks_ahead(color, Square::rank(sq) as usize);
+        (enemy_pawns & span) == 0
     }
 
+    // An own pawn on a neighbouring file, level with this one or a rank
+    // away.
     fn has_adjacent_pawn(&self, sq: u8, _color: Color, own_pawns: u64) -> bool {
-        let file = Square::file(sq) as usize;
         let rank = Square::rank(sq) as usize;
-
-        for f in file.saturating_sub(1)..=(file + 1).min(7) {
-            if f == file {
-                continue;
-            }
-            for r in rank.saturating_sub(1)..=(rank + 1).min(7) {
-                let check_sq = r * 8 + f;
-                if (own_pawns & (1u64 << check_sq)) != 0 {
-                    return true;
-                }
-            }
-        }
-        false
+        let band =
+            (0xFFu64 << (8 * rank)) | (0xFFu64 << (8 * rank) >> 8) | (0xFFu64 << (8 * rank) << 8);
+        own_pawns & adjacent_files(Square::file(sq) as usize) & band != 0
     }
 
+    // No own pawn on a neighbouring file level with or behind it can
+    // support it, and an enemy pawn controls the square in front.
     fn is_backward_pawn(&self, sq: u8, color: Color, own_pawns: u64, enemy_pawns: u64) -> bool {
         let file = Square::file(sq) as usize;
         let rank = Square::rank(sq) as usize;
-
-        let _support_files = match file {
-            0 => 0x0202020202020202u64,
-            7 => 0x4040404040404040u64,
-            _ => (0x0101010101010101u64 << (file - 1)) | (0x0101010101010101u64 << (file + 1)),
+        let support = adjacent_files(file) & !ranks_ahead(color, rank);
+        if own_pawns & support != 0 {
+            return false;
+        }
+        let advance = match color {
+            Color::White if rank < 7 => sq as usize + 8,
+            Color::Black if rank > 0 => sq as usize - 8,
+            _ => return false,
         };
+        enemy_pawns & piece_attacks(PieceType::Pawn, color, advance, 0) != 0
+    }
 
-        let support_mask = match color {
-            Color::White => {
-                let mut m = 0u64;
-                for r in 0..=rank {
-                    for f in file.saturating_sub(1)..=(file + 1).min(7) {
-                        if f != file {
-                            m |= 1u64 << (r * 8 + f);
-                        }
-                    }
-                }
-                m
-            }
-            Color::Black => {
-                let mut m = 0u64;
-                for r in rank..8 {
-                    for f in file.saturating_sub(1)..=(file + 1).min(7) {
-                        if f != file {
-                            m |= 1u64 << (r * 8 + f);
-                        }
-                    }
-                }
-                m
-            }
-        };
+    fn eval_pieces(&self) -> Score {
+        self.eval_piece_terms(Color::White) - self.eval_piece_terms(Color::Black)
+            + self.eval_mobility(Color::White)
+            - self.eval_mobility(Color::Black)
+    }
 
-        if (own_pawns & support_mask) == 0 {
-            let advance_sq = match color {
-                Color::White if rank < 7 => Some((rank + 1) * 8 + file),
-                Color::Black if rank > 0 => Some((rank - 1) * 8 + file),
-                _ => None,
-            };
+    // Bishop pair, rooks, knight outposts, pins and development of `color`.
+    fn eval_piece_terms(&self, color: Color) -> Score {
+        let mut score = Score::ZERO;
+        if self.board.pieces_of(color, PieceType::Bishop).count_ones() >= 2 {
+            score += BISHOP_PAIR_BONUS;
+        }
+        score += self.eval_bishop_pawns(color);
+        score += self.eval_rooks(color);
+        score += self.eval_knight_outposts(color);
+        score -= PINNED_PIECE_PENALTY * self.pinned_pieces(color);
+        score += self.eval_development(color);
+        score
+    }
 
-            if let Some(_adv) = advance_sq {
-                let enemy_attacks = match color {
-                    Color::White => {
-                        let mut attacks = 0u64;
-                        if file > 0 && rank < 6 {
-                            attacks |= 1u64 << ((rank + 2) * 8 + file - 1);
-                        }
-                        if file < 7 && rank < 6 {
-                            attacks |= 1u64 << ((rank + 2) * 8 + file + 1);
-                        }
-                        attacks
-                    }
-                    Color::Black => {
-                        let mut attacks = 0u64;
-                        if file > 0 && rank > 1 {
-                            attacks |= 1u64 << ((rank - 2) * 8 + file - 1);
-                        }
-                        if file < 7 && rank > 1 {
-                            attacks |= 1u64 << ((rank - 2) * 8 + file + 1);
-                        }
-                        attacks
-                    }
-                };
-                return (enemy_pawns & enemy_attacks) != 0;
+    // The board keeps no move history, so development is read off the move
+    // counter and the pieces still on their starting squares.
+    fn eval_development(&self, color: Color) -> Score {
+        let fullmove = self.board.fullmove;
+        let home = |pt: PieceType| match color {
+            Color::White => HOME_SQUARES[piece_index(pt)],
+            Color::Black => HOME_SQUARES[piece_index(pt)].swap_bytes(),
+        };
+        let at_home = |pt: PieceType| self.board.pieces_of(color, pt) & home(pt);
+        let home_minors = (at_home(PieceType::Knight) | at_home(PieceType::Bishop)).count_ones();
+        let home_minors = home_minors as i32;
+
+        let mut score = Score::ZERO;
+        if fullmove > DEVELOPMENT_MOVES {
+            score -= UNDEVELOPED_MINOR_PENALTY * home_minors;
+        }
+        if fullmove <= OPENING_MOVES {
+            let queen = self.board.pieces_of(color, PieceType::Queen);
+            if queen & !home(PieceType::Queen) != 0 {
+                score -= EARLY_QUEEN_PENALTY * home_minors;
             }
+            let moved: i32 = PIECE_TYPES
+                .iter()
+                .map(|&pt| (self.board.pieces_of(color, pt) & !home(pt)).count_ones() as i32)
+                .sum();
+            let wasted = i32::from(fullmove) - 1 - moved - WASTED_TEMPO_SLACK;
+            score -= WASTED_TEMPO_PENALTY * wasted.clamp(0, MAX_WASTED_TEMPI);
         }
+        score
+    }
 
-        false
+    // Pinned pieces other than pawns, which are tied to their king's defence.
+    fn pinned_pieces(&self, color: Color) -> i32 {
+        let pawns = self.board.pieces_of(color, PieceType::Pawn);
+        (self.board.pinned(color) & !pawns).count_ones() as i32
     }
 
-    fn eval_pieces(&self) -> Score {
-        let mut score = Score::ZERO;
+    fn eval_mobility(&self, color: Color) -> Score {
+        let enemy = if color == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+        let enemy_pawns = self.board.pieces_of(enemy, PieceType::Pawn);
+        let own = if color == Color::White {
+            self.white_pieces
+        } else {
+            self.black_pieces
+        };
+        let safe = !own & !pawn_attacks(enemy_pawns, enemy);
 
-        if self.board.bitboards[0][2].count_ones() >= 2 {
-            score += BISHOP_PAIR_BONUS;
-        }
-        if self.board.bitboards[1][2].count_ones() >= 2 {
-            score -= BISHOP_PAIR_BONUS;
+        let mut score = Score::ZERO;
+        for piece_type in [
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            let pt = piece_index(piece_type);
+            let mut bb = self.board.pieces_of(color, piece_type);
+            while bb != 0 {
+                let sq = bb.trailing_zeros() as usize;
+                let squares = piece_attacks(piece_type, color, sq, self.occupied) & safe;
+                let moves = squares.count_ones() as i16 - MOBILITY_BASELINE[pt];
+                score += Score::new(MOBILITY_BONUS_MG[pt] * moves, MOBILITY_BONUS_EG[pt] * moves);
+                bb &= bb - 1;
+            }
         }
+        score
+    }
 
-        score += self.eval_rooks(Color::White);
-        score -= self.eval_rooks(Color::Black);
-
-        score += self.eval_knight_outposts(Color::White);
-        score -= self.eval_knight_outposts(Color::Black);
+    // Good and bad bishops, by the own pawns standing on the bishop's square
+    // color and how many of them are fixed behind another piece.
+    fn eval_bishop_pawns(&self, color: Color) -> Score {
+        let pawns = self.board.pieces_of(color, PieceType::Pawn);
+        let ahead = if color == Color::White {
+            self.occupied >> 8
+        } else {
+            self.occupied << 8
+        };
+        let fixed = pawns & ahead;
 
+        let mut score = Score::ZERO;
+        let mut bb = self.board.pieces_of(color, PieceType::Bishop);
+        while bb != 0 {
+            let sq = bb.trailing_zeros();
+            let complex = if DARK_SQUARES & (1u64 << sq) != 0 {
+                DARK_SQUARES
+            } else {
+                !DARK_SQUARES
+            };
+            let same = (pawns & complex).count_ones() as i32;
+            score += BISHOP_PAWN_COLOR * (BISHOP_PAWNS_BASELINE - same);
+            score -= BISHOP_FIXED_PAWN_PENALTY * (fixed & complex).count_ones() as i32;
+            bb &= bb - 1;
+        }
         score
     }
 
@@ -551,58 +953,15 @@ impl<'a> Evaluator<'a> {
             };
 
             if in_enemy_territory {
-                let supported = match color {
-                    Color::White => {
-                        let support_mask = if file > 0 && rank > 0 {
-                            1u64 << ((rank - 1) * 8 + file - 1)
-                        } else {
-                            0
-                        } | if file < 7 && rank > 0 {
-                            1u64 << ((rank - 1) * 8 + file + 1)
-                        } else {
-                            0
-                        };
-                        (own_pawns & support_mask) != 0
-                    }
-                    Color::Black => {
-                        let support_mask = if file > 0 && rank < 7 {
-                            1u64 << ((rank + 1) * 8 + file - 1)
-                        } else {
-                            0
-                        } | if file < 7 && rank < 7 {
-                            1u64 << ((rank + 1) * 8 + file + 1)
-                        } else {
-                            0
-                        };
-                        (own_pawns & support_mask) != 0
-                    }
-                };
-
-                let adjacent_files = match file {
-                    0 => 0x0202020202020202u64,
-                    7 => 0x4040404040404040u64,
-                    _ => {
-                        (0x0101010101010101u64 << (file - 1))
-                            | (0x0101010101010101u64 << (file + 1))
-                    }
-                };
-
-                let cant_be_attacked = match color {
-                    Color::White => {
-                        let mut attack_mask = 0u64;
-                        for r in (rank + 1)..8 {
-                            attack_mask |= adjacent_files & (0xFFu64 << (r * 8));
-                        }
-                        (enemy_pawns & attack_mask) == 0
-                    }
-                    Color::Black => {
-                        let mut attack_mask = 0u64;
-                        for r in 0..rank {
-                            attack_mask |= adjacent_files & (0xFFu64 << (r * 8));
-                        }
-                        (enemy_pawns & attack_mask) == 0
-                    }
+                // Own pawns attack the knight's square from behind; enemy
+                // pawns on the neighbouring files ahead could still chase it.
+                let behind = match color {
+                    Color::White => Color::Black,
+                    Color::Black => Color::White,
                 };
+                let supported = own_pawns & piece_attacks(PieceType::Pawn, behind, sq, 0) != 0;
+                let cant_be_attacked =
+                    enemy_pawns & adjacent_files(file) & ranks_ahead(color, rank) == 0;
 
                 if supported && cant_be_attacked {
                     score += KNIGHT_OUTPOST_BONUS;
@@ -615,6 +974,48 @@ impl<'a> Evaluator<'a> {
         score
     }
 
+    // Enemy pawns marching on the king's file and the two next to it; only the
+    // most advanced pawn of each file counts.
+    fn pawn_storm(&self, color: Color, king_sq: usize) -> Score {
+        let cidx = color_idx(color);
+        let own_pawns = self.board.bitboards[cidx][0];
+        let enemy_pawns = self.board.bitboards[1 - cidx][0];
+        let enemy_king = self.board.bitboards[1 - cidx][5];
+        let king_file = king_sq % 8;
+        let opposite_wings =
+            enemy_king != 0 && (enemy_king.trailing_zeros() as usize % 8).abs_diff(king_file) >= 3;
+
+        let mut penalty = 0;
+        for f in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+            let storm = enemy_pawns & (0x0101010101010101u64 << f);
+            if storm == 0 {
+                continue;
+            }
+            // The pawn nearest our side, and its rank seen from there.
+            let (sq, rank) = if color == Color::White {
+                let sq = storm.trailing_zeros() as usize;
+                (sq, sq / 8)
+            } else {
+                let sq = 63 - storm.leading_zeros() as usize;
+                (sq, 7 - sq / 8)
+            };
+            let mut value = PAWN_STORM_PENALTY[rank] as i32;
+            let front = if color == Color::White {
+                sq.checked_sub(8)
+            } else {
+                Some(sq + 8).filter(|&s| s < 64)
+            };
+            if front.is_some_and(|s| own_pawns & (1u64 << s) != 0) {
+                value /= 2;
+            }
+            if opposite_wings {
+                value *= 2;
+            }
+            penalty += value;
+        }
+        Score::new(penalty as i16, 0)
+    }
+
     fn eval_king_safety(&self) -> Score {
         let mut score = Score::ZERO;
 
@@ -647,12 +1048,9 @@ impl<'a> Evaluator<'a> {
             };
 
             if shelter_rank < 8 {
-                for f in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
-                    let shelter_sq = shelter_rank * 8 + f;
-                    if (own_pawns & (1u64 << shelter_sq)) == 0 {
-                        score -= PAWN_SHELTER_PENALTY;
-                    }
-                }
+                let shelter = (adjacent_files(king_file) | FILE_A << king_file)
+                    & (0xFFu64 << (8 * shelter_rank));
+                score -= PAWN_SHELTER_PENALTY * (shelter & !own_pawns).count_ones() as i32;
             }
 
             let file_mask = 0x0101010101010101u64 << king_file;
@@ -661,6 +1059,8 @@ impl<'a> Evaluator<'a> {
             } else if (own_pawns & file_mask) == 0 {
                 score -= KING_SEMI_OPEN_FILE_PENALTY;
             }
+
+            score -= self.pawn_storm(color, king_sq);
         }
 
         score
@@ -673,43 +1073,25 @@ pub fn evaluate(board: &Board, color: Color) -> i32 {
     evaluator.evaluate(color)
 }
 
+#[inline]
+pub fn evaluate_weighted(board: &Board, color: Color, weights: &EvalWeights) -> i32 {
+    Evaluator::new(board).evaluate_weighted(color, weights)
+}
+
+pub fn explain(board: &Board) -> EvalBreakdown {
+    Evaluator::new(board).explain()
+}
+
 #[inline]
 pub fn game_phase(board: &Board) -> i32 {
     Evaluator::calculate_phase(board)
 }
 
 pub fn is_drawn_endgame(board: &Board) -> bool {
-    let white_pieces: u64 = board.bitboards[0].iter().fold(0, |a, b| a | b);
-    let black_pieces: u64 = board.bitboards[1].iter().fold(0, |a, b| a | b);
-    let total = (white_pieces | black_pieces).count_ones();
-
-    if total <= 2 {
-        return true;
-    }
-
-    if total == 3 {
-        let white_minors = board.bitboards[0][1].count_ones() + board.bitboards[0][2].count_ones();
-        let black_minors = board.bitboards[1][1].count_ones() + board.bitboards[1][2].count_ones();
-        if white_minors + black_minors == 1 {
-            return true;
-        }
-    }
-
-    if total == 4 {
-        let white_knights = board.bitboards[0][1].count_ones();
-        let black_knights = board.bitboards[1][1].count_ones();
-        if (white_knights == 2 && black_knights == 0) || (white_knights == 0 && black_knights == 2)
-        {
-            if board.bitboards[0][0] == 0 && board.bitboards[1][0] == 0 {
-                return true;
-            }
-        }
-    }
-
-    false
+    matches!(find_endgame(board.material_key), Some((Endgame::Draw, _)))
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use crate::game::Game;
@@ -753,4 +1135,258 @@ mod tests {
         let score = evaluate(&game.board, Color::White);
         assert!(score > 800, "Score with queen up: {}", score);
     }
+
+    #[test]
+    fn test_eval_weights() {
+        let mut game = Game::new();
+        game.board.set_index(3, 7, None);
+        let default = evaluate(&game.board, Color::White);
+        assert_eq!(
+            evaluate_weighted(&game.board, Color::White, &EvalWeights::default()),
+            default
+        );
+
+        let weights = EvalWeights {
+            material: 50,
+            ..EvalWeights::default()
+        };
+        let halved = evaluate_weighted(&game.board, Color::White, &weights);
+        assert!(
+            halved < default && halved > 300,
+            "Halved material: {}",
+            halved
+        );
+    }
+
+    fn eval_fen(fen: &str) -> i32 {
+        let (board, color) = Board::from_fen(fen).unwrap();
+        evaluate(&board, color)
+    }
+
+    #[test]
+    fn test_explain_adds_up_to_evaluation() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4r3/3p1k2/8/8/B7/8/4N3/4K3 w - - 0 1",
+        ] {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let breakdown = Evaluator::new(&board).explain();
+            assert_eq!(breakdown.endgame, None);
+            assert_eq!(
+                breakdown.total + TEMPO_BONUS,
+                evaluate(&board, Color::White)
+            );
+            let terms: i32 = breakdown.terms().iter().map(|&(_, v)| v).sum();
+            assert!((terms - breakdown.total).abs() <= 6, "{fen}");
+        }
+
+        let (board, _) = Board::from_fen("8/8/4k3/8/8/2B5/8/4K1N1 w - - 0 1").unwrap();
+        let breakdown = Evaluator::new(&board).explain();
+        assert_eq!(breakdown.endgame, Some(breakdown.total));
+        assert_eq!(breakdown.total, evaluate(&board, Color::White));
+
+        let (board, _) = Board::from_fen("4k3/8/8/8/3N4/8/8/n3K3 w - - 0 1").unwrap();
+        let map = Evaluator::new(&board).pst_map();
+        assert!(map[27] > 0 && map[0] > 0);
+        assert_eq!(map[1], 0);
+
+        // Symmetric start: every term cancels out.
+        let mut board = Board::new();
+        board.setup_standard();
+        let breakdown = Evaluator::new(&board).explain();
+        assert_eq!(breakdown.material.white, breakdown.material.black);
+        assert!(breakdown.terms().iter().all(|&(_, v)| v == 0));
+    }
+
+    #[test]
+    fn test_passed_pawn_path_and_kings() {
+        let passer = |fen: &str| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let sq = board
+                .pieces_of(Color::White, PieceType::Pawn)
+                .trailing_zeros() as u8;
+            Evaluator::new(&board).eval_passed_pawn(sq, Color::White, 5)
+        };
+        // A passer on b6 with the kings equally far from b7.
+        let free = passer("8/8/1P6/8/8/8/8/k3K3 w - - 0 1");
+        let blocked = passer("1r6/1r6/1P6/8/8/8/8/k3K3 w - - 0 1");
+        let held = passer("1r6/8/1P6/8/8/8/8/k3K3 w - - 0 1");
+        assert_eq!(free.mg(), PASSED_PAWN_BONUS_MG[5] as i32);
+        assert_eq!(blocked.mg(), PASSED_PAWN_BONUS_MG[5] as i32 / 2);
+        assert_eq!(held.mg(), PASSED_PAWN_BONUS_MG[5] as i32 * 3 / 4);
+        assert!(free.eg() > held.eg() && held.eg() > blocked.eg());
+
+        let escorted = passer("8/8/1P6/2K5/8/8/8/7k w - - 0 1");
+        let caught = passer("8/8/1P6/2k5/8/8/8/7K w - - 0 1");
+        assert_eq!(escorted.mg(), caught.mg());
+        assert!(escorted.eg() > free.eg() && free.eg() > caught.eg());
+    }
+
+    #[test]
+    fn test_bad_bishop() {
+        let bishops = |fen: &str| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            Evaluator::new(&board).eval_bishop_pawns(Color::White)
+        };
+        // White's pawns are fixed on the dark squares c3, d4 and e5.
+        let pawns = "4k3/8/4p3/3pP3/2pP4/2P5/8/";
+        let bad = bishops(&format!("{}2B1K3 w - - 0 1", pawns));
+        let good = bishops(&format!("{}3BK3 w - - 0 1", pawns));
+        assert_eq!(bad, Score::ZERO - BISHOP_FIXED_PAWN_PENALTY * 3);
+        assert_eq!(good, BISHOP_PAWN_COLOR * BISHOP_PAWNS_BASELINE);
+    }
+
+    #[test]
+    fn test_pawn_storm() {
+        let storm = |fen: &str| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let evaluator = Evaluator::new(&board);
+            let king_sq = board
+                .pieces_of(Color::White, PieceType::King)
+                .trailing_zeros();
+            evaluator.pawn_storm(Color::White, king_sq as usize).mg()
+        };
+        // Black pawns on g6, g4 and h3 against White's king on g1.
+        let far = storm("r1bq1rk1/pp3p1p/2n3p1/8/8/2N5/PP3PPP/R1BQ1RK1 w - - 0 1");
+        let near = storm("r1bq1rk1/pp3p1p/2n5/8/6p1/2N5/PP3PPP/R1BQ1RK1 w - - 0 1");
+        let close = storm("r1bq1rk1/pp3p2/2n5/8/8/2N4p/PP3PP1/R1BQ1RK1 w - - 0 1");
+        assert_eq!(far, 0);
+        assert!(near > far);
+        assert!(close > near);
+
+        // The same storm counts double with the kings castled on opposite wings.
+        let opposite = storm("2kr1b1r/pp3p1p/2n5/8/6p1/2N5/PP3PPP/R1BQ1RK1 w - - 0 1");
+        assert_eq!(opposite, 2 * near);
+        // And half as much when blocked by a pawn.
+        let blocked = storm("r1bq1rk1/pp3p1p/2n5/8/6p1/2N3P1/PP3P1P/R1BQ1RK1 w - - 0 1");
+        assert_eq!(2 * blocked, near);
+    }
+
+    #[test]
+    fn test_eval_is_side_to_move_relative() {
+        let opposite = |c| {
+            if c == Color::White {
+                Color::Black
+            } else {
+                Color::White
+            }
+        };
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "r1bq1rk1/pp2ppbp/2np1np1/8/3NP3/2N1BP2/PPPQ2PP/R3KB1R w KQ - 0 9",
+            "8/8/4k3/8/8/2B5/8/4K1N1 b - - 0 1",
+        ] {
+            let (board, color) = Board::from_fen(fen).unwrap();
+            let flipped = board.flipped();
+            for side in [color, opposite(color)] {
+                assert_eq!(
+                    evaluate(&board, side),
+                    evaluate(&flipped, opposite(side)),
+                    "{fen}"
+                );
+            }
+            if Evaluator::new(&board).eval_endgame().is_none() {
+                let white = evaluate(&board, Color::White) - TEMPO_BONUS;
+                let black = evaluate(&board, Color::Black) - TEMPO_BONUS;
+                assert_eq!(white, -black, "{fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_recognised_endgames() {
+        assert_eq!(eval_fen("8/8/4k3/8/8/8/8/1NN1K3 w - - 0 1"), 0);
+        assert_eq!(eval_fen("8/8/4k3/8/8/2b5/8/4K1N1 b - - 0 1"), 0);
+        assert!(is_drawn_endgame(
+            &Board::from_fen("8/8/4k3/8/8/8/8/1N2K3 w - - 0 1")
+                .unwrap()
+                .0
+        ));
+        assert!(!is_drawn_endgame(
+            &Board::from_fen("8/8/4k3/8/8/8/8/R3K3 w - - 0 1").unwrap().0
+        ));
+
+        // A lone king is better off in the center, and scored from both sides.
+        let edge = eval_fen("7k/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let center = eval_fen("8/8/8/4k3/8/8/8/R3K3 w - - 0 1");
+        assert!(edge > center && center > KNOWN_WIN, "{} {}", edge, center);
+        assert_eq!(eval_fen("8/8/8/4k3/8/8/8/R3K3 b - - 0 1"), -center);
+        assert_eq!(eval_fen("r3k3/8/8/8/4K3/8/8/8 b - - 0 1"), center);
+
+        // With a dark-squared bishop the king must be mated on a1 or h8.
+        let right_corner = eval_fen("7k/8/5K2/8/8/8/8/2B3N1 w - - 0 1");
+        let wrong_corner = eval_fen("k7/8/2K5/8/8/8/8/2B3N1 w - - 0 1");
+        assert!(
+            right_corner > wrong_corner,
+            "{} {}",
+            right_corner,
+            wrong_corner
+        );
+    }
+
+    #[test]
+    fn test_pawn_masks() {
+        assert_eq!(adjacent_files(0), FILE_A << 1);
+        assert_eq!(adjacent_files(7), FILE_A << 6);
+        assert_eq!(adjacent_files(3), FILE_A << 2 | FILE_A << 4);
+        assert_eq!(ranks_ahead(Color::White, 6), 0xFF << 56);
+        assert_eq!(ranks_ahead(Color::White, 7), 0);
+        assert_eq!(ranks_ahead(Color::Black, 0), 0);
+        assert_eq!(ranks_ahead(Color::Black, 2), 0xFFFF);
+    }
+
+    #[test]
+    fn test_mobility_counts_safe_squares() {
+        let mobility = |fen: &str| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let score = Evaluator::new(&board).eval_mobility(Color::White);
+            (score.mg(), score.eg())
+        };
+        let corner = mobility("4k3/8/8/8/8/8/8/N3K3 w - - 0 1");
+        let center = mobility("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(corner, (-8, -8));
+        assert_eq!(center, (16, 16));
+
+        // Black pawns on c6 and e6 guard b5 and f5, but can be captured.
+        let restricted = mobility("4k3/8/2p1p3/8/3N4/8/8/4K3 w - - 0 1");
+        assert_eq!(restricted, (8, 8));
+    }
+
+    #[test]
+    fn test_pinned_pieces_counted() {
+        let pinned = |fen: &str, color| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            Evaluator::new(&board).pinned_pieces(color)
+        };
+        assert_eq!(pinned("k3r3/8/8/8/8/8/4N3/4K3 w - - 0 1", Color::White), 1);
+        assert_eq!(pinned("k3r3/8/8/8/8/8/4P3/4K3 w - - 0 1", Color::White), 0);
+        assert_eq!(pinned("4k3/3b4/8/8/B7/8/8/4K3 b - - 0 1", Color::Black), 1);
+    }
+
+    #[test]
+    fn test_development_terms() {
+        let development = |fen: &str| {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let eval = Evaluator::new(&board);
+            [Color::White, Color::Black].map(|c| eval.eval_development(c).mg())
+        };
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        assert_eq!(development(&format!("{} w KQkq - 0 1", start)), [0, 0]);
+        // Knights hopping out and back for four moves.
+        assert_eq!(development(&format!("{} w KQkq - 8 5", start)), [-30, -30]);
+        // 2. Qh5 with every minor piece at home.
+        assert_eq!(
+            development("rnbqkbnr/pppp1ppp/8/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2"),
+            [-32, 0]
+        );
+        // Bishops still at home on move 9, after eight moves that only
+        // brought out the knights and the e-pawns.
+        assert_eq!(
+            development("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 14 9"),
+            [-70, -70]
+        );
+    }
 }
@@ -1,6 +1,24 @@
-use crate::board::Board;
-use crate::pieces::Color;
+use crate::board::{Board, FenError};
+use crate::pieces::{Color, PieceType};
+use crate::types::UndoState;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MoveEvent {
+    pub capture: bool,
+    pub castle: bool,
+    pub check: bool,
+    pub game_over: bool,
+}
+
+// Draws a player may claim under the FIDE rules but which do not end the game
+// by themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawClaim {
+    Repetition,
+    FiftyMoves,
+}
+
+#[derive(Clone)]
 pub struct Game {
     pub board: Board,
     pub current_turn: Color,
@@ -8,68 +26,458 @@ pub struct Game {
     pub hash_history: Vec<u64>,
     pub hash_counts: std::collections::HashMap<u64, usize>,
     pub result: Option<Color>,
+    // Set once a draw claim has been accepted.
+    pub draw: Option<DrawClaim>,
+    pub start_board: Board,
+    pub start_turn: Color,
+    pub states: Vec<UndoState>,
 }
 
 impl Game {
     pub fn new() -> Self {
         let mut board = Board::new();
         board.setup_standard();
-        let hash = board.hash(Color::White);
+        Self::from_board(board, Color::White)
+    }
+
+    pub fn from_fen(fen: &str) -> Option<Self> {
+        Self::parse_fen(fen).ok()
+    }
+
+    pub fn parse_fen(fen: &str) -> Result<Self, FenError> {
+        let (board, turn) = Board::parse_fen(fen)?;
+        board.validate(turn).map_err(FenError::Position)?;
+        Ok(Self::from_board(board, turn))
+    }
+
+    pub fn from_board(board: Board, turn: Color) -> Self {
+        let hash = board.hash(turn);
+        let key = board.repetition_key(turn);
         Self {
-            board,
-            current_turn: Color::White,
+            board: board.clone(),
+            current_turn: turn,
             history: Vec::new(),
             hash_history: vec![hash],
             hash_counts: {
                 let mut m = std::collections::HashMap::new();
-                m.insert(hash, 1);
+                m.insert(key, 1);
                 m
             },
             result: None,
+            draw: None,
+            start_board: board,
+            start_turn: turn,
+            states: Vec::new(),
         }
     }
 
+    pub fn is_standard_start(&self) -> bool {
+        let mut standard = Board::new();
+        standard.setup_standard();
+        self.start_turn == Color::White
+            && self.start_board.hash(Color::White) == standard.hash(Color::White)
+            && self.start_board.castling == standard.castling
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.board.to_fen(self.current_turn)
+    }
+
     pub fn make_move(&mut self, start: &str, end: &str) -> bool {
-        if start == end {
-            return false;
+        self.make_move_event(start, end).is_some()
+    }
+
+    pub fn make_move_event(&mut self, start: &str, end: &str) -> Option<MoveEvent> {
+        if start == end || self.draw.is_some() {
+            return None;
         }
         if !self.board.is_legal(start, end, self.current_turn) {
-            return false;
+            return None;
+        }
+        // A promotion letter is only accepted on a promoting pawn move.
+        if let Some((dest, Some(_))) = Board::split_promotion(end)
+            && (self.board.get(start)?.piece_type != PieceType::Pawn
+                || !matches!(&dest[1..2], "1" | "8"))
+        {
+            return None;
+        }
+        let state = self.board.make_move_state(start, end)?;
+        let mut event = MoveEvent {
+            capture: state.has_capture(),
+            castle: state.mv.is_castle(),
+            ..Default::default()
+        };
+        self.states.push(state);
+        self.history.push((start.to_string(), end.to_string()));
+        self.current_turn = if self.current_turn == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+        self.hash_history.push(self.board.hash(self.current_turn));
+        let key = self.board.repetition_key(self.current_turn);
+        *self.hash_counts.entry(key).or_insert(0) += 1;
+        event.check = self.board.in_check(self.current_turn);
+        if self
+            .board
+            .all_legal_moves_fast(self.current_turn)
+            .is_empty()
+        {
+            event.game_over = true;
+            if event.check {
+                self.result = Some(if self.current_turn == Color::White {
+                    Color::Black
+                } else {
+                    Color::White
+                });
+            }
+        }
+        Some(event)
+    }
+
+    pub fn undo_move(&mut self) -> bool {
+        let state = match self.states.pop() {
+            Some(s) => s,
+            None => return false,
+        };
+        let key = self.board.repetition_key(self.current_turn);
+        self.board.unmake_move(state);
+        self.history.pop();
+        self.hash_history.pop();
+        if let Some(count) = self.hash_counts.get_mut(&key) {
+            *count -= 1;
+            if *count == 0 {
+                self.hash_counts.remove(&key);
+            }
         }
-        if self.board.make_move_state(start, end).is_some() {
-            self.history.push((start.to_string(), end.to_string()));
-            self.current_turn = if self.current_turn == Color::White {
+        self.current_turn = if self.current_turn == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+        self.result = None;
+        self.draw = None;
+        true
+    }
+
+    // The moves played so far in SAN. A move the SAN generator cannot
+    // describe is kept in coordinates.
+    pub fn san_history(&self) -> Vec<String> {
+        let mut board = self.start_board.clone();
+        let mut color = self.start_turn;
+        let mut sans = Vec::with_capacity(self.history.len());
+        for (start, end) in &self.history {
+            sans.push(
+                crate::san::move_to_san(&board, start, end, color)
+                    .unwrap_or_else(|| format!("{}{}", start, end)),
+            );
+            board.make_move_state(start, end);
+            color = if color == Color::White {
                 Color::Black
             } else {
                 Color::White
             };
-            let h = self.board.hash(self.current_turn);
-            self.hash_history.push(h);
-            *self.hash_counts.entry(h).or_insert(0) += 1;
-            if self
-                .board
-                .all_legal_moves_fast(self.current_turn)
-                .is_empty()
-            {
-                if self.board.in_check(self.current_turn) {
-                    self.result = Some(if self.current_turn == Color::White {
-                        Color::Black
-                    } else {
-                        Color::White
-                    });
-                }
+        }
+        sans
+    }
+
+    // Numbered SAN move list such as "1. e4 e5 2. Nf3", starting with "1..."
+    // when Black moved first.
+    pub fn san_movetext(&self) -> String {
+        let offset = usize::from(self.start_turn == Color::Black);
+        let mut text = String::new();
+        for (i, san) in self.san_history().iter().enumerate() {
+            let number = (i + offset) / 2 + 1;
+            if !text.is_empty() {
+                text.push(' ');
             }
-            true
-        } else {
-            false
+            if (i + offset).is_multiple_of(2) {
+                text.push_str(&format!("{}. ", number));
+            } else if i == 0 {
+                text.push_str(&format!("{}... ", number));
+            }
+            text.push_str(san);
         }
+        text
+    }
+
+    pub fn last_move(&self) -> Option<(&str, &str)> {
+        self.history.last().map(|(s, e)| (s.as_str(), e.as_str()))
     }
 
     pub fn legal_moves(&mut self) -> Vec<(String, String)> {
         self.board.all_legal_moves_fast(self.current_turn)
     }
 
-    pub fn repetition_count(&self, hash: u64) -> usize {
-        *self.hash_counts.get(&hash).unwrap_or(&0)
+    // How often the position with `Board::repetition_key` `key` occurred.
+    pub fn repetition_count(&self, key: u64) -> usize {
+        *self.hash_counts.get(&key).unwrap_or(&0)
+    }
+
+    pub fn is_stalemate(&mut self) -> bool {
+        self.result.is_none()
+            && !self.board.in_check(self.current_turn)
+            && self.legal_moves().is_empty()
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count(self.board.repetition_key(self.current_turn)) >= 3
+    }
+
+    // Five occurrences of a position end the game without a claim.
+    pub fn is_fivefold_repetition(&self) -> bool {
+        self.repetition_count(self.board.repetition_key(self.current_turn)) >= 5
+    }
+
+    // As does 75 moves by each side without a capture or pawn move, unless
+    // the last of them mated.
+    pub fn is_seventy_five_move_draw(&self) -> bool {
+        self.board.halfmove_clock >= 150 && self.result.is_none()
+    }
+
+    // The draw the side to move may claim now, if any: a position seen three
+    // times, or 50 moves by each side without a capture or pawn move.
+    pub fn can_claim_draw(&self) -> Option<DrawClaim> {
+        if self.result.is_some() || self.draw.is_some() {
+            None
+        } else if self.is_threefold_repetition() {
+            Some(DrawClaim::Repetition)
+        } else if self.board.halfmove_clock >= 100 {
+            Some(DrawClaim::FiftyMoves)
+        } else {
+            None
+        }
+    }
+
+    // Ends the game as a draw if a claim is available, returning its ground.
+    pub fn claim_draw(&mut self) -> Option<DrawClaim> {
+        let claim = self.can_claim_draw()?;
+        self.draw = Some(claim);
+        Some(claim)
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        self.board.insufficient_material()
+    }
+
+    pub fn position_at(&self, ply: usize) -> Board {
+        let mut board = self.start_board.clone();
+        for (start, end) in self.history.iter().take(ply) {
+            board.make_move_state(start, end);
+        }
+        board
+    }
+
+    pub fn turn_at(&self, ply: usize) -> Color {
+        if ply.is_multiple_of(2) {
+            self.start_turn
+        } else if self.start_turn == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        }
+    }
+
+    pub fn truncate(&mut self, ply: usize) {
+        if ply >= self.history.len() {
+            return;
+        }
+        let moves: Vec<(String, String)> = self.history.drain(..).take(ply).collect();
+        self.board = self.start_board.clone();
+        self.current_turn = self.start_turn;
+        self.result = None;
+        self.draw = None;
+        self.hash_history = vec![self.board.hash(self.current_turn)];
+        self.hash_counts.clear();
+        self.hash_counts
+            .insert(self.board.repetition_key(self.current_turn), 1);
+        self.states.clear();
+        for (start, end) in moves {
+            self.make_move(&start, &end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::PositionError;
+
+    #[test]
+    fn undo_restores_position() {
+        let mut game = Game::new();
+        let hash = game.board.hash(game.current_turn);
+        assert!(game.make_move("e2", "e4"));
+        assert!(game.make_move("d7", "d5"));
+        assert!(game.make_move("e4", "d5"));
+
+        assert!(game.undo_move());
+        assert!(game.undo_move());
+        assert!(game.undo_move());
+        assert!(!game.undo_move());
+
+        assert_eq!(game.current_turn, Color::White);
+        assert!(game.history.is_empty());
+        assert_eq!(game.board.hash(game.current_turn), hash);
+        let key = game.board.repetition_key(game.current_turn);
+        assert_eq!(game.repetition_count(key), 1);
+        assert_eq!(game.legal_moves().len(), 20);
+    }
+
+    #[test]
+    fn truncate_replays_prefix() {
+        let mut game = Game::new();
+        assert!(game.make_move("e2", "e4"));
+        assert!(game.make_move("e7", "e5"));
+        assert!(game.make_move("g1", "f3"));
+        let expected = game.position_at(1).hash(Color::Black);
+
+        game.truncate(1);
+        assert_eq!(game.history.len(), 1);
+        assert_eq!(game.current_turn, Color::Black);
+        assert_eq!(game.board.hash(game.current_turn), expected);
+    }
+
+    #[test]
+    fn detects_stalemate_and_repetition() {
+        let mut game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(game.is_stalemate());
+
+        let mut game = Game::new();
+        assert!(!game.is_stalemate());
+        for _ in 0..2 {
+            assert!(!game.is_threefold_repetition());
+            assert!(game.make_move("g1", "f3"));
+            assert!(game.make_move("g8", "f6"));
+            assert!(game.make_move("f3", "g1"));
+            assert!(game.make_move("f6", "g8"));
+        }
+        assert!(game.is_threefold_repetition());
+    }
+
+    #[test]
+    fn draws_are_claimed_before_they_are_automatic() {
+        let mut game = Game::new();
+        let shuffle = [("g1", "f3"), ("g8", "f6"), ("f3", "g1"), ("f6", "g8")];
+        for _ in 0..2 {
+            assert_eq!(game.can_claim_draw(), None);
+            for (s, e) in shuffle {
+                assert!(game.make_move(s, e));
+            }
+        }
+        assert_eq!(game.can_claim_draw(), Some(DrawClaim::Repetition));
+        for _ in 0..2 {
+            assert!(!game.is_fivefold_repetition());
+            for (s, e) in shuffle {
+                assert!(game.make_move(s, e));
+            }
+        }
+        assert!(game.is_fivefold_repetition());
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert_eq!(game.can_claim_draw(), None);
+        assert!(game.make_move("a1", "a2"));
+        assert_eq!(game.can_claim_draw(), Some(DrawClaim::FiftyMoves));
+        assert!(!game.is_seventy_five_move_draw());
+        assert_eq!(game.claim_draw(), Some(DrawClaim::FiftyMoves));
+        assert_eq!(game.draw, Some(DrawClaim::FiftyMoves));
+        assert!(!game.make_move("e8", "d8"));
+        assert!(game.undo_move());
+        assert_eq!(game.draw, None);
+        assert_eq!(game.claim_draw(), None);
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 150 100").unwrap();
+        assert!(game.is_seventy_five_move_draw());
+    }
+
+    #[test]
+    fn repetitions_respect_castling_rights() {
+        // The first position still has castling rights, its repeats do not.
+        let mut game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let shuffle = [("e1", "f1"), ("e8", "f8"), ("f1", "e1"), ("f8", "e8")];
+        for _ in 0..2 {
+            for (s, e) in shuffle {
+                assert!(game.make_move(s, e));
+            }
+        }
+        assert_eq!(game.can_claim_draw(), None);
+        for (s, e) in shuffle {
+            assert!(game.make_move(s, e));
+        }
+        assert_eq!(game.can_claim_draw(), Some(DrawClaim::Repetition));
+
+        // An en passant square no pawn can use changes nothing.
+        let mut game = Game::new();
+        assert!(game.make_move("e2", "e4"));
+        let mut plain = game.board.clone();
+        assert!(plain.en_passant.take().is_some());
+        assert_eq!(
+            game.board.repetition_key(Color::Black),
+            plain.repetition_key(Color::Black)
+        );
+    }
+
+    #[test]
+    fn move_events_report_outcomes() {
+        let mut game = Game::new();
+        let quiet = game.make_move_event("f2", "f3").unwrap();
+        assert_eq!(quiet, MoveEvent::default());
+        assert!(game.make_move("e7", "e5"));
+        assert!(game.make_move("g2", "g4"));
+        let mate = game.make_move_event("d8", "h4").unwrap();
+        assert!(mate.check && mate.game_over && !mate.capture);
+        assert!(game.make_move_event("e2", "e4").is_none());
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/3p4/8/R3K3 w Q - 0 1").unwrap();
+        let castle = game.make_move_event("e1", "c1").unwrap();
+        assert!(castle.castle);
+        let capture = game.make_move_event("e8", "e7").map(|e| e.capture);
+        assert_eq!(capture, Some(false));
+        assert!(game.make_move_event("d1", "d3").unwrap().capture);
+    }
+
+    #[test]
+    fn san_history_numbers_moves() {
+        let mut game = Game::new();
+        assert_eq!(game.san_movetext(), "");
+        for (start, end) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3"), ("b8", "c6")] {
+            assert!(game.make_move(start, end));
+        }
+        assert!(game.make_move("f1", "b5"));
+        assert_eq!(game.san_history(), ["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!(game.san_movetext(), "1. e4 e5 2. Nf3 Nc6 3. Bb5");
+
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 0 12").unwrap();
+        assert!(game.make_move("e8", "d7"));
+        assert!(game.make_move("e1", "c1"));
+        assert_eq!(game.san_movetext(), "1... Kd7 2. O-O-O+");
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        let mut game = Game::new();
+        assert!(game.make_move("e2", "e4"));
+        assert!(game.make_move("c7", "c5"));
+        assert!(game.make_move("e1", "e2"));
+        let fen = game.to_fen();
+        assert_eq!(
+            fen,
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2"
+        );
+
+        let loaded = Game::from_fen(&fen).unwrap();
+        assert_eq!(loaded.current_turn, Color::Black);
+        assert_eq!(loaded.to_fen(), fen);
+        assert_eq!(
+            loaded.board.hash(Color::Black),
+            game.board.hash(Color::Black)
+        );
+        assert!(Game::from_fen("8/8/8 w - - 0 1").is_none());
+        assert_eq!(
+            Game::parse_fen("8/8/8/8/8/8/8/8 w - - 0 1").err(),
+            Some(FenError::Position(PositionError::MissingKing(Color::White)))
+        );
+        assert!(Game::new().is_standard_start());
+        assert!(!loaded.is_standard_start());
     }
 }
@@ -0,0 +1,157 @@
+use crate::arena::append_pgn;
+use crate::engine::Engine;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Directory the GUI, the arena and the WebSocket server log their finished
+// games to, when set.
+pub const GAME_LOG_ENV: &str = "CHESSMIND_GAME_LOG";
+
+// Appends finished games as PGN to one file per day ("2026-10-15.pgn") in a
+// directory, stamped with the date and time they ended.
+#[derive(Clone, Debug)]
+pub struct GameLog {
+    dir: PathBuf,
+}
+
+impl GameLog {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    // The log in `CHESSMIND_GAME_LOG`, or `None` when it is not set.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        match std::env::var(GAME_LOG_ENV) {
+            Ok(dir) if !dir.is_empty() => Self::open(dir).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Appends `pgn`, a whole game with its tags, and returns the file written.
+    pub fn record(&self, pgn: &str) -> io::Result<PathBuf> {
+        self.record_at(pgn, SystemTime::now())
+    }
+
+    fn record_at(&self, pgn: &str, now: SystemTime) -> io::Result<PathBuf> {
+        let (date, _) = utc(now);
+        let path = self.dir.join(format!("{}.pgn", date.replace('.', "-")));
+        append_pgn(&path, &stamp(pgn, now))?;
+        Ok(path)
+    }
+}
+
+// One-line summary of the engine's settings for the PGN headers.
+pub fn engine_settings(engine: &Engine) -> String {
+    let params = &engine.params;
+    let mut summary = format!(
+        "depth {}, {} threads, book {}",
+        engine.depth,
+        engine.threads,
+        if params.use_book { "on" } else { "off" }
+    );
+    if let Some(human) = &params.human {
+        summary.push_str(&format!(
+            ", human style depth {}, temperature {}, mistakes {}%, blunders {}%",
+            human.depth, human.temperature, human.mistake_rate, human.blunder_rate
+        ));
+    }
+    if let Some(mcts) = &params.mcts {
+        summary.push_str(&format!(", MCTS {} playouts", mcts.playouts));
+    }
+    if let Some(seed) = params.seed {
+        summary.push_str(&format!(", seed {}", seed));
+    }
+    summary
+}
+
+// Date ("2026.10.15") and time ("21:04:05") of `now` in UTC.
+fn utc(now: SystemTime) -> (String, String) {
+    let secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    // Civil date from the day count, in 400-year eras from 0000-03-01.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        format!("{:04}.{:02}.{:02}", year, month, day),
+        format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60),
+    )
+}
+
+// `pgn` with an unknown Date tag filled in and UTCDate and UTCTime tags after
+// it.
+fn stamp(pgn: &str, now: SystemTime) -> String {
+    let (date, time) = utc(now);
+    let tags = format!("[UTCDate \"{}\"]\n[UTCTime \"{}\"]\n", date, time);
+    let Some(start) = pgn.find("[Date \"") else {
+        return format!("{}{}", tags, pgn);
+    };
+    let end = pgn[start..].find('\n').map_or(pgn.len(), |i| start + i + 1);
+    let line = if pgn[start..end].starts_with("[Date \"????.??.??\"]") {
+        format!("[Date \"{}\"]\n", date)
+    } else {
+        pgn[start..end].to_string()
+    };
+    format!("{}{}{}{}", &pgn[..start], line, tags, &pgn[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+    use crate::pgn::{parse_pgn_collection, to_pgn};
+    use std::time::Duration;
+
+    #[test]
+    fn dates_are_utc() {
+        let at = |secs| utc(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), ("1970.01.01".to_string(), "00:00:00".to_string()));
+        assert_eq!(
+            at(1_700_000_000),
+            ("2023.11.14".to_string(), "22:13:20".to_string())
+        );
+        assert_eq!(at(951_782_400).0, "2000.02.29");
+    }
+
+    #[test]
+    fn games_are_appended_with_timestamps() {
+        let dir = std::env::temp_dir().join(format!("game_log_{}", std::process::id()));
+        let log = GameLog::open(&dir).unwrap();
+        let mut game = Game::new();
+        for (s, e) in [("f2", "f3"), ("e7", "e5"), ("g2", "g4"), ("d8", "h4")] {
+            assert!(game.make_move(s, e));
+        }
+        let tags = vec![
+            ("Event".to_string(), "Test".to_string()),
+            ("Date".to_string(), "????.??.??".to_string()),
+        ];
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let path = log.record_at(&to_pgn(&game, &tags), now).unwrap();
+        log.record_at(&to_pgn(&game, &tags[..1]), now).unwrap();
+        assert_eq!(path, dir.join("2023-11-14.pgn"));
+
+        let games = parse_pgn_collection(&std::fs::read_to_string(&path).unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(games.len(), 2);
+        for pgn in &games {
+            assert_eq!(pgn.tag("UTCDate"), Some("2023.11.14"));
+            assert_eq!(pgn.tag("UTCTime"), Some("22:13:20"));
+            assert_eq!(pgn.game.history.len(), 4);
+        }
+        assert_eq!(games[0].tag("Date"), Some("2023.11.14"));
+        assert_eq!(games[0].tag("Result"), Some("0-1"));
+    }
+}
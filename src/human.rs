@@ -0,0 +1,121 @@
+use crate::engine::PvLine;
+use rand::Rng;
+
+// Settings for the weak, human-like playing mode: every legal move is scored
+// by a shallow search and one is drawn at random, good moves being the most
+// likely, with the occasional outright mistake thrown in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HumanStyle {
+    // Depth of the search scoring the root moves.
+    pub depth: u32,
+    // Centipawns a move may lose for its chance to drop by a factor of e.
+    pub temperature: u32,
+    // Percent of moves that are a deliberate mistake (losing 100 to 300
+    // centipawns) or blunder (losing more), when one is available.
+    pub mistake_rate: u32,
+    pub blunder_rate: u32,
+}
+
+impl Default for HumanStyle {
+    // Roughly a club player.
+    fn default() -> Self {
+        Self {
+            depth: 4,
+            temperature: 40,
+            mistake_rate: 8,
+            blunder_rate: 2,
+        }
+    }
+}
+
+const MISTAKE_LOSS: i32 = 100;
+const BLUNDER_LOSS: i32 = 300;
+
+impl HumanStyle {
+    // Picks one of `lines`, sorted best first with scores for the side to move.
+    pub fn choose<'a>(&self, lines: &'a [PvLine], rng: &mut impl Rng) -> Option<&'a PvLine> {
+        let best = lines.first()?.score;
+        let loss = |line: &PvLine| best - line.score;
+
+        let roll = rng.gen_range(0..100);
+        let graded: Vec<&PvLine> = if roll < self.blunder_rate {
+            lines.iter().filter(|l| loss(l) >= BLUNDER_LOSS).collect()
+        } else if roll < self.blunder_rate + self.mistake_rate {
+            lines
+                .iter()
+                .filter(|l| (MISTAKE_LOSS..BLUNDER_LOSS).contains(&loss(l)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if !graded.is_empty() {
+            return Some(graded[rng.gen_range(0..graded.len())]);
+        }
+
+        let temperature = self.temperature.max(1) as f64;
+        let weights: Vec<f64> = lines
+            .iter()
+            .map(|l| (-(loss(l) as f64) / temperature).exp())
+            .collect();
+        let mut pick = rng.gen_range(0.0..weights.iter().sum::<f64>());
+        for (line, weight) in lines.iter().zip(&weights) {
+            if pick < *weight {
+                return Some(line);
+            }
+            pick -= weight;
+        }
+        lines.first()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn line(score: i32, mv: &str) -> PvLine {
+        PvLine {
+            score,
+            moves: vec![(mv[0..2].to_string(), mv[2..4].to_string())],
+        }
+    }
+
+    #[test]
+    fn samples_by_score_and_grades_mistakes() {
+        let lines = [
+            line(50, "e2e4"),
+            line(40, "d2d4"),
+            line(-100, "g1h3"),
+            line(-600, "d1h5"),
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        let careful = HumanStyle {
+            mistake_rate: 0,
+            blunder_rate: 0,
+            ..HumanStyle::default()
+        };
+        let mut counts = [0; 4];
+        for _ in 0..1000 {
+            let pick = careful.choose(&lines, &mut rng).unwrap();
+            counts[lines.iter().position(|l| l.moves == pick.moves).unwrap()] += 1;
+        }
+        assert!(counts[0] > counts[1] && counts[1] > 300);
+        assert!(counts[2] < 30 && counts[3] == 0);
+
+        let blunderer = HumanStyle {
+            blunder_rate: 100,
+            ..HumanStyle::default()
+        };
+        assert_eq!(blunderer.choose(&lines, &mut rng).unwrap().score, -600);
+        let sloppy = HumanStyle {
+            mistake_rate: 100,
+            blunder_rate: 0,
+            ..HumanStyle::default()
+        };
+        assert_eq!(sloppy.choose(&lines, &mut rng).unwrap().score, -100);
+        // Without a bad enough move, the mistake falls back to sampling.
+        assert!(blunderer.choose(&lines[..3], &mut rng).unwrap().score > -600);
+        assert!(careful.choose(&[], &mut rng).is_none());
+    }
+}
@@ -1,15 +1,52 @@
+// Without the `std` feature only the engine core (board, move generation,
+// evaluation and transposition table) is built, on `core` and `alloc`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod arena;
 pub mod board;
+#[cfg(feature = "std")]
+pub mod coach;
+#[cfg(feature = "std")]
+pub mod convert;
+#[cfg(feature = "cozy")]
+pub mod cozy;
+#[cfg(feature = "std")]
+pub mod eco;
+#[cfg(feature = "std")]
 pub mod engine;
+#[cfg(feature = "std")]
+pub mod epd;
 pub mod eval;
+#[cfg(feature = "std")]
 pub mod game;
+#[cfg(feature = "std")]
+pub mod gamelog;
+#[cfg(feature = "std")]
+pub mod human;
+pub mod material;
+#[cfg(feature = "std")]
+pub mod mcts;
 pub mod movegen;
+#[cfg(feature = "std")]
 pub mod opening;
+#[cfg(feature = "std")]
+pub mod pgn;
 pub mod pieces;
+#[cfg(feature = "std")]
+pub mod play;
+#[cfg(feature = "std")]
+pub mod rating;
+#[cfg(feature = "std")]
 pub mod san;
 pub mod transposition;
 pub mod types;
+#[cfg(feature = "std")]
+pub mod uci;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use crate::{engine::Engine, game::Game};
     use num_cpus;
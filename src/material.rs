@@ -0,0 +1,158 @@
+use crate::board::{color_idx, piece_index};
+use crate::pieces::{Color, PieceType};
+
+// Material signature of a position: the number of pieces of each color and
+// type, one nibble each in `bitboards` order (white pawns in the lowest
+// nibble, black pieces in the upper 24 bits). `Board` keeps it up to date in
+// `set_index`, so it costs nothing to read during search.
+pub type MaterialKey = u64;
+
+const COLOR_BITS: u32 = 24;
+const COLOR_MASK: u64 = (1 << COLOR_BITS) - 1;
+
+#[inline(always)]
+pub const fn unit(cidx: usize, pidx: usize) -> MaterialKey {
+    1 << (4 * (cidx * 6 + pidx))
+}
+
+#[inline(always)]
+pub fn count(key: MaterialKey, color: Color, piece_type: PieceType) -> u32 {
+    ((key >> (4 * (color_idx(color) * 6 + piece_index(piece_type)))) & 0xF) as u32
+}
+
+// Number of pieces on the board, kings included.
+pub fn piece_count(key: MaterialKey) -> u32 {
+    (0..12).map(|i| ((key >> (4 * i)) & 0xF) as u32).sum()
+}
+
+// Number of pieces of `color`, kings included.
+pub fn side_count(key: MaterialKey, color: Color) -> u32 {
+    piece_count(key >> (COLOR_BITS * color_idx(color) as u32) & COLOR_MASK)
+}
+
+// Whether `color` has a knight, bishop, rook or queen. Without one, its
+// positions are the ones where zugzwang is common.
+pub fn has_non_pawn_material(key: MaterialKey, color: Color) -> bool {
+    [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+    ]
+    .iter()
+    .any(|&pt| count(key, color, pt) > 0)
+}
+
+// The same material with colors swapped.
+pub const fn mirror(key: MaterialKey) -> MaterialKey {
+    (key >> COLOR_BITS) | ((key & COLOR_MASK) << COLOR_BITS)
+}
+
+// Key of a signature in tablebase notation such as "KBNvK", White first.
+pub const fn from_signature(signature: &str) -> Option<MaterialKey> {
+    let bytes = signature.as_bytes();
+    let mut key = 0;
+    let mut cidx = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let pidx = match bytes[i] {
+            b'v' if cidx == 0 => {
+                cidx = 1;
+                i += 1;
+                continue;
+            }
+            b'P' => 0,
+            b'N' => 1,
+            b'B' => 2,
+            b'R' => 3,
+            b'Q' => 4,
+            b'K' => 5,
+            _ => return None,
+        };
+        key += unit(cidx, pidx);
+        i += 1;
+    }
+    if cidx == 0 {
+        return None;
+    }
+    Some(key)
+}
+
+// Material with which neither side can ever checkmate, whatever the
+// placement: bare kings, or a single minor piece against a bare king.
+pub fn is_insufficient(key: MaterialKey) -> bool {
+    let kings = unit(0, 5) + unit(1, 5);
+    let minors = [unit(0, 1), unit(0, 2), unit(1, 1), unit(1, 2)];
+    key == kings || minors.iter().any(|&minor| key == kings + minor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn signatures_match_board_keys() {
+        let mut board = Board::new();
+        board.setup_standard();
+        let start = from_signature("KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP").unwrap();
+        assert_eq!(board.material_key, start);
+        assert_eq!(piece_count(start), 32);
+        assert_eq!(side_count(start, Color::Black), 16);
+        assert_eq!(count(start, Color::White, PieceType::Pawn), 8);
+
+        let (board, _) = Board::from_fen("8/8/4k3/8/8/2B5/8/4K1N1 w - - 0 1").unwrap();
+        let key = from_signature("KBNvK").unwrap();
+        assert_eq!(board.material_key, key);
+        assert_eq!(mirror(key), from_signature("KvKBN").unwrap());
+        assert_eq!(side_count(key, Color::White), 3);
+        assert!(from_signature("KXvK").is_none());
+        assert!(from_signature("KQK").is_none());
+        assert!(from_signature("KvKvK").is_none());
+    }
+
+    #[test]
+    fn non_pawn_material_per_side() {
+        let key = from_signature("KRPPvKPPP").unwrap();
+        assert!(has_non_pawn_material(key, Color::White));
+        assert!(!has_non_pawn_material(key, Color::Black));
+        let pawns = from_signature("KPPPPPvKPPPP").unwrap();
+        assert!(!has_non_pawn_material(pawns, Color::White));
+        assert!(has_non_pawn_material(
+            from_signature("KvKN").unwrap(),
+            Color::Black
+        ));
+    }
+
+    #[test]
+    fn key_follows_captures_and_promotions() {
+        let mut list = crate::types::MoveList::new();
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            let before = board.material_key;
+            list.clear();
+            crate::movegen::generate_moves_fast(&mut board, color, &mut list);
+            for i in 0..list.len() {
+                let mv = list.get(i).unwrap();
+                let state = board.make_move_fast(mv, color);
+                let (rebuilt, _) = Board::from_fen(&board.to_fen(color)).unwrap();
+                assert_eq!(board.material_key, rebuilt.material_key);
+                board.unmake_move_fast(state, color);
+                assert_eq!(board.material_key, before);
+            }
+        }
+    }
+
+    #[test]
+    fn recognises_insufficient_material() {
+        for signature in ["KvK", "KBvK", "KvKN"] {
+            assert!(is_insufficient(from_signature(signature).unwrap()));
+        }
+        for signature in ["KPvK", "KRvK", "KNNvK", "KBvKN"] {
+            assert!(!is_insufficient(from_signature(signature).unwrap()));
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use crate::board::Board;
+use crate::pieces::Color;
+use crate::types::{Move, MoveList, UndoState};
+
+// Settings of the Monte Carlo tree searcher, an alternative to alpha-beta for
+// experiments: the tree grows one position per playout, following PUCT
+// (visits steered by the move priors) and backing up the value of each new
+// leaf as a win probability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MctsParams {
+    // Playouts per move, unless the clock stops the search first.
+    pub playouts: u32,
+    // PUCT exploration constant, in hundredths.
+    pub exploration: u32,
+    // Centipawns a move may trail the best one for its prior to drop by a
+    // factor of e.
+    pub prior_temperature: u32,
+}
+
+impl Default for MctsParams {
+    fn default() -> Self {
+        Self {
+            playouts: 20_000,
+            exploration: 150,
+            prior_temperature: 100,
+        }
+    }
+}
+
+// Where the searcher gets its numbers from. Scores are centipawns for `color`
+// to move.
+pub trait MctsEvaluator {
+    // Value of a new leaf, or `None` once the search has to stop.
+    fn value(&mut self, board: &mut Board, color: Color) -> Option<i32>;
+    // Cheap score of the position after each candidate move, ranking them.
+    fn prior(&mut self, board: &Board, color: Color) -> i32;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MctsResult {
+    pub best: Move,
+    // Centipawns for the side to move, converted back from the win rate.
+    pub score: i32,
+    pub playouts: u32,
+    // Deepest playout.
+    pub depth: u32,
+    pub pv: Vec<Move>,
+}
+
+// Centipawns for which the win probability is 1/(1 + 10^(-cp/SCALE)).
+const SCALE: f64 = 400.0;
+// Draw value of repetitions, the fifty-move rule and stalemate.
+const DRAW: f64 = 0.5;
+
+struct Node {
+    mv: Move,
+    prior: f32,
+    visits: u32,
+    // Sum of the results for the side that played `mv`.
+    value: f64,
+    first_child: usize,
+    children: usize,
+    expanded: bool,
+    // Result for the side to move, once the node is known to end the game.
+    terminal: Option<f64>,
+}
+
+impl Node {
+    fn new(mv: Move, prior: f32) -> Self {
+        Self {
+            mv,
+            prior,
+            visits: 0,
+            value: 0.0,
+            first_child: 0,
+            children: 0,
+            expanded: false,
+            terminal: None,
+        }
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+fn win_probability(score: i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-score as f64 / SCALE))
+}
+
+// Certain results are capped at 2400 centipawns.
+fn to_score(probability: f64) -> i32 {
+    let p = probability.clamp(1e-6, 1.0 - 1e-6);
+    (SCALE * (p / (1.0 - p)).log10()).round() as i32
+}
+
+// Index of the child of `parent` with the highest PUCT score.
+fn select(nodes: &[Node], parent: usize, exploration: f64) -> usize {
+    let node = &nodes[parent];
+    let sqrt_visits = (node.visits.max(1) as f64).sqrt();
+    // Unvisited moves are assumed as good as the position itself.
+    let first_play = if node.visits > 0 {
+        1.0 - node.value / node.visits as f64
+    } else {
+        DRAW
+    };
+    let children = node.first_child..node.first_child + node.children;
+    let puct = |i: usize| {
+        let child = &nodes[i];
+        let q = if child.visits > 0 {
+            child.value / child.visits as f64
+        } else {
+            first_play
+        };
+        q + exploration * child.prior as f64 * sqrt_visits / (1.0 + child.visits as f64)
+    };
+    children
+        .max_by(|&a, &b| puct(a).total_cmp(&puct(b)))
+        .unwrap_or(parent)
+}
+
+// Adds the children of the leaf `index`, with priors from a softmax over the
+// evaluator's scores, or marks the leaf terminal. Returns the leaf's result
+// when it ends the game.
+fn expand<E: MctsEvaluator>(
+    nodes: &mut Vec<Node>,
+    index: usize,
+    board: &mut Board,
+    color: Color,
+    repeated: bool,
+    params: &MctsParams,
+    evaluator: &mut E,
+) -> Option<f64> {
+    nodes[index].expanded = true;
+    let mut moves = MoveList::new();
+    crate::movegen::generate_moves_fast(board, color, &mut moves);
+    let result = if moves.is_empty() {
+        Some(if board.in_check(color) { 0.0 } else { DRAW })
+    } else if repeated || board.halfmove_clock >= 100 || board.insufficient_material() {
+        Some(DRAW)
+    } else {
+        None
+    };
+    if result.is_some() {
+        nodes[index].terminal = result;
+        return result;
+    }
+
+    let scores: Vec<i32> = moves
+        .iter()
+        .map(|&mv| {
+            let state = board.make_move_fast(mv, color);
+            let score = -evaluator.prior(board, opposite(color));
+            board.unmake_move_fast(state, color);
+            score
+        })
+        .collect();
+    let best = scores.iter().copied().max().unwrap_or(0);
+    let temperature = params.prior_temperature.max(1) as f64;
+    let weights: Vec<f64> = scores
+        .iter()
+        .map(|&s| ((s - best) as f64 / temperature).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    nodes[index].first_child = nodes.len();
+    nodes[index].children = moves.len();
+    for (&mv, weight) in moves.iter().zip(weights) {
+        nodes.push(Node::new(mv, (weight / total) as f32));
+    }
+    None
+}
+
+// Searches `board` with `color` to move. `history` holds the hashes of the
+// positions played before it, for repetitions. Returns `None` without legal
+// moves.
+pub fn search<E: MctsEvaluator>(
+    board: &Board,
+    color: Color,
+    history: &[u64],
+    params: &MctsParams,
+    evaluator: &mut E,
+) -> Option<MctsResult> {
+    let exploration = params.exploration as f64 / 100.0;
+    let mut board = board.clone();
+    let mut nodes = vec![Node::new(Move::NONE, 1.0)];
+    let mut hashes: Vec<u64> = history.to_vec();
+    let mut path: Vec<(usize, UndoState, Color)> = Vec::new();
+    let mut playouts = 0;
+    let mut depth = 0;
+
+    while playouts < params.playouts.max(1) {
+        let mut index = 0;
+        let mut side = color;
+        while nodes[index].expanded && nodes[index].terminal.is_none() {
+            let child = select(&nodes, index, exploration);
+            let state = board.make_move_fast(nodes[child].mv, side);
+            path.push((child, state, side));
+            side = opposite(side);
+            hashes.push(board.hash(side));
+            index = child;
+        }
+
+        let result = match nodes[index].terminal {
+            Some(result) => Some(result),
+            None => {
+                let hash = board.hash(side);
+                let repeated = !path.is_empty() && hashes[..hashes.len() - 1].contains(&hash);
+                match expand(
+                    &mut nodes, index, &mut board, side, repeated, params, evaluator,
+                ) {
+                    Some(result) => Some(result),
+                    None => evaluator.value(&mut board, side).map(win_probability),
+                }
+            }
+        };
+
+        depth = depth.max(path.len() as u32);
+        if let Some(mut result) = result {
+            playouts += 1;
+            nodes[0].visits += 1;
+            for &(node, _, _) in path.iter().rev() {
+                nodes[node].visits += 1;
+                nodes[node].value += 1.0 - result;
+                result = 1.0 - result;
+            }
+        }
+        for (_, state, side) in path.drain(..).rev() {
+            board.unmake_move_fast(state, side);
+            hashes.pop();
+        }
+        if result.is_none() {
+            break;
+        }
+    }
+
+    // The most visited move, the priors breaking ties before any playout.
+    let most_visited = |nodes: &[Node], parent: usize| {
+        let node = &nodes[parent];
+        (node.first_child..node.first_child + node.children).max_by(|&a, &b| {
+            (nodes[a].visits, nodes[a].prior)
+                .partial_cmp(&(nodes[b].visits, nodes[b].prior))
+                .unwrap_or(core::cmp::Ordering::Equal)
+        })
+    };
+    let best = most_visited(&nodes, 0)?;
+    let score = if nodes[best].visits > 0 {
+        to_score(nodes[best].value / nodes[best].visits as f64)
+    } else {
+        0
+    };
+    let mut pv = vec![nodes[best].mv];
+    let mut index = best;
+    while let Some(next) = most_visited(&nodes, index).filter(|&i| nodes[i].visits > 0) {
+        pv.push(nodes[next].mv);
+        index = next;
+    }
+
+    Some(MctsResult {
+        best: nodes[best].mv,
+        score,
+        playouts,
+        depth,
+        pv,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values leaves with the static evaluation alone.
+    struct Static;
+
+    impl MctsEvaluator for Static {
+        fn value(&mut self, board: &mut Board, color: Color) -> Option<i32> {
+            Some(crate::eval::evaluate(board, color))
+        }
+
+        fn prior(&mut self, board: &Board, color: Color) -> i32 {
+            crate::eval::evaluate(board, color)
+        }
+    }
+
+    fn moves(result: &MctsResult) -> Vec<String> {
+        result.pv.iter().map(|m| m.to_algebraic()).collect()
+    }
+
+    #[test]
+    fn finds_mate_and_free_material() {
+        let params = MctsParams {
+            playouts: 2000,
+            ..MctsParams::default()
+        };
+        // Back-rank mate.
+        let (board, color) = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let result = search(&board, color, &[], &params, &mut Static).unwrap();
+        assert_eq!(moves(&result)[0], "a1a8");
+        assert!(result.score > 1000, "{}", result.score);
+        assert_eq!(result.playouts, 2000);
+
+        // A queen for the taking.
+        let (board, color) = Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let result = search(&board, color, &[], &params, &mut Static).unwrap();
+        assert_eq!(moves(&result)[0], "d2d5");
+        assert!(result.depth >= 2);
+
+        let (board, color) = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(search(&board, color, &[], &params, &mut Static).is_none());
+    }
+
+    #[test]
+    fn scores_round_trip() {
+        for score in [-800, -120, 0, 35, 400] {
+            assert!((to_score(win_probability(score)) - score).abs() <= 1);
+        }
+        assert_eq!(to_score(1.0), 2400);
+        assert_eq!(to_score(0.0), -2400);
+    }
+}
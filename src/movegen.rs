@@ -1,8 +1,10 @@
 use crate::board::{Board, color_idx, piece_index};
 use crate::pieces::{Color, PieceType};
-use once_cell::sync::Lazy;
+use crate::types::Move;
+use alloc::string::String;
+use alloc::vec::Vec;
 
-const DIRS_KNIGHT: &[(isize, isize)] = &[
+const DIRS_KNIGHT: [(isize, isize); 8] = [
     (-2, -1),
     (-2, 1),
     (-1, -2),
@@ -12,7 +14,7 @@ const DIRS_KNIGHT: &[(isize, isize)] = &[
     (2, -1),
     (2, 1),
 ];
-const DIRS_KING: &[(isize, isize)] = &[
+const DIRS_KING: [(isize, isize); 8] = [
     (-1, -1),
     (-1, 0),
     (-1, 1),
@@ -23,186 +25,144 @@ const DIRS_KING: &[(isize, isize)] = &[
     (1, 1),
 ];
 
-pub static KNIGHT_TABLE: Lazy<[u64; 64]> = Lazy::new(|| {
-    let mut arr = [0u64; 64];
-    for y in 0..8 {
-        for x in 0..8 {
-            let mut bb = 0u64;
-            for (dx, dy) in DIRS_KNIGHT {
-                let nx = x as isize + dx;
-                let ny = y as isize + dy;
-                if nx >= 0 && nx < 8 && ny >= 0 && ny < 8 {
-                    bb |= 1u64 << (ny * 8 + nx);
-                }
-            }
-            arr[y * 8 + x] = bb;
-        }
-    }
-    arr
-});
+// The tables below are built at compile time, so they need no lazy
+// initialisation (and no `std`) at run time.
+const fn on_board(x: isize, y: isize) -> bool {
+    x >= 0 && x < 8 && y >= 0 && y < 8
+}
 
-pub static KING_TABLE: Lazy<[u64; 64]> = Lazy::new(|| {
+const fn step_table(dirs: &[(isize, isize)]) -> [u64; 64] {
     let mut arr = [0u64; 64];
-    for y in 0..8 {
-        for x in 0..8 {
-            let mut bb = 0u64;
-            for (dx, dy) in DIRS_KING {
-                let nx = x as isize + dx;
-                let ny = y as isize + dy;
-                if nx >= 0 && nx < 8 && ny >= 0 && ny < 8 {
-                    bb |= 1u64 << (ny * 8 + nx);
-                }
+    let mut sq = 0;
+    while sq < 64 {
+        let (x, y) = ((sq % 8) as isize, (sq / 8) as isize);
+        let mut i = 0;
+        while i < dirs.len() {
+            let (nx, ny) = (x + dirs[i].0, y + dirs[i].1);
+            if on_board(nx, ny) {
+                arr[sq] |= 1u64 << (ny * 8 + nx);
             }
-            arr[y * 8 + x] = bb;
+            i += 1;
         }
+        sq += 1;
     }
     arr
-});
+}
 
-pub static WHITE_PAWN_ATTACKS: Lazy<[u64; 64]> = Lazy::new(|| {
-    let mut arr = [0u64; 64];
-    for y in 0..8 {
-        for x in 0..8 {
-            let mut bb = 0u64;
-            if x > 0 && y < 7 {
-                bb |= 1u64 << ((y + 1) * 8 + (x - 1));
+pub static KNIGHT_TABLE: [u64; 64] = step_table(&DIRS_KNIGHT);
+pub static KING_TABLE: [u64; 64] = step_table(&DIRS_KING);
+pub static WHITE_PAWN_ATTACKS: [u64; 64] = step_table(&[(-1, 1), (1, 1)]);
+pub static BLACK_PAWN_ATTACKS: [u64; 64] = step_table(&[(-1, -1), (1, -1)]);
+
+// Squares strictly between two squares sharing a rank, file or diagonal, and
+// the whole line through them, edge to edge. Both are empty for squares that
+// are not aligned.
+pub static BETWEEN: [[u64; 64]; 64] = aligned_tables().0;
+pub static LINE: [[u64; 64]; 64] = aligned_tables().1;
+
+const fn aligned_tables() -> ([[u64; 64]; 64], [[u64; 64]; 64]) {
+    let mut between = [[0u64; 64]; 64];
+    let mut line = [[0u64; 64]; 64];
+    let mut a = 0;
+    while a < 64 {
+        let (x, y) = ((a % 8) as isize, (a / 8) as isize);
+        let mut d = 0;
+        while d < DIRS_KING.len() {
+            let (dx, dy) = DIRS_KING[d];
+            let mut full = 1u64 << a;
+            let (mut nx, mut ny) = (x + dx, y + dy);
+            while on_board(nx, ny) {
+                full |= 1u64 << (ny * 8 + nx);
+                nx += dx;
+                ny += dy;
+            }
+            let (mut nx, mut ny) = (x - dx, y - dy);
+            while on_board(nx, ny) {
+                full |= 1u64 << (ny * 8 + nx);
+                nx -= dx;
+                ny -= dy;
             }
-            if x < 7 && y < 7 {
-                bb |= 1u64 << ((y + 1) * 8 + (x + 1));
+            let mut passed = 0u64;
+            let (mut nx, mut ny) = (x + dx, y + dy);
+            while on_board(nx, ny) {
+                let b = (ny * 8 + nx) as usize;
+                between[a][b] = passed;
+                line[a][b] = full;
+                passed |= 1u64 << b;
+                nx += dx;
+                ny += dy;
             }
-            arr[y * 8 + x] = bb;
+            d += 1;
         }
+        a += 1;
     }
-    arr
-});
+    (between, line)
+}
 
-pub static BLACK_PAWN_ATTACKS: Lazy<[u64; 64]> = Lazy::new(|| {
-    let mut arr = [0u64; 64];
-    for y in 0..8 {
-        for x in 0..8 {
-            let mut bb = 0u64;
-            if x > 0 && y > 0 {
-                bb |= 1u64 << ((y - 1) * 8 + (x - 1));
-            }
-            if x < 7 && y > 0 {
-                bb |= 1u64 << ((y - 1) * 8 + (x + 1));
+// Rays from every square to the board edge, the first four pointing to
+// higher square indices (north, east, north-east, north-west) and the last
+// four to lower ones.
+const RAY_DIRS: [(isize, isize); 8] = [
+    (0, 1),
+    (1, 0),
+    (1, 1),
+    (-1, 1),
+    (0, -1),
+    (-1, 0),
+    (-1, -1),
+    (1, -1),
+];
+
+static RAYS: [[u64; 64]; 8] = {
+    let mut rays = [[0u64; 64]; 8];
+    let mut d = 0;
+    while d < 8 {
+        let (dx, dy) = RAY_DIRS[d];
+        let mut sq = 0;
+        while sq < 64 {
+            let (mut nx, mut ny) = ((sq % 8) as isize + dx, (sq / 8) as isize + dy);
+            while on_board(nx, ny) {
+                rays[d][sq] |= 1u64 << (ny * 8 + nx);
+                nx += dx;
+                ny += dy;
             }
-            arr[y * 8 + x] = bb;
+            sq += 1;
         }
+        d += 1;
     }
-    arr
-});
+    rays
+};
+
+// Squares a slider on `sq` reaches along ray `dir`, up to and including the
+// first blocker, which is the nearest set bit of the ray's occupancy: the
+// lowest one on rays going up, the highest on rays going down.
+#[inline(always)]
+fn ray_attacks(sq: usize, occ: u64, dir: usize) -> u64 {
+    let ray = RAYS[dir][sq];
+    let blockers = ray & occ;
+    if blockers == 0 {
+        return ray;
+    }
+    let first = if dir < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ RAYS[dir][first as usize]
+}
 
 fn rook_attacks(sq: usize, occ: u64) -> u64 {
-    let x = (sq % 8) as isize;
-    let y = (sq / 8) as isize;
-    let mut attacks = 0u64;
-    let mut ny = y + 1;
-    while ny < 8 {
-        let idx = (ny * 8 + x) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        ny += 1;
-    }
-    ny = y - 1;
-    while ny >= 0 {
-        let idx = (ny * 8 + x) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        if ny == 0 {
-            break;
-        }
-        ny -= 1;
-    }
-    let mut nx = x + 1;
-    while nx < 8 {
-        let idx = (y * 8 + nx) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        nx += 1;
-    }
-    nx = x - 1;
-    while nx >= 0 {
-        let idx = (y * 8 + nx) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        if nx == 0 {
-            break;
-        }
-        nx -= 1;
-    }
-    attacks
+    ray_attacks(sq, occ, 0)
+        | ray_attacks(sq, occ, 1)
+        | ray_attacks(sq, occ, 4)
+        | ray_attacks(sq, occ, 5)
 }
 
 fn bishop_attacks(sq: usize, occ: u64) -> u64 {
-    let x = (sq % 8) as isize;
-    let y = (sq / 8) as isize;
-    let mut attacks = 0u64;
-    let mut nx = x + 1;
-    let mut ny = y + 1;
-    while nx < 8 && ny < 8 {
-        let idx = (ny * 8 + nx) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        nx += 1;
-        ny += 1;
-    }
-    nx = x - 1;
-    ny = y + 1;
-    while nx >= 0 && ny < 8 {
-        let idx = (ny * 8 + nx) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        if nx == 0 {
-            break;
-        }
-        nx -= 1;
-        ny += 1;
-    }
-    nx = x + 1;
-    ny = y - 1;
-    while nx < 8 && ny >= 0 {
-        let idx = (ny * 8 + nx) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        if ny == 0 {
-            break;
-        }
-        nx += 1;
-        ny -= 1;
-    }
-    nx = x - 1;
-    ny = y - 1;
-    while nx >= 0 && ny >= 0 {
-        let idx = (ny * 8 + nx) as usize;
-        attacks |= 1u64 << idx;
-        if (occ & (1u64 << idx)) != 0 {
-            break;
-        }
-        if nx == 0 || ny == 0 {
-            attacks |= 0;
-        };
-        nx -= 1;
-        ny -= 1;
-        if nx < 0 || ny < 0 {
-            break;
-        }
-    }
-    attacks
+    ray_attacks(sq, occ, 2)
+        | ray_attacks(sq, occ, 3)
+        | ray_attacks(sq, occ, 6)
+        | ray_attacks(sq, occ, 7)
 }
 
 fn pawn_moves(
@@ -258,34 +218,191 @@ fn pawn_moves(
     moves
 }
 
+// Squares attacked by a piece standing on `sq`, given the occupancy.
+pub fn piece_attacks(piece_type: PieceType, color: Color, sq: usize, occ: u64) -> u64 {
+    match piece_type {
+        PieceType::Pawn if color == Color::White => WHITE_PAWN_ATTACKS[sq],
+        PieceType::Pawn => BLACK_PAWN_ATTACKS[sq],
+        PieceType::Knight => KNIGHT_TABLE[sq],
+        PieceType::Bishop => bishop_attacks(sq, occ),
+        PieceType::Rook => rook_attacks(sq, occ),
+        PieceType::Queen => bishop_attacks(sq, occ) | rook_attacks(sq, occ),
+        PieceType::King => KING_TABLE[sq],
+    }
+}
+
+// Squares attacked by all the pawns of `color` in `pawns`.
+pub fn pawn_attacks(pawns: u64, color: Color) -> u64 {
+    const NOT_FILE_A: u64 = !0x0101_0101_0101_0101;
+    const NOT_FILE_H: u64 = !0x8080_8080_8080_8080;
+    if color == Color::White {
+        ((pawns & NOT_FILE_A) << 7) | ((pawns & NOT_FILE_H) << 9)
+    } else {
+        ((pawns & NOT_FILE_A) >> 9) | ((pawns & NOT_FILE_H) >> 7)
+    }
+}
+
+// Pieces of `by` attacking `sq`, given the occupancy.
+pub fn attackers(board: &Board, sq: usize, by: Color, occ: u64) -> u64 {
+    // A pawn attacks `sq` when a pawn of the other color on `sq` would attack it.
+    let pawn_attacks = if by == Color::White {
+        BLACK_PAWN_ATTACKS[sq]
+    } else {
+        WHITE_PAWN_ATTACKS[sq]
+    };
+    let queens = board.pieces_of(by, PieceType::Queen);
+    let bishops = board.pieces_of(by, PieceType::Bishop) | queens;
+    let rooks = board.pieces_of(by, PieceType::Rook) | queens;
+    (pawn_attacks & board.pieces_of(by, PieceType::Pawn))
+        | (KNIGHT_TABLE[sq] & board.pieces_of(by, PieceType::Knight))
+        | (KING_TABLE[sq] & board.pieces_of(by, PieceType::King))
+        | (bishop_attacks(sq, occ) & bishops)
+        | (rook_attacks(sq, occ) & rooks)
+}
+
+// Sliders of `by` on a line with `sq`, whatever stands between them.
+fn snipers(board: &Board, sq: usize, by: Color) -> u64 {
+    let queens = board.pieces_of(by, PieceType::Queen);
+    let bishops = board.pieces_of(by, PieceType::Bishop) | queens;
+    let rooks = board.pieces_of(by, PieceType::Rook) | queens;
+    (bishop_attacks(sq, 0) & bishops) | (rook_attacks(sq, 0) & rooks)
+}
+
+// Pieces of either color that are the only piece between the king of
+// `color` and an enemy slider. Moving one of them off the line exposes the
+// king: to check when it belongs to `color`, or with a discovered check when
+// it belongs to the enemy.
+pub fn blockers_for_king(board: &Board, color: Color) -> u64 {
+    let king = board.pieces_of(color, PieceType::King);
+    if king == 0 {
+        return 0;
+    }
+    let king_sq = king.trailing_zeros() as usize;
+    let occ = board.occupied();
+    let mut blockers = 0u64;
+    let mut candidates = snipers(board, king_sq, opposite(color));
+    while candidates != 0 {
+        let sniper = candidates.trailing_zeros() as usize;
+        let between = BETWEEN[king_sq][sniper] & occ;
+        if between.count_ones() == 1 {
+            blockers |= between;
+        }
+        candidates &= candidates - 1;
+    }
+    blockers
+}
+
+// Pieces of `color` pinned to their king, so they may only move along the
+// line of the pin.
+pub fn pinned(board: &Board, color: Color) -> u64 {
+    blockers_for_king(board, color) & board.occupied_by(color)
+}
+
+// Whether `mv` by `color` checks the enemy king, directly or by uncovering
+// one of its sliders, without playing it.
+pub fn gives_check(board: &Board, mv: Move, color: Color) -> bool {
+    let enemy_king = board.pieces_of(opposite(color), PieceType::King);
+    let Some((piece_type, _)) = board.piece_at_sq(mv.from_sq()) else {
+        return false;
+    };
+    if enemy_king == 0 {
+        return false;
+    }
+    let king_sq = enemy_king.trailing_zeros() as usize;
+    let (from, to) = (mv.from_sq() as usize, mv.to_sq() as usize);
+    let mut occ = (board.occupied() & !(1u64 << from)) | 1u64 << to;
+    let mut moved_from = 1u64 << from;
+
+    let (checker, checker_sq) = if mv.is_castle() {
+        // The rook is the piece that can give check. In Chess960 it may
+        // start on the king's destination.
+        let (rook_from, rook_to) = board.castle_rook(mv);
+        moved_from |= 1u64 << rook_from;
+        occ = (board.occupied() & !moved_from) | 1u64 << to | 1u64 << rook_to;
+        (PieceType::Rook, rook_to as usize)
+    } else {
+        if mv.is_ep() {
+            occ &= !(1u64 << (from / 8 * 8 + to % 8));
+        }
+        (mv.promotion_piece().unwrap_or(piece_type), to)
+    };
+    if piece_attacks(checker, color, checker_sq, occ) & enemy_king != 0 {
+        return true;
+    }
+
+    let mut discovered = snipers(board, king_sq, color) & !moved_from;
+    while discovered != 0 {
+        let sniper = discovered.trailing_zeros() as usize;
+        if BETWEEN[king_sq][sniper] & occ == 0 {
+            return true;
+        }
+        discovered &= discovered - 1;
+    }
+    false
+}
+
+fn opposite(color: Color) -> Color {
+    if color == Color::White {
+        Color::Black
+    } else {
+        Color::White
+    }
+}
+
+// In check, other pieces must capture the checker or block its line, and
+// pinned pieces may only move along their pin. King moves are still
+// verified by playing them, en passant by `ep_is_legal`.
+fn legal_masks(board: &Board, color: Color, occ_all: u64) -> (u64, u64) {
+    let king_bb = board.pieces_of(color, PieceType::King);
+    if king_bb == 0 {
+        return (!0u64, 0u64);
+    }
+    let king_sq = king_bb.trailing_zeros() as usize;
+    let checkers = attackers(board, king_sq, opposite(color), occ_all);
+    let check_mask = match checkers.count_ones() {
+        0 => !0u64,
+        1 => checkers | BETWEEN[king_sq][checkers.trailing_zeros() as usize],
+        _ => 0,
+    };
+    (check_mask, board.pinned(color))
+}
+
+// En passant takes two pawns off the board at once, so it can expose the
+// king in ways the masks miss: both pawns may stand between the king and a
+// rook on their rank, or the captured pawn may be the only blocker of a
+// diagonal. It is legal when no enemy piece but the captured pawn attacks
+// the king once both pawns have moved.
+fn ep_is_legal(board: &Board, color: Color, from: usize, to: usize) -> bool {
+    let opp = opposite(color);
+    let captured = 1u64 << (from / 8 * 8 + to % 8);
+    if board.pieces_of(opp, PieceType::Pawn) & captured == 0 {
+        return false;
+    }
+    let king = board.pieces_of(color, PieceType::King);
+    if king == 0 {
+        return true;
+    }
+    let occ = (board.occupied() ^ (1u64 << from) ^ captured) | (1u64 << to);
+    attackers(board, king.trailing_zeros() as usize, opp, occ) & !captured == 0
+}
+
 pub fn generate_moves(board: &mut Board, color: Color) -> Vec<(String, String)> {
     let mut list = crate::types::MoveList::new();
     generate_moves_fast(board, color, &mut list);
+    move_strings(board, &list)
+}
 
-    let mut res = Vec::new();
-    for i in 0..list.len() {
-        let m = list.get(i).unwrap();
-        let f_str =
-            Board::index_to_algebraic((m.from_sq() % 8) as usize, (m.from_sq() / 8) as usize)
-                .unwrap();
-        let mut t_str =
-            Board::index_to_algebraic((m.to_sq() % 8) as usize, (m.to_sq() / 8) as usize).unwrap();
-
-        if m.is_promotion() {
-            if let Some(pt) = m.promotion_piece() {
-                let c = match pt {
-                    PieceType::Queen => 'q',
-                    PieceType::Rook => 'r',
-                    PieceType::Bishop => 'b',
-                    PieceType::Knight => 'n',
-                    _ => 'q',
-                };
-                t_str.push(c);
-            }
-        }
-        res.push((f_str, t_str));
-    }
-    res
+pub fn generate_captures(board: &mut Board, color: Color) -> Vec<(String, String)> {
+    let mut list = crate::types::MoveList::new();
+    generate_captures_fast(board, color, &mut list);
+    move_strings(board, &list)
+}
+
+fn move_strings(board: &Board, list: &crate::types::MoveList) -> Vec<(String, String)> {
+    (0..list.len())
+        .filter_map(|i| list.get(i))
+        .map(|m| board.move_to_strings(m))
+        .collect()
 }
 
 pub fn generate_moves_fast(board: &mut Board, color: Color, list: &mut crate::types::MoveList) {
@@ -295,10 +412,15 @@ pub fn generate_moves_fast(board: &mut Board, color: Color, list: &mut crate::ty
     } else {
         Color::White
     };
-    let occ_self: u64 = board.bitboards[cidx].iter().fold(0u64, |a, &b| a | b);
-    let occ_opp: u64 = board.bitboards[1 - cidx].iter().fold(0u64, |a, &b| a | b);
+    let occ_self = board.occupied_by(color);
+    let occ_opp = board.occupied_by(opp_color);
     let occ_all = occ_self | occ_opp;
 
+    let king_bb = board.pieces_of(color, PieceType::King);
+    let king_sq = king_bb.trailing_zeros() as usize;
+    let (check_mask, pinned) = legal_masks(board, color, occ_all);
+    let ep_bb = board.en_passant.map_or(0u64, |(x, y)| 1u64 << (y * 8 + x));
+
     for pt in [
         PieceType::Pawn,
         PieceType::Knight,
@@ -325,29 +447,27 @@ pub fn generate_moves_fast(board: &mut Board, color: Color, list: &mut crate::ty
                 }
                 PieceType::King => {
                     targets = KING_TABLE[sq];
-                    let rank = if color == Color::White { 0 } else { 7 };
-                    if sq == rank * 8 + 4 {
-                        if board.castling[cidx][0]
-                            && board.get_index(5, rank).is_none()
-                            && board.get_index(6, rank).is_none()
-                            && !board.is_square_attacked_by(sq as u8, opp_color) // King not in check
-                            && !board.is_square_attacked_by((rank*8+5) as u8, opp_color)
-                        {
-                            targets |= 1u64 << (rank * 8 + 6);
-                        }
-                        if board.castling[cidx][1]
-                            && board.get_index(1, rank).is_none()
-                            && board.get_index(2, rank).is_none()
-                            && board.get_index(3, rank).is_none()
-                            && !board.is_square_attacked_by(sq as u8, opp_color)
-                            && !board.is_square_attacked_by((rank * 8 + 3) as u8, opp_color)
-                        {
-                            targets |= 1u64 << (rank * 8 + 2);
+                    // Castling is not a target: in Chess960 the king may
+                    // end on its own square or on its rook's.
+                    for kingside in [true, false] {
+                        if board.can_castle(color, kingside) {
+                            let castle = board.castle_move(color, kingside);
+                            if board.keeps_king_safe(castle, color) {
+                                list.push(castle);
+                            }
                         }
                     }
                 }
             }
             targets &= !occ_self;
+            if pt != PieceType::King {
+                let ep_targets = if pt == PieceType::Pawn { ep_bb } else { 0 };
+                targets &= check_mask | ep_targets;
+                if pinned & (1u64 << sq) != 0 {
+                    targets &= LINE[king_sq][sq];
+                }
+            }
+            let verify = king_bb == 0 || pt == PieceType::King;
 
             while targets != 0 {
                 let to_sq = targets.trailing_zeros() as usize;
@@ -374,10 +494,7 @@ pub fn generate_moves_fast(board: &mut Board, color: Color, list: &mut crate::ty
                     if rank_to == 0 || rank_to == 7 {
                         let next_is_capture = (occ_opp & (1u64 << to_sq)) != 0;
 
-                        let f_s = Board::index_to_algebraic(sq % 8, sq / 8).unwrap();
-                        let t_s = Board::index_to_algebraic(to_sq % 8, to_sq / 8).unwrap();
-
-                        if board.is_legal(&f_s, &t_s, color) {
+                        if !verify || board.is_legal_sq(sq as u8, to_sq as u8, color) {
                             list.push(crate::types::Move::promotion(
                                 from,
                                 to,
@@ -409,23 +526,99 @@ pub fn generate_moves_fast(board: &mut Board, color: Color, list: &mut crate::ty
                     }
                 }
 
-                if pt == PieceType::King && (to_sq as isize - sq as isize).abs() == 2 {
-                    if to_sq > sq {
-                        flags = crate::types::Move::FLAG_KING_CASTLE;
-                    } else {
-                        flags = crate::types::Move::FLAG_QUEEN_CASTLE;
-                    }
+                let mv = crate::types::Move::new(from, to, flags);
+
+                let legal = if flags == crate::types::Move::FLAG_EP_CAPTURE {
+                    ep_is_legal(board, color, sq, to_sq)
+                } else {
+                    !verify || board.is_legal_sq(sq as u8, to_sq as u8, color)
+                };
+                if legal {
+                    list.push(mv);
                 }
 
-                let mv = crate::types::Move::new(from, to, flags);
+                targets &= targets - 1;
+            }
+            bb &= bb - 1;
+        }
+    }
+}
 
-                let f_s = Board::index_to_algebraic(sq % 8, sq / 8).unwrap();
-                let t_s = Board::index_to_algebraic(to_sq % 8, to_sq / 8).unwrap();
+// Captures, queen promotions and en passant only, for quiescence search.
+// Targets are masked with the enemy pieces up front instead of filtering a
+// full move list.
+pub fn generate_captures_fast(board: &mut Board, color: Color, list: &mut crate::types::MoveList) {
+    use crate::types::Move;
+
+    let occ_opp = board.occupied_by(opposite(color));
+    let occ_all = board.occupied_by(color) | occ_opp;
+    let king_bb = board.pieces_of(color, PieceType::King);
+    let king_sq = king_bb.trailing_zeros() as usize;
+    let (check_mask, pinned) = legal_masks(board, color, occ_all);
+    let ep_bb = board.en_passant.map_or(0u64, |(x, y)| 1u64 << (y * 8 + x));
+    let last_rank: u64 = if color == Color::White {
+        0xFF00_0000_0000_0000
+    } else {
+        0xFF
+    };
 
-                if board.is_legal(&f_s, &t_s, color) {
-                    list.push(mv);
+    let mut pawns = board.pieces_of(color, PieceType::Pawn);
+    while pawns != 0 {
+        let sq = pawns.trailing_zeros() as usize;
+        let push = if color == Color::White {
+            (1u64 << sq) << 8
+        } else {
+            (1u64 << sq) >> 8
+        };
+        let mut targets = piece_attacks(PieceType::Pawn, color, sq, occ_all) & (occ_opp | ep_bb);
+        targets |= push & last_rank & !occ_all;
+        targets &= check_mask | ep_bb;
+        if pinned & (1u64 << sq) != 0 {
+            targets &= LINE[king_sq][sq];
+        }
+        while targets != 0 {
+            let to = targets.trailing_zeros() as u8;
+            let bit = 1u64 << to;
+            if bit & last_rank != 0 {
+                let capture = occ_opp & bit != 0;
+                if king_bb != 0 || board.is_legal_sq(sq as u8, to, color) {
+                    list.push(Move::promotion(sq as u8, to, PieceType::Queen, capture));
+                }
+            } else if bit & ep_bb != 0 {
+                if ep_is_legal(board, color, sq, to as usize) {
+                    list.push(Move::new(sq as u8, to, Move::FLAG_EP_CAPTURE));
                 }
+            } else if king_bb != 0 || board.is_legal_sq(sq as u8, to, color) {
+                list.push(Move::new(sq as u8, to, Move::FLAG_CAPTURE));
+            }
+            targets &= targets - 1;
+        }
+        pawns &= pawns - 1;
+    }
 
+    for pt in [
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ] {
+        let mut bb = board.pieces_of(color, pt);
+        while bb != 0 {
+            let sq = bb.trailing_zeros() as usize;
+            let mut targets = piece_attacks(pt, color, sq, occ_all) & occ_opp;
+            if pt != PieceType::King {
+                targets &= check_mask;
+                if pinned & (1u64 << sq) != 0 {
+                    targets &= LINE[king_sq][sq];
+                }
+            }
+            let verify = king_bb == 0 || pt == PieceType::King;
+            while targets != 0 {
+                let to = targets.trailing_zeros() as u8;
+                if !verify || board.is_legal_sq(sq as u8, to, color) {
+                    list.push(Move::new(sq as u8, to, Move::FLAG_CAPTURE));
+                }
                 targets &= targets - 1;
             }
             bb &= bb - 1;
@@ -433,7 +626,45 @@ pub fn generate_moves_fast(board: &mut Board, color: Color, list: &mut crate::ty
     }
 }
 
-#[cfg(test)]
+// Leaf nodes of the legal move tree `depth` plies below the position, each
+// move played and taken back on `board`. Known counts for standard test
+// positions check the generator, castling, en passant and promotions
+// included.
+pub fn perft(board: &mut Board, color: Color, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut list = crate::types::MoveList::new();
+    generate_moves_fast(board, color, &mut list);
+    if depth == 1 {
+        return list.len() as u64;
+    }
+    let mut nodes = 0;
+    for i in 0..list.len() {
+        let mv = list.get(i).unwrap();
+        let state = board.make_move_fast(mv, color);
+        nodes += perft(board, opposite(color), depth - 1);
+        board.unmake_move_fast(state, color);
+    }
+    nodes
+}
+
+// `perft` split by root move, in generation order, to find the move whose
+// subtree disagrees with a reference engine.
+pub fn perft_divide(board: &mut Board, color: Color, depth: u32) -> Vec<(Move, u64)> {
+    let mut list = crate::types::MoveList::new();
+    generate_moves_fast(board, color, &mut list);
+    let mut counts = Vec::with_capacity(list.len());
+    for i in 0..list.len() {
+        let mv = list.get(i).unwrap();
+        let state = board.make_move_fast(mv, color);
+        counts.push((mv, perft(board, opposite(color), depth.saturating_sub(1))));
+        board.unmake_move_fast(state, color);
+    }
+    counts
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
     use crate::types::MoveList;
@@ -839,6 +1070,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_castling_needs_safe_squares_and_rooks() {
+        // (FEN, kingside allowed, queenside allowed) for White.
+        let cases = [
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", true, true),
+            // In check from the e-file rook.
+            ("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", false, false),
+            // f1 and d1 attacked, the king would pass through them.
+            ("5rk1/8/8/8/8/8/8/R3K2R w KQ - 0 1", false, true),
+            ("3r2k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", true, false),
+            // g1 and c1 attacked, the king would land on them.
+            ("6rk/8/8/8/8/8/8/R3K2R w KQ - 0 1", false, true),
+            ("2r3k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", true, false),
+            // Only the rook crosses b1.
+            ("1r4k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", true, true),
+            // The rights remain but the h1 rook is gone.
+            ("6k1/8/8/8/8/8/8/R3K2N w KQ - 0 1", false, true),
+        ];
+        for (fen, kingside, queenside) in cases {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            assert_eq!(board.can_castle(color, true), kingside, "{}", fen);
+            assert_eq!(board.can_castle(color, false), queenside, "{}", fen);
+
+            let mut list = MoveList::new();
+            generate_moves_fast(&mut board, color, &mut list);
+            let mut fast: Vec<u8> = (0..list.len())
+                .map(|i| list.get(i).unwrap())
+                .filter(|mv| mv.is_castle())
+                .map(|mv| mv.to_sq())
+                .collect();
+            let expected: Vec<u8> = [(kingside, 6), (queenside, 2)]
+                .iter()
+                .filter(|(allowed, _)| *allowed)
+                .map(|&(_, sq)| sq)
+                .collect();
+            fast.sort_unstable_by(|a, b| b.cmp(a));
+            assert_eq!(fast, expected, "{}", fen);
+
+            let king_targets = board.pseudo_legal_moves("e1");
+            assert_eq!(
+                king_targets.contains(&"g1".to_string()),
+                kingside,
+                "{}",
+                fen
+            );
+            assert_eq!(
+                king_targets.contains(&"c1".to_string()),
+                queenside,
+                "{}",
+                fen
+            );
+            assert_eq!(board.is_legal("e1", "g1", color), kingside, "{}", fen);
+            assert_eq!(board.is_legal("e1", "c1", color), queenside, "{}", fen);
+        }
+    }
+
     #[test]
     fn test_en_passant_moves() {
         let mut board = Board::new();
@@ -883,6 +1170,43 @@ mod tests {
         assert!(ep_count >= 1, "Should have at least 1 en passant move");
     }
 
+    #[test]
+    fn test_en_passant_exposing_the_king() {
+        let cases = [
+            // Both pawns leave the rank between the king and the rook.
+            ("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1", false),
+            ("4k3/8/8/8/R2Pp2k/8/8/4K3 b - d3 0 1", false),
+            // The captured pawn was the only blocker of a diagonal.
+            ("4k3/5b2/8/3pP3/8/1K6/8/8 w - d6 0 1", false),
+            // Capturing the checking pawn, and capturing along a pin.
+            ("8/8/8/3pP3/4K3/8/8/7k w - d6 0 1", true),
+            ("1b5k/8/8/3pP3/8/8/7K/8 w - d6 0 1", true),
+            // En passant does not block a rook check.
+            ("4k3/8/8/3pP3/8/8/8/r3K3 w - d6 0 1", false),
+        ];
+        for (fen, legal) in cases {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            let (from, to) = if color == Color::White {
+                ("e5", "d6")
+            } else {
+                ("e4", "d3")
+            };
+            let ep = (from.to_string(), to.to_string());
+            assert_eq!(
+                generate_moves(&mut board, color).contains(&ep),
+                legal,
+                "{}",
+                fen
+            );
+            assert_eq!(
+                generate_captures(&mut board, color).contains(&ep),
+                legal,
+                "{}",
+                fen
+            );
+        }
+    }
+
     #[test]
     fn test_knight_table() {
         let attacks = KNIGHT_TABLE[28];
@@ -912,4 +1236,226 @@ mod tests {
 
         assert_eq!(attacks.count_ones(), 8);
     }
+
+    // Positions with pins, checks, discovered checks, en passant and castling.
+    const TRICKY_FENS: [&str; 6] = [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "8/8/8/2k5/3Pp3/8/8/4K2Q b - d3 0 1",
+        "4k3/8/8/q2pP2K/8/8/8/8 w - d6 0 1",
+    ];
+
+    // Chess960 castling: a start position, the king already on its
+    // destination, the rook landing where the king stood or crossing it, the
+    // rook covering the king's destination, and rooks the king stands
+    // between.
+    const CHESS960_FENS: [&str; 6] = [
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        "rk4r1/pppppppp/8/8/8/8/PPPPPPPP/6KR w Hg - 0 1",
+        "1k3r1r/8/8/8/8/8/8/5K1R w Hh - 0 1",
+        "1rk5/8/8/8/8/8/8/RK6 w Ab - 0 1",
+        "2k5/8/8/8/8/8/8/qRK5 w B - 0 1",
+        "1r2k1r1/3pp3/8/8/8/8/3PP3/1R2K1R1 w GBgb - 0 1",
+    ];
+
+    fn reference_perft(pos: &shakmaty::Chess, depth: u32) -> u64 {
+        use shakmaty::Position;
+        if depth == 0 {
+            return 1;
+        }
+        pos.legal_moves()
+            .iter()
+            .map(|m| {
+                let mut next = pos.clone();
+                next.play_unchecked(*m);
+                reference_perft(&next, depth - 1)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_pawn_attacks() {
+        // Pawns on a2 and h2 attack b3 and g3; on a7 and h7, b6 and g6.
+        let edges = (1u64 << 8) | (1u64 << 15);
+        assert_eq!(
+            pawn_attacks(edges, Color::White),
+            (1u64 << 17) | (1u64 << 22)
+        );
+        let edges = (1u64 << 48) | (1u64 << 55);
+        assert_eq!(
+            pawn_attacks(edges, Color::Black),
+            (1u64 << 41) | (1u64 << 46)
+        );
+    }
+
+    #[test]
+    fn test_pins_and_king_blockers() {
+        let sq = |s: &str| {
+            let (x, y) = Board::algebraic_to_index(s).unwrap();
+            1u64 << (y * 8 + x)
+        };
+        // The e2 knight is pinned by the e8 rook, the d7 pawn by the a4 bishop.
+        let (board, _) = Board::from_fen("k3r3/3p4/8/8/B7/8/4N3/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.pinned(Color::White), sq("e2"));
+        assert_eq!(board.blockers_for_king(Color::White), sq("e2"));
+        assert_eq!(board.pinned(Color::Black), 0);
+
+        let (board, _) = Board::from_fen("4k3/3p4/8/8/B7/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(board.pinned(Color::Black), sq("d7"));
+
+        // Two pieces between king and slider: neither is pinned.
+        let (board, _) = Board::from_fen("k3r3/8/8/8/8/4P3/4N3/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.pinned(Color::White), 0);
+        assert_eq!(board.blockers_for_king(Color::White), 0);
+
+        // An enemy piece alone on the line blocks without being pinned.
+        let (board, _) = Board::from_fen("4r2k/8/8/8/8/8/4n3/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.blockers_for_king(Color::White), sq("e2"));
+        assert_eq!(board.pinned(Color::White), 0);
+    }
+
+    #[test]
+    fn test_between_and_line_tables() {
+        // a1 and h8 share the long diagonal, a1 and b3 share nothing.
+        assert_eq!(BETWEEN[0][63].count_ones(), 6);
+        assert_eq!(BETWEEN[0][63], BETWEEN[63][0]);
+        assert_eq!(BETWEEN[0][1], 0);
+        assert_eq!(BETWEEN[0][17], 0);
+        assert_eq!(LINE[0][17], 0);
+        // e1 and e4: e2, e3 between, the whole e-file as line.
+        assert_eq!(BETWEEN[4][28], (1u64 << 12) | (1u64 << 20));
+        assert_eq!(LINE[4][28], 0x1010_1010_1010_1010);
+        assert_eq!(LINE[12][20], LINE[4][28]);
+    }
+
+    #[test]
+    fn test_perft_reference_counts() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                [20, 400, 8902],
+            ),
+            (TRICKY_FENS[0], [48, 2039, 97862]),
+            (TRICKY_FENS[1], [14, 191, 2812]),
+            (TRICKY_FENS[2], [6, 264, 9467]),
+            (TRICKY_FENS[3], [44, 1486, 62379]),
+        ];
+        for (fen, counts) in cases {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            let before = board.to_fen(color);
+            for (depth, count) in (1..).zip(counts) {
+                assert_eq!(perft(&mut board, color, depth), count, "{} {}", fen, depth);
+            }
+            assert_eq!(board.to_fen(color), before);
+        }
+
+        let (mut board, color) = Board::from_fen(TRICKY_FENS[0]).unwrap();
+        let divide = perft_divide(&mut board, color, 2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|&(_, n)| n).sum::<u64>(), 2039);
+        let castle = divide.iter().find(|(m, _)| m.to_algebraic() == "e1g1");
+        assert_eq!(castle.map(|&(_, n)| n), Some(43));
+        assert_eq!(perft_divide(&mut board, color, 1)[0].1, 1);
+    }
+
+    #[test]
+    fn test_generator_matches_reference_perft() {
+        for fen in TRICKY_FENS {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            let reference = crate::convert::to_chess(&board, color).unwrap();
+            assert_eq!(
+                perft(&mut board, color, 3),
+                reference_perft(&reference, 3),
+                "perft mismatch for {}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_chess960_matches_reference_perft() {
+        let (mut board, color) = Board::from_fen(CHESS960_FENS[0]).unwrap();
+        for (depth, count) in (1..).zip([21, 528, 12189]) {
+            assert_eq!(perft(&mut board, color, depth), count);
+        }
+        for fen in CHESS960_FENS {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            let reference = crate::convert::to_chess(&board, color).unwrap();
+            assert_eq!(
+                perft(&mut board, color, 3),
+                reference_perft(&reference, 3),
+                "perft mismatch for {}",
+                fen
+            );
+            assert_eq!(
+                board.to_fen(color),
+                Board::from_fen(fen).unwrap().0.to_fen(color)
+            );
+        }
+    }
+
+    #[test]
+    fn test_gives_check_matches_played_moves() {
+        for fen in TRICKY_FENS.iter().chain(&CHESS960_FENS) {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            let opponent = if color == Color::White {
+                Color::Black
+            } else {
+                Color::White
+            };
+            let mut list = MoveList::new();
+            generate_moves_fast(&mut board, color, &mut list);
+            for i in 0..list.len() {
+                let mv = list.get(i).unwrap();
+                let predicted = gives_check(&board, mv, color);
+                let state = board.make_move_fast(mv, color);
+                assert_eq!(
+                    predicted,
+                    board.in_check_fast(opponent),
+                    "gives_check wrong for {:?} in {}",
+                    mv,
+                    fen
+                );
+                board.unmake_move_fast(state, color);
+            }
+        }
+    }
+
+    #[test]
+    fn captures_match_filtered_full_generation() {
+        let mut list = crate::types::MoveList::new();
+        let mut replies = crate::types::MoveList::new();
+        let noisy = |board: &mut Board, color: Color, list: &mut crate::types::MoveList| {
+            list.clear();
+            generate_moves_fast(board, color, list);
+            let mut expected: Vec<u16> = list
+                .iter()
+                .filter(|m| match m.promotion_piece() {
+                    Some(piece) => piece == PieceType::Queen,
+                    None => m.is_capture(),
+                })
+                .map(|m| m.0)
+                .collect();
+            list.clear();
+            generate_captures_fast(board, color, list);
+            let mut actual: Vec<u16> = list.iter().map(|m| m.0).collect();
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(actual, expected, "{}", board.to_fen(color));
+        };
+        for fen in TRICKY_FENS {
+            let (mut board, color) = Board::from_fen(fen).unwrap();
+            noisy(&mut board, color, &mut list);
+            list.clear();
+            generate_moves_fast(&mut board, color, &mut list);
+            for i in 0..list.len() {
+                let mv = list.get(i).unwrap();
+                let state = board.make_move_fast(mv, color);
+                noisy(&mut board, opposite(color), &mut replies);
+                board.unmake_move_fast(state, color);
+            }
+        }
+    }
 }
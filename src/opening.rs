@@ -1,5 +1,15 @@
 use crate::board::Board;
-use crate::pieces::Color;
+use crate::game::Game;
+use crate::pieces::{Color, PieceType};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use shakmaty::EnPassantMode;
+use shakmaty::zobrist::{Zobrist64, ZobristHash};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
 
 const BOOK_LINES: &[&[&str]] = &[
     &[
@@ -30,37 +40,430 @@ pub fn book_move(
     board: &Board,
     color: Color,
 ) -> Option<(String, String)> {
+    line_moves(BOOK_LINES, history, board, color)
+        .into_iter()
+        .next()
+        .map(|(mv, _)| mv)
+}
+
+// Next moves of the lines that the game has followed so far, weighted by the
+// number of lines playing them, the most popular first and ties in book order.
+fn line_moves<L: AsRef<[M]>, M: AsRef<str>>(
+    lines: &[L],
+    history: &[(String, String)],
+    board: &Board,
+    color: Color,
+) -> Vec<((String, String), u32)> {
     let played: Vec<String> = history.iter().map(|(s, e)| format!("{}{}", s, e)).collect();
+    let mut moves: Vec<((String, String), u32)> = Vec::new();
 
-    'outer: for line in BOOK_LINES {
+    'outer: for line in lines {
+        let line = line.as_ref();
         if played.len() >= line.len() {
             continue;
         }
 
         for (idx, mv) in played.iter().enumerate() {
-            if mv != line[idx] {
+            if mv != line[idx].as_ref() {
                 continue 'outer;
             }
         }
 
-        let next = line[played.len()];
-        if next.len() != 4 {
+        let next = line[played.len()].as_ref();
+        if next.len() != 4 || !next.is_char_boundary(2) {
             continue;
         }
         let (s, e) = next.split_at(2);
-        let mut board_copy = board.clone();
-        if board_copy.is_legal(s, e, color) {
-            return Some((s.to_string(), e.to_string()));
+        let mv = (s.to_string(), e.to_string());
+        if let Some((_, count)) = moves.iter_mut().find(|(known, _)| *known == mv) {
+            *count += 1;
+        } else if board.clone().is_legal(s, e, color) {
+            moves.push((mv, 1));
         }
     }
 
-    None
+    moves.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    moves
+}
+
+// How the engine picks among the book moves of a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookOptions {
+    // The book is only consulted during the first `max_ply` plies of a game.
+    pub max_ply: usize,
+    // Pick at random in proportion to the move weights instead of always
+    // playing the most popular move.
+    pub weighted: bool,
+    // With book learning, moves scoring below this percentage once they have
+    // been played often enough are skipped.
+    pub min_score: u32,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        Self {
+            max_ply: 255,
+            weighted: false,
+            min_score: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolyglotEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+// Opening book used by the engine: the built-in lines, lines of coordinate
+// moves read from a text file, or a Polyglot `.bin` book.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OpeningBook {
+    #[default]
+    Builtin,
+    Lines(Vec<Vec<String>>),
+    Polyglot(Vec<PolyglotEntry>),
+}
+
+impl OpeningBook {
+    // Polyglot for `.bin` files, otherwise one line per book line with
+    // whitespace-separated coordinate moves and `#` comments.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let book = if path.extension().is_some_and(|ext| ext == "bin") {
+            Self::from_polyglot(&bytes).ok_or_else(|| invalid("not a Polyglot book"))?
+        } else {
+            let text = String::from_utf8(bytes).map_err(|_| invalid("book is not text"))?;
+            Self::from_lines(&text)
+        };
+        if book.is_empty() {
+            return Err(invalid("book has no moves"));
+        }
+        Ok(book)
+    }
+
+    pub fn from_lines(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .map(|line| line.split_whitespace().map(str::to_string).collect())
+            .filter(|line: &Vec<String>| !line.is_empty())
+            .collect();
+        OpeningBook::Lines(lines)
+    }
+
+    // Entries are 16 big-endian bytes each, sorted by key.
+    pub fn from_polyglot(bytes: &[u8]) -> Option<Self> {
+        if !bytes.len().is_multiple_of(16) {
+            return None;
+        }
+        let mut entries: Vec<PolyglotEntry> = bytes
+            .chunks_exact(16)
+            .map(|c| PolyglotEntry {
+                key: u64::from_be_bytes(c[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes([c[8], c[9]]),
+                weight: u16::from_be_bytes([c[10], c[11]]),
+                learn: u32::from_be_bytes([c[12], c[13], c[14], c[15]]),
+            })
+            .collect();
+        entries.sort_by_key(|e| e.key);
+        Some(OpeningBook::Polyglot(entries))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            OpeningBook::Builtin => false,
+            OpeningBook::Lines(lines) => lines.is_empty(),
+            OpeningBook::Polyglot(entries) => entries.is_empty(),
+        }
+    }
+
+    // Book move for the current position. Lines are followed move by move
+    // from the standard start; Polyglot books are looked up by position, so
+    // they also apply after transpositions and to games set up from a FEN.
+    pub fn probe(&self, game: &Game) -> Option<(String, String)> {
+        self.probe_learned(game, None)
+    }
+
+    // Like `probe`, but skips the moves that keep losing according to
+    // `learning`.
+    pub fn probe_learned(
+        &self,
+        game: &Game,
+        learning: Option<&BookLearning>,
+    ) -> Option<(String, String)> {
+        self.choose(
+            game,
+            learning,
+            &BookOptions::default(),
+            &mut rand::thread_rng(),
+        )
+    }
+
+    // Book move for the current position under `options`, skipping the moves
+    // that score too badly according to `learning`.
+    pub fn choose(
+        &self,
+        game: &Game,
+        learning: Option<&BookLearning>,
+        options: &BookOptions,
+        rng: &mut impl Rng,
+    ) -> Option<(String, String)> {
+        if game.history.len() >= options.max_ply {
+            return None;
+        }
+        let (board, color) = (&game.board, game.current_turn);
+        let candidates: Vec<((String, String), u32)> = self
+            .weighted_moves(game)
+            .into_iter()
+            .filter(|(mv, _)| {
+                learning.is_none_or(|learning| {
+                    !learning.scores_below(board, color, mv, options.min_score)
+                })
+            })
+            .collect();
+        let total: u32 = candidates.iter().map(|&(_, weight)| weight).sum();
+        if !options.weighted || total == 0 {
+            return candidates.into_iter().next().map(|(mv, _)| mv);
+        }
+        let mut pick = rng.gen_range(0..total);
+        candidates.into_iter().find_map(|(mv, weight)| {
+            if pick < weight {
+                Some(mv)
+            } else {
+                pick -= weight;
+                None
+            }
+        })
+    }
+
+    // Legal book moves of the position, the preferred one first.
+    pub fn moves(&self, game: &Game) -> Vec<(String, String)> {
+        self.weighted_moves(game)
+            .into_iter()
+            .map(|(mv, _)| mv)
+            .collect()
+    }
+
+    // Legal book moves with their weights: the Polyglot weight, or the number
+    // of lines playing the move.
+    pub fn weighted_moves(&self, game: &Game) -> Vec<((String, String), u32)> {
+        let (board, color) = (&game.board, game.current_turn);
+        match self {
+            OpeningBook::Builtin if game.is_standard_start() => {
+                line_moves(BOOK_LINES, &game.history, board, color)
+            }
+            OpeningBook::Lines(lines) if game.is_standard_start() => {
+                line_moves(lines, &game.history, board, color)
+            }
+            OpeningBook::Polyglot(entries) => polyglot_moves(entries, board, color),
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub fn polyglot_key(board: &Board, color: Color) -> Option<u64> {
+    let pos = crate::convert::to_chess(board, color).ok()?;
+    Some(pos.zobrist_hash::<Zobrist64>(EnPassantMode::Legal).0)
+}
+
+// Legal moves of the position, highest weight first.
+fn polyglot_moves(
+    entries: &[PolyglotEntry],
+    board: &Board,
+    color: Color,
+) -> Vec<((String, String), u32)> {
+    let Some(key) = polyglot_key(board, color) else {
+        return Vec::new();
+    };
+    let start = entries.partition_point(|e| e.key < key);
+    let mut candidates: Vec<&PolyglotEntry> = entries[start..]
+        .iter()
+        .take_while(|e| e.key == key)
+        .collect();
+    candidates.sort_by_key(|e| std::cmp::Reverse(e.weight));
+    candidates
+        .into_iter()
+        .filter_map(|entry| {
+            let (s, e) = decode_polyglot_move(entry.mv, board)?;
+            board
+                .clone()
+                .is_legal(&s, &e, color)
+                .then_some(((s, e), entry.weight as u32))
+        })
+        .collect()
+}
+
+// Polyglot moves are to/from squares in bits 0-11 and the promotion piece in
+// bits 12-14. Castling is written as the king taking its own rook.
+fn decode_polyglot_move(mv: u16, board: &Board) -> Option<(String, String)> {
+    let square = |sq: u16| ((sq % 8) as usize, (sq / 8 % 8) as usize);
+    let (from, mut to) = (square(mv >> 6), square(mv));
+    // Promotions other than to a queen can't be played by the engine.
+    let promotion = (mv >> 12) & 7;
+    if promotion != 0 && promotion != 4 {
+        return None;
+    }
+    let king = board
+        .get_index(from.0, from.1)
+        .is_some_and(|p| p.piece_type == PieceType::King);
+    if king && from.0 == 4 && to.1 == from.1 {
+        match to.0 {
+            7 => to.0 = 6,
+            0 => to.0 = 2,
+            _ => {}
+        }
+    }
+    Some((
+        Board::index_to_algebraic(from.0, from.1)?,
+        Board::index_to_algebraic(to.0, to.1)?,
+    ))
+}
+
+// A book move needs this many games before its results count.
+const LEARN_MIN_GAMES: u32 = 3;
+
+// Results of one book move, from the point of view of the side playing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LearnedMove {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl LearnedMove {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+}
+
+// Results of the book moves an engine has played, keyed by Polyglot position
+// key and move, so that lines which keep losing are left out of the book.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookLearning {
+    moves: HashMap<String, LearnedMove>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+static SHARED_LEARNING: Lazy<Mutex<HashMap<PathBuf, Weak<Mutex<BookLearning>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl BookLearning {
+    // Reads the results saved at `path`, starting empty if there are none.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut learning = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e),
+        };
+        learning.path = Some(path.to_path_buf());
+        Ok(learning)
+    }
+
+    // One instance per file for the whole process, so engines playing in
+    // parallel all learn from each other's games.
+    pub fn shared(path: impl AsRef<Path>) -> io::Result<Arc<Mutex<Self>>> {
+        let path = path.as_ref().to_path_buf();
+        let mut shared = SHARED_LEARNING.lock().unwrap();
+        if let Some(learning) = shared.get(&path).and_then(Weak::upgrade) {
+            return Ok(learning);
+        }
+        let learning = Arc::new(Mutex::new(Self::open(&path)?));
+        shared.insert(path, Arc::downgrade(&learning));
+        Ok(learning)
+    }
+
+    // Writes through a temporary file, like the arena match state.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = serde_json::to_string_pretty(self)?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(tmp, path)
+    }
+
+    fn key(board: &Board, color: Color, mv: &(String, String)) -> Option<String> {
+        let position = polyglot_key(board, color)?;
+        Some(format!("{:016x} {}{}", position, mv.0, mv.1))
+    }
+
+    pub fn get(&self, board: &Board, color: Color, mv: &(String, String)) -> Option<LearnedMove> {
+        self.moves.get(&Self::key(board, color, mv)?).copied()
+    }
+
+    pub fn is_losing(&self, board: &Board, color: Color, mv: &(String, String)) -> bool {
+        self.scores_below(board, color, mv, BookOptions::default().min_score)
+    }
+
+    // Whether `mv` has scored under `percent` over enough games to tell.
+    pub fn scores_below(
+        &self,
+        board: &Board,
+        color: Color,
+        mv: &(String, String),
+        percent: u32,
+    ) -> bool {
+        self.get(board, color, mv).is_some_and(|learned| {
+            learned.games() >= LEARN_MIN_GAMES && learned.score() * 100.0 < percent as f64
+        })
+    }
+
+    // Records the result of every book move `color` played in `game` from
+    // `from_ply` on, until the game leaves the book.
+    pub fn record(
+        &mut self,
+        book: &OpeningBook,
+        game: &Game,
+        from_ply: usize,
+        color: Color,
+        winner: Option<Color>,
+    ) {
+        let Some(mut replay) = Game::from_fen(&game.start_board.to_fen(game.start_turn)) else {
+            return;
+        };
+        for (ply, mv) in game.history.iter().enumerate() {
+            if ply >= from_ply && replay.current_turn == color {
+                let book_moves = book.moves(&replay);
+                if book_moves.is_empty() {
+                    break;
+                }
+                if book_moves.contains(mv)
+                    && let Some(key) = Self::key(&replay.board, color, mv)
+                {
+                    let learned = self.moves.entry(key).or_default();
+                    match winner {
+                        Some(w) if w == color => learned.wins += 1,
+                        Some(_) => learned.losses += 1,
+                        None => learned.draws += 1,
+                    }
+                }
+            }
+            if !replay.make_move(&mv.0, &mv.1) {
+                break;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::book_move;
+    use super::{BookLearning, BookOptions, OpeningBook, book_move, polyglot_key};
     use crate::game::Game;
+    use crate::pieces::Color;
+    use std::sync::Arc;
 
     #[test]
     fn suggests_first_white_move() {
@@ -76,4 +479,137 @@ mod tests {
         let mv = book_move(&game.history, &game.board, game.current_turn);
         assert_eq!(mv, Some(("e7".into(), "e5".into())));
     }
+
+    fn polyglot_entry(key: u64, mv: u16, weight: u16) -> Vec<u8> {
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend(mv.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn reads_polyglot_books() {
+        let start = Game::new();
+        let key = polyglot_key(&start.board, start.current_turn).unwrap();
+        assert_eq!(key, 0x463b_9618_1691_fc9c);
+
+        // e2e4 and a more popular d2d4 from the start, O-O in a later
+        // position, stored with the king taking the rook.
+        let mut castle = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castle_key = polyglot_key(&castle.board, castle.current_turn).unwrap();
+        let mut bytes = polyglot_entry(key, (12 << 6) | 28, 10);
+        bytes.extend(polyglot_entry(key, (11 << 6) | 27, 20));
+        bytes.extend(polyglot_entry(castle_key, (4 << 6) | 7, 1));
+        let book = OpeningBook::from_polyglot(&bytes).unwrap();
+
+        assert_eq!(book.probe(&start), Some(("d2".into(), "d4".into())));
+        assert_eq!(book.probe(&castle), Some(("e1".into(), "g1".into())));
+        assert!(castle.make_move("e1", "g1"));
+        assert_eq!(book.probe(&castle), None);
+        assert!(OpeningBook::from_polyglot(&bytes[1..]).is_none());
+    }
+
+    #[test]
+    fn reads_text_books() {
+        let book = OpeningBook::from_lines("# comment\n\ng1f3 d7d5 # Reti\nd2d4 d7d5 c2c4\n");
+        let mut game = Game::new();
+        assert_eq!(book.probe(&game), Some(("g1".into(), "f3".into())));
+        assert!(game.make_move("d2", "d4"));
+        assert!(game.make_move("d7", "d5"));
+        assert_eq!(book.probe(&game), Some(("c2".into(), "c4".into())));
+        assert!(game.make_move("c2", "c4"));
+        assert_eq!(book.probe(&game), None);
+    }
+
+    #[test]
+    fn book_options_limit_and_vary_moves() {
+        use rand::SeedableRng;
+
+        let book = OpeningBook::from_lines("g1f3 d7d5\nd2d4 d7d5\nd2d4 g8f6\n");
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut game = Game::new();
+        let best = BookOptions::default();
+        assert_eq!(
+            book.weighted_moves(&game),
+            vec![
+                (("d2".into(), "d4".into()), 2),
+                (("g1".into(), "f3".into()), 1)
+            ]
+        );
+        assert_eq!(
+            book.choose(&game, None, &best, &mut rng),
+            Some(("d2".into(), "d4".into()))
+        );
+
+        let weighted = BookOptions {
+            weighted: true,
+            ..best
+        };
+        let knight = (0..300)
+            .filter(|_| book.choose(&game, None, &weighted, &mut rng).unwrap().0 == "g1")
+            .count();
+        assert!((60..140).contains(&knight), "{knight}");
+
+        assert!(game.make_move("d2", "d4"));
+        let shallow = BookOptions { max_ply: 1, ..best };
+        assert!(book.choose(&game, None, &best, &mut rng).is_some());
+        assert_eq!(book.choose(&game, None, &shallow, &mut rng), None);
+    }
+
+    #[test]
+    fn learning_drops_losing_book_moves() {
+        let mut game = Game::new();
+        for (s, e) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3"), ("a7", "a6")] {
+            assert!(game.make_move(s, e));
+        }
+        let book = OpeningBook::default();
+        let start = Game::new();
+        let mut learning = BookLearning::default();
+        for _ in 0..3 {
+            learning.record(&book, &game, 0, Color::White, Some(Color::Black));
+            learning.record(&book, &game, 0, Color::Black, None);
+        }
+
+        let e4 = ("e2".to_string(), "e4".to_string());
+        let learned = learning.get(&start.board, Color::White, &e4).unwrap();
+        assert_eq!((learned.wins, learned.losses), (0, 3));
+        assert!(learning.is_losing(&start.board, Color::White, &e4));
+        let e4_ok = |min_score| !learning.scores_below(&start.board, Color::White, &e4, min_score);
+        assert!(!e4_ok(1) && e4_ok(0));
+        assert_eq!(
+            book.probe_learned(&start, Some(&learning)),
+            Some(("d2".into(), "d4".into()))
+        );
+        // Black's a6 left the book, and draws are not losses.
+        let mut after_e4 = Game::new();
+        assert!(after_e4.make_move("e2", "e4"));
+        let e5 = ("e7".to_string(), "e5".to_string());
+        assert_eq!(
+            learning
+                .get(&after_e4.board, Color::Black, &e5)
+                .unwrap()
+                .draws,
+            3
+        );
+        assert_eq!(book.probe_learned(&after_e4, Some(&learning)), Some(e5));
+
+        let path = std::env::temp_dir().join(format!("book_learning_{}.json", std::process::id()));
+        let shared = BookLearning::shared(&path).unwrap();
+        assert!(Arc::ptr_eq(&shared, &BookLearning::shared(&path).unwrap()));
+        {
+            let mut shared = shared.lock().unwrap();
+            shared.record(&book, &game, 0, Color::White, Some(Color::Black));
+            shared.save().unwrap();
+        }
+        let reopened = BookLearning::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            reopened
+                .get(&start.board, Color::White, &e4)
+                .unwrap()
+                .losses,
+            1
+        );
+    }
 }
@@ -0,0 +1,546 @@
+use crate::{
+    game::Game,
+    pieces::Color,
+    san::{SanError, parse_san},
+};
+
+const STANDARD_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub game: Game,
+    // Time left to the player of each ply, in milliseconds, from its
+    // `[%clk h:mm:ss]` comment.
+    pub clocks: Vec<Option<u64>>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // The game as it stood after its first `ply` moves, or `None` past the
+    // end of the game.
+    pub fn position(&self, ply: usize) -> Option<Game> {
+        let undo = self.game.history.len().checked_sub(ply)?;
+        let mut game = self.game.clone();
+        for _ in 0..undo {
+            game.undo_move();
+        }
+        Some(game)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    Tag(String),
+    Fen(String),
+    // The move numbered `number` for `color` could not be read or played.
+    Move {
+        number: usize,
+        color: Color,
+        error: SanError,
+    },
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Tag(line) => write!(f, "malformed tag {}", line),
+            PgnError::Fen(fen) => write!(f, "invalid FEN \"{}\"", fen),
+            PgnError::Move {
+                number,
+                color,
+                error,
+            } => {
+                let dots = if *color == Color::White { "." } else { "..." };
+                write!(f, "move {}{}: {}", number, dots, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+fn opposite(color: Color) -> Color {
+    if color == Color::White {
+        Color::Black
+    } else {
+        Color::White
+    }
+}
+
+pub fn game_result(game: &Game) -> &'static str {
+    match game.result {
+        Some(Color::White) => "1-0",
+        Some(Color::Black) => "0-1",
+        None => "*",
+    }
+}
+
+pub fn to_pgn(game: &Game, tags: &[(String, String)]) -> String {
+    to_pgn_annotated(game, tags, &[])
+}
+
+// Like `to_pgn`, with `comments[i]` written as a `{...}` comment after ply `i`.
+pub fn to_pgn_annotated(
+    game: &Game,
+    tags: &[(String, String)],
+    comments: &[Option<String>],
+) -> String {
+    let mut out = String::new();
+    let mut has_result = false;
+    for (key, value) in tags {
+        has_result |= key == "Result";
+        out.push_str(&format!("[{} \"{}\"]\n", key, value.replace('"', "\\\"")));
+    }
+    let result = tags
+        .iter()
+        .find(|(k, _)| k == "Result")
+        .map(|(_, v)| v.as_str())
+        .unwrap_or_else(|| game_result(game));
+    if !has_result {
+        out.push_str(&format!("[Result \"{}\"]\n", result));
+    }
+    let start_fen = game.start_board.to_fen(game.start_turn);
+    if start_fen != STANDARD_FEN {
+        out.push_str("[SetUp \"1\"]\n");
+        out.push_str(&format!("[FEN \"{}\"]\n", start_fen));
+    }
+    out.push('\n');
+
+    let mut color = game.start_turn;
+    let mut line = String::new();
+    let offset = usize::from(game.start_turn == Color::Black);
+    for (i, san) in game.san_history().iter().enumerate() {
+        let number = (i + offset) / 2 + 1;
+        let mut token = String::new();
+        if color == Color::White {
+            token.push_str(&format!("{}. ", number));
+        } else if i == 0 || comments.get(i - 1).is_some_and(Option::is_some) {
+            token.push_str(&format!("{}... ", number));
+        }
+        token.push_str(san);
+        if let Some(Some(comment)) = comments.get(i) {
+            token.push_str(&format!(" {{{}}}", comment.replace('}', "")));
+        }
+        if !line.is_empty() && line.len() + token.len() + 1 > 80 {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+        color = opposite(color);
+    }
+    if !line.is_empty() && line.len() + result.len() + 1 > 80 {
+        out.push_str(&line);
+        out.push('\n');
+        line.clear();
+    }
+    if !line.is_empty() {
+        line.push(' ');
+    }
+    line.push_str(result);
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+// Formats a clock as h:mm:ss, with tenths below a whole second.
+pub fn format_clock(ms: u64) -> String {
+    let secs = ms / 1000;
+    let mut text = format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if ms % 1000 >= 100 {
+        text.push_str(&format!(".{}", ms % 1000 / 100));
+    }
+    text
+}
+
+// Reads h:mm:ss or m:ss, the seconds possibly fractional.
+pub fn parse_clock(text: &str) -> Option<u64> {
+    let (rest, secs) = text.trim().rsplit_once(':')?;
+    let (whole, fraction) = secs.split_once('.').unwrap_or((secs, ""));
+    if whole.len() != 2 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, mins) = rest.split_once(':').unwrap_or(("0", rest));
+    let (hours, mins, whole): (u64, u64, u64) =
+        (hours.parse().ok()?, mins.parse().ok()?, whole.parse().ok()?);
+    if mins >= 60 || whole >= 60 {
+        return None;
+    }
+    let millis = format!("{:0<3}", &fraction[..fraction.len().min(3)]);
+    Some(((hours * 60 + mins) * 60 + whole) * 1000 + millis.parse::<u64>().ok()?)
+}
+
+// `[%clk ...]` command for a comment after a move.
+pub fn clock_comment(ms: u64) -> String {
+    format!("[%clk {}]", format_clock(ms))
+}
+
+// The `[%clk ...]` time in a comment, if it has one.
+fn comment_clock(comment: &str) -> Option<u64> {
+    let (_, rest) = comment.split_once("[%clk")?;
+    let (time, _) = rest.split_once(']')?;
+    parse_clock(time)
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (key, rest) = inner.split_once(char::is_whitespace)?;
+    let value = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+    Some((key.to_string(), value.replace("\\\"", "\"")))
+}
+
+// Moves, move numbers and results, and the comments of the main line kept
+// whole within their braces.
+fn movetext_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut comment = false;
+    let mut comment_text = String::new();
+    let mut line_comment = false;
+    let mut variation = 0usize;
+    for ch in text.chars() {
+        if line_comment {
+            if ch == '\n' {
+                line_comment = false;
+            }
+            continue;
+        }
+        if comment {
+            if ch != '}' {
+                comment_text.push(ch);
+            } else {
+                comment = false;
+                if variation == 0 {
+                    tokens.push(format!("{{{}}}", comment_text));
+                }
+            }
+            continue;
+        }
+        match ch {
+            '{' => {
+                comment = true;
+                comment_text.clear();
+            }
+            ';' => line_comment = true,
+            '(' => variation += 1,
+            ')' => variation = variation.saturating_sub(1),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            c if variation == 0 => {
+                current.push(c);
+                continue;
+            }
+            _ => {}
+        }
+        if !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+pub fn parse_pgn(text: &str) -> Result<PgnGame, PgnError> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if movetext.trim().is_empty() && trimmed.starts_with('[') {
+            tags.push(parse_tag(trimmed).ok_or_else(|| PgnError::Tag(trimmed.to_string()))?);
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let mut game = match tags.iter().find(|(k, _)| k == "FEN") {
+        Some((_, fen)) => Game::from_fen(fen).ok_or_else(|| PgnError::Fen(fen.clone()))?,
+        None => Game::new(),
+    };
+
+    let mut clocks = Vec::new();
+    for token in movetext_tokens(&movetext) {
+        if token.starts_with('{') {
+            if let Some(clock) = clocks.last_mut() {
+                *clock = comment_clock(&token).or(*clock);
+            }
+            continue;
+        }
+        // "e.p." after an en passant capture is a separate token.
+        if matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*" | "e.p.")
+            || token.starts_with('$')
+        {
+            continue;
+        }
+        let san = strip_move_number(&token).trim_end_matches(['!', '?']);
+        if san.is_empty() {
+            continue;
+        }
+        let color = game.current_turn;
+        let number = game.board.fullmove as usize;
+        let error = |error| PgnError::Move {
+            number,
+            color,
+            error,
+        };
+        let (start, end) = parse_san(&mut game, san, color).map_err(error)?;
+        if !game.make_move(&start, &end) {
+            return Err(error(SanError::NoMatch(san.to_string())));
+        }
+        clocks.push(None);
+    }
+
+    Ok(PgnGame { tags, game, clocks })
+}
+
+// `token` without its leading move number ("4." or "4..."). Digits not
+// followed by a dot are part of the move, as in zero-style castling "0-0".
+fn strip_move_number(token: &str) -> &str {
+    let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() < token.len() && rest.starts_with('.') {
+        rest.trim_start_matches('.')
+    } else {
+        token
+    }
+}
+
+// Splits a multi-game file on the tag section that follows each game's movetext.
+// Games that fail to parse are skipped.
+pub fn parse_pgn_collection(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut current = String::new();
+    let mut in_movetext = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            if in_movetext {
+                games.extend(parse_pgn(&current));
+                current.clear();
+                in_movetext = false;
+            }
+        } else if !trimmed.is_empty() {
+            in_movetext = true;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        games.extend(parse_pgn(&current));
+    }
+    games
+}
+
+// Contents of a game file, told apart by content rather than extension.
+pub enum GameFile {
+    Pgn(PgnGame),
+    Fen(Game),
+}
+
+// Reads a .pgn or .fen file: a file whose first non-empty line is a FEN is a
+// position, anything else is parsed as a PGN game.
+pub fn parse_game_file(text: &str) -> Result<GameFile, PgnError> {
+    let first = text.lines().map(str::trim).find(|l| !l.is_empty());
+    if let Some(game) = first
+        .filter(|l| !l.starts_with('['))
+        .and_then(Game::from_fen)
+    {
+        return Ok(GameFile::Fen(game));
+    }
+    parse_pgn(text).map(GameFile::Pgn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pgn_round_trip() {
+        let mut game = Game::new();
+        for (s, e) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3"), ("b8", "c6")] {
+            assert!(game.make_move(s, e));
+        }
+        let tags = vec![("Event".to_string(), "Test".to_string())];
+        let text = to_pgn(&game, &tags);
+        assert!(text.contains("1. e4 e5 2. Nf3 Nc6 *"));
+
+        let loaded = parse_pgn(&text).unwrap();
+        assert_eq!(loaded.tag("Event"), Some("Test"));
+        assert_eq!(loaded.game.history, game.history);
+    }
+
+    #[test]
+    fn annotated_pgn_keeps_comments() {
+        let mut game = Game::new();
+        for (s, e) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3")] {
+            assert!(game.make_move(s, e));
+        }
+        let comments = [
+            None,
+            Some("+0.20/8".to_string()),
+            Some("+0.35/9".to_string()),
+        ];
+        let text = to_pgn_annotated(&game, &[], &comments);
+        assert!(text.contains("1. e4 e5 {+0.20/8} 2. Nf3 {+0.35/9} *"));
+        let comments = [Some("book".to_string())];
+        let text = to_pgn_annotated(&game, &[], &comments);
+        assert!(text.contains("1. e4 {book} 1... e5 2. Nf3"));
+        assert_eq!(parse_pgn(&text).unwrap().game.history, game.history);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_variations() {
+        let text =
+            "[Event \"x\"]\n\n1. e4 {best by test} e5 (1... c5 2. Nf3) 2. Nf3!? $1 Nc6 1-0\n";
+        let loaded = parse_pgn(text).unwrap();
+        assert_eq!(loaded.game.history.len(), 4);
+        assert_eq!(loaded.game.history[2], ("g1".to_string(), "f3".to_string()));
+
+        for castle in ["4. 0-0", "4.0-0", "4. O-O"] {
+            let text = format!("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 {} *", castle);
+            let loaded = parse_pgn(&text).unwrap();
+            assert_eq!(loaded.game.history[6], ("e1".to_string(), "g1".to_string()));
+        }
+    }
+
+    #[test]
+    fn parse_accepts_en_passant_suffix() {
+        let loaded = parse_pgn("1. e4 a6 2. e5 d5 3. exd6 e.p. Nc6 *").unwrap();
+        assert_eq!(loaded.game.history.len(), 6);
+        assert_eq!(loaded.game.history[4], ("e5".to_string(), "d6".to_string()));
+        assert_eq!(loaded.game.board.get("d5"), None);
+    }
+
+    #[test]
+    fn positions_at_any_ply() {
+        let text = "[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n\n\
+                    1. e4 {push} Kd7 (1... Ke7) 2. Kd2 Kc6 1/2-1/2\n";
+        let loaded = parse_pgn(text).unwrap();
+        assert_eq!(loaded.tag("FEN"), Some("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert_eq!(
+            loaded.position(0).unwrap().to_fen(),
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+        );
+        assert_eq!(
+            loaded.position(2).unwrap().to_fen(),
+            "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2"
+        );
+        assert_eq!(loaded.position(4).unwrap().history, loaded.game.history);
+        assert!(loaded.position(5).is_none());
+    }
+
+    #[test]
+    fn clock_comments_round_trip() {
+        assert_eq!(format_clock(300_000), "0:05:00");
+        assert_eq!(format_clock(3_723_450), "1:02:03.4");
+        assert_eq!(parse_clock("0:05:00"), Some(300_000));
+        assert_eq!(parse_clock("1:02:03.45"), Some(3_723_450));
+        assert_eq!(parse_clock("4:59"), Some(299_000));
+        assert_eq!(parse_clock("0:05"), Some(5_000));
+        assert_eq!(parse_clock("five"), None);
+        assert_eq!(parse_clock("0:05:61"), None);
+
+        // As exported by lichess, with evaluations next to the clocks.
+        let text = "1. e4 { [%eval 0.3] [%clk 0:03:00] } 1... e5 { [%clk 0:02:58] } \
+                    2. Nf3 { [%clk 0:02:57.5] } (2. f4 { [%clk 0:01:00] }) Nc6 *";
+        let loaded = parse_pgn(text).unwrap();
+        assert_eq!(
+            loaded.clocks,
+            vec![Some(180_000), Some(178_000), Some(177_500), None]
+        );
+
+        let comments: Vec<Option<String>> = loaded
+            .clocks
+            .iter()
+            .map(|clock| clock.map(clock_comment))
+            .collect();
+        let text = to_pgn_annotated(&loaded.game, &[], &comments);
+        assert!(text.contains("1. e4 {[%clk 0:03:00]} 1... e5 {[%clk 0:02:58]}"));
+        assert!(text.contains("2. Nf3 {[%clk 0:02:57.5]}"));
+        assert!(text.contains("2... Nc6 *"));
+        assert_eq!(parse_pgn(&text).unwrap().clocks, loaded.clocks);
+    }
+
+    #[test]
+    fn parse_collection_splits_games() {
+        let text =
+            "\n[Event \"a\"]\n\n1. e4 e5 *\n\n[Event \"b\"]\n[Round \"2\"]\n\n1. d4\nd5 2. c4 *\n";
+        let games = parse_pgn_collection(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("a"));
+        assert_eq!(games[0].game.history.len(), 2);
+        assert_eq!(games[1].tag("Round"), Some("2"));
+        assert_eq!(games[1].game.history.len(), 3);
+    }
+
+    #[test]
+    fn parse_errors_name_the_move() {
+        let err = parse_pgn("1. Nf3 Nf6 2. Nc3 Nc6 3. Ng5 Ng4 4. Ne4 *")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            PgnError::Move {
+                number: 4,
+                color: Color::White,
+                error: SanError::Ambiguous(
+                    "Ne4".to_string(),
+                    vec!["c3".to_string(), "g5".to_string()]
+                ),
+            }
+        );
+        let err = parse_pgn("1. e4 e5 2. Kf3 *").err().unwrap();
+        assert_eq!(err.to_string(), "move 2.: no legal move matches \"Kf3\"");
+        assert!(matches!(
+            parse_pgn("[FEN \"8/8 w\"]\n\n1. e4 *"),
+            Err(PgnError::Fen(_))
+        ));
+    }
+
+    #[test]
+    fn fen_tag_sets_start_position() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        assert!(game.make_move("e8", "d7"));
+        assert!(game.make_move("e2", "e4"));
+        let text = to_pgn(&game, &[]);
+        assert!(text.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 1\"]"));
+        assert!(text.contains("1... Kd7 2. e4"));
+
+        let loaded = parse_pgn(&text).unwrap();
+        assert_eq!(loaded.game.start_turn, Color::Black);
+        assert_eq!(loaded.game.history, game.history);
+    }
+
+    #[test]
+    fn game_files_are_detected() {
+        let fen = "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1";
+        match parse_game_file(&format!("\n{}\n", fen)) {
+            Ok(GameFile::Fen(game)) => assert_eq!(game.to_fen(), fen),
+            _ => panic!("FEN not detected"),
+        }
+        match parse_game_file("[Event \"Test\"]\n\n1. e4 e5 2. Nf3 *\n") {
+            Ok(GameFile::Pgn(pgn)) => assert_eq!(pgn.game.history.len(), 3),
+            _ => panic!("PGN not detected"),
+        }
+        match parse_game_file("1. e4 c5 *") {
+            Ok(GameFile::Pgn(pgn)) => assert_eq!(pgn.game.history.len(), 2),
+            _ => panic!("PGN without tags not detected"),
+        }
+        assert!(parse_game_file("1. e4 Ke7 *").is_err());
+    }
+}
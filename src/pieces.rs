@@ -14,7 +14,7 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Piece {
     pub piece_type: PieceType,
     pub color: Color,
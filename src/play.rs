@@ -0,0 +1,387 @@
+use crate::arena::{
+    AdjudicationRules, Adjudicator, GameClock, GameOutcome, Termination, TimeControl, game_outcome,
+};
+use crate::engine::Engine;
+use crate::game::Game;
+use crate::pieces::Color;
+use rand::seq::SliceRandom;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+
+// Plies after which a game is drawn by the move limit.
+pub const MAX_PLIES: usize = 400;
+
+type MoveSource<'a> = Box<dyn FnMut(&Game, &GameClock) -> Option<(String, String)> + Send + 'a>;
+type ScoredMove = ((String, String), Option<i32>);
+type ScoredMoveSource<'a> = Box<dyn FnMut(&mut Game, &GameClock) -> Option<ScoredMove> + Send + 'a>;
+type MoveCallback<'a> = Box<dyn FnMut(&Game, &str, &str) + Send + 'a>;
+type ResultCallback<'a> = Box<dyn FnMut(&Game, &GameOutcome) + Send + 'a>;
+type Adjudication<'a> = Box<dyn FnMut(&Game, Option<i32>) -> Option<GameOutcome> + Send + 'a>;
+
+// Who plays one side of a `Match`.
+pub enum PlayerKind<'a> {
+    // Moves come from a callback, e.g. a GUI or a network client, which is
+    // asked again after an illegal move. `None` resigns.
+    Human(MoveSource<'a>),
+    // Searches under the match's time control at the engine's own depth.
+    Engine(Box<Engine>),
+    // Moves and the mover's evaluation come from a callback, e.g. an engine
+    // the caller searches with itself. `None` or an illegal move ends the
+    // game without a result.
+    Bot(ScoredMoveSource<'a>),
+    // Plays uniformly random legal moves.
+    Random,
+}
+
+impl<'a> PlayerKind<'a> {
+    pub fn human<F>(next_move: F) -> Self
+    where
+        F: FnMut(&Game, &GameClock) -> Option<(String, String)> + Send + 'a,
+    {
+        PlayerKind::Human(Box::new(next_move))
+    }
+
+    pub fn engine(engine: Engine) -> Self {
+        PlayerKind::Engine(Box::new(engine))
+    }
+
+    // An engine player whose engine stays the caller's, to learn from the
+    // game or keep its tables for the next one.
+    pub fn engine_mut(engine: &'a mut Engine) -> Self {
+        PlayerKind::bot(move |game, clock| search(engine, game, clock))
+    }
+
+    pub fn bot<F>(next_move: F) -> Self
+    where
+        F: FnMut(&mut Game, &GameClock) -> Option<ScoredMove> + Send + 'a,
+    {
+        PlayerKind::Bot(Box::new(next_move))
+    }
+
+    fn next_move(&mut self, game: &mut Game, clock: &GameClock) -> Option<ScoredMove> {
+        match self {
+            PlayerKind::Human(next_move) => next_move(game, clock).map(|mv| (mv, None)),
+            PlayerKind::Engine(engine) => search(engine, game, clock),
+            PlayerKind::Bot(next_move) => next_move(game, clock),
+            PlayerKind::Random => {
+                let mv = game
+                    .legal_moves()
+                    .choose(&mut rand::thread_rng())
+                    .cloned()?;
+                Some((mv, None))
+            }
+        }
+    }
+}
+
+fn search(engine: &mut Engine, game: &mut Game, clock: &GameClock) -> Option<ScoredMove> {
+    let config = clock.time_config(engine.depth);
+    let (mv, _) = engine.best_move_timed(game, &config)?;
+    Some((mv, engine.last_score()))
+}
+
+// A whole game between two players, with the turn loop, the clock and the
+// end of the game taken care of:
+//
+//     let outcome = Match::new(PlayerKind::engine(Engine::new(4)), PlayerKind::Random, tc)
+//         .on_move(|_, from, to| println!("{}{}", from, to))
+//         .play();
+pub struct Match<'a> {
+    // White first.
+    players: [PlayerKind<'a>; 2],
+    game: Game,
+    clock: GameClock,
+    max_plies: usize,
+    on_move: Option<MoveCallback<'a>>,
+    on_result: Option<ResultCallback<'a>>,
+    adjudicate: Option<Adjudication<'a>>,
+}
+
+impl<'a> Match<'a> {
+    pub fn new(white: PlayerKind<'a>, black: PlayerKind<'a>, time_control: TimeControl) -> Self {
+        Self {
+            players: [white, black],
+            game: Game::new(),
+            clock: GameClock::new(time_control),
+            max_plies: MAX_PLIES,
+            on_move: None,
+            on_result: None,
+            adjudicate: None,
+        }
+    }
+
+    // Starts from `game` instead of the standard position.
+    pub fn with_game(mut self, game: Game) -> Self {
+        self.game = game;
+        self
+    }
+
+    pub fn with_max_plies(mut self, max_plies: usize) -> Self {
+        self.max_plies = max_plies;
+        self
+    }
+
+    // Called after every move with the game and the move's squares.
+    pub fn on_move<F>(mut self, on_move: F) -> Self
+    where
+        F: FnMut(&Game, &str, &str) + Send + 'a,
+    {
+        self.on_move = Some(Box::new(on_move));
+        self
+    }
+
+    // Called once the game is over.
+    pub fn on_result<F>(mut self, on_result: F) -> Self
+    where
+        F: FnMut(&Game, &GameOutcome) + Send + 'a,
+    {
+        self.on_result = Some(Box::new(on_result));
+        self
+    }
+
+    // Called before every move the rules leave open, with the evaluation the
+    // last mover gave for its move; an outcome ends the game there, as an
+    // `arena::Adjudicator` does.
+    pub fn adjudicate<F>(mut self, adjudicate: F) -> Self
+    where
+        F: FnMut(&Game, Option<i32>) -> Option<GameOutcome> + Send + 'a,
+    {
+        self.adjudicate = Some(Box::new(adjudicate));
+        self
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn into_game(self) -> Game {
+        self.game
+    }
+
+    pub fn clock(&self) -> &GameClock {
+        &self.clock
+    }
+
+    // Plays the game to its end.
+    pub fn play(&mut self) -> GameOutcome {
+        let outcome = self.play_moves();
+        if let Some(on_result) = &mut self.on_result {
+            on_result(&self.game, &outcome);
+        }
+        outcome
+    }
+
+    fn play_moves(&mut self) -> GameOutcome {
+        let mut score = None;
+        loop {
+            if let Some(outcome) = game_outcome(&mut self.game, self.max_plies) {
+                return outcome;
+            }
+            if let Some(adjudicate) = &mut self.adjudicate
+                && let Some(outcome) = adjudicate(&self.game, score)
+            {
+                return outcome;
+            }
+            let mover = self.game.current_turn;
+            let player = &mut self.players[mover as usize];
+            let start = Instant::now();
+            let (from, to) = loop {
+                let Some(((from, to), eval)) = player.next_move(&mut self.game, &self.clock) else {
+                    return match player {
+                        PlayerKind::Human(_) => GameOutcome {
+                            winner: Some(opposite(mover)),
+                            termination: Termination::Resignation,
+                        },
+                        _ => GameOutcome::draw(Termination::NoMove),
+                    };
+                };
+                // `make_move` trusts the move's geometry, so it is checked first.
+                let legal = self
+                    .game
+                    .legal_moves()
+                    .contains(&(from.clone(), to.clone()));
+                if legal && self.game.make_move(&from, &to) {
+                    score = eval;
+                    break (from, to);
+                }
+                if !matches!(player, PlayerKind::Human(_)) {
+                    return GameOutcome::draw(Termination::NoMove);
+                }
+            };
+            if !self.clock.spend(mover, start.elapsed()) {
+                self.game.undo_move();
+                return GameOutcome::timeout(&self.game, mover);
+            }
+            self.clock.record(self.game.history.len() - 1, mover);
+            if let Some(on_move) = &mut self.on_move {
+                on_move(&self.game, &from, &to);
+            }
+        }
+    }
+}
+
+// One thread of a series of adjudicated engine games: its two engines, a
+// fork of the first probing the tablebases for the adjudicator while they
+// play, and the counter every thread takes its next game from.
+pub struct MatchWorker {
+    pub engines: [Engine; 2],
+    referee: Engine,
+    adjudicator: Adjudicator,
+    next_game: Arc<AtomicU32>,
+    games: u32,
+}
+
+impl MatchWorker {
+    pub fn new(
+        engines: [Engine; 2],
+        rules: AdjudicationRules,
+        next_game: Arc<AtomicU32>,
+        games: u32,
+    ) -> Self {
+        let referee = engines[0].fork();
+        Self {
+            engines,
+            referee,
+            adjudicator: Adjudicator::new(rules),
+            next_game,
+            games,
+        }
+    }
+
+    // Index of the next game to play, `None` once all of them are taken.
+    pub fn next_index(&self) -> Option<u32> {
+        let index = self.next_game.fetch_add(1, Ordering::Relaxed);
+        (index < self.games).then_some(index)
+    }
+
+    // The adjudicated match from `game`, with both engines and the
+    // adjudicator reset and `players` making White and Black of the engines.
+    pub fn start<'a, F>(
+        &'a mut self,
+        game: Game,
+        time_control: TimeControl,
+        players: F,
+    ) -> Match<'a>
+    where
+        F: FnOnce(&'a mut [Engine; 2]) -> [PlayerKind<'a>; 2],
+    {
+        let Self {
+            engines,
+            referee,
+            adjudicator,
+            ..
+        } = self;
+        engines.iter_mut().for_each(Engine::new_game);
+        adjudicator.reset();
+        let [white, black] = players(engines);
+        let referee: &Engine = referee;
+        Match::new(white, black, time_control)
+            .with_game(game)
+            .with_max_plies(MAX_PLIES)
+            .adjudicate(move |game, score| adjudicator.check(game, score, referee))
+    }
+}
+
+fn opposite(color: Color) -> Color {
+    match color {
+        Color::White => Color::Black,
+        Color::Black => Color::White,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn engines_play_to_mate() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let moves = Arc::new(Mutex::new(Vec::new()));
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let (seen_moves, seen_results) = (moves.clone(), results.clone());
+        let mut engine = Engine::new(3);
+        engine.params.use_book = false;
+        let outcome = Match::new(
+            PlayerKind::engine(engine.clone()),
+            PlayerKind::engine(engine),
+            TimeControl::Depth,
+        )
+        .with_game(game)
+        .on_move(move |_, from, to| seen_moves.lock().unwrap().push(format!("{}{}", from, to)))
+        .on_result(move |_, outcome| seen_results.lock().unwrap().push(*outcome))
+        .play();
+        assert_eq!(outcome.winner, Some(Color::White));
+        assert_eq!(outcome.termination, Termination::Checkmate);
+        assert_eq!(moves.lock().unwrap().last().unwrap(), "a1a8");
+        assert_eq!(*results.lock().unwrap(), vec![outcome]);
+    }
+
+    #[test]
+    fn workers_share_the_game_indices() {
+        let next_game = Arc::new(AtomicU32::new(0));
+        let engines = || [Engine::new(2), Engine::new(2)];
+        let rules = AdjudicationRules::default();
+        let mut worker = MatchWorker::new(engines(), rules.clone(), next_game.clone(), 3);
+        let other = MatchWorker::new(engines(), rules, next_game, 3);
+        assert_eq!(worker.next_index(), Some(0));
+        assert_eq!(other.next_index(), Some(1));
+        assert_eq!(worker.next_index(), Some(2));
+        assert_eq!(other.next_index(), None);
+
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut played = worker.start(game, TimeControl::Depth, |engines| {
+            engines.each_mut().map(PlayerKind::engine_mut)
+        });
+        let outcome = played.play();
+        assert_eq!(outcome.winner, Some(Color::White));
+        assert_eq!(outcome.termination, Termination::Checkmate);
+    }
+
+    #[test]
+    fn humans_retry_illegal_moves_and_resign() {
+        let mut replies = vec![None, Some(("e2", "e4")), Some(("e2", "e5"))];
+        let human = PlayerKind::human(move |_, _| {
+            replies
+                .pop()
+                .flatten()
+                .map(|(s, e)| (s.to_string(), e.to_string()))
+        });
+        let mut played = Match::new(human, PlayerKind::Random, TimeControl::Depth);
+        let outcome = played.play();
+        assert_eq!(outcome.winner, Some(Color::Black));
+        assert_eq!(outcome.termination, Termination::Resignation);
+        assert_eq!(played.game().history.len(), 2);
+        assert_eq!(
+            played.game().history[0],
+            ("e2".to_string(), "e4".to_string())
+        );
+    }
+
+    #[test]
+    fn random_players_reach_an_end_and_slow_humans_lose_on_time() {
+        let mut played = Match::new(PlayerKind::Random, PlayerKind::Random, TimeControl::Depth)
+            .with_max_plies(40);
+        let outcome = played.play();
+        let plies = played.game().history.len();
+        assert!(plies <= 40);
+        assert_eq!(outcome.termination == Termination::MoveLimit, plies == 40);
+
+        let slow = PlayerKind::human(|_, _| {
+            std::thread::sleep(Duration::from_millis(300));
+            Some(("e2".to_string(), "e4".to_string()))
+        });
+        let clock = TimeControl::Clock {
+            base_ms: 100,
+            inc_ms: 0,
+        };
+        let mut played = Match::new(slow, PlayerKind::Random, clock);
+        let outcome = played.play();
+        assert_eq!(outcome.winner, Some(Color::Black));
+        assert_eq!(outcome.termination, Termination::Timeout);
+        assert!(played.game().history.is_empty());
+        assert_eq!(played.clock().remaining_ms(Color::White), 0);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::arena::MatchStats;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f64::consts::{LN_10, PI};
+use std::io;
+use std::path::Path;
+
+// Glicko parameters: a newcomer starts at 1500 ± 350, and the deviation of a
+// rating grows by `DEVIATION_GROWTH` every match it sits out, up to the
+// starting deviation again.
+pub const INITIAL_RATING: f64 = 1500.0;
+pub const INITIAL_DEVIATION: f64 = 350.0;
+const DEVIATION_GROWTH: f64 = 30.0;
+const MIN_DEVIATION: f64 = 30.0;
+const Q: f64 = LN_10 / 400.0;
+
+// Glicko rating of one engine configuration and its record.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub matches: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+            matches: 0,
+        }
+    }
+}
+
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+}
+
+// Weight of a result against an opponent whose rating has deviation `rd`.
+fn g(rd: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * Q * Q * rd * rd / (PI * PI)).sqrt()
+}
+
+// Glicko update of `player` after scoring `points` out of `games` against
+// `opponent`, the match being one rating period.
+fn update(player: &Rating, opponent: &Rating, games: u32, points: f64) -> (f64, f64) {
+    let g = g(opponent.deviation);
+    let expected = 1.0 / (1.0 + 10f64.powf(-g * (player.rating - opponent.rating) / 400.0));
+    let d2_inv = Q * Q * g * g * games as f64 * expected * (1.0 - expected);
+    let variance = 1.0 / (1.0 / (player.deviation * player.deviation) + d2_inv);
+    let rating = player.rating + Q * variance * g * (points - games as f64 * expected);
+    (rating, variance.sqrt().max(MIN_DEVIATION))
+}
+
+// Ratings of named engine configurations, kept in a JSON file across arena
+// sessions. Every match is one rating period.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ladder {
+    pub players: BTreeMap<String, Rating>,
+}
+
+impl Ladder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An empty ladder when the file does not exist yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Writes through a temporary file, like `MatchState::save`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = serde_json::to_string_pretty(self)?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, text)?;
+        std::fs::rename(tmp, path)
+    }
+
+    pub fn rating(&self, name: &str) -> Option<&Rating> {
+        self.players.get(name)
+    }
+
+    // Rates a match between `a` and `b`, `stats` holding `a`'s results.
+    // Everyone else's deviation grows for the period they sat out.
+    pub fn record_match(&mut self, a: &str, b: &str, stats: &MatchStats) {
+        let games = stats.games();
+        if games == 0 || a == b {
+            return;
+        }
+        for (name, rating) in self.players.iter_mut() {
+            if name != a && name != b {
+                rating.deviation = rating
+                    .deviation
+                    .hypot(DEVIATION_GROWTH)
+                    .min(INITIAL_DEVIATION);
+            }
+        }
+        let old_a = self.players.get(a).cloned().unwrap_or_default();
+        let old_b = self.players.get(b).cloned().unwrap_or_default();
+        let points = stats.wins as f64 + stats.draws as f64 / 2.0;
+        let (rating_a, deviation_a) = update(&old_a, &old_b, games, points);
+        let (rating_b, deviation_b) = update(&old_b, &old_a, games, games as f64 - points);
+
+        let a = self.players.entry(a.to_string()).or_default();
+        a.rating = rating_a;
+        a.deviation = deviation_a;
+        a.wins += stats.wins;
+        a.draws += stats.draws;
+        a.losses += stats.losses;
+        a.matches += 1;
+        let b = self.players.entry(b.to_string()).or_default();
+        b.rating = rating_b;
+        b.deviation = deviation_b;
+        b.wins += stats.losses;
+        b.draws += stats.draws;
+        b.losses += stats.wins;
+        b.matches += 1;
+    }
+
+    // Players from the highest rating down.
+    pub fn standings(&self) -> Vec<(&str, &Rating)> {
+        let mut standings: Vec<(&str, &Rating)> = self
+            .players
+            .iter()
+            .map(|(name, rating)| (name.as_str(), rating))
+            .collect();
+        standings.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating));
+        standings
+    }
+
+    // The standings as a text table.
+    pub fn table(&self) -> String {
+        let mut out = format!(
+            "{:>3}  {:>6}  {:>5}  {:>6}  {:>5}  {:>5}  {:>5}  Name\n",
+            "#", "Rating", "±", "Games", "W", "D", "L"
+        );
+        for (rank, (name, rating)) in self.standings().iter().enumerate() {
+            out.push_str(&format!(
+                "{:>3}  {:>6.0}  {:>5.0}  {:>6}  {:>5}  {:>5}  {:>5}  {}\n",
+                rank + 1,
+                rating.rating,
+                2.0 * rating.deviation,
+                rating.games(),
+                rating.wins,
+                rating.draws,
+                rating.losses,
+                name
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::GameScore;
+
+    fn results(wins: u32, draws: u32, losses: u32) -> MatchStats {
+        let mut stats = MatchStats::new();
+        let scores = [
+            (wins, GameScore::Win),
+            (draws, GameScore::Draw),
+            (losses, GameScore::Loss),
+        ];
+        let mut index = 0;
+        for (count, score) in scores {
+            for _ in 0..count {
+                stats.record(index, score);
+                index += 1;
+            }
+        }
+        stats
+    }
+
+    #[test]
+    fn glicko_matches_the_reference_example() {
+        // First game of Glickman's example: 1500 ± 200 beating 1400 ± 30.
+        let player = Rating {
+            deviation: 200.0,
+            ..Rating::default()
+        };
+        let opponent = |rating, deviation| Rating {
+            rating,
+            deviation,
+            ..Rating::default()
+        };
+        let (rating, deviation) = update(&player, &opponent(1400.0, 30.0), 1, 1.0);
+        assert!((rating - 1563.6).abs() < 0.5, "{}", rating);
+        assert!((deviation - 175.2).abs() < 0.5, "{}", deviation);
+        assert!((g(30.0) - 0.9955).abs() < 1e-4);
+        assert!((g(300.0) - 0.7242).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ladder_ranks_and_persists() {
+        let mut ladder = Ladder::new();
+        ladder.record_match("new", "old", &results(30, 40, 10));
+        ladder.record_match("old", "weak", &results(45, 5, 0));
+        let new = ladder.rating("new").unwrap();
+        assert!(new.rating > INITIAL_RATING && new.deviation < INITIAL_DEVIATION);
+        assert_eq!(
+            (new.wins, new.draws, new.losses, new.matches),
+            (30, 40, 10, 1)
+        );
+        assert_eq!(ladder.rating("old").unwrap().games(), 130);
+        let names: Vec<&str> = ladder.standings().iter().map(|(n, _)| *n).collect();
+        assert_eq!(names.last(), Some(&"weak"));
+        assert!(ladder.table().lines().nth(1).unwrap().starts_with("  1"));
+
+        ladder.record_match("same", "same", &results(1, 0, 0));
+        ladder.record_match("new", "old", &MatchStats::new());
+        assert_eq!(ladder.players.len(), 3);
+
+        let path = std::env::temp_dir().join(format!("ladder_{}.json", std::process::id()));
+        assert_eq!(Ladder::load(&path).unwrap(), Ladder::new());
+        ladder.save(&path).unwrap();
+        let loaded = Ladder::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded, ladder);
+    }
+}
@@ -1,45 +1,87 @@
 use crate::{
+    board::Board,
     game::Game,
     pieces::{Color, PieceType},
+    types::Move,
 };
 use regex::Regex;
 
-pub fn parse_san(game: &mut Game, san: &str, color: Color) -> Option<(String, String)> {
-    let mut san = san.replace("0", "O");
-    san = san.trim_end_matches(|c| c == '+' || c == '#').to_string();
-    let upper = san.to_uppercase();
-    if upper == "O-O" {
-        let start = if color == Color::White { "e1" } else { "e8" };
-        let end = if color == Color::White { "g1" } else { "g8" };
-        return Some((start.into(), end.into()));
-    }
-    if upper == "O-O-O" {
-        let start = if color == Color::White { "e1" } else { "e8" };
-        let end = if color == Color::White { "c1" } else { "c8" };
-        return Some((start.into(), end.into()));
-    }
-    let re = Regex::new(r"^([NBRQK])?([a-h])?([1-8])?[x-]?([a-h][1-8])(=?[NBRQK])?$").ok()?;
-    let caps = re.captures(&san)?;
-    let mut piece_letter = caps.get(1).map(|m| m.as_str());
-    let mut dfile = caps.get(2).map(|m| m.as_str());
-    let drank = caps.get(3).map(|m| m.as_str());
-    let dest = caps.get(4)?.as_str();
-    if let Some(pl) = piece_letter {
-        if pl.chars().all(|c| c.is_ascii_lowercase()) && "abcdefgh".contains(pl) && dfile.is_none()
-        {
-            dfile = Some(pl);
-            piece_letter = None;
+#[derive(Clone, Debug, PartialEq)]
+pub enum SanError {
+    // Not a move in any notation `parse_san` reads.
+    Syntax(String),
+    // Well formed, but no legal move fits it.
+    NoMatch(String),
+    // More than one legal move fits; the origin squares of each.
+    Ambiguous(String, Vec<String>),
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Syntax(san) => write!(f, "\"{}\" is not a move", san),
+            SanError::NoMatch(san) => write!(f, "no legal move matches \"{}\"", san),
+            SanError::Ambiguous(san, starts) => write!(
+                f,
+                "\"{}\" is ambiguous between pieces on {}",
+                san,
+                starts.join(", ")
+            ),
         }
     }
-    let ptype = match piece_letter {
+}
+
+impl std::error::Error for SanError {}
+
+// Also accepts long algebraic ("Ng1-f3", "e2-e4", "Rd1xd7") and ICCF
+// numeric notation ("5254").
+pub fn parse_san(game: &mut Game, san: &str, color: Color) -> Result<(String, String), SanError> {
+    let text = san;
+    if !san.is_empty() && san.bytes().all(|b| b.is_ascii_digit()) {
+        return parse_iccf(game, san, color);
+    }
+    // Check and mate marks, annotation glyphs ("!?", "??") and an "e.p."
+    // suffix carry nothing the move itself does not.
+    let marks: &[char] = &['+', '#', '!', '?'];
+    let san = san.trim().trim_end_matches(marks);
+    let san = san
+        .strip_suffix("e.p.")
+        .or_else(|| san.strip_suffix("ep"))
+        .unwrap_or(san)
+        .trim_end()
+        .trim_end_matches(marks);
+    let san = san.replace("0", "O");
+    let upper = san.to_uppercase();
+    if upper == "O-O" || upper == "O-O-O" {
+        let castle = game.board.castle_move(color, upper == "O-O");
+        let mv = game.board.move_to_strings(castle);
+        return if game.board.all_legal_moves(color).contains(&mv) {
+            Ok(mv)
+        } else {
+            Err(SanError::NoMatch(text.to_string()))
+        };
+    }
+    let syntax = || SanError::Syntax(text.to_string());
+    let re = Regex::new(r"^([NBRQK])?([a-h])?([1-8])?[x-]?([a-h][1-8])=?([NBRQ])?$")
+        .map_err(|_| syntax())?;
+    let caps = re.captures(&san).ok_or_else(syntax)?;
+    let dfile = caps.get(2).map(|m| m.as_str());
+    let drank = caps.get(3).map(|m| m.as_str());
+    let dest = caps.get(4).ok_or_else(syntax)?.as_str();
+    let promotion = caps.get(5).map(|m| m.as_str().to_lowercase());
+    let ptype = match caps.get(1).map(|m| m.as_str()) {
         Some("N") => PieceType::Knight,
         Some("B") => PieceType::Bishop,
         Some("R") => PieceType::Rook,
         Some("Q") => PieceType::Queen,
         Some("K") => PieceType::King,
         None => PieceType::Pawn,
-        _ => return None,
+        _ => return Err(syntax()),
     };
+    let last_rank = if color == Color::White { "8" } else { "1" };
+    if promotion.is_some() && (ptype != PieceType::Pawn || &dest[1..2] != last_rank) {
+        return Err(SanError::NoMatch(text.to_string()));
+    }
     let moves = game.board.all_legal_moves(color);
     let mut candidates = Vec::new();
     for (start, end) in moves {
@@ -63,11 +105,173 @@ pub fn parse_san(game: &mut Game, san: &str, color: Color) -> Option<(String, St
             candidates.push(start);
         }
     }
-    if candidates.len() == 1 {
-        Some((candidates.remove(0), dest.to_string()))
+    match candidates.len() {
+        0 => Err(SanError::NoMatch(text.to_string())),
+        1 => Ok((
+            candidates.remove(0),
+            format!("{}{}", dest, promotion.unwrap_or_default()),
+        )),
+        _ => Err(SanError::Ambiguous(text.to_string(), candidates)),
+    }
+}
+
+// ICCF numeric notation: file and rank digits of the origin and destination
+// squares ("5254" is e2-e4, "5171" is O-O), plus a promotion digit for
+// queen, rook, bishop or knight (1-4).
+pub fn parse_iccf(game: &mut Game, text: &str, color: Color) -> Result<(String, String), SanError> {
+    let syntax = || SanError::Syntax(text.to_string());
+    let digits = text.as_bytes();
+    if !(4..=5).contains(&digits.len()) {
+        return Err(syntax());
+    }
+    let promotion = match digits.get(4) {
+        None => "",
+        Some(b'1') => "q",
+        Some(b'2') => "r",
+        Some(b'3') => "b",
+        Some(b'4') => "n",
+        Some(_) => return Err(syntax()),
+    };
+    let square = |file: u8, rank: u8| {
+        if !(b'1'..=b'8').contains(&file) || !(b'1'..=b'8').contains(&rank) {
+            return Err(syntax());
+        }
+        Ok(format!("{}{}", (file - b'1' + b'a') as char, rank as char))
+    };
+    let start = square(digits[0], digits[1])?;
+    let end = square(digits[2], digits[3])?;
+    let no_match = || SanError::NoMatch(text.to_string());
+    let promotes = game.board.get(&start).ok_or_else(no_match)?.piece_type == PieceType::Pawn
+        && matches!(digits[3], b'1' | b'8');
+    if promotes == promotion.is_empty() {
+        return Err(no_match());
+    }
+    let mv = (start, end);
+    if !game.board.all_legal_moves(color).contains(&mv) {
+        return Err(no_match());
+    }
+    Ok((mv.0, format!("{}{}", mv.1, promotion)))
+}
+
+fn piece_letter(pt: PieceType) -> &'static str {
+    match pt {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+    }
+}
+
+fn apply_move(board: &mut Board, start: &str, end: &str) -> Option<()> {
+    board.make_move_state(start, end)?;
+    Some(())
+}
+
+pub fn line_to_san(board: &Board, color: Color, moves: &[(String, String)]) -> Vec<String> {
+    let mut board = board.clone();
+    let mut color = color;
+    let mut out = Vec::new();
+    for (start, end) in moves {
+        let Some(san) = move_to_san(&board, start, end, color) else {
+            break;
+        };
+        apply_move(&mut board, start, end);
+        out.push(san);
+        color = if color == Color::White {
+            Color::Black
+        } else {
+            Color::White
+        };
+    }
+    out
+}
+
+pub fn move_to_san(board: &Board, start: &str, end: &str, color: Color) -> Option<String> {
+    let (dest, promo) = Board::split_promotion(end)?;
+    let (sx, sy) = Board::algebraic_to_index(start)?;
+    let (ex, ey) = Board::algebraic_to_index(dest)?;
+    let piece = board.get_index(sx, sy)?;
+    if piece.color != color {
+        return None;
+    }
+    let promo = if piece.piece_type == PieceType::Pawn && (ey == 0 || ey == 7) {
+        Some(promo.unwrap_or(PieceType::Queen))
     } else {
         None
+    };
+
+    let mut b = board.clone();
+    let legal = b.all_legal_moves_fast(color);
+    if !legal.iter().any(|(s, e)| s == start && &e[0..2] == dest) {
+        return None;
+    }
+
+    let mut san = String::new();
+    let mv = board.encode_move((sy * 8 + sx) as u8, (ey * 8 + ex) as u8, promo);
+    if mv.is_castle() {
+        san.push_str(if mv.flags() == Move::FLAG_KING_CASTLE {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        let is_capture =
+            board.get_index(ex, ey).is_some() || (piece.piece_type == PieceType::Pawn && sx != ex);
+        if piece.piece_type == PieceType::Pawn {
+            if is_capture {
+                san.push_str(&start[0..1]);
+            }
+        } else {
+            san.push_str(piece_letter(piece.piece_type));
+            let others: Vec<&String> = legal
+                .iter()
+                .filter(|(s, e)| {
+                    s != start
+                        && &e[0..2] == dest
+                        && board
+                            .get(s)
+                            .is_some_and(|p| p.piece_type == piece.piece_type)
+                })
+                .map(|(s, _)| s)
+                .collect();
+            if !others.is_empty() {
+                let same_file = others.iter().any(|s| s[0..1] == start[0..1]);
+                let same_rank = others.iter().any(|s| s[1..2] == start[1..2]);
+                if !same_file {
+                    san.push_str(&start[0..1]);
+                } else if !same_rank {
+                    san.push_str(&start[1..2]);
+                } else {
+                    san.push_str(start);
+                }
+            }
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(dest);
+        if let Some(pt) = promo {
+            san.push('=');
+            san.push_str(piece_letter(pt));
+        }
     }
+
+    apply_move(&mut b, start, end)?;
+    let opp = if color == Color::White {
+        Color::Black
+    } else {
+        Color::White
+    };
+    if b.in_check_fast(opp) {
+        if b.all_legal_moves_fast(opp).is_empty() {
+            san.push('#');
+        } else {
+            san.push('+');
+        }
+    }
+    Some(san)
 }
 
 #[cfg(test)]
@@ -75,11 +279,41 @@ mod tests {
     use super::*;
     use crate::game::Game;
 
+    #[test]
+    fn line_to_san_follows_moves() {
+        let game = Game::new();
+        let line = vec![
+            ("e2".to_string(), "e4".to_string()),
+            ("e7".to_string(), "e5".to_string()),
+            ("d1".to_string(), "h5".to_string()),
+            ("a7".to_string(), "a1".to_string()),
+        ];
+        assert_eq!(
+            line_to_san(&game.board, Color::White, &line),
+            vec!["e4", "e5", "Qh5"]
+        );
+    }
+
     #[test]
     fn castle_kingside() {
         let mut game = Game::new();
+        assert!(parse_san(&mut game, "O-O", Color::White).is_err());
+        for (s, e) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3"), ("b8", "c6")] {
+            assert!(game.make_move(s, e));
+        }
+        assert!(game.make_move("f1", "c4"));
+        assert!(game.make_move("g8", "f6"));
         let mv = parse_san(&mut game, "O-O", Color::White).unwrap();
         assert_eq!(mv, ("e1".to_string(), "g1".to_string()));
+
+        // In Chess960 the castling move is the king taking its rook.
+        let mut game = Game::from_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1").unwrap();
+        let mv = parse_san(&mut game, "O-O-O", Color::White).unwrap();
+        assert_eq!(mv, ("e1".to_string(), "b1".to_string()));
+        assert_eq!(
+            move_to_san(&game.board, "e1", "b1", Color::White).as_deref(),
+            Some("O-O-O")
+        );
     }
 
     #[test]
@@ -90,4 +324,130 @@ mod tests {
         let mv = parse_san(&mut game, "Qd3", Color::White).unwrap();
         assert_eq!(mv, ("d1".to_string(), "d3".to_string()));
     }
+
+    #[test]
+    fn san_round_trip() {
+        let mut game = Game::new();
+        for (s, e) in [
+            ("b1", "c3"),
+            ("b8", "c6"),
+            ("c3", "e4"),
+            ("c6", "b8"),
+            ("g1", "f3"),
+            ("b8", "c6"),
+        ] {
+            assert!(game.make_move(s, e));
+        }
+        assert_eq!(
+            move_to_san(&game.board, "f3", "g5", Color::White).as_deref(),
+            Some("Nfg5")
+        );
+        assert_eq!(
+            move_to_san(&game.board, "e2", "e3", Color::White).as_deref(),
+            Some("e3")
+        );
+        let mv = parse_san(&mut game, "Nfg5", Color::White).unwrap();
+        assert_eq!(mv, ("f3".to_string(), "g5".to_string()));
+    }
+
+    #[test]
+    fn long_algebraic_and_iccf() {
+        let mut game = Game::new();
+        let mv = parse_san(&mut game, "Ng1-f3", Color::White).unwrap();
+        assert_eq!(mv, ("g1".to_string(), "f3".to_string()));
+        let mv = parse_san(&mut game, "e2-e4", Color::White).unwrap();
+        assert_eq!(mv, ("e2".to_string(), "e4".to_string()));
+        assert!(parse_san(&mut game, "Ng1-e2", Color::White).is_err());
+
+        let mv = parse_san(&mut game, "5254", Color::White).unwrap();
+        assert_eq!(mv, ("e2".to_string(), "e4".to_string()));
+        assert!(parse_san(&mut game, "5255", Color::White).is_err());
+        assert!(parse_san(&mut game, "9254", Color::White).is_err());
+
+        for (s, e) in [("e2", "e4"), ("e7", "e5"), ("g1", "f3"), ("b8", "c6")] {
+            assert!(game.make_move(s, e));
+        }
+        assert!(game.make_move("f1", "c4"));
+        assert!(game.make_move("g8", "f6"));
+        assert!(parse_san(&mut game, "Bc4xf7+", Color::White).is_ok());
+        let mv = parse_san(&mut game, "5171", Color::White).unwrap();
+        assert_eq!(mv, ("e1".to_string(), "g1".to_string()));
+
+        let mut game = Game::from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert!(parse_iccf(&mut game, "1718", Color::White).is_err());
+        let mv = parse_iccf(&mut game, "17184", Color::White).unwrap();
+        assert_eq!(mv, ("a7".to_string(), "a8n".to_string()));
+    }
+
+    #[test]
+    fn promotions_en_passant_and_annotations() {
+        let mut game = Game::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let mv = parse_san(&mut game, "b8=N", Color::White).unwrap();
+        assert_eq!(mv, ("b7".to_string(), "b8n".to_string()));
+        let mv = parse_san(&mut game, "bxa8Q+!", Color::White).unwrap();
+        assert_eq!(mv, ("b7".to_string(), "a8q".to_string()));
+        assert!(parse_san(&mut game, "e6=Q", Color::White).is_err());
+        assert!(parse_san(&mut game, "Ke2=Q", Color::White).is_err());
+        for text in ["exd6 e.p.", "exd6e.p.", "exd6 ep", "exd6!?"] {
+            let mv = parse_san(&mut game, text, Color::White).unwrap();
+            assert_eq!(mv, ("e5".to_string(), "d6".to_string()), "{}", text);
+        }
+
+        assert!(game.make_move("b7", "b8n"));
+        assert_eq!(game.board.get("b8").unwrap().piece_type, PieceType::Knight);
+        assert_eq!(game.san_history(), ["b8=N"]);
+        assert!(!game.make_move("e8", "e7q"));
+        assert!(game.make_move("e8", "e7"));
+
+        let mut game = Game::new();
+        for (s, e) in [("f2", "f3"), ("e7", "e5"), ("g2", "g4")] {
+            assert!(game.make_move(s, e));
+        }
+        let mv = parse_san(&mut game, "Qh4#!!", Color::Black).unwrap();
+        assert_eq!(mv, ("d8".to_string(), "h4".to_string()));
+    }
+
+    #[test]
+    fn errors_say_what_went_wrong() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        assert_eq!(
+            parse_san(&mut game, "Rd1", Color::White),
+            Err(SanError::Ambiguous(
+                "Rd1".to_string(),
+                vec!["a1".to_string(), "h1".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_san(&mut game, "Rad1", Color::White),
+            Ok(("a1".to_string(), "d1".to_string()))
+        );
+        assert_eq!(
+            parse_san(&mut game, "Nf3", Color::White),
+            Err(SanError::NoMatch("Nf3".to_string()))
+        );
+        assert_eq!(
+            parse_san(&mut game, "Zz9", Color::White),
+            Err(SanError::Syntax("Zz9".to_string()))
+        );
+        assert_eq!(
+            parse_san(&mut game, "O-O-O", Color::Black),
+            Err(SanError::NoMatch("O-O-O".to_string()))
+        );
+        assert_eq!(
+            SanError::Ambiguous("Rd1".to_string(), vec!["a1".into(), "h1".into()]).to_string(),
+            "\"Rd1\" is ambiguous between pieces on a1, h1"
+        );
+    }
+
+    #[test]
+    fn san_checkmate_suffix() {
+        let mut game = Game::new();
+        for (s, e) in [("f2", "f3"), ("e7", "e5"), ("g2", "g4")] {
+            assert!(game.make_move(s, e));
+        }
+        assert_eq!(
+            move_to_san(&game.board, "d8", "h4", Color::Black).as_deref(),
+            Some("Qh4#")
+        );
+    }
 }
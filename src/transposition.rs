@@ -1,9 +1,10 @@
-use once_cell::sync::Lazy;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicI32, AtomicU8, AtomicU64, Ordering};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicI32, AtomicU8, AtomicU64, Ordering};
 
 use crate::board::Board;
-use crate::pieces::Color;
+use crate::pieces::{Color, PieceType};
+use crate::types::Move;
 
 #[derive(Clone, Copy)]
 pub enum Bound {
@@ -17,7 +18,7 @@ pub struct TTEntry {
     pub depth: u32,
     pub value: i32,
     pub bound: Bound,
-    pub best: Option<(u8, u8)>,
+    pub best: Option<Move>,
 }
 
 struct RawEntry {
@@ -36,6 +37,34 @@ impl Default for RawEntry {
     }
 }
 
+impl RawEntry {
+    fn packed(&self) -> u64 {
+        self.packed.load(Ordering::Relaxed)
+    }
+
+    fn write(&self, key: u64, value: i32, packed: u64) {
+        self.key.store(key, Ordering::Release);
+        self.value.store(value, Ordering::Relaxed);
+        self.packed.store(packed, Ordering::Relaxed);
+    }
+}
+
+fn packed_depth(packed: u64) -> u32 {
+    (packed >> 32) as u32
+}
+
+fn packed_age(packed: u64) -> u8 {
+    ((packed >> 24) & 0xFF) as u8
+}
+
+// Entries are grouped in clusters sharing one index. The first slots keep the
+// deepest recent results; the last one takes whatever they turn down, so the
+// shallow entries near the leaves are not lost.
+const CLUSTER: usize = 4;
+const ALWAYS_REPLACE: usize = CLUSTER - 1;
+// Searches after which an entry no longer protects its slot.
+const STALE_AGE: u8 = 5;
+
 struct Inner {
     entries: Vec<RawEntry>,
     age: AtomicU8,
@@ -45,7 +74,9 @@ struct Inner {
 pub struct Table(Arc<Inner>);
 
 impl Table {
+    // A table of `size` entries, rounded down to whole clusters.
     pub fn new(size: usize) -> Self {
+        let size = (size / CLUSTER).max(1) * CLUSTER;
         let mut entries = Vec::with_capacity(size);
         entries.resize_with(size, RawEntry::default);
         Self(Arc::new(Inner {
@@ -63,35 +94,36 @@ impl Table {
         self.0.age.store(age, Ordering::Relaxed);
     }
 
+    fn cluster(&self, key: u64) -> &[RawEntry] {
+        let clusters = self.0.entries.len() / CLUSTER;
+        let start = (key as usize) % clusters * CLUSTER;
+        &self.0.entries[start..start + CLUSTER]
+    }
+
     pub fn get(&self, key: u64) -> Option<TTEntry> {
-        let idx = (key as usize) % self.0.entries.len();
-        let entry = &self.0.entries[idx];
-        if entry.key.load(Ordering::Acquire) == key {
-            let value = entry.value.load(Ordering::Relaxed);
-            let packed = entry.packed.load(Ordering::Relaxed);
-            let depth = (packed >> 32) as u32;
-            let bound = match ((packed >> 16) & 0xFF) as u8 {
-                1 => Bound::Lower,
-                2 => Bound::Upper,
-                _ => Bound::Exact,
-            };
-            let from = ((packed >> 8) & 0xFF) as u8;
-            let to = (packed & 0xFF) as u8;
-            let best = if from == 0xFF { None } else { Some((from, to)) };
-            Some(TTEntry {
-                depth,
-                value,
-                bound,
-                best,
-            })
-        } else {
-            None
-        }
+        let entry = self
+            .cluster(key)
+            .iter()
+            .find(|e| e.key.load(Ordering::Acquire) == key)?;
+        let value = entry.value.load(Ordering::Relaxed);
+        let packed = entry.packed();
+        let bound = match ((packed >> 16) & 0xFF) as u8 {
+            1 => Bound::Lower,
+            2 => Bound::Upper,
+            _ => Bound::Exact,
+        };
+        // a1a1 is never a legal move, so 0 stands for "no move".
+        let best = Some(Move((packed & 0xFFFF) as u16)).filter(|m| m.0 != 0);
+        Some(TTEntry {
+            depth: packed_depth(packed),
+            value,
+            bound,
+            best,
+        })
     }
 
     pub fn store(&self, key: u64, entry: TTEntry) {
-        let idx = (key as usize) % self.0.entries.len();
-        let slot = &self.0.entries[idx];
+        let cluster = self.cluster(key);
         let age = self.current_age();
         let packed_new = ((entry.depth as u64) << 32)
             | ((age as u64) << 24)
@@ -101,58 +133,123 @@ impl Table {
                 Bound::Upper => 2,
             }) as u64)
                 << 16)
-            | ((entry.best.map(|b| b.0).unwrap_or(0xFF) as u64) << 8)
-            | (entry.best.map(|b| b.1).unwrap_or(0xFF) as u64);
-
-        let existing_key = slot.key.load(Ordering::Acquire);
-        if existing_key != key {
-            let existing = slot.packed.load(Ordering::Relaxed);
-            let existing_depth = (existing >> 32) as u32;
-            let existing_age = ((existing >> 24) & 0xFF) as u8;
-            if entry.depth >= existing_depth || age.wrapping_sub(existing_age) > 5 {
-                slot.key.store(key, Ordering::Release);
+            | (entry.best.map_or(0, |m| m.0) as u64);
+
+        let position = cluster
+            .iter()
+            .position(|e| e.key.load(Ordering::Acquire) == key);
+        if let Some(i) = position.filter(|&i| i != ALWAYS_REPLACE) {
+            let slot = &cluster[i];
+            if entry.depth >= packed_depth(slot.packed()) {
                 slot.value.store(entry.value, Ordering::Relaxed);
                 slot.packed.store(packed_new, Ordering::Relaxed);
             }
-        } else {
-            let existing = slot.packed.load(Ordering::Relaxed);
-            let existing_depth = (existing >> 32) as u32;
-            if entry.depth >= existing_depth {
-                slot.value.store(entry.value, Ordering::Relaxed);
-                slot.packed.store(packed_new, Ordering::Relaxed);
+            return;
+        }
+
+        // The shallowest of the depth-preferred slots, stale ones first.
+        let stale = |packed: u64| age.wrapping_sub(packed_age(packed)) > STALE_AGE;
+        let victim = cluster[..ALWAYS_REPLACE]
+            .iter()
+            .min_by_key(|e| {
+                let packed = e.packed();
+                (!stale(packed), packed_depth(packed))
+            })
+            .unwrap_or(&cluster[ALWAYS_REPLACE]);
+        let packed = victim.packed();
+        let always = &cluster[ALWAYS_REPLACE];
+        if entry.depth >= packed_depth(packed) || stale(packed) {
+            // The entry pushed out still gets a chance in the last slot,
+            // which otherwise must not keep an older copy of this one.
+            let old_key = victim.key.load(Ordering::Acquire);
+            if old_key != 0 && !stale(packed) {
+                always.write(old_key, victim.value.load(Ordering::Relaxed), packed);
+            } else if position.is_some() {
+                always.write(0, 0, 0);
             }
+            victim.write(key, entry.value, packed_new);
+        } else {
+            always.write(key, entry.value, packed_new);
         }
     }
 }
 
-pub static ZOBRIST: Lazy<[[[u64; 64]; 6]; 2]> = Lazy::new(|| {
+pub static ZOBRIST: [[[u64; 64]; 6]; 2] = {
     let mut arr = [[[0u64; 64]; 6]; 2];
     let mut seed: u64 = 0xcbf29ce484222325;
-    for c in 0..2 {
-        for p in 0..6 {
-            for s in 0..64 {
-                seed ^= seed >> 12;
-                seed ^= seed << 25;
-                seed ^= seed >> 27;
-                seed = seed.wrapping_mul(0x2545F4914F6CDD1D);
-                arr[c][p][s] = seed;
-            }
-        }
+    let mut i = 0;
+    while i < 2 * 6 * 64 {
+        seed ^= seed >> 12;
+        seed ^= seed << 25;
+        seed ^= seed >> 27;
+        seed = seed.wrapping_mul(0x2545F4914F6CDD1D);
+        arr[i / 384][i / 64 % 6][i % 64] = seed;
+        i += 1;
     }
     arr
-});
+};
+
+pub const ZOBRIST_SIDE: u64 = 0x9d39247e33776d41;
 
-pub static ZOBRIST_SIDE: Lazy<u64> = Lazy::new(|| 0x9d39247e33776d41);
+// Keys for the four castling rights, in `Board::castling` order, then the
+// eight en passant files. `hash` leaves them out; repetitions need them.
+static ZOBRIST_RIGHTS: [u64; 12] = {
+    let mut arr = [0u64; 12];
+    let mut seed: u64 = 0x84222325cbf29ce4;
+    let mut i = 0;
+    while i < 12 {
+        seed ^= seed >> 12;
+        seed ^= seed << 25;
+        seed ^= seed >> 27;
+        seed = seed.wrapping_mul(0x2545F4914F6CDD1D);
+        arr[i] = seed;
+        i += 1;
+    }
+    arr
+};
 
 impl Board {
     pub fn hash(&self, side: Color) -> u64 {
         if side == Color::White {
-            self.hash ^ *ZOBRIST_SIDE
+            self.hash ^ ZOBRIST_SIDE
         } else {
             self.hash
         }
     }
 
+    // `hash` plus the castling rights and an en passant capture `side` can
+    // legally make: positions with the same key are the same under the
+    // repetition rules.
+    pub fn repetition_key(&self, side: Color) -> u64 {
+        let mut key = self.hash(side);
+        for (i, &right) in self.castling.iter().flatten().enumerate() {
+            if right {
+                key ^= ZOBRIST_RIGHTS[i];
+            }
+        }
+        if let Some((x, y)) = self.en_passant {
+            let to = (y * 8 + x) as u8;
+            let from_rank = if side == Color::White {
+                y.wrapping_sub(1)
+            } else {
+                y + 1
+            };
+            let mut board = self.clone();
+            let capturable = [x.wrapping_sub(1), x + 1]
+                .into_iter()
+                .filter(|&fx| fx < 8 && from_rank < 8)
+                .map(|fx| (from_rank * 8 + fx) as u8)
+                .any(|from| {
+                    board.piece_at_sq(from) == Some((PieceType::Pawn, side))
+                        && board.is_legal_sq(from, to, side)
+                });
+            if capturable {
+                key ^= ZOBRIST_RIGHTS[4 + x];
+            }
+        }
+        key
+    }
+
     pub fn recompute_hash(&mut self) {
         let mut h = 0u64;
         for c in 0..2 {
@@ -170,3 +267,52 @@ impl Board {
 }
 
 pub const TABLE_SIZE: usize = 4_194_304;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(depth: u32, value: i32) -> TTEntry {
+        TTEntry {
+            depth,
+            value,
+            bound: Bound::Exact,
+            best: None,
+        }
+    }
+
+    #[test]
+    fn shallow_entries_survive_deep_ones() {
+        // One cluster, so every key shares it.
+        let table = Table::new(CLUSTER);
+        for key in 1..=3 {
+            table.store(key, entry(10, key as i32));
+        }
+        // Too shallow for the depth-preferred slots.
+        table.store(4, entry(2, 4));
+        assert_eq!(table.get(4).map(|e| e.value), Some(4));
+        table.store(5, entry(1, 5));
+        assert!(table.get(4).is_none());
+        assert_eq!(table.get(5).map(|e| e.value), Some(5));
+
+        // A deeper entry takes a depth-preferred slot and moves the old one
+        // to the last slot.
+        table.store(6, entry(12, 6));
+        assert_eq!(table.get(6).map(|e| e.depth), Some(12));
+        assert_eq!((1..=3).filter(|&k| table.get(k).is_some()).count(), 3);
+        assert!(table.get(5).is_none());
+
+        // A shallower result for a known key does not overwrite it.
+        table.store(6, entry(3, -6));
+        assert_eq!(table.get(6).map(|e| e.value), Some(6));
+
+        // Old entries give way after a few searches.
+        for _ in 0..=STALE_AGE {
+            table.next_age();
+        }
+        table.store(7, entry(1, 7));
+        table.store(8, entry(1, 8));
+        assert_eq!(table.get(7).map(|e| e.value), Some(7));
+        assert_eq!(table.get(8).map(|e| e.value), Some(8));
+    }
+}
@@ -1,4 +1,6 @@
 use crate::pieces::PieceType;
+use alloc::format;
+use alloc::string::String;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
 #[repr(transparent)]
@@ -248,7 +250,7 @@ impl MoveList {
     }
 }
 
-impl std::ops::Index<usize> for MoveList {
+impl core::ops::Index<usize> for MoveList {
     type Output = Move;
 
     #[inline(always)]
@@ -257,7 +259,7 @@ impl std::ops::Index<usize> for MoveList {
     }
 }
 
-impl std::ops::IndexMut<usize> for MoveList {
+impl core::ops::IndexMut<usize> for MoveList {
     #[inline(always)]
     fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
         &mut self.moves[idx]
@@ -362,6 +364,7 @@ pub struct UndoState {
     pub prev_ep: u8,
     pub prev_castling: u8,
     pub prev_hash: u64,
+    pub prev_halfmove: u16,
 }
 
 impl UndoState {
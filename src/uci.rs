@@ -0,0 +1,344 @@
+use crate::board::Board;
+use crate::engine::{PvLine, SearchInfo, TimeConfig};
+use crate::game::Game;
+use crate::types::Move;
+
+// Reply to `uci`, before the options and `uciok`.
+pub const ID: [&str; 2] = ["id name chessmind", "id author leopoldch"];
+pub const OPTIONS: [&str; 2] = [
+    "option name MultiPV type spin default 1 min 1 max 256",
+    "option name UCI_Chess960 type check default false",
+];
+
+// The subset of the UCI protocol a front end needs to play and analyse.
+#[derive(Clone)]
+pub enum UciCommand {
+    Uci,
+    IsReady,
+    NewGame,
+    Position(Box<Game>),
+    Go(TimeConfig),
+    Stop,
+    // `d` or `eval`: show the position and its static evaluation.
+    Eval,
+    SetOption { name: String, value: String },
+    Quit,
+}
+
+// The move `start`-`end` as `game`'s board encodes it.
+fn encode(game: &Game, start: &str, end: &str) -> Option<Move> {
+    let (sx, sy) = Board::algebraic_to_index(start)?;
+    let (ex, ey) = Board::algebraic_to_index(end.get(0..2)?)?;
+    Some(
+        game.board
+            .encode_move((sy * 8 + sx) as u8, (ey * 8 + ex) as u8, None),
+    )
+}
+
+// Castling the way `Game` lists it. Chess960 front ends write it as the king
+// taking its own rook ("e1h1"), others as the king's two-square move
+// ("e1g1").
+fn castling_move(game: &Game, mv: &str) -> Option<String> {
+    if mv.len() != 4 || !mv.is_char_boundary(2) {
+        return None;
+    }
+    let mv = encode(game, &mv[0..2], &mv[2..4]).filter(|m| m.is_castle())?;
+    let (start, end) = game.board.move_to_strings(mv);
+    Some(format!("{}{}", start, end))
+}
+
+fn parse_position(mut words: std::slice::Iter<'_, &str>) -> Result<Game, String> {
+    let mut game = match words.next() {
+        Some(&"startpos") => Game::new(),
+        Some(&"fen") => {
+            let fen: Vec<&str> = words
+                .clone()
+                .take_while(|&&w| w != "moves")
+                .copied()
+                .collect();
+            for _ in 0..fen.len() {
+                words.next();
+            }
+            let fen = fen.join(" ");
+            Game::parse_fen(&fen).map_err(|e| format!("invalid FEN {}: {}", fen, e))?
+        }
+        _ => return Err("position needs startpos or fen".to_string()),
+    };
+    match words.next() {
+        None => return Ok(game),
+        Some(&"moves") => {}
+        Some(word) => return Err(format!("unexpected {} in position", word)),
+    }
+    for &text in words {
+        let castle = castling_move(&game, text);
+        let mv = castle.as_deref().unwrap_or(text);
+        let legal = matches!(mv.len(), 4 | 5)
+            && mv.is_char_boundary(2)
+            && game
+                .legal_moves()
+                .iter()
+                .any(|(s, e)| s == &mv[0..2] && e.get(0..2) == mv.get(2..4));
+        if !legal || !game.make_move(&mv[0..2], &mv[2..]) {
+            return Err(format!("illegal move: {}", text));
+        }
+    }
+    Ok(game)
+}
+
+fn parse_go(mut words: std::slice::Iter<'_, &str>) -> Result<TimeConfig, String> {
+    let mut config = TimeConfig::default();
+    while let Some(&word) = words.next() {
+        let mut number = || -> Result<u64, String> {
+            let value = words
+                .next()
+                .ok_or_else(|| format!("missing value for {}", word))?;
+            value
+                .parse()
+                .map_err(|_| format!("invalid {}: {}", word, value))
+        };
+        match word {
+            // A negative clock is read as an empty one.
+            "wtime" | "btime" => {
+                let ms = words
+                    .next()
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .ok_or_else(|| format!("invalid {}", word))?
+                    .max(0) as u64;
+                if word == "wtime" {
+                    config.wtime = Some(ms);
+                } else {
+                    config.btime = Some(ms);
+                }
+            }
+            "winc" => config.winc = Some(number()?),
+            "binc" => config.binc = Some(number()?),
+            "movestogo" => config.movestogo = Some(number()? as u32),
+            "depth" => config.depth = Some(number()? as u32),
+            "movetime" => config.movetime = Some(number()?),
+            "infinite" => config.infinite = true,
+            // Accepted but not supported: these searches run to the clock.
+            "ponder" => {}
+            "nodes" | "mate" => {
+                number()?;
+            }
+            _ => return Err(format!("unknown go parameter: {}", word)),
+        }
+    }
+    // Without a limit, search until `stop`.
+    if config.wtime.is_none()
+        && config.btime.is_none()
+        && config.depth.is_none()
+        && config.movetime.is_none()
+    {
+        config.infinite = true;
+    }
+    Ok(config)
+}
+
+// Reads one command line; unknown commands are an error, which a front end
+// should report and otherwise ignore.
+pub fn parse_command(line: &str) -> Result<UciCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let mut rest = words.iter();
+    let command = match rest.next() {
+        Some(&word) => word,
+        None => return Err("empty command".to_string()),
+    };
+    match command {
+        "uci" => Ok(UciCommand::Uci),
+        "isready" => Ok(UciCommand::IsReady),
+        "ucinewgame" => Ok(UciCommand::NewGame),
+        "position" => parse_position(rest).map(|game| UciCommand::Position(Box::new(game))),
+        "go" => parse_go(rest).map(UciCommand::Go),
+        "stop" => Ok(UciCommand::Stop),
+        "d" | "eval" => Ok(UciCommand::Eval),
+        "quit" => Ok(UciCommand::Quit),
+        "setoption" => {
+            let text = words[1..].join(" ");
+            let text = text.strip_prefix("name ").ok_or("setoption needs a name")?;
+            let (name, value) = text.split_once(" value ").unwrap_or((text, ""));
+            Ok(UciCommand::SetOption {
+                name: name.trim().to_string(),
+                value: value.trim().to_string(),
+            })
+        }
+        _ => Err(format!("unknown command: {}", command)),
+    }
+}
+
+// `info` line for the `index`th line (from 0) of a completed iteration.
+pub fn info_line(info: &SearchInfo, index: usize, line: &PvLine) -> String {
+    let score = match line.mate_in() {
+        Some(m) => format!("mate {}", m),
+        None => format!("cp {}", line.score),
+    };
+    let pv: Vec<String> = line
+        .moves
+        .iter()
+        .map(|(s, e)| format!("{}{}", s, e))
+        .collect();
+    format!(
+        "info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        index + 1,
+        score,
+        info.nodes,
+        info.nodes * 1000 / info.time_ms.max(1),
+        info.time_ms,
+        pv.join(" ")
+    )
+}
+
+// `info` rewritten for `UCI_Chess960`: castling moves in its lines, played
+// from `game`, become the king taking its own rook.
+pub fn chess960_info(game: &Game, info: &SearchInfo) -> SearchInfo {
+    let mut info = info.clone();
+    for line in &mut info.lines {
+        let mut game = game.clone();
+        for (start, end) in &mut line.moves {
+            let played = end.clone();
+            if let Some(mv) = encode(&game, start, end).filter(|m| m.is_castle()) {
+                *end = game.board.move_to_uci(mv, true)[2..].to_string();
+            }
+            if !game.make_move(start, &played) {
+                break;
+            }
+        }
+    }
+    info
+}
+
+// `bestmove` line ending a search, with the expected reply to ponder on.
+pub fn bestmove_line(info: &SearchInfo) -> String {
+    let moves = info.best().map_or(&[][..], |line| &line.moves[..]);
+    match moves {
+        [] => "bestmove 0000".to_string(),
+        [(s, e)] => format!("bestmove {}{}", s, e),
+        [(s, e), (ps, pe), ..] => format!("bestmove {}{} ponder {}{}", s, e, ps, pe),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pieces::Color;
+
+    #[test]
+    fn parses_positions_and_searches() {
+        let Ok(UciCommand::Position(game)) =
+            parse_command("position startpos moves e2e4 e7e5 g1f3")
+        else {
+            panic!("position not read");
+        };
+        assert_eq!(game.history.len(), 3);
+        assert_eq!(game.current_turn, Color::Black);
+
+        let Ok(UciCommand::Position(game)) =
+            parse_command("position fen 4k3/P7/8/8/8/8/8/4K3 w - - 0 1 moves a7a8n")
+        else {
+            panic!("fen not read");
+        };
+        assert_eq!(game.history, vec![("a7".to_string(), "a8n".to_string())]);
+        assert!(parse_command("position startpos moves e2e5").is_err());
+        assert!(parse_command("position fen 8/8 w").is_err());
+
+        let Ok(UciCommand::Position(game)) =
+            parse_command("position fen r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1 moves e1h1 e8a8")
+        else {
+            panic!("king-takes-rook castling not read");
+        };
+        assert_eq!(game.to_fen(), "2kr3r/8/8/8/8/8/8/R4RK1 w - - 2 2");
+        let Ok(UciCommand::Position(game)) = parse_command(
+            "position fen 1r2k1r1/3pp3/8/8/8/8/3PP3/1R2K1R1 w GBgb - 0 1 moves e1b1 e8c8",
+        ) else {
+            panic!("Chess960 castling not read");
+        };
+        assert_eq!(game.to_fen(), "2kr2r1/3pp3/8/8/8/8/3PP3/2KR2R1 w - - 2 2");
+
+        let Ok(UciCommand::Go(config)) =
+            parse_command("go wtime 60000 btime -5 winc 1000 binc 1000 movestogo 20")
+        else {
+            panic!("go not read");
+        };
+        assert_eq!(config.wtime, Some(60000));
+        assert_eq!(config.btime, Some(0));
+        assert_eq!(config.movestogo, Some(20));
+        assert!(!config.infinite);
+        let Ok(UciCommand::Go(config)) = parse_command("go") else {
+            panic!("bare go not read");
+        };
+        assert!(config.infinite);
+        assert!(parse_command("go depth").is_err());
+
+        let Ok(UciCommand::SetOption { name, value }) =
+            parse_command("setoption name MultiPV value 3")
+        else {
+            panic!("setoption not read");
+        };
+        assert_eq!((name.as_str(), value.as_str()), ("MultiPV", "3"));
+        assert!(matches!(parse_command("isready"), Ok(UciCommand::IsReady)));
+        assert!(matches!(parse_command("d"), Ok(UciCommand::Eval)));
+        assert!(matches!(parse_command("eval"), Ok(UciCommand::Eval)));
+        assert!(parse_command("register later").is_err());
+    }
+
+    #[test]
+    fn formats_search_output() {
+        let line = PvLine {
+            score: 35,
+            moves: vec![
+                ("e2".to_string(), "e4".to_string()),
+                ("e7".to_string(), "e5".to_string()),
+            ],
+        };
+        let info = SearchInfo {
+            depth: 7,
+            seldepth: 12,
+            nodes: 20000,
+            time_ms: 100,
+            lines: vec![line.clone()],
+        };
+        assert_eq!(
+            info_line(&info, 0, &line),
+            "info depth 7 seldepth 12 multipv 1 score cp 35 nodes 20000 nps 200000 time 100 pv e2e4 e7e5"
+        );
+        assert_eq!(bestmove_line(&info), "bestmove e2e4 ponder e7e5");
+        assert_eq!(bestmove_line(&SearchInfo::default()), "bestmove 0000");
+
+        let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castles = SearchInfo {
+            lines: vec![PvLine {
+                score: 0,
+                moves: vec![
+                    ("e1".to_string(), "g1".to_string()),
+                    ("e8".to_string(), "c8".to_string()),
+                    ("g1".to_string(), "h2".to_string()),
+                ],
+            }],
+            ..info
+        };
+        assert_eq!(
+            bestmove_line(&chess960_info(&game, &castles)),
+            "bestmove e1h1 ponder e8a8"
+        );
+        let pv = &chess960_info(&game, &castles).lines[0];
+        assert_eq!(pv.moves[2], ("g1".to_string(), "h2".to_string()));
+
+        let game = Game::from_fen("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1").unwrap();
+        let line = |end: &str| PvLine {
+            score: 0,
+            moves: vec![("e1".to_string(), end.to_string())],
+        };
+        let castles = SearchInfo {
+            lines: vec![line("c1"), line("b1"), line("g1")],
+            ..SearchInfo::default()
+        };
+        let ends: Vec<String> = chess960_info(&game, &castles)
+            .lines
+            .into_iter()
+            .map(|line| line.moves[0].1.clone())
+            .collect();
+        assert_eq!(ends, ["b1", "b1", "g1"]);
+    }
+}